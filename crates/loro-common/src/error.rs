@@ -60,6 +60,10 @@ pub enum LoroError {
     FrontiersNotFound(ID),
     #[error("Cannot import when the doc is in a transaction")]
     ImportWhenInTxn,
+    #[error("The text update timed out")]
+    TextUpdateTimeout,
+    #[error("Cannot import, checkout, change the peer id or start another transaction inside `transact`")]
+    TransactionInProgress,
     #[error("The given method ({method}) is not allowed when the container is detached. You should insert the container to the doc first.")]
     MisuseDetachedContainer { method: &'static str },
     #[error("Not implemented: {0}")]
@@ -94,6 +98,18 @@ pub enum LoroError {
     ContainerDeleted { container: Box<ContainerID> },
     #[error("You cannot set the `PeerID` with `PeerID::MAX`, which is an internal specific value")]
     InvalidPeerID,
+    #[error("Decode error: The data is encrypted with the key \"{key_id}\". It needs to be decrypted with this key first.")]
+    DecryptionKeyRequired { key_id: Box<str> },
+    #[error("Decode error: Failed to decrypt the data. The key is wrong or the data has been tampered with.")]
+    DecryptionError,
+    #[error("Decode error: The data is not encrypted, but only encrypted data is accepted.")]
+    NotEncrypted,
+    #[error("The change {id} is unsigned or its signature doesn't match the key of its peer")]
+    InvalidChangeSignature { id: ID },
+    #[error("The change {id} can't be verified, because the JSON format doesn't carry the signatures. Import the binary updates instead.")]
+    SignatureRequired { id: ID },
+    #[error("The ops since {id} are placeholders of a partial history in this doc, so the real ops cannot be imported. Import the full history into a new doc instead.")]
+    PlaceholdersNotReplaceable { id: ID },
 }
 
 #[derive(Error, Debug, PartialEq)]
@@ -114,6 +130,40 @@ pub enum LoroTreeError {
     TreeNodeDeletedOrNotExist(TreeID),
 }
 
+/// The error of converting between [`LoroValue`](crate::LoroValue) and Rust types with serde
+#[derive(Error, Debug, PartialEq)]
+pub enum LoroSerdeError {
+    #[error("{0}")]
+    Message(Box<str>),
+    #[error(transparent)]
+    Loro(#[from] LoroError),
+}
+
+impl serde::de::Error for LoroSerdeError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        LoroSerdeError::Message(msg.to_string().into())
+    }
+}
+
+impl serde::ser::Error for LoroSerdeError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        LoroSerdeError::Message(msg.to_string().into())
+    }
+}
+
+/// The error of accessing a map through a schema
+#[derive(Error, Debug, PartialEq)]
+pub enum LoroSchemaError {
+    #[error("The entry \"{path}\" doesn't match the schema: {reason}")]
+    WrongType { path: Box<str>, reason: Box<str> },
+    #[error("The entry \"{path}\" is missing")]
+    MissingField { path: Box<str> },
+    #[error(transparent)]
+    Serde(#[from] LoroSerdeError),
+    #[error(transparent)]
+    Loro(#[from] LoroError),
+}
+
 #[non_exhaustive]
 #[derive(Error, Debug, PartialEq)]
 pub enum LoroEncodeError {
@@ -123,6 +173,10 @@ pub enum LoroEncodeError {
     ShallowSnapshotIncompatibleWithOldFormat,
     #[error("Cannot export shallow snapshot with unknown container type. Please upgrade the Loro version.")]
     UnknownContainer,
+    #[error("Failed to write the exported data: {0}")]
+    WriteError(String),
+    #[error("Cannot export the placeholders of a partial history. Export the ops from the doc with the full history instead.")]
+    PartialHistory,
 }
 
 #[cfg(feature = "wasm")]
@@ -12,8 +12,11 @@ mod internal_string;
 mod macros;
 mod span;
 mod value;
+mod value_serde;
 
-pub use error::{LoroEncodeError, LoroError, LoroResult, LoroTreeError};
+pub use error::{
+    LoroEncodeError, LoroError, LoroResult, LoroSchemaError, LoroSerdeError, LoroTreeError,
+};
 #[doc(hidden)]
 pub use fxhash::FxHashMap;
 pub use internal_string::InternalString;
@@ -21,6 +24,7 @@ pub use span::*;
 pub use value::{
     to_value, LoroBinaryValue, LoroListValue, LoroMapValue, LoroStringValue, LoroValue,
 };
+pub use value_serde::{from_loro_value, to_loro_value};
 
 /// Unique id for each peer. It's a random u64 by default.
 pub type PeerID = u64;
@@ -0,0 +1,869 @@
+//! Converting between [`LoroValue`] and Rust types with serde.
+//!
+//! [`LoroValue`] implements [`Deserializer`], so a value read from the doc can be deserialized
+//! into any type that implements [`Deserialize`]. [`to_loro_value`] goes the other way, its
+//! conventions follow `serde_json::Value`:
+//!
+//! - Structs and maps become [`LoroValue::Map`]. Map keys must be strings, chars or integers.
+//! - Unit variants become their names. The other variants become a map with a single key.
+//! - Unsigned integers greater than [`i64::MAX`] cannot be represented.
+//!
+//! Deserializing is lenient on numbers: integers can be read as floats and floats with no
+//! fractional part can be read as integers.
+use fxhash::FxHashMap;
+use serde::{
+    de::{
+        value::{MapDeserializer, SeqDeserializer},
+        DeserializeOwned, EnumAccess, IntoDeserializer, Unexpected, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any,
+    ser::{
+        SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+        SerializeTupleStruct, SerializeTupleVariant,
+    },
+    Deserializer, Serialize, Serializer,
+};
+
+use crate::{value::LORO_CONTAINER_ID_PREFIX, LoroSerdeError, LoroValue};
+
+/// Deserialize a [`LoroValue`] into `T`
+pub fn from_loro_value<T: DeserializeOwned>(value: LoroValue) -> Result<T, LoroSerdeError> {
+    T::deserialize(value)
+}
+
+/// Serialize `value` into a [`LoroValue`]
+pub fn to_loro_value<T: Serialize + ?Sized>(value: &T) -> Result<LoroValue, LoroSerdeError> {
+    value.serialize(ValueSerializer)
+}
+
+impl LoroValue {
+    fn unexpected(&self) -> Unexpected {
+        match self {
+            LoroValue::Null => Unexpected::Unit,
+            LoroValue::Bool(b) => Unexpected::Bool(*b),
+            LoroValue::Double(d) => Unexpected::Float(*d),
+            LoroValue::I64(i) => Unexpected::Signed(*i),
+            LoroValue::Binary(b) => Unexpected::Bytes(b),
+            LoroValue::String(s) => Unexpected::Str(s),
+            LoroValue::List(_) => Unexpected::Seq,
+            LoroValue::Map(_) => Unexpected::Map,
+            LoroValue::Container(_) => Unexpected::Other("container id"),
+        }
+    }
+
+    fn deserialize_integer<'de, V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, LoroSerdeError> {
+        match self {
+            LoroValue::Double(d)
+                if d.fract() == 0.0 && d >= i64::MIN as f64 && d < i64::MAX as f64 =>
+            {
+                visitor.visit_i64(d as i64)
+            }
+            other => other.deserialize_any(visitor),
+        }
+    }
+}
+
+fn visit_list<'de, V: Visitor<'de>>(
+    list: Vec<LoroValue>,
+    visitor: V,
+) -> Result<V::Value, LoroSerdeError> {
+    let mut seq: SeqDeserializer<_, LoroSerdeError> = SeqDeserializer::new(list.into_iter());
+    let ans = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(ans)
+}
+
+fn visit_map<'de, V: Visitor<'de>>(
+    map: FxHashMap<String, LoroValue>,
+    visitor: V,
+) -> Result<V::Value, LoroSerdeError> {
+    let mut map: MapDeserializer<_, LoroSerdeError> = MapDeserializer::new(map.into_iter());
+    let ans = visitor.visit_map(&mut map)?;
+    map.end()?;
+    Ok(ans)
+}
+
+impl<'de> Deserializer<'de> for LoroValue {
+    type Error = LoroSerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            LoroValue::Null => visitor.visit_unit(),
+            LoroValue::Bool(b) => visitor.visit_bool(b),
+            LoroValue::Double(d) => visitor.visit_f64(d),
+            LoroValue::I64(i) => visitor.visit_i64(i),
+            LoroValue::Binary(b) => visitor.visit_byte_buf(b.unwrap()),
+            LoroValue::String(s) => visitor.visit_string(s.unwrap()),
+            LoroValue::List(l) => visit_list(l.unwrap(), visitor),
+            LoroValue::Map(m) => visit_map(m.unwrap(), visitor),
+            LoroValue::Container(id) => {
+                visitor.visit_string(format!("{}{}", LORO_CONTAINER_ID_PREFIX, id))
+            }
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_integer(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            // So that `Vec<u8>` can be read from the binary value
+            LoroValue::Binary(b) => {
+                let mut seq: SeqDeserializer<_, LoroSerdeError> =
+                    SeqDeserializer::new(b.unwrap().into_iter());
+                let ans = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(ans)
+            }
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            LoroValue::Null => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self {
+            LoroValue::String(s) => visitor.visit_enum(EnumDeserializer {
+                variant: s.unwrap(),
+                value: None,
+            }),
+            LoroValue::Map(m) if m.len() == 1 => {
+                let (variant, value) = m.unwrap().into_iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer {
+                    variant,
+                    value: Some(value),
+                })
+            }
+            other => Err(serde::de::Error::invalid_type(
+                other.unexpected(),
+                &"a string or a map with a single key",
+            )),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i128 u128 f32 f64 char str string bytes byte_buf unit unit_struct map struct
+        identifier
+    }
+}
+
+impl<'de> IntoDeserializer<'de, LoroSerdeError> for LoroValue {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    value: Option<LoroValue>,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = LoroSerdeError;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V: serde::de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = seed.deserialize(LoroValue::String(self.variant.into()))?;
+        Ok((variant, VariantDeserializer(self.value)))
+    }
+}
+
+struct VariantDeserializer(Option<LoroValue>);
+
+impl<'de> VariantAccess<'de> for VariantDeserializer {
+    type Error = LoroSerdeError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        match self.0 {
+            None | Some(LoroValue::Null) => Ok(()),
+            Some(other) => Err(serde::de::Error::invalid_type(
+                other.unexpected(),
+                &"a unit variant",
+            )),
+        }
+    }
+
+    fn newtype_variant_seed<T: serde::de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        match self.0 {
+            Some(value) => seed.deserialize(value),
+            None => Err(serde::de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"a newtype variant",
+            )),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Some(LoroValue::List(l)) => visit_list(l.unwrap(), visitor),
+            Some(other) => Err(serde::de::Error::invalid_type(
+                other.unexpected(),
+                &"a tuple variant",
+            )),
+            None => Err(serde::de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"a tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Some(LoroValue::Map(m)) => visit_map(m.unwrap(), visitor),
+            Some(other) => Err(serde::de::Error::invalid_type(
+                other.unexpected(),
+                &"a struct variant",
+            )),
+            None => Err(serde::de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"a struct variant",
+            )),
+        }
+    }
+}
+
+/// The serializer behind [`to_loro_value`]
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = LoroValue;
+    type Error = LoroSerdeError;
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeList;
+    type SerializeMap = SerializeMapValue;
+    type SerializeStruct = SerializeMapValue;
+    type SerializeStructVariant = SerializeMapValue;
+
+    fn serialize_bool(self, v: bool) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::I64(v as i64))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::I64(v as i64))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::I64(v as i64))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::I64(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::I64(v as i64))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::I64(v as i64))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::I64(v as i64))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<LoroValue, Self::Error> {
+        i64::try_from(v).map(LoroValue::I64).map_err(|_| {
+            LoroSerdeError::Message(format!("{} is out of the range of i64", v).into())
+        })
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::Double(v as f64))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::Double(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::String(v.to_string().into()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::String(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::Binary(v.to_vec().into()))
+    }
+
+    fn serialize_none(self) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<LoroValue, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<LoroValue, Self::Error> {
+        Ok(LoroValue::String(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<LoroValue, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<LoroValue, Self::Error> {
+        let mut map = FxHashMap::default();
+        map.insert(variant.to_string(), to_loro_value(value)?);
+        Ok(LoroValue::Map(map.into()))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SerializeList {
+            variant: None,
+            list: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(SerializeList {
+            variant: Some(variant),
+            list: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(SerializeMapValue {
+            variant: None,
+            map: FxHashMap::default(),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(SerializeMapValue {
+            variant: Some(variant),
+            map: FxHashMap::default(),
+            next_key: None,
+        })
+    }
+}
+
+/// Wrap the value of a variant in a map with a single key
+fn wrap_variant(variant: Option<&'static str>, value: LoroValue) -> LoroValue {
+    match variant {
+        Some(variant) => {
+            let mut map = FxHashMap::default();
+            map.insert(variant.to_string(), value);
+            LoroValue::Map(map.into())
+        }
+        None => value,
+    }
+}
+
+struct SerializeList {
+    variant: Option<&'static str>,
+    list: Vec<LoroValue>,
+}
+
+impl SerializeSeq for SerializeList {
+    type Ok = LoroValue;
+    type Error = LoroSerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.list.push(to_loro_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<LoroValue, Self::Error> {
+        Ok(wrap_variant(
+            self.variant,
+            LoroValue::List(self.list.into()),
+        ))
+    }
+}
+
+impl SerializeTuple for SerializeList {
+    type Ok = LoroValue;
+    type Error = LoroSerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<LoroValue, Self::Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl SerializeTupleStruct for SerializeList {
+    type Ok = LoroValue;
+    type Error = LoroSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<LoroValue, Self::Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl SerializeTupleVariant for SerializeList {
+    type Ok = LoroValue;
+    type Error = LoroSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<LoroValue, Self::Error> {
+        SerializeSeq::end(self)
+    }
+}
+
+struct SerializeMapValue {
+    variant: Option<&'static str>,
+    map: FxHashMap<String, LoroValue>,
+    next_key: Option<String>,
+}
+
+impl SerializeMap for SerializeMapValue {
+    type Ok = LoroValue;
+    type Error = LoroSerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.next_key = Some(key.serialize(MapKeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .next_key
+            .take()
+            .expect("serialize_value is called before serialize_key");
+        self.map.insert(key, to_loro_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<LoroValue, Self::Error> {
+        Ok(wrap_variant(self.variant, LoroValue::Map(self.map.into())))
+    }
+}
+
+impl SerializeStruct for SerializeMapValue {
+    type Ok = LoroValue;
+    type Error = LoroSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.map.insert(key.to_string(), to_loro_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<LoroValue, Self::Error> {
+        SerializeMap::end(self)
+    }
+}
+
+impl SerializeStructVariant for SerializeMapValue {
+    type Ok = LoroValue;
+    type Error = LoroSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<LoroValue, Self::Error> {
+        SerializeMap::end(self)
+    }
+}
+
+/// The keys of [`LoroValue::Map`] are strings
+struct MapKeySerializer;
+
+fn key_must_be_a_string() -> LoroSerdeError {
+    LoroSerdeError::Message("map key must be a string, a char or an integer".into())
+}
+
+impl Serializer for MapKeySerializer {
+    type Ok = String;
+    type Error = LoroSerdeError;
+    type SerializeSeq = serde::ser::Impossible<String, LoroSerdeError>;
+    type SerializeTuple = serde::ser::Impossible<String, LoroSerdeError>;
+    type SerializeTupleStruct = serde::ser::Impossible<String, LoroSerdeError>;
+    type SerializeTupleVariant = serde::ser::Impossible<String, LoroSerdeError>;
+    type SerializeMap = serde::ser::Impossible<String, LoroSerdeError>;
+    type SerializeStruct = serde::ser::Impossible<String, LoroSerdeError>;
+    type SerializeStructVariant = serde::ser::Impossible<String, LoroSerdeError>;
+
+    fn serialize_bool(self, _v: bool) -> Result<String, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<String, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<String, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_none(self) -> Result<String, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<String, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit(self) -> Result<String, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, Self::Error> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(key_must_be_a_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+
+    use super::{from_loro_value, to_loro_value};
+    use crate::{ContainerID, ContainerType, LoroValue};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rect { w: u32, h: u32 },
+        Line(i32, i32),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Doc {
+        title: String,
+        tags: Vec<String>,
+        count: u8,
+        ratio: f32,
+        note: Option<String>,
+        shapes: Vec<Shape>,
+        bytes: Vec<u8>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let doc = Doc {
+            title: "a".into(),
+            tags: vec!["x".into(), "y".into()],
+            count: 3,
+            ratio: 0.5,
+            note: None,
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(1.5),
+                Shape::Rect { w: 1, h: 2 },
+                Shape::Line(-1, 1),
+            ],
+            bytes: vec![1, 2, 3],
+        };
+        let value = to_loro_value(&doc).unwrap();
+        assert_eq!(value.as_map().unwrap().get("note"), Some(&LoroValue::Null));
+        assert_eq!(
+            value
+                .as_map()
+                .unwrap()
+                .get("shapes")
+                .unwrap()
+                .as_list()
+                .unwrap()[0],
+            LoroValue::String("Empty".into())
+        );
+        assert_eq!(from_loro_value::<Doc>(value).unwrap(), doc);
+    }
+
+    #[test]
+    fn lenient_numbers() {
+        assert_eq!(from_loro_value::<u8>(LoroValue::Double(3.0)).unwrap(), 3);
+        assert_eq!(from_loro_value::<f64>(LoroValue::I64(3)).unwrap(), 3.0);
+        assert!(from_loro_value::<u8>(LoroValue::Double(3.5)).is_err());
+        assert!(from_loro_value::<u8>(LoroValue::I64(256)).is_err());
+        assert!(to_loro_value(&u64::MAX).is_err());
+    }
+
+    #[test]
+    fn container_and_binary() {
+        let id = ContainerID::new_root("text", ContainerType::Text);
+        let value = LoroValue::Container(id.clone());
+        assert_eq!(from_loro_value::<LoroValue>(value.clone()).unwrap(), value);
+        let bytes = LoroValue::Binary(vec![1, 2].into());
+        assert_eq!(from_loro_value::<Vec<u8>>(bytes).unwrap(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,250 @@
+//! Derive macros for Loro.
+//!
+//! They are re-exported by the `loro` crate when its `derive` feature is enabled.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    ext::IdentExt, parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, Ident,
+    LitStr, Type,
+};
+
+/// The container types that are stored as child containers of the map
+const CONTAINERS: &[&str] = &[
+    "LoroText",
+    "LoroList",
+    "LoroMap",
+    "LoroMovableList",
+    "LoroTree",
+    "LoroCounter",
+];
+
+/// Derive `loro::schema::LoroSchema` for a struct and generate its typed handle.
+///
+/// For a struct `Task`, a `TaskHandle` over a `LoroMap` is generated with the same visibility.
+/// Each field is an entry of the map, keyed by the field name. Attaching the handle doesn't
+/// create anything, the entries are created by the first write:
+///
+/// - A field of a container type (`LoroText`, `LoroList`, `LoroMap`, `LoroMovableList`,
+///   `LoroTree` or `LoroCounter`) gets a getter returning the container if it exists, and a
+///   `get_or_create_` method that creates it for writing.
+/// - A field marked with `#[loro(nested)]` is another `LoroSchema` type. It's stored as a
+///   child map and its getter returns the nested handle, whose map is created by the first
+///   write into it.
+/// - Any other field is a value. The handle gets a getter and a `set_` setter for it, which
+///   convert the value with serde.
+///
+/// Use `#[loro(rename = "key")]` to store a field under another key.
+///
+/// See `examples/schema.rs` of the `loro` crate for an example.
+#[proc_macro_derive(LoroSchema, attributes(loro))]
+pub fn derive_loro_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_loro_schema(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+enum FieldKind {
+    Container,
+    Nested,
+    Value,
+}
+
+struct SchemaField {
+    ident: Ident,
+    ty: Type,
+    key: String,
+    kind: FieldKind,
+}
+
+fn is_container(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => {
+            path.path.segments.last().map_or(false, |segment| {
+                segment.arguments.is_empty()
+                    && CONTAINERS.contains(&segment.ident.to_string().as_str())
+            })
+        }
+        _ => false,
+    }
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<SchemaField> {
+    let ident = field.ident.clone().unwrap();
+    if ident == "map" {
+        return Err(Error::new(
+            ident.span(),
+            "`map` is reserved by the handle, use another name with `#[loro(rename = \"map\")]`",
+        ));
+    }
+
+    let mut key = ident.unraw().to_string();
+    let mut nested = false;
+    for attr in &field.attrs {
+        if !attr.path().is_ident("loro") {
+            continue;
+        }
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("nested") {
+                nested = true;
+                Ok(())
+            } else if meta.path.is_ident("rename") {
+                let value: LitStr = meta.value()?.parse()?;
+                key = value.value();
+                Ok(())
+            } else {
+                Err(meta.error("expected `nested` or `rename = \"...\"`"))
+            }
+        })?;
+    }
+
+    let kind = match (nested, is_container(&field.ty)) {
+        (true, true) => {
+            return Err(Error::new(
+                field.ty.span(),
+                "`#[loro(nested)]` is for the types deriving `LoroSchema`",
+            ))
+        }
+        (true, false) => FieldKind::Nested,
+        (false, true) => FieldKind::Container,
+        (false, false) => FieldKind::Value,
+    };
+
+    Ok(SchemaField {
+        ident,
+        ty: field.ty.clone(),
+        key,
+        kind,
+    })
+}
+
+fn expand_loro_schema(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "`LoroSchema` cannot be derived for generic types",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    name.span(),
+                    "`LoroSchema` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                name.span(),
+                "`LoroSchema` can only be derived for structs with named fields",
+            ))
+        }
+    };
+    let fields = fields
+        .iter()
+        .map(parse_field)
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let handle = format_ident!("{}Handle", name);
+    let mut validate = Vec::new();
+    let mut methods = Vec::new();
+    for SchemaField {
+        ident,
+        ty,
+        key,
+        kind,
+    } in &fields
+    {
+        match kind {
+            FieldKind::Container => {
+                let creator = format_ident!("get_or_create_{}", ident.unraw());
+                let get_doc = format!("Get the container under \"{}\" if it exists", key);
+                let create_doc = format!("Get or create the container under \"{}\"", key);
+                validate.push(quote! {
+                    ::loro::schema::validate_container::<#ty>(map, #key)?;
+                });
+                methods.push(quote! {
+                    #[doc = #get_doc]
+                    pub fn #ident(&self) -> ::std::option::Option<#ty> {
+                        ::loro::schema::get_container(&self.map, #key)
+                    }
+
+                    #[doc = #create_doc]
+                    pub fn #creator(&self) -> ::std::result::Result<#ty, ::loro::LoroSchemaError> {
+                        ::loro::schema::get_or_create_container(&self.map, #key)
+                    }
+                });
+            }
+            FieldKind::Nested => {
+                let doc = format!("The nested map under \"{}\"", key);
+                validate.push(quote! {
+                    ::loro::schema::validate_nested::<#ty>(map, #key)?;
+                });
+                methods.push(quote! {
+                    #[doc = #doc]
+                    pub fn #ident(&self) -> <#ty as ::loro::schema::LoroSchema>::Handle {
+                        <#ty as ::loro::schema::LoroSchema>::handle(self.map.child(#key))
+                    }
+                });
+            }
+            FieldKind::Value => {
+                let setter = format_ident!("set_{}", ident.unraw());
+                let get_doc = format!("Get the value under \"{}\"", key);
+                let set_doc = format!("Set the value under \"{}\"", key);
+                validate.push(quote! {
+                    ::loro::schema::validate_value::<#ty>(map, #key)?;
+                });
+                methods.push(quote! {
+                    #[doc = #get_doc]
+                    pub fn #ident(&self) -> ::std::result::Result<#ty, ::loro::LoroSchemaError> {
+                        ::loro::schema::get_value(&self.map, #key)
+                    }
+
+                    #[doc = #set_doc]
+                    pub fn #setter(&self, value: #ty) -> ::std::result::Result<(), ::loro::LoroSchemaError> {
+                        ::loro::schema::set_value(&self.map, #key, &value)
+                    }
+                });
+            }
+        }
+    }
+
+    let handle_doc = format!("The typed handle of [`{}`] over a `LoroMap`", name);
+    Ok(quote! {
+        #[doc = #handle_doc]
+        #[derive(Debug, Clone)]
+        #vis struct #handle {
+            map: ::loro::schema::SchemaMap,
+        }
+
+        impl ::loro::schema::LoroSchema for #name {
+            type Handle = #handle;
+
+            #[allow(unused_variables)]
+            fn validate(map: &::loro::LoroMap) -> ::std::result::Result<(), ::loro::LoroSchemaError> {
+                #(#validate)*
+                ::std::result::Result::Ok(())
+            }
+
+            fn handle(map: ::loro::schema::SchemaMap) -> #handle {
+                #handle { map }
+            }
+        }
+
+        impl #handle {
+            /// The underlying map, if it exists
+            pub fn map(&self) -> ::std::option::Option<::loro::LoroMap> {
+                self.map.get()
+            }
+
+            #(#methods)*
+        }
+    })
+}
@@ -35,6 +35,11 @@ pub struct Change<O = Op> {
     /// It is the number of seconds that have elapsed since 00:00:00 UTC on 1 January 1970.
     pub(crate) timestamp: Timestamp,
     pub(crate) commit_msg: Option<Arc<str>>,
+    /// The signature of the author over the encoded change. It needs the `signature` feature
+    /// to sign and verify it.
+    ///
+    /// It's dropped when the change is sliced, because the slice cannot be verified.
+    pub(crate) signature: Option<Arc<[u8]>>,
     pub(crate) ops: RleVec<[O; 1]>,
 }
 
@@ -53,6 +58,7 @@ impl<O> Change<O> {
             lamport,
             timestamp,
             commit_msg: None,
+            signature: None,
         }
     }
 
@@ -98,6 +104,10 @@ impl<O> Change<O> {
     pub fn message(&self) -> Option<&Arc<str>> {
         self.commit_msg.as_ref()
     }
+
+    pub fn signature(&self) -> Option<&Arc<[u8]>> {
+        self.signature.as_ref()
+    }
 }
 
 impl<O: EstimatedSize> EstimatedSize for Change<O> {
@@ -113,7 +123,8 @@ impl<O: EstimatedSize> EstimatedSize for Change<O> {
             .iter()
             .map(|op| op.estimate_storage_size())
             .sum::<usize>();
-        id_size + lamport_size + timestamp_size + ops_size + deps_size
+        let signature_size = self.signature.as_ref().map_or(0, |s| s.len());
+        id_size + lamport_size + timestamp_size + ops_size + deps_size + signature_size
     }
 }
 
@@ -232,6 +243,11 @@ impl<O: Mergable + HasLength + HasIndex + Sliceable + HasCounter + Debug> Slicea
             lamport: self.lamport + from as Lamport,
             timestamp: self.timestamp,
             commit_msg: self.commit_msg.clone(),
+            signature: if from == 0 && to == self.atom_len() {
+                self.signature.clone()
+            } else {
+                None
+            },
         }
     }
 }
@@ -250,6 +266,9 @@ impl Change {
             && other.deps.as_single().unwrap().peer == self.id.peer
             && other.timestamp - self.timestamp < merge_interval
             && self.commit_msg == other.commit_msg
+            // A signed change is verified as a whole, so it cannot be merged
+            && self.signature.is_none()
+            && other.signature.is_none()
         {
             debug_assert!(other.timestamp >= self.timestamp);
             debug_assert!(other.lamport == self.lamport + self.len() as Lamport);
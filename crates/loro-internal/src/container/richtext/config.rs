@@ -1,8 +1,22 @@
 use fxhash::FxHashMap;
-use loro_common::InternalString;
+use loro_common::{InternalString, ID};
 
 use super::{ExpandType, TextStyleInfoFlag};
 
+/// The char that stands for an embedded object in the text
+pub const EMBED_CHAR: char = '\u{FFFC}';
+
+/// The reserved style key of the embeds. The value of the style on an [`EMBED_CHAR`] is the
+/// value of the embed.
+pub const EMBED_KEY: &str = "$embed";
+
+/// The config of [`EMBED_KEY`], which is always configured
+const EMBED_CONFIG: StyleConfig = StyleConfig {
+    expand: ExpandType::None,
+    annotation: false,
+    block: false,
+};
+
 #[derive(Debug, Default, Clone)]
 pub struct StyleConfigMap {
     map: FxHashMap<InternalString, StyleConfig>,
@@ -19,14 +33,39 @@ impl StyleConfigMap {
         if key.contains(':') {
             panic!("style key should not contain ':'");
         }
+        if key.as_str() == EMBED_KEY {
+            panic!("style key {} is reserved for the embeds", EMBED_KEY);
+        }
 
         self.map.insert(key, value);
     }
 
     pub fn get(&self, key: &InternalString) -> Option<&StyleConfig> {
+        if key.as_str() == EMBED_KEY {
+            return Some(&EMBED_CONFIG);
+        }
+
         self.map.get(key)
     }
 
+    /// Whether the marks of the key are annotations, see [`StyleConfig::annotation`]
+    pub fn is_annotation(&self, key: &str) -> bool {
+        let key = match key.find(':') {
+            Some(index) => &key[..index],
+            None => key,
+        };
+        self.map
+            .get(&key.into())
+            .map_or(false, |config| config.annotation)
+    }
+
+    /// Whether the key is a block attribute, see [`StyleConfig::block`]
+    pub fn is_block(&self, key: &str) -> bool {
+        self.map
+            .get(&key.into())
+            .map_or(false, |config| config.block)
+    }
+
     pub fn get_style_flag(&self, key: &InternalString) -> Option<TextStyleInfoFlag> {
         self._get_style_flag(key, false)
     }
@@ -43,7 +82,7 @@ impl StyleConfigMap {
             let key = key[..index].into();
             self.map.get(&key).map(f)
         } else {
-            self.map.get(key).map(f)
+            self.get(key).map(f)
         }
     }
 
@@ -56,6 +95,8 @@ impl StyleConfigMap {
             "bold".into(),
             StyleConfig {
                 expand: ExpandType::After,
+                annotation: false,
+                block: false,
             },
         );
 
@@ -63,6 +104,8 @@ impl StyleConfigMap {
             "italic".into(),
             StyleConfig {
                 expand: ExpandType::After,
+                annotation: false,
+                block: false,
             },
         );
 
@@ -70,6 +113,8 @@ impl StyleConfigMap {
             "underline".into(),
             StyleConfig {
                 expand: ExpandType::After,
+                annotation: false,
+                block: false,
             },
         );
 
@@ -77,6 +122,8 @@ impl StyleConfigMap {
             "link".into(),
             StyleConfig {
                 expand: ExpandType::None,
+                annotation: false,
+                block: false,
             },
         );
 
@@ -84,6 +131,8 @@ impl StyleConfigMap {
             "highlight".into(),
             StyleConfig {
                 expand: ExpandType::None,
+                annotation: false,
+                block: false,
             },
         );
 
@@ -91,6 +140,8 @@ impl StyleConfigMap {
             "comment".into(),
             StyleConfig {
                 expand: ExpandType::None,
+                annotation: false,
+                block: false,
             },
         );
 
@@ -98,22 +149,52 @@ impl StyleConfigMap {
             "code".into(),
             StyleConfig {
                 expand: ExpandType::None,
+                annotation: false,
+                block: false,
             },
         );
 
+        for key in ["header", "list", "blockquote"] {
+            map.map.insert(
+                key.into(),
+                StyleConfig {
+                    expand: ExpandType::None,
+                    annotation: false,
+                    block: true,
+                },
+            );
+        }
+
         map
     }
 }
 
+/// The config of a style key. Build it with [`StyleConfig::new`] and the builder methods.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct StyleConfig {
     pub expand: ExpandType,
+    /// Whether each mark of the key is an annotation with its own identity.
+    ///
+    /// The marks of a normal style key are merged: the last one wins on the overlapping range.
+    /// An annotation is stored under the key `key:counter@peer`, where `counter@peer` is the ID
+    /// of its mark op, so the annotations of the same key overlap freely and can be removed
+    /// one by one. They are reported as a map from the IDs to their values under `key`.
+    pub annotation: bool,
+    /// Whether the key is a block attribute of the lines, like a heading or a list item.
+    ///
+    /// A block attribute is only set on the line breaks: the attribute of a line is on the
+    /// `\n` that ends it, as in the Quill deltas. Marking a range with the key only marks
+    /// the line breaks in the range.
+    pub block: bool,
 }
 
 impl StyleConfig {
     pub fn new() -> Self {
         Self {
             expand: ExpandType::None,
+            annotation: false,
+            block: false,
         }
     }
 
@@ -121,6 +202,28 @@ impl StyleConfig {
         self.expand = expand;
         self
     }
+
+    pub fn annotation(mut self, annotation: bool) -> Self {
+        self.annotation = annotation;
+        self
+    }
+
+    pub fn block(mut self, block: bool) -> Self {
+        self.block = block;
+        self
+    }
+}
+
+/// The style key of the annotation created by the mark op with the ID
+pub(crate) fn annotation_key(key: &str, id: ID) -> InternalString {
+    format!("{}:{}", key, id).into()
+}
+
+/// Split the style key of an annotation into its key and the ID of its mark op
+pub(crate) fn parse_annotation_key(key: &str) -> Option<(&str, ID)> {
+    let index = key.find(':')?;
+    let id = ID::try_from(&key[index + 1..]).ok()?;
+    Some((&key[..index], id))
 }
 
 impl Default for StyleConfig {
@@ -5,9 +5,10 @@ use generic_btree::{
     BTree, BTreeTrait, Cursor, LeafIndex,
 };
 use loro_common::{
-    Counter, IdFull, IdLpSpan, IdSpan, Lamport, LoroError, LoroResult, LoroValue, ID,
+    Counter, IdFull, IdLpSpan, IdSpan, InternalString, Lamport, LoroError, LoroResult, LoroValue,
+    ID,
 };
-use query::{ByteQuery, ByteQueryT};
+use query::{ByteQuery, ByteQueryT, LineBreakQuery};
 use serde::{ser::SerializeStruct, Serialize};
 use std::{
     fmt::{Display, Formatter},
@@ -32,6 +33,7 @@ use self::query::{
 };
 
 use super::{
+    config::{StyleConfigMap, EMBED_CHAR, EMBED_KEY},
     style_range_map::{IterAnchorItem, StyleRangeMap, Styles},
     AnchorType, RichtextSpan, StyleOp,
 };
@@ -275,6 +277,12 @@ mod text_chunk {
         bytes: BytesSlice,
         unicode_len: i32,
         utf16_len: i32,
+        /// The number of `\n` in the text.
+        ///
+        /// It's always maintained, like `utf16_len`: it's counted in the same pass over the
+        /// chars that counts the lengths, so it only costs 4 bytes per chunk. The line and
+        /// block attribute APIs of the text rely on it to find a line in O(log n).
+        line_breaks: i32,
         id: IdFull,
     }
 
@@ -284,6 +292,7 @@ mod text_chunk {
                 .field("text", &self.as_str())
                 .field("unicode_len", &self.unicode_len)
                 .field("utf16_len", &self.utf16_len)
+                .field("line_breaks", &self.line_breaks)
                 .field("id", &self.id)
                 .finish()
         }
@@ -293,15 +302,20 @@ mod text_chunk {
         pub fn new(bytes: BytesSlice, id: IdFull) -> Self {
             let mut utf16_len = 0;
             let mut unicode_len = 0;
+            let mut line_breaks = 0;
             for c in std::str::from_utf8(&bytes).unwrap().chars() {
                 utf16_len += c.len_utf16();
                 unicode_len += 1;
+                if c == '\n' {
+                    line_breaks += 1;
+                }
             }
 
             Self {
                 unicode_len,
                 bytes,
                 utf16_len: utf16_len as i32,
+                line_breaks,
                 id,
             }
         }
@@ -353,6 +367,11 @@ mod text_chunk {
             self.utf16_len
         }
 
+        #[inline]
+        pub fn line_breaks(&self) -> i32 {
+            self.line_breaks
+        }
+
         #[inline]
         pub fn event_len(&self) -> i32 {
             if cfg!(feature = "wasm") {
@@ -362,6 +381,29 @@ mod text_chunk {
             }
         }
 
+        /// Count the line breaks in the first `unicode_offset` chars of this text
+        pub fn line_breaks_before(&self, unicode_offset: usize) -> usize {
+            if unicode_offset >= self.unicode_len as usize {
+                return self.line_breaks as usize;
+            }
+
+            self.as_str()
+                .chars()
+                .take(unicode_offset)
+                .filter(|c| *c == '\n')
+                .count()
+        }
+
+        /// Get the unicode offset right after the `n`-th (0-based) line break in this text
+        pub fn unicode_offset_after_line_break(&self, n: usize) -> Option<usize> {
+            self.as_str()
+                .chars()
+                .enumerate()
+                .filter(|(_, c)| *c == '\n')
+                .nth(n)
+                .map(|(i, _)| i + 1)
+        }
+
         /// Convert a unicode index on this text to an event index
         pub fn convert_unicode_offset_to_event_offset(&self, offset: usize) -> usize {
             if cfg!(feature = "wasm") {
@@ -383,6 +425,7 @@ mod text_chunk {
                 unicode_len: 0,
                 bytes: BytesSlice::empty(),
                 utf16_len: 0,
+                line_breaks: 0,
                 // This is a dummy value.
                 // It's fine because the length is 0. We never actually use this value.
                 id: IdFull::NONE_ID,
@@ -402,6 +445,7 @@ mod text_chunk {
             let mut start_utf16_index = 0;
             let mut current_utf16_index = 0;
             let mut current_utf8_index = 0;
+            let mut deleted_line_breaks = 0;
             for (current_unicode_index, c) in s.chars().enumerate() {
                 if current_unicode_index == start_unicode_index {
                     start_utf16_index = current_utf16_index;
@@ -413,11 +457,16 @@ mod text_chunk {
                     break;
                 }
 
+                if current_unicode_index >= start_unicode_index && c == '\n' {
+                    deleted_line_breaks += 1;
+                }
+
                 current_utf16_index += c.len_utf16();
                 current_utf8_index += c.len_utf8();
             }
 
             self.utf16_len -= (current_utf16_index - start_utf16_index) as i32;
+            self.line_breaks -= deleted_line_breaks;
 
             let event_len = if cfg!(feature = "wasm") {
                 current_utf16_index - start_utf16_index
@@ -445,6 +494,7 @@ mod text_chunk {
                     let next = Self::new(next, self.id.inc(end_unicode_index as i32));
                     self.unicode_len -= next.unicode_len;
                     self.utf16_len -= next.utf16_len;
+                    self.line_breaks -= next.line_breaks;
                     self.bytes.slice_(..start_byte);
                     Some(next)
                 }
@@ -464,6 +514,7 @@ mod text_chunk {
                     self.utf16_len,
                     self.as_str().chars().map(|c| c.len_utf16()).sum::<usize>() as i32
                 );
+                assert_eq!(self.line_breaks, count_line_breaks(self.as_str()));
             }
         }
 
@@ -500,6 +551,10 @@ mod text_chunk {
         }
     }
 
+    fn count_line_breaks(s: &str) -> i32 {
+        s.bytes().filter(|b| *b == b'\n').count() as i32
+    }
+
     impl generic_btree::rle::HasLength for TextChunk {
         fn rle_len(&self) -> usize {
             self.unicode_len as usize
@@ -510,6 +565,7 @@ mod text_chunk {
         fn _slice(&self, range: Range<usize>) -> Self {
             assert!(range.start < range.end);
             let mut utf16_len = 0;
+            let mut line_breaks = 0;
             let mut start = 0;
             let mut end = 0;
             let mut started = false;
@@ -526,6 +582,9 @@ mod text_chunk {
                 }
                 if started {
                     utf16_len += c.len_utf16();
+                    if c == '\n' {
+                        line_breaks += 1;
+                    }
                 }
 
                 last_unicode_index = unicode_index;
@@ -541,6 +600,7 @@ mod text_chunk {
                 unicode_len: range.len() as i32,
                 bytes: self.bytes.slice_clone(start..end),
                 utf16_len: utf16_len as i32,
+                line_breaks,
                 id: self.id.inc(range.start as i32),
             };
             ans.check();
@@ -549,6 +609,7 @@ mod text_chunk {
 
         fn split(&mut self, pos: usize) -> Self {
             let mut utf16_len = 0;
+            let mut line_breaks = 0;
             let mut byte_offset = 0;
             for (unicode_index, (i, c)) in self.as_str().char_indices().enumerate() {
                 if unicode_index == pos {
@@ -557,16 +618,21 @@ mod text_chunk {
                 }
 
                 utf16_len += c.len_utf16();
+                if c == '\n' {
+                    line_breaks += 1;
+                }
             }
             let right = Self {
                 unicode_len: self.unicode_len - pos as i32,
                 bytes: self.bytes.slice_clone(byte_offset..),
                 utf16_len: self.utf16_len - utf16_len as i32,
+                line_breaks: self.line_breaks - line_breaks,
                 id: self.id.inc(pos as i32),
             };
 
             self.unicode_len = pos as i32;
             self.utf16_len = utf16_len as i32;
+            self.line_breaks = line_breaks;
             self.bytes.slice_(..byte_offset);
             right.check();
             self.check();
@@ -583,6 +649,7 @@ mod text_chunk {
             self.bytes.try_merge(&rhs.bytes).unwrap();
             self.utf16_len += rhs.utf16_len;
             self.unicode_len += rhs.unicode_len;
+            self.line_breaks += rhs.line_breaks;
             self.check();
         }
 
@@ -592,6 +659,7 @@ mod text_chunk {
             self.bytes = new;
             self.utf16_len += left.utf16_len;
             self.unicode_len += left.unicode_len;
+            self.line_breaks += left.line_breaks;
             self.id = left.id;
             self.check();
         }
@@ -966,6 +1034,8 @@ pub(crate) struct PosCache {
     pub(super) bytes: i32,
     pub(super) utf16_len: i32,
     pub(crate) entity_len: i32,
+    /// The number of `\n` in the subtree, see `TextChunk::line_breaks`
+    pub(super) line_breaks: i32,
 }
 
 impl PosCache {
@@ -995,6 +1065,7 @@ impl AddAssign for PosCache {
         self.bytes += rhs.bytes;
         self.utf16_len += rhs.utf16_len;
         self.entity_len += rhs.entity_len;
+        self.line_breaks += rhs.line_breaks;
     }
 }
 
@@ -1007,6 +1078,7 @@ impl Add for PosCache {
             unicode_len: self.unicode_len + rhs.unicode_len,
             utf16_len: self.utf16_len + rhs.utf16_len,
             entity_len: self.entity_len + rhs.entity_len,
+            line_breaks: self.line_breaks + rhs.line_breaks,
         }
     }
 }
@@ -1020,6 +1092,7 @@ impl Sub for PosCache {
             unicode_len: self.unicode_len - rhs.unicode_len,
             utf16_len: self.utf16_len - rhs.utf16_len,
             entity_len: self.entity_len - rhs.entity_len,
+            line_breaks: self.line_breaks - rhs.line_breaks,
         }
     }
 }
@@ -1085,12 +1158,14 @@ impl BTreeTrait for RichtextTreeTrait {
                 unicode_len: s.unicode_len(),
                 utf16_len: s.utf16_len(),
                 entity_len: s.unicode_len(),
+                line_breaks: s.line_breaks(),
             },
             RichtextStateChunk::Style { .. } => PosCache {
                 bytes: 0,
                 unicode_len: 0,
                 utf16_len: 0,
                 entity_len: 1,
+                line_breaks: 0,
             },
         }
     }
@@ -1107,6 +1182,7 @@ impl BTreeTrait for RichtextTreeTrait {
             unicode_len: cache_lhs.unicode_len - cache_rhs.unicode_len,
             utf16_len: cache_lhs.utf16_len - cache_rhs.utf16_len,
             entity_len: cache_lhs.entity_len - cache_rhs.entity_len,
+            line_breaks: cache_lhs.line_breaks - cache_rhs.line_breaks,
         }
     }
 }
@@ -1295,6 +1371,39 @@ mod query {
             cache.entity_len as usize
         }
     }
+
+    /// Find the `n`-th (0-based) line break. The found offset is right after it.
+    pub(super) struct LineBreakQueryT;
+    pub(super) type LineBreakQuery = IndexQuery<LineBreakQueryT, RichtextTreeTrait>;
+    impl QueryByLen<RichtextTreeTrait> for LineBreakQueryT {
+        fn get_cache_len(cache: &<RichtextTreeTrait as BTreeTrait>::Cache) -> usize {
+            cache.line_breaks as usize
+        }
+
+        fn get_elem_len(elem: &<RichtextTreeTrait as BTreeTrait>::Elem) -> usize {
+            match elem {
+                RichtextStateChunk::Text(s) => s.line_breaks() as usize,
+                RichtextStateChunk::Style { .. } => 0,
+            }
+        }
+
+        fn get_offset_and_found(
+            left: usize,
+            elem: &<RichtextTreeTrait as BTreeTrait>::Elem,
+        ) -> (usize, bool) {
+            match elem {
+                RichtextStateChunk::Text(s) => match s.unicode_offset_after_line_break(left) {
+                    Some(offset) => (offset, true),
+                    None => (left, false),
+                },
+                RichtextStateChunk::Style { .. } => (1, false),
+            }
+        }
+
+        fn get_cache_entity_len(cache: &<RichtextTreeTrait as BTreeTrait>::Cache) -> usize {
+            cache.entity_len as usize
+        }
+    }
 }
 
 impl RichtextState {
@@ -1412,6 +1521,128 @@ impl RichtextState {
         result
     }
 
+    /// Get the styles that the text inserted at the given event index would have
+    pub(crate) fn get_styles_at_event_index_for_insert(
+        &mut self,
+        event_index: usize,
+    ) -> LoroResult<StyleMeta> {
+        let (entity_index, _) =
+            self.get_entity_index_for_text_insert(event_index, PosType::Event)?;
+        Ok(self.get_styles_at_entity_index_for_insert(entity_index))
+    }
+
+    /// Get the styles of the text in the given event range.
+    ///
+    /// It returns the styles of each text span in the range. When the whole range shares the
+    /// same styles, only one item is returned.
+    pub(crate) fn get_styles_in_event_range(&mut self, range: Range<usize>) -> Vec<StyleMeta> {
+        if range.is_empty() {
+            return Vec::new();
+        }
+
+        let (entity_range, styles) =
+            self.get_entity_range_and_text_styles_at_range(range, PosType::Event);
+        if let Some(styles) = styles {
+            return vec![styles.into()];
+        }
+
+        if !self.has_styles() {
+            return vec![StyleMeta::default()];
+        }
+
+        self.iter_range(entity_range)
+            .filter(|item| matches!(item.chunk, RichtextStateChunk::Text(_)))
+            .map(|item| item.styles.into())
+            .collect()
+    }
+
+    /// Get the first span of the text in the given event range, which is the longest prefix
+    /// of the range with the same styles.
+    ///
+    /// Only the chunks of the span are visited, so the range can be read span by span.
+    pub(crate) fn get_first_span_in_event_range(
+        &mut self,
+        range: Range<usize>,
+    ) -> Option<RichtextSpan> {
+        if range.is_empty() {
+            return None;
+        }
+
+        let (entity_range, _) =
+            self.get_entity_range_and_text_styles_at_range(range, PosType::Event);
+        let mut text = String::new();
+        let mut styles: Option<&Styles> = None;
+        for item in self.iter_range(entity_range) {
+            let RichtextStateChunk::Text(chunk) = item.chunk else {
+                continue;
+            };
+            match styles {
+                None => styles = Some(item.styles),
+                Some(styles) if styles == item.styles => {}
+                Some(_) => break,
+            }
+
+            text.push_str(
+                chunk
+                    .slice(item.entity_offset..item.entity_offset + item.entity_len)
+                    .as_str(),
+            );
+        }
+
+        styles.map(|styles| RichtextSpan {
+            text: text.into(),
+            attributes: styles.into(),
+        })
+    }
+
+    /// Get the event index ranges of the styles whose keys match `is_key`, with their values.
+    ///
+    /// The text is not read, only the chunks and their styles are visited. The adjacent
+    /// ranges of the same key are merged.
+    pub(crate) fn get_style_ranges(
+        &self,
+        is_key: impl Fn(&str) -> bool,
+    ) -> Vec<(InternalString, LoroValue, Range<usize>)> {
+        let mut ans: Vec<(InternalString, LoroValue, Range<usize>)> = Vec::new();
+        if !self.has_styles() {
+            return ans;
+        }
+
+        let mut index = 0;
+        for item in self.iter_range(..) {
+            if !matches!(item.chunk, RichtextStateChunk::Text(_)) {
+                continue;
+            }
+
+            let end = index + item.event_len;
+            for (key, value) in item.styles.iter() {
+                let key = key.key();
+                if !is_key(key) {
+                    continue;
+                }
+
+                let Some(value) = value.get().map(|style| style.to_value()) else {
+                    continue;
+                };
+                if value.is_null() {
+                    continue;
+                }
+
+                match ans
+                    .iter_mut()
+                    .find(|(k, _, range)| *k == *key && range.end == index)
+                {
+                    Some((_, _, range)) => range.end = end,
+                    None => ans.push((key.clone(), value, index..end)),
+                }
+            }
+
+            index = end;
+        }
+
+        ans
+    }
+
     /// This is used to accept changes from DiffCalculator
     pub(crate) fn insert_at_entity_index(
         &mut self,
@@ -2118,6 +2349,97 @@ impl RichtextState {
         self.cursor_to_event_index(cursor.cursor)
     }
 
+    /// Convert the index of one position type into another.
+    ///
+    /// The index should be on the boundary of the Unicode code points.
+    pub(crate) fn convert_index(&self, index: usize, from: PosType, to: PosType) -> usize {
+        if from == to || self.tree.is_empty() {
+            return index;
+        }
+
+        let cursor = match from {
+            PosType::Entity => self.tree.query::<EntityQuery>(&index).unwrap(),
+            PosType::Utf16 => self.tree.query::<Utf16Query>(&index).unwrap(),
+            PosType::Bytes => self.tree.query::<ByteQuery>(&index).unwrap(),
+            PosType::Event => self.tree.query::<EventIndexQuery>(&index).unwrap(),
+            PosType::Unicode => self.tree.query::<UnicodeQuery>(&index).unwrap(),
+        };
+
+        self.get_index_from_cursor(cursor.cursor, to).unwrap()
+    }
+
+    /// The number of `\n` in the text
+    pub(crate) fn len_line_breaks(&self) -> usize {
+        self.tree.root_cache().line_breaks as usize
+    }
+
+    /// Get the event index of the start of the given line.
+    ///
+    /// Returns `None` if the line doesn't exist.
+    pub(crate) fn line_start_event_index(&self, line: usize) -> Option<usize> {
+        if line == 0 {
+            return Some(0);
+        }
+
+        if line > self.len_line_breaks() {
+            return None;
+        }
+
+        let cursor = self.tree.query::<LineBreakQuery>(&(line - 1)).unwrap();
+        self.get_index_from_cursor(cursor.cursor, PosType::Event)
+    }
+
+    /// Count the line breaks before the given event index
+    pub(crate) fn line_breaks_before_event_index(&self, index: usize) -> usize {
+        if index == 0 || self.tree.is_empty() {
+            return 0;
+        }
+
+        let cursor = self.tree.query::<EventIndexQuery>(&index).unwrap();
+        let mut count = 0;
+        self.tree
+            .visit_previous_caches(cursor.cursor, |cache| match cache {
+                generic_btree::PreviousCache::NodeCache(c) => {
+                    count += c.line_breaks as usize;
+                }
+                generic_btree::PreviousCache::PrevSiblingElem(c) => {
+                    if let RichtextStateChunk::Text(t) = c {
+                        count += t.line_breaks() as usize;
+                    }
+                }
+                generic_btree::PreviousCache::ThisElemAndOffset { elem, offset } => {
+                    if let RichtextStateChunk::Text(t) = elem {
+                        count += t.line_breaks_before(offset);
+                    }
+                }
+            });
+        count
+    }
+
+    /// Convert the position of one type into another.
+    ///
+    /// Returns `None` if the position is out of bound or inside a Unicode code point.
+    pub(crate) fn convert_pos(&self, pos: usize, from: PosType, to: PosType) -> Option<usize> {
+        let len = match from {
+            PosType::Bytes => self.len_utf8(),
+            PosType::Unicode => self.len_unicode(),
+            PosType::Utf16 => self.len_utf16(),
+            PosType::Entity => self.len_entity(),
+            PosType::Event => self.len_event(),
+        };
+        if pos > len {
+            return None;
+        }
+
+        // A position inside a code point is moved to its boundary by the query
+        let unicode = self.convert_index(pos, from, PosType::Unicode);
+        if self.convert_index(unicode, PosType::Unicode, from) != pos {
+            return None;
+        }
+
+        Some(self.convert_index(unicode, PosType::Unicode, to))
+    }
+
     pub fn event_index_to_unicode_index(&self, index: usize) -> usize {
         if !cfg!(feature = "wasm") {
             return index;
@@ -2210,13 +2532,42 @@ impl RichtextState {
         self.tree.iter()
     }
 
+    /// Get the value of the text, without grouping any annotation. See
+    /// [`RichtextState::get_richtext_value_with_config`].
     pub fn get_richtext_value(&self) -> LoroValue {
+        self.get_richtext_value_with_config(&StyleConfigMap::new())
+    }
+
+    /// Get the value of the text, where the annotations of the keys configured as annotations
+    /// are grouped by their keys
+    pub fn get_richtext_value_with_config(&self, config: &StyleConfigMap) -> LoroValue {
         self.check_cache();
         let result = {
             let mut ans: Vec<LoroValue> = Vec::new();
             let mut last_attributes: Option<LoroValue> = None;
             for span in self.iter() {
-                let attributes: LoroValue = span.attributes.to_value();
+                let attributes: LoroValue = span.attributes.with_annotations(config).to_value();
+                if let Some(embed) = attributes.as_map().unwrap().get(EMBED_KEY) {
+                    // Each embed is reported as an insert of its value
+                    let mut rest = (**attributes.as_map().unwrap()).clone();
+                    rest.remove(EMBED_KEY);
+                    for c in span.text.as_str().chars() {
+                        let mut value = FxHashMap::default();
+                        let insert = if c == EMBED_CHAR {
+                            embed.clone()
+                        } else {
+                            LoroValue::String(c.to_string().into())
+                        };
+                        value.insert("insert".into(), insert);
+                        if !rest.is_empty() {
+                            value.insert("attributes".into(), LoroValue::Map(rest.clone().into()));
+                        }
+                        ans.push(LoroValue::Map(value.into()));
+                    }
+                    last_attributes = None;
+                    continue;
+                }
+
                 if let Some(last) = last_attributes.as_ref() {
                     if &attributes == last {
                         let hash_map = ans.last_mut().unwrap().as_map_mut().unwrap();
@@ -2455,6 +2806,7 @@ impl RichtextState {
                 let iter_chunk = chunk.as_ref()?;
 
                 let styles = cur_style;
+                let entity_offset = offset;
                 let iter_len;
                 let event_range;
                 if chunk_left_len >= style_left_len {
@@ -2487,6 +2839,7 @@ impl RichtextState {
                 Some(IterRangeItem {
                     chunk: iter_chunk.elem,
                     styles,
+                    entity_offset,
                     entity_len: iter_len,
                     event_len: event_range.len(),
                 })
@@ -2635,6 +2988,8 @@ pub(crate) struct DrainInfo {
 pub(crate) struct IterRangeItem<'a> {
     pub(crate) chunk: &'a RichtextStateChunk,
     pub(crate) styles: &'a Styles,
+    /// The offset of the item in the chunk
+    pub(crate) entity_offset: usize,
     pub(crate) entity_len: usize,
     pub(crate) event_len: usize,
 }
@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 use loro_common::{ContainerID, ID};
 use serde::{Deserialize, Serialize};
 
@@ -52,6 +54,44 @@ pub struct AbsolutePosition {
     pub side: Side,
 }
 
+/// A range of text anchored by two cursors.
+///
+/// Like [`Cursor`], it keeps covering the same text when the text is edited concurrently.
+/// Whether the text inserted at its boundaries is included is decided by the expand type
+/// given when it's created.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TextRange {
+    /// The cursor that marks the start of the range
+    pub start: Cursor,
+    /// The cursor that marks the end of the range
+    pub end: Cursor,
+}
+
+impl TextRange {
+    pub fn encode(&self) -> Vec<u8> {
+        postcard::to_allocvec(self).unwrap()
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(data)
+    }
+}
+
+/// The current position of a [`TextRange`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedTextRange {
+    /// The range in Unicode code points
+    pub unicode: Range<usize>,
+    /// The range in UTF-16 code units
+    pub utf16: Range<usize>,
+    /// The range in UTF-8 bytes
+    pub utf8: Range<usize>,
+    /// Whether the range is empty
+    pub collapsed: bool,
+    /// Whether the range is empty because the characters it's anchored to are deleted
+    pub deleted: bool,
+}
+
 #[derive(Debug, Clone, Copy, thiserror::Error)]
 pub enum CannotFindRelativePosition {
     #[error("Cannot find relative position. The container is deleted.")]
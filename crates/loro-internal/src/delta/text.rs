@@ -1,20 +1,35 @@
-
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use loro_common::{InternalString, LoroValue, PeerID};
 use loro_delta::delta_trait::DeltaAttr;
 use serde::{Deserialize, Serialize};
 
 use crate::change::Lamport;
-use crate::container::richtext::{Style, Styles};
+use crate::container::richtext::{
+    config::{parse_annotation_key, StyleConfigMap},
+    Style, Styles,
+};
 use crate::ToJson;
 
 use super::Meta;
 
-#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct StyleMeta {
     map: FxHashMap<InternalString, StyleMetaItem>,
+    /// The keys configured as annotations. Only the styles of these keys are grouped
+    /// when the meta is converted into a map. See [`StyleMeta::resolve_annotations`].
+    #[serde(skip)]
+    annotations: FxHashSet<InternalString>,
 }
 
+// The annotation keys only decide how the styles are rendered
+impl PartialEq for StyleMeta {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl Eq for StyleMeta {}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StyleMetaItem {
     // We need lamport and peer to compose the event
@@ -48,7 +63,10 @@ impl From<&Styles> for StyleMeta {
                 );
             }
         }
-        Self { map }
+        Self {
+            map,
+            annotations: Default::default(),
+        }
     }
 }
 
@@ -75,6 +93,7 @@ impl Meta for StyleMeta {
                 }
             }
         }
+        self.annotations.extend(other.annotations.iter().cloned());
     }
 
     fn is_mergeable(&self, other: &Self) -> bool {
@@ -105,28 +124,43 @@ impl StyleMeta {
         self.map.contains_key(key)
     }
 
+    /// Look up which of the styles are annotations in the config.
+    ///
+    /// A key shaped like `key:counter@peer` is only grouped under `key` if `key` is
+    /// configured as an annotation; otherwise it's reported as it is.
+    pub(crate) fn resolve_annotations(&mut self, config: &StyleConfigMap) {
+        for key in self.map.keys() {
+            if let Some((base, _)) = parse_annotation_key(key) {
+                if config.is_annotation(base) {
+                    self.annotations.insert(base.into());
+                }
+            }
+        }
+    }
+
+    pub(crate) fn with_annotations(mut self, config: &StyleConfigMap) -> Self {
+        self.resolve_annotations(config);
+        self
+    }
+
+    /// Mark the key as an annotation, see [`StyleMeta::resolve_annotations`]
+    pub(crate) fn add_annotation(&mut self, key: InternalString) {
+        self.annotations.insert(key);
+    }
+
     pub(crate) fn to_value(&self) -> LoroValue {
         LoroValue::Map(self.to_map_without_null_value().into())
     }
 
-    fn to_map_without_null_value(&self) -> FxHashMap<String, LoroValue> {
-        self.map
-            .iter()
-            .filter_map(|(key, value)| {
-                if value.value.is_null() {
-                    None
-                } else {
-                    Some((key.to_string(), value.value.clone()))
-                }
-            })
-            .collect()
+    pub(crate) fn to_map_without_null_value(&self) -> FxHashMap<String, LoroValue> {
+        group_annotations(
+            self.map.iter().filter(|(_, value)| !value.value.is_null()),
+            &self.annotations,
+        )
     }
 
     pub(crate) fn to_map(&self) -> FxHashMap<String, LoroValue> {
-        self.map
-            .iter()
-            .map(|(key, value)| (key.to_string(), value.value.clone()))
-            .collect()
+        group_annotations(self.map.iter(), &self.annotations)
     }
 
     pub(crate) fn to_option_map(&self) -> Option<FxHashMap<String, LoroValue>> {
@@ -138,6 +172,37 @@ impl StyleMeta {
     }
 }
 
+/// Collect the styles into a map, where the annotations of a key are grouped into a map from
+/// the IDs of their mark ops to their values
+fn group_annotations<'a>(
+    iter: impl Iterator<Item = (&'a InternalString, &'a StyleMetaItem)>,
+    annotation_keys: &FxHashSet<InternalString>,
+) -> FxHashMap<String, LoroValue> {
+    let mut ans = FxHashMap::default();
+    let mut annotations: FxHashMap<&str, FxHashMap<String, LoroValue>> = FxHashMap::default();
+    for (key, value) in iter {
+        match parse_annotation_key(key)
+            .filter(|(base, _)| annotation_keys.contains(&InternalString::from(*base)))
+        {
+            Some((key, id)) => {
+                annotations
+                    .entry(key)
+                    .or_default()
+                    .insert(id.to_string(), value.value.clone());
+            }
+            None => {
+                ans.insert(key.to_string(), value.value.clone());
+            }
+        }
+    }
+
+    for (key, group) in annotations {
+        ans.insert(key.to_string(), LoroValue::Map(group.into()));
+    }
+
+    ans
+}
+
 impl ToJson for StyleMeta {
     fn to_json_value(&self) -> serde_json::Value {
         let mut map = serde_json::Map::new();
@@ -159,6 +224,7 @@ impl DeltaAttr for StyleMeta {
         for (key, value) in other.map.iter() {
             self.map.insert(key.clone(), value.clone());
         }
+        self.annotations.extend(other.annotations.iter().cloned());
     }
 
     fn attr_is_empty(&self) -> bool {
@@ -16,6 +16,7 @@
 //! Brandon Williams.
 use crate::change::get_sys_timestamp;
 use fxhash::FxHashMap;
+use loro_common::LoroError;
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 use std::iter::zip;
@@ -25,10 +26,17 @@ use std::ops::{Index, IndexMut};
 ///
 /// - `timeout_ms`: Optional timeout in milliseconds for the diff computation
 /// - `use_refined_diff`: Whether to use a more refined but slower diff algorithm. Defaults to true.
+/// - `granularity`: The unit the texts are diffed by. Defaults to [`UpdateGranularity::Char`].
+/// - `preserve_marks`: Whether the inserted text should not inherit the styles of the text
+///   around it, so the existing marks stay on the unchanged text they cover. Defaults to false.
+///
+/// It can also be built from [`UpdateOptions::default`] with the setters.
 #[derive(Clone, Debug)]
 pub struct UpdateOptions {
     pub timeout_ms: Option<f64>,
     pub use_refined_diff: bool,
+    pub granularity: UpdateGranularity,
+    pub preserve_marks: bool,
 }
 
 impl Default for UpdateOptions {
@@ -36,14 +44,74 @@ impl Default for UpdateOptions {
         Self {
             timeout_ms: None,
             use_refined_diff: true,
+            granularity: UpdateGranularity::Char,
+            preserve_marks: false,
         }
     }
 }
 
+impl UpdateOptions {
+    pub fn timeout_ms(mut self, timeout_ms: f64) -> Self {
+        self.timeout_ms = Some(timeout_ms);
+        self
+    }
+
+    pub fn use_refined_diff(mut self, use_refined_diff: bool) -> Self {
+        self.use_refined_diff = use_refined_diff;
+        self
+    }
+
+    pub fn granularity(mut self, granularity: UpdateGranularity) -> Self {
+        self.granularity = granularity;
+        self
+    }
+
+    pub fn preserve_marks(mut self, preserve_marks: bool) -> Self {
+        self.preserve_marks = preserve_marks;
+        self
+    }
+}
+
+/// The unit that the old and the new texts are diffed by when updating a text.
+///
+/// The ops of a coarser granularity are aligned to the token boundaries, so an edited word
+/// or sentence is replaced as a whole. It interleaves better with the concurrent edits on
+/// prose than the char diff, which may keep the scattered chars of a rewritten word.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum UpdateGranularity {
+    /// Unicode code points
+    #[default]
+    Char,
+    /// Words, whitespaces and punctuations, split by the Unicode word boundaries
+    Word,
+    /// Lines, each with its trailing line break
+    Line,
+    /// Sentences, each with its trailing whitespaces, split by the Unicode sentence boundaries
+    Sentence,
+}
+
 #[derive(thiserror::Error, Debug, PartialEq)]
 pub enum UpdateTimeoutError {
     #[error("Timeout")]
     Timeout,
+    /// The diff was computed but applying it to the text failed
+    #[error("Failed to apply the update: {0}")]
+    Apply(LoroError),
+}
+
+impl From<LoroError> for UpdateTimeoutError {
+    fn from(value: LoroError) -> Self {
+        UpdateTimeoutError::Apply(value)
+    }
+}
+
+impl From<UpdateTimeoutError> for LoroError {
+    fn from(value: UpdateTimeoutError) -> Self {
+        match value {
+            UpdateTimeoutError::Timeout => LoroError::TextUpdateTimeout,
+            UpdateTimeoutError::Apply(err) => err,
+        }
+    }
 }
 
 /// Utility function to check if a range is empty that works on older rust versions
@@ -109,8 +177,7 @@ impl<D: DiffHandler> OperateProxy<D> {
         self.handler.insert(old_index, new_index, new_len);
     }
 
-    #[allow(unused)]
-    fn unwrap(self) -> D {
+    pub fn unwrap(self) -> D {
         self.handler
     }
 }
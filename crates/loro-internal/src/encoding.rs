@@ -1,8 +1,12 @@
 pub(crate) mod arena;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub(crate) mod fast_snapshot;
 pub(crate) mod json_schema;
 mod outdated_encode_reordered;
+pub mod partition;
 mod shallow_snapshot;
+pub(crate) mod stream;
 pub(crate) mod value;
 pub(crate) mod value_register;
 pub(crate) use outdated_encode_reordered::{
@@ -11,11 +15,14 @@ pub(crate) use outdated_encode_reordered::{
 use outdated_encode_reordered::{import_changes_to_oplog, ImportChangesResult};
 pub(crate) use value::OwnedValue;
 
+use crate::change::Change;
 use crate::op::OpWithId;
 use crate::version::{Frontiers, VersionRange};
 use crate::LoroDoc;
 use crate::{oplog::OpLog, LoroError, VersionVector};
-use loro_common::{HasIdSpan, IdLpSpan, IdSpan, LoroEncodeError, LoroResult, PeerID, ID};
+use loro_common::{
+    ContainerID, HasIdSpan, IdLpSpan, IdSpan, LoroEncodeError, LoroResult, PeerID, ID,
+};
 use num_traits::{FromPrimitive, ToPrimitive};
 use rle::{HasLength, Sliceable};
 use std::borrow::Cow;
@@ -58,6 +65,16 @@ pub enum ExportMode<'a> {
     Updates { from: Cow<'a, VersionVector> },
     /// This mode exports the history in the specified range.
     UpdatesInRange { spans: Cow<'a, [IdSpan]> },
+    /// It contains the history since the `from` version vector, with only the ops of the
+    /// given containers and their descendants.
+    ///
+    /// The ops of the other containers are replaced by placeholders, so the receiver
+    /// doesn't treat them as missing. The receiver cannot export the placeholders as updates
+    /// again, and cannot replace them with the real ops later. See [`partition`] for details.
+    UpdatesForContainers {
+        from: Cow<'a, VersionVector>,
+        containers: Cow<'a, [ContainerID]>,
+    },
     /// The shallow snapshot only contains the history since the target frontiers
     ShallowSnapshot(Cow<'a, Frontiers>),
     /// The state only snapshot exports the state of the target version
@@ -105,6 +122,18 @@ impl<'a> ExportMode<'a> {
         }
     }
 
+    /// It contains the history since the `from` version vector, with only the ops of the
+    /// given containers and their descendants.
+    pub fn updates_for_containers(
+        from: &'a VersionVector,
+        containers: impl Into<Cow<'a, [ContainerID]>>,
+    ) -> Self {
+        ExportMode::UpdatesForContainers {
+            from: Cow::Borrowed(from),
+            containers: containers.into(),
+        }
+    }
+
     /// The shallow snapshot only contains the history since the target frontiers.
     pub fn shallow_snapshot(frontiers: &'a Frontiers) -> Self {
         ExportMode::ShallowSnapshot(Cow::Borrowed(frontiers))
@@ -154,6 +183,12 @@ impl<'a> ExportMode<'a> {
 }
 
 const MAGIC_BYTES: [u8; 4] = *b"loro";
+/// The mode bytes of a blob wrapped in the encryption envelope.
+///
+/// It's not an [`EncodeMode`] because the envelope contains another encoded blob.
+/// The header is parsed even when the `encryption` feature is disabled, so that we
+/// can tell which key is needed to decrypt the blob.
+const ENCRYPTED_MODE_BYTES: [u8; 2] = 5u16.to_be_bytes();
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum EncodeMode {
@@ -163,6 +198,9 @@ pub(crate) enum EncodeMode {
     OutdatedSnapshot = 2,
     FastSnapshot = 3,
     FastUpdates = 4,
+    /// The updates written by [`LoroDoc::export_to_writer`], where each change block
+    /// carries its own checksum. See [`stream`].
+    StreamedUpdates = 6,
 }
 
 impl num_traits::FromPrimitive for EncodeMode {
@@ -175,6 +213,7 @@ impl num_traits::FromPrimitive for EncodeMode {
             n if n == EncodeMode::OutdatedSnapshot as i64 => Some(EncodeMode::OutdatedSnapshot),
             n if n == EncodeMode::FastSnapshot as i64 => Some(EncodeMode::FastSnapshot),
             n if n == EncodeMode::FastUpdates as i64 => Some(EncodeMode::FastUpdates),
+            n if n == EncodeMode::StreamedUpdates as i64 => Some(EncodeMode::StreamedUpdates),
             _ => None,
         }
     }
@@ -194,6 +233,7 @@ impl num_traits::ToPrimitive for EncodeMode {
             EncodeMode::OutdatedSnapshot => EncodeMode::OutdatedSnapshot as i64,
             EncodeMode::FastSnapshot => EncodeMode::FastSnapshot as i64,
             EncodeMode::FastUpdates => EncodeMode::FastUpdates as i64,
+            EncodeMode::StreamedUpdates => EncodeMode::StreamedUpdates as i64,
         })
     }
     #[inline]
@@ -316,8 +356,22 @@ pub(crate) fn decode_oplog(
         }
         EncodeMode::FastSnapshot => fast_snapshot::decode_oplog(oplog, body),
         EncodeMode::FastUpdates => fast_snapshot::decode_updates(oplog, body.to_vec().into()),
+        EncodeMode::StreamedUpdates => {
+            fast_snapshot::decode_update_blocks(oplog, stream::BlockReader::new(body))
+        }
         EncodeMode::Auto => unreachable!(),
     }?;
+    import_decoded_changes(oplog, changes)
+}
+
+pub(crate) fn import_decoded_changes(
+    oplog: &mut OpLog,
+    changes: Vec<Change>,
+) -> Result<ImportStatus, LoroError> {
+    oplog.check_placeholders_not_replaced(&changes)?;
+    #[cfg(feature = "signature")]
+    let changes = oplog.verify_change_signatures(changes)?;
+    let changes = oplog.validate_imported_changes(changes);
     let ImportChangesResult {
         mut imported,
         latest_ids,
@@ -364,6 +418,17 @@ impl ParsedHeaderAndBody<'_> {
                     return Err(LoroError::DecodeChecksumMismatchError);
                 }
             }
+            EncodeMode::StreamedUpdates => {
+                let expected = u32::from_le_bytes(self.checksum[12..16].try_into().unwrap());
+                if expected != stream::streamed_updates_header_checksum() {
+                    return Err(LoroError::DecodeChecksumMismatchError);
+                }
+
+                // Each block carries its own checksum
+                for block in stream::BlockReader::new(self.body) {
+                    block?;
+                }
+            }
             EncodeMode::Auto => unreachable!(),
         }
 
@@ -390,6 +455,13 @@ pub(crate) fn parse_header_and_body(
     let (checksum, reader) = reader.split_at(16);
     let checksum_body = reader;
     let (mode_bytes, reader) = reader.split_at(2);
+    if mode_bytes == ENCRYPTED_MODE_BYTES {
+        let (key_id, _) = read_encryption_key_id(reader)?;
+        return Err(LoroError::DecryptionKeyRequired {
+            key_id: key_id.into(),
+        });
+    }
+
     let mode: EncodeMode = [mode_bytes[0], mode_bytes[1]].try_into()?;
 
     let ans = ParsedHeaderAndBody {
@@ -405,6 +477,23 @@ pub(crate) fn parse_header_and_body(
     Ok(ans)
 }
 
+/// Read the leb128 length prefixed key id at the start of the encryption envelope body.
+///
+/// Return the key id and the rest of the body.
+fn read_encryption_key_id(mut body: &[u8]) -> LoroResult<(&str, &[u8])> {
+    let len = leb128::read::unsigned(&mut body)
+        .map_err(|_| LoroError::DecodeError("Invalid encryption key id".into()))?
+        as usize;
+    if body.len() < len {
+        return Err(LoroError::DecodeError("Invalid encryption key id".into()));
+    }
+
+    let (key_id, rest) = body.split_at(len);
+    let key_id = std::str::from_utf8(key_id)
+        .map_err(|_| LoroError::DecodeError("Invalid encryption key id".into()))?;
+    Ok((key_id, rest))
+}
+
 fn encode_header_and_body(mode: EncodeMode, body: Vec<u8>) -> Vec<u8> {
     let mut ans = Vec::new();
     ans.extend(MAGIC_BYTES);
@@ -454,6 +543,21 @@ pub(crate) fn export_fast_updates(doc: &LoroDoc, vv: &VersionVector) -> Vec<u8>
     .unwrap()
 }
 
+pub(crate) fn export_fast_updates_for_containers(
+    oplog: &OpLog,
+    vv: &VersionVector,
+    containers: &[ContainerID],
+) -> Vec<u8> {
+    encode_with(EncodeMode::FastUpdates, &mut |ans| {
+        oplog.for_each_block_bytes_for_containers(vv, containers, |block| {
+            leb128::write::unsigned(ans, block.len() as u64).unwrap();
+            ans.extend_from_slice(&block);
+            Ok(())
+        })
+    })
+    .unwrap()
+}
+
 pub(crate) fn export_fast_updates_in_range(oplog: &OpLog, spans: &[IdSpan]) -> Vec<u8> {
     encode_with(EncodeMode::FastUpdates, &mut |ans| {
         fast_snapshot::encode_updates_in_range(oplog, spans, ans);
@@ -596,7 +700,9 @@ impl LoroDoc {
                 outdated_encode_reordered::decode_import_blob_meta(parsed)
             }
             EncodeMode::FastSnapshot => fast_snapshot::decode_snapshot_blob_meta(parsed),
-            EncodeMode::FastUpdates => fast_snapshot::decode_updates_blob_meta(parsed),
+            EncodeMode::FastUpdates | EncodeMode::StreamedUpdates => {
+                fast_snapshot::decode_updates_blob_meta(parsed)
+            }
         }
     }
 }
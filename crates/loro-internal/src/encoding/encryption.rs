@@ -0,0 +1,288 @@
+//! Authenticated encryption envelope for the exported blobs.
+//!
+//! The blobs exported by [`LoroDoc::export`] carry the plaintext changes after the header.
+//! When the blobs are synced through untrusted relays or stored on untrusted storage, they
+//! can be wrapped in an envelope encrypted by XChaCha20-Poly1305:
+//!
+//! ```text
+//! ┌────────────┬──────────────┬──────────┬─────────────────┬──────────┬──────────┬────────────────┐
+//! │ "loro" u32 │ checksum 16B │ mode u16 │ key id len leb  │ key id   │ nonce 24B│ ciphertext+tag │
+//! └────────────┴──────────────┴──────────┴─────────────────┴──────────┴──────────┴────────────────┘
+//! ```
+//!
+//! - The mode is `5`, so the older versions of Loro reject the blob instead of decoding garbage.
+//! - The key id is kept in plaintext so that [`LoroDoc::decode_import_blob_meta`] can report
+//!   which key is needed. It's authenticated as the associated data.
+//! - The plaintext is the whole inner blob, including its header. So every export mode is supported.
+//! - The checksum is the xxhash32 of everything after it, like the fast encoding modes.
+//!   It only detects corruption; the integrity is guaranteed by the Poly1305 tag.
+use std::fmt::Debug;
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use loro_common::{LoroEncodeError, LoroError, LoroResult};
+use zeroize::Zeroize;
+
+use super::{
+    parse_header_and_body, read_encryption_key_id, ExportMode, ImportBlobMetadata, ImportStatus,
+    ENCRYPTED_MODE_BYTES, MAGIC_BYTES, MIN_HEADER_SIZE, XXH_SEED,
+};
+use crate::LoroDoc;
+
+/// The size of an encryption key in bytes
+pub const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 24;
+
+/// A 256-bit key used to encrypt the exported blobs and the kv-store values.
+///
+/// Each key has an id, which is stored in plaintext in the encrypted blobs so that
+/// the receiver can find the key to decrypt them. The id should not contain any secret.
+///
+/// The key is zeroed when it's dropped.
+#[derive(Clone)]
+pub struct EncryptionKey {
+    id: Box<str>,
+    key: [u8; KEY_SIZE],
+}
+
+impl Drop for EncryptionKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the key
+        f.debug_struct("EncryptionKey")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EncryptionKey {
+    /// Create a key with the given id.
+    ///
+    /// The array is copied into the key, so the caller should zero its own copy.
+    pub fn new(id: impl Into<Box<str>>, key: [u8; KEY_SIZE]) -> Self {
+        Self { id: id.into(), key }
+    }
+
+    /// Generate a random key with the given id
+    pub fn generate(id: impl Into<Box<str>>) -> Self {
+        let mut ans = Self::new(id, [0; KEY_SIZE]);
+        getrandom::getrandom(&mut ans.key).unwrap();
+        ans
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn as_bytes(&self) -> &[u8; KEY_SIZE] {
+        &self.key
+    }
+
+    /// Encrypt the plaintext with a random nonce.
+    ///
+    /// The output is the nonce followed by the ciphertext and the tag.
+    pub(crate) fn seal(&self, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut nonce = [0; NONCE_SIZE];
+        getrandom::getrandom(&mut nonce).unwrap();
+        let cipher = XChaCha20Poly1305::new(&self.key.into());
+        let ciphertext = cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .unwrap();
+        let mut ans = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        ans.extend_from_slice(&nonce);
+        ans.extend_from_slice(&ciphertext);
+        ans
+    }
+
+    /// Decrypt the output of [`EncryptionKey::seal`]
+    pub(crate) fn open(&self, aad: &[u8], sealed: &[u8]) -> LoroResult<Vec<u8>> {
+        if sealed.len() < NONCE_SIZE {
+            return Err(LoroError::DecryptionError);
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        let cipher = XChaCha20Poly1305::new(&self.key.into());
+        cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| LoroError::DecryptionError)
+    }
+}
+
+/// Wrap an encoded blob in the encryption envelope
+pub(crate) fn encrypt_blob(blob: &[u8], key: &EncryptionKey) -> Vec<u8> {
+    let mut ans = Vec::with_capacity(MIN_HEADER_SIZE + key.id.len() + blob.len() + 48);
+    ans.extend(MAGIC_BYTES);
+    ans.extend([0; 16]);
+    ans.extend(ENCRYPTED_MODE_BYTES);
+    leb128::write::unsigned(&mut ans, key.id.len() as u64).unwrap();
+    ans.extend_from_slice(key.id.as_bytes());
+    let sealed = key.seal(&ans[20..], blob);
+    ans.extend(sealed);
+
+    let checksum = xxhash_rust::xxh32::xxh32(&ans[20..], XXH_SEED);
+    ans[16..20].copy_from_slice(&checksum.to_le_bytes());
+    ans
+}
+
+/// Return the id of the key used to encrypt the blob.
+///
+/// Return `None` if the blob is not encrypted.
+pub fn encrypted_blob_key_id(blob: &[u8]) -> Option<&str> {
+    if blob.len() < MIN_HEADER_SIZE
+        || blob[..4] != MAGIC_BYTES
+        || blob[20..22] != ENCRYPTED_MODE_BYTES
+    {
+        return None;
+    }
+
+    read_encryption_key_id(&blob[MIN_HEADER_SIZE..])
+        .ok()
+        .map(|(id, _)| id)
+}
+
+/// Decrypt the blob with the matching key in `keys`.
+///
+/// Return the inner blob, which can be imported by [`LoroDoc::import`].
+/// Return [`LoroError::NotEncrypted`] if the blob is not encrypted, so that a plaintext
+/// blob injected by an untrusted relay or storage is never accepted silently. Use
+/// [`decrypt_blob_or_plaintext`] to accept the plaintext blobs explicitly.
+pub fn decrypt_blob(blob: &[u8], keys: &[EncryptionKey]) -> LoroResult<Vec<u8>> {
+    if !is_encrypted_blob(blob)? {
+        return Err(LoroError::NotEncrypted);
+    }
+
+    let expected = u32::from_le_bytes(blob[16..20].try_into().unwrap());
+    if xxhash_rust::xxh32::xxh32(&blob[20..], XXH_SEED) != expected {
+        return Err(LoroError::DecodeChecksumMismatchError);
+    }
+
+    let (key_id, sealed) = read_encryption_key_id(&blob[MIN_HEADER_SIZE..])?;
+    let Some(key) = keys.iter().find(|k| &*k.id == key_id) else {
+        return Err(LoroError::DecryptionKeyRequired {
+            key_id: key_id.into(),
+        });
+    };
+
+    let aad = &blob[20..blob.len() - sealed.len()];
+    key.open(aad, sealed)
+}
+
+/// Same as [`decrypt_blob`], but the blob that is not encrypted is returned as is.
+///
+/// It's an opt-in for the stores that are being migrated to encryption and still contain
+/// plaintext blobs. The plaintext blobs are not authenticated.
+pub fn decrypt_blob_or_plaintext(blob: &[u8], keys: &[EncryptionKey]) -> LoroResult<Vec<u8>> {
+    if is_encrypted_blob(blob)? {
+        decrypt_blob(blob, keys)
+    } else {
+        Ok(blob.to_vec())
+    }
+}
+
+fn is_encrypted_blob(blob: &[u8]) -> LoroResult<bool> {
+    if blob.len() < MIN_HEADER_SIZE {
+        return Err(LoroError::DecodeError("Invalid import data".into()));
+    }
+
+    if blob[..4] != MAGIC_BYTES {
+        return Err(LoroError::DecodeError("Invalid magic bytes".into()));
+    }
+
+    Ok(blob[20..22] == ENCRYPTED_MODE_BYTES)
+}
+
+/// Decrypt the blob and encrypt it again with `new_key`.
+///
+/// It's used to rotate the key of the stored snapshots and updates without importing them.
+pub fn reencrypt_blob(
+    blob: &[u8],
+    keys: &[EncryptionKey],
+    new_key: &EncryptionKey,
+) -> LoroResult<Vec<u8>> {
+    let inner = decrypt_blob(blob, keys)?;
+    // Make sure we don't wrap garbage
+    parse_header_and_body(&inner, true)?;
+    Ok(encrypt_blob(&inner, new_key))
+}
+
+impl LoroDoc {
+    /// Export the document in the given mode, wrapped in the encryption envelope.
+    pub fn export_encrypted(
+        &self,
+        mode: ExportMode,
+        key: &EncryptionKey,
+    ) -> Result<Vec<u8>, LoroEncodeError> {
+        let blob = self.export(mode)?;
+        Ok(encrypt_blob(&blob, key))
+    }
+
+    /// Import an encrypted blob. The key is looked up in `keys` by the key id in the blob.
+    ///
+    /// Blobs that are not encrypted are rejected with [`LoroError::NotEncrypted`].
+    pub fn import_encrypted(
+        &self,
+        blob: &[u8],
+        keys: &[EncryptionKey],
+    ) -> LoroResult<ImportStatus> {
+        self.import_encrypted_with(blob, keys, Default::default())
+    }
+
+    pub fn import_encrypted_with(
+        &self,
+        blob: &[u8],
+        keys: &[EncryptionKey],
+        origin: loro_common::InternalString,
+    ) -> LoroResult<ImportStatus> {
+        let inner = decrypt_blob(blob, keys)?;
+        self.import_with(&inner, origin)
+    }
+
+    /// Decodes the metadata of an encrypted blob with the matching key in `keys`.
+    pub fn decode_encrypted_blob_meta(
+        blob: &[u8],
+        keys: &[EncryptionKey],
+        check_checksum: bool,
+    ) -> LoroResult<ImportBlobMetadata> {
+        let inner = decrypt_blob(blob, keys)?;
+        Self::decode_import_blob_meta(&inner, check_checksum)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let key = EncryptionKey::generate("k1");
+        let sealed = key.seal(b"aad", b"hello");
+        assert_eq!(key.open(b"aad", &sealed).unwrap(), b"hello");
+        assert_eq!(
+            key.open(b"other aad", &sealed),
+            Err(LoroError::DecryptionError)
+        );
+        assert_eq!(
+            EncryptionKey::generate("k1").open(b"aad", &sealed),
+            Err(LoroError::DecryptionError)
+        );
+    }
+}
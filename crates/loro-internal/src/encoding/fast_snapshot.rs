@@ -22,7 +22,9 @@ use bytes::{Buf, Bytes};
 use loro_common::{HasCounterSpan, IdSpan, LoroError, LoroResult};
 use tracing::trace;
 
-use super::{EncodedBlobMode, ImportBlobMetadata, ParsedHeaderAndBody};
+use super::{
+    stream::BlockReader, EncodeMode, EncodedBlobMode, ImportBlobMetadata, ParsedHeaderAndBody,
+};
 pub(crate) const EMPTY_MARK: &[u8] = b"E";
 pub(crate) struct Snapshot {
     pub oplog_bytes: Bytes,
@@ -45,6 +47,24 @@ pub(super) fn _encode_snapshot<W: Write>(s: Snapshot, w: &mut W) {
     w.write_all(&s.shallow_root_state_bytes).unwrap();
 }
 
+/// Split the encoded snapshot into chunks without concatenating them.
+///
+/// Writing all the chunks in order produces the same bytes as [`_encode_snapshot`].
+pub(super) fn _encode_snapshot_chunks(s: Snapshot) -> [Bytes; 6] {
+    let state_bytes = s
+        .state_bytes
+        .unwrap_or_else(|| Bytes::from_static(EMPTY_MARK));
+    let len_bytes = |b: &Bytes| Bytes::copy_from_slice(&(b.len() as u32).to_le_bytes());
+    [
+        len_bytes(&s.oplog_bytes),
+        s.oplog_bytes,
+        len_bytes(&state_bytes),
+        state_bytes,
+        len_bytes(&s.shallow_root_state_bytes),
+        s.shallow_root_state_bytes,
+    ]
+}
+
 pub(super) fn _decode_snapshot_bytes(bytes: Bytes) -> LoroResult<Snapshot> {
     let mut r = bytes.reader();
     let oplog_bytes_len = read_u32_le(&mut r) as usize;
@@ -174,6 +194,7 @@ pub(crate) fn decode_snapshot_inner(snapshot: Snapshot, doc: &LoroDoc) -> Result
 impl OpLog {
     pub(super) fn decode_change_store(&mut self, bytes: bytes::Bytes) -> LoroResult<()> {
         let v = self.change_store().import_all(bytes)?;
+        self.placeholder_end = v.placeholder_end.clone();
         self.dag.set_version_by_fast_snapshot_import(v);
         Ok(())
     }
@@ -235,11 +256,16 @@ pub(crate) fn encode_snapshot_inner(doc: &LoroDoc) -> Snapshot {
 pub(crate) fn decode_oplog(oplog: &mut OpLog, bytes: &[u8]) -> Result<Vec<Change>, LoroError> {
     let oplog_len = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
     let oplog_bytes = &bytes[4..4 + oplog_len as usize];
-    let mut changes = ChangeStore::decode_snapshot_for_updates(
-        oplog_bytes.to_vec().into(),
-        &oplog.arena,
-        oplog.vv(),
-    )?;
+    decode_oplog_bytes(oplog, oplog_bytes.to_vec().into())
+}
+
+/// Decode the changes from the oplog part of a snapshot.
+pub(crate) fn decode_oplog_bytes(
+    oplog: &mut OpLog,
+    oplog_bytes: Bytes,
+) -> Result<Vec<Change>, LoroError> {
+    let mut changes =
+        ChangeStore::decode_snapshot_for_updates(oplog_bytes, &oplog.arena, oplog.vv())?;
     changes.sort_unstable_by_key(|x| x.lamport);
     Ok(changes)
 }
@@ -260,18 +286,34 @@ pub(crate) fn encode_updates_in_range<W: std::io::Write>(
 pub(crate) fn decode_updates(oplog: &mut OpLog, body: Bytes) -> Result<Vec<Change>, LoroError> {
     let mut reader: &[u8] = body.as_ref();
     let mut index = 0;
-    let self_vv = oplog.vv();
-    let mut changes = Vec::new();
+    let mut blocks = Vec::new();
     while !reader.is_empty() {
         let old_reader_len = reader.len();
         let len = leb128::read::unsigned(&mut reader).unwrap() as usize;
         index += old_reader_len - reader.len();
-        let block_bytes = body.slice(index..index + len);
+        blocks.push(body.slice(index..index + len));
+        index += len;
+        reader = &reader[len..];
+    }
+
+    decode_update_blocks(oplog, blocks.into_iter().map(Ok))
+}
+
+/// Decode the changes from the blocks of an update blob.
+///
+/// Each item is the bytes of a single block, without the leb128 length prefix. The
+/// blocks are decoded one by one, and the first error item is returned.
+pub(crate) fn decode_update_blocks(
+    oplog: &mut OpLog,
+    blocks: impl IntoIterator<Item = LoroResult<Bytes>>,
+) -> Result<Vec<Change>, LoroError> {
+    let self_vv = oplog.vv();
+    let mut changes = Vec::new();
+    for block_bytes in blocks {
+        let block_bytes = block_bytes?;
         trace!("decoded block_bytes = {:?}", &block_bytes);
         let new_changes = ChangeStore::decode_block_bytes(block_bytes, &oplog.arena, self_vv)?;
         changes.extend(new_changes);
-        index += len;
-        reader = &reader[len..];
     }
 
     changes.sort_unstable_by_key(|x| x.lamport);
@@ -312,12 +354,18 @@ pub(crate) fn decode_updates_blob_meta(
 ) -> LoroResult<ImportBlobMetadata> {
     let doc = LoroDoc::new();
     let mut oplog = doc.oplog.try_lock().unwrap();
-    let changes = decode_updates(&mut oplog, parsed.body.to_vec().into())?;
+    let changes = if parsed.mode == EncodeMode::StreamedUpdates {
+        decode_update_blocks(&mut oplog, BlockReader::new(parsed.body))?
+    } else {
+        decode_updates(&mut oplog, parsed.body.to_vec().into())?
+    };
     let mut start_vv = VersionVector::new();
     let mut end_vv = VersionVector::new();
     for c in changes.iter() {
-        start_vv.insert(c.id.peer, c.id.counter);
-        end_vv.insert(c.id.peer, c.ctr_end());
+        let start = start_vv.entry(c.id.peer).or_insert(c.id.counter);
+        *start = (*start).min(c.id.counter);
+        let end = end_vv.entry(c.id.peer).or_insert(c.ctr_end());
+        *end = (*end).max(c.ctr_end());
     }
 
     Ok(ImportBlobMetadata {
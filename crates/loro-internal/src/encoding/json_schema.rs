@@ -1,5 +1,6 @@
 use super::{
     outdated_encode_reordered::{import_changes_to_oplog, ImportChangesResult, ValueRegister},
+    partition::is_hidden_container,
     ImportStatus,
 };
 use crate::{
@@ -66,6 +67,10 @@ pub(crate) fn export_json<'a, 'c: 'a>(
 
 pub(crate) fn import_json(oplog: &mut OpLog, json: JsonSchema) -> LoroResult<ImportStatus> {
     let changes = decode_changes(json, &oplog.arena)?;
+    oplog.check_placeholders_not_replaced(&changes)?;
+    #[cfg(feature = "signature")]
+    oplog.check_json_changes_can_be_verified(&changes)?;
+    let changes = oplog.validate_imported_changes(changes);
     let ImportChangesResult {
         latest_ids,
         pending_changes,
@@ -124,28 +129,49 @@ fn init_encode<'s, 'a: 's>(
     diff_changes
 }
 
-fn register_id(id: &ID, peer_register: &mut ValueRegister<PeerID>) -> ID {
-    let peer = peer_register.register(&id.peer);
-    ID::new(peer as PeerID, id.counter)
+/// Maps the peers in the encoded changes.
+///
+/// [`ValueRegister`] replaces the peers with their indexes in the peer table of
+/// [`JsonSchema`], while [`KeepPeers`] keeps the original peers.
+trait PeerRegister {
+    fn register_peer(&mut self, peer: PeerID) -> PeerID;
 }
 
-fn register_idlp(idlp: &IdLp, peer_register: &mut ValueRegister<PeerID>) -> IdLp {
+impl PeerRegister for ValueRegister<PeerID> {
+    fn register_peer(&mut self, peer: PeerID) -> PeerID {
+        self.register(&peer) as PeerID
+    }
+}
+
+struct KeepPeers;
+
+impl PeerRegister for KeepPeers {
+    fn register_peer(&mut self, peer: PeerID) -> PeerID {
+        peer
+    }
+}
+
+fn register_id(id: &ID, peer_register: &mut impl PeerRegister) -> ID {
+    ID::new(peer_register.register_peer(id.peer), id.counter)
+}
+
+fn register_idlp(idlp: &IdLp, peer_register: &mut impl PeerRegister) -> IdLp {
     IdLp {
-        peer: peer_register.register(&idlp.peer) as PeerID,
+        peer: peer_register.register_peer(idlp.peer),
         lamport: idlp.lamport,
     }
 }
 
-fn register_tree_id(tree: &TreeID, peer_register: &mut ValueRegister<PeerID>) -> TreeID {
+fn register_tree_id(tree: &TreeID, peer_register: &mut impl PeerRegister) -> TreeID {
     TreeID {
-        peer: peer_register.register(&tree.peer) as PeerID,
+        peer: peer_register.register_peer(tree.peer),
         counter: tree.counter,
     }
 }
 
 fn register_container_id(
     container: ContainerID,
-    peer_register: &mut ValueRegister<PeerID>,
+    peer_register: &mut impl PeerRegister,
 ) -> ContainerID {
     match container {
         ContainerID::Normal {
@@ -153,7 +179,7 @@ fn register_container_id(
             counter,
             container_type,
         } => ContainerID::Normal {
-            peer: peer_register.register(&peer) as PeerID,
+            peer: peer_register.register_peer(peer),
             counter,
             container_type,
         },
@@ -161,6 +187,12 @@ fn register_container_id(
     }
 }
 
+/// Convert the change to [`json::JsonChange`] with the original peers,
+/// so the ids in it can be read without the peer table.
+pub(crate) fn change_to_json(change: &Change, arena: &SharedArena) -> json::JsonChange {
+    encode_change(change, arena, &mut KeepPeers)
+}
+
 fn convert_container_id(container: ContainerID, peers: &[PeerID]) -> ContainerID {
     match container {
         ContainerID::Normal {
@@ -200,251 +232,264 @@ fn convert_tree_id(tree: &TreeID, peers: &[PeerID]) -> TreeID {
 fn encode_changes(
     diff_changes: &[Either<BlockChangeRef, Change>],
     arena: &SharedArena,
-    peer_register: &mut ValueRegister<PeerID>,
+    peer_register: &mut impl PeerRegister,
 ) -> Vec<json::JsonChange> {
-    let mut changes = Vec::with_capacity(diff_changes.len());
-    for change in diff_changes.iter() {
-        let change: &Change = match change {
-            Either::Left(c) => c,
-            Either::Right(c) => c,
-        };
-        let mut ops = Vec::with_capacity(change.ops().len());
-        for Op {
-            counter,
-            container,
-            content,
-        } in change.ops().iter()
-        {
-            let mut container = arena.get_container_id(*container).unwrap();
-            if container.is_normal() {
-                container = register_container_id(container, peer_register);
-            }
-            let op = match container.container_type() {
-                ContainerType::List => match content {
-                    InnerContent::List(list) => JsonOpContent::List(match list {
-                        InnerListOp::Insert { slice, pos } => {
-                            let mut values =
-                                arena.get_values(slice.0.start as usize..slice.0.end as usize);
-                            values.iter_mut().for_each(|x| {
-                                if let LoroValue::Container(id) = x {
-                                    if id.is_normal() {
-                                        *id = register_container_id(id.clone(), peer_register);
-                                    }
+    diff_changes
+        .iter()
+        .map(|change| {
+            let change: &Change = match change {
+                Either::Left(c) => c,
+                Either::Right(c) => c,
+            };
+            encode_change(change, arena, peer_register)
+        })
+        .collect()
+}
+
+fn encode_change(
+    change: &Change,
+    arena: &SharedArena,
+    peer_register: &mut impl PeerRegister,
+) -> json::JsonChange {
+    let mut ops = Vec::with_capacity(change.ops().len());
+    for Op {
+        counter,
+        container,
+        content,
+    } in change.ops().iter()
+    {
+        let mut container = arena.get_container_id(*container).unwrap();
+        if container.is_normal() {
+            container = register_container_id(container, peer_register);
+        }
+        let op = match container.container_type() {
+            ContainerType::List => match content {
+                InnerContent::List(list) => JsonOpContent::List(match list {
+                    InnerListOp::Insert { slice, pos } => {
+                        let mut values =
+                            arena.get_values(slice.0.start as usize..slice.0.end as usize);
+                        values.iter_mut().for_each(|x| {
+                            if let LoroValue::Container(id) = x {
+                                if id.is_normal() {
+                                    *id = register_container_id(id.clone(), peer_register);
                                 }
-                            });
-                            json::ListOp::Insert {
-                                pos: *pos as u32,
-                                value: values,
                             }
+                        });
+                        json::ListOp::Insert {
+                            pos: *pos as u32,
+                            value: values,
                         }
-                        InnerListOp::Delete(DeleteSpanWithId {
-                            id_start,
-                            span: DeleteSpan { pos, signed_len },
-                        }) => json::ListOp::Delete {
-                            pos: *pos as i32,
-                            len: *signed_len as i32,
-                            start_id: register_id(id_start, peer_register),
-                        },
-                        _ => unreachable!(),
-                    }),
+                    }
+                    InnerListOp::Delete(DeleteSpanWithId {
+                        id_start,
+                        span: DeleteSpan { pos, signed_len },
+                    }) => json::ListOp::Delete {
+                        pos: *pos as i32,
+                        len: *signed_len as i32,
+                        start_id: register_id(id_start, peer_register),
+                    },
                     _ => unreachable!(),
-                },
-                ContainerType::MovableList => match content {
-                    InnerContent::List(list) => JsonOpContent::MovableList(match list {
-                        InnerListOp::Insert { slice, pos } => {
-                            let mut values =
-                                arena.get_values(slice.0.start as usize..slice.0.end as usize);
-                            values.iter_mut().for_each(|x| {
-                                if let LoroValue::Container(id) = x {
-                                    if id.is_normal() {
-                                        *id = register_container_id(id.clone(), peer_register);
-                                    }
+                }),
+                _ => unreachable!(),
+            },
+            ContainerType::MovableList => match content {
+                InnerContent::List(list) => JsonOpContent::MovableList(match list {
+                    InnerListOp::Insert { slice, pos } => {
+                        let mut values =
+                            arena.get_values(slice.0.start as usize..slice.0.end as usize);
+                        values.iter_mut().for_each(|x| {
+                            if let LoroValue::Container(id) = x {
+                                if id.is_normal() {
+                                    *id = register_container_id(id.clone(), peer_register);
                                 }
-                            });
-                            json::MovableListOp::Insert {
-                                pos: *pos as u32,
-                                value: values,
                             }
+                        });
+                        json::MovableListOp::Insert {
+                            pos: *pos as u32,
+                            value: values,
                         }
-                        InnerListOp::Delete(DeleteSpanWithId {
-                            id_start,
-                            span: DeleteSpan { pos, signed_len },
-                        }) => json::MovableListOp::Delete {
-                            pos: *pos as i32,
-                            len: *signed_len as i32,
-                            start_id: register_id(id_start, peer_register),
-                        },
-                        InnerListOp::Move {
-                            from,
-                            elem_id: from_id,
-                            to,
-                        } => json::MovableListOp::Move {
-                            from: *from,
-                            to: *to,
-                            elem_id: register_idlp(from_id, peer_register),
-                        },
-                        InnerListOp::Set { elem_id, value } => {
-                            let value = if let LoroValue::Container(id) = value {
-                                if id.is_normal() {
-                                    LoroValue::Container(register_container_id(
-                                        id.clone(),
-                                        peer_register,
-                                    ))
-                                } else {
-                                    value.clone()
-                                }
+                    }
+                    InnerListOp::Delete(DeleteSpanWithId {
+                        id_start,
+                        span: DeleteSpan { pos, signed_len },
+                    }) => json::MovableListOp::Delete {
+                        pos: *pos as i32,
+                        len: *signed_len as i32,
+                        start_id: register_id(id_start, peer_register),
+                    },
+                    InnerListOp::Move {
+                        from,
+                        elem_id: from_id,
+                        to,
+                    } => json::MovableListOp::Move {
+                        from: *from,
+                        to: *to,
+                        elem_id: register_idlp(from_id, peer_register),
+                    },
+                    InnerListOp::Set { elem_id, value } => {
+                        let value = if let LoroValue::Container(id) = value {
+                            if id.is_normal() {
+                                LoroValue::Container(register_container_id(
+                                    id.clone(),
+                                    peer_register,
+                                ))
                             } else {
                                 value.clone()
-                            };
-                            json::MovableListOp::Set {
-                                elem_id: register_idlp(elem_id, peer_register),
-                                value,
                             }
+                        } else {
+                            value.clone()
+                        };
+                        json::MovableListOp::Set {
+                            elem_id: register_idlp(elem_id, peer_register),
+                            value,
                         }
-                        _ => unreachable!(),
-                    }),
+                    }
                     _ => unreachable!(),
-                },
-                ContainerType::Text => match content {
-                    InnerContent::List(list) => JsonOpContent::Text(match list {
-                        InnerListOp::InsertText {
-                            slice,
-                            unicode_start: _,
-                            unicode_len: _,
-                            pos,
-                        } => {
-                            let text = String::from_utf8(slice.as_bytes().to_vec()).unwrap();
-                            json::TextOp::Insert { pos: *pos, text }
-                        }
-                        InnerListOp::Delete(DeleteSpanWithId {
-                            id_start,
-                            span: DeleteSpan { pos, signed_len },
-                        }) => json::TextOp::Delete {
-                            pos: *pos as i32,
-                            len: *signed_len as i32,
-                            start_id: register_id(id_start, peer_register),
-                        },
-                        InnerListOp::StyleStart {
-                            start,
-                            end,
-                            key,
-                            value,
-                            info,
-                        } => json::TextOp::Mark {
-                            start: *start,
-                            end: *end,
-                            style_key: key.to_string(),
-                            style_value: value.clone(),
-                            info: info.to_byte(),
-                        },
-                        InnerListOp::StyleEnd => json::TextOp::MarkEnd,
-                        _ => unreachable!(),
-                    }),
+                }),
+                _ => unreachable!(),
+            },
+            ContainerType::Text => match content {
+                InnerContent::List(list) => JsonOpContent::Text(match list {
+                    InnerListOp::InsertText {
+                        slice,
+                        unicode_start: _,
+                        unicode_len: _,
+                        pos,
+                    } => {
+                        let text = String::from_utf8(slice.as_bytes().to_vec()).unwrap();
+                        json::TextOp::Insert { pos: *pos, text }
+                    }
+                    InnerListOp::Delete(DeleteSpanWithId {
+                        id_start,
+                        span: DeleteSpan { pos, signed_len },
+                    }) => json::TextOp::Delete {
+                        pos: *pos as i32,
+                        len: *signed_len as i32,
+                        start_id: register_id(id_start, peer_register),
+                    },
+                    InnerListOp::StyleStart {
+                        start,
+                        end,
+                        key,
+                        value,
+                        info,
+                    } => json::TextOp::Mark {
+                        start: *start,
+                        end: *end,
+                        style_key: key.to_string(),
+                        style_value: value.clone(),
+                        info: info.to_byte(),
+                    },
+                    InnerListOp::StyleEnd => json::TextOp::MarkEnd,
                     _ => unreachable!(),
-                },
-                ContainerType::Map => match content {
-                    InnerContent::Map(MapSet { key, value }) => {
-                        JsonOpContent::Map(if let Some(v) = value {
-                            let value = if let LoroValue::Container(id) = v {
-                                if id.is_normal() {
-                                    LoroValue::Container(register_container_id(
-                                        id.clone(),
-                                        peer_register,
-                                    ))
-                                } else {
-                                    v.clone()
-                                }
+                }),
+                _ => unreachable!(),
+            },
+            ContainerType::Map => match content {
+                InnerContent::Map(MapSet { key, value }) => {
+                    JsonOpContent::Map(if let Some(v) = value {
+                        let value = if let LoroValue::Container(id) = v {
+                            if id.is_normal() {
+                                LoroValue::Container(register_container_id(
+                                    id.clone(),
+                                    peer_register,
+                                ))
                             } else {
                                 v.clone()
-                            };
-                            json::MapOp::Insert {
-                                key: key.to_string(),
-                                value,
                             }
                         } else {
-                            json::MapOp::Delete {
-                                key: key.to_string(),
-                            }
-                        })
-                    }
+                            v.clone()
+                        };
+                        json::MapOp::Insert {
+                            key: key.to_string(),
+                            value,
+                        }
+                    } else {
+                        json::MapOp::Delete {
+                            key: key.to_string(),
+                        }
+                    })
+                }
 
-                    _ => unreachable!(),
-                },
-
-                ContainerType::Tree => match content {
-                    InnerContent::Tree(op) => JsonOpContent::Tree(match &**op {
-                        TreeOp::Create {
-                            target,
-                            parent,
-                            position,
-                        } => json::TreeOp::Create {
-                            target: register_tree_id(target, peer_register),
-                            parent: parent.map(|p| register_tree_id(&p, peer_register)),
-                            fractional_index: position.clone(),
-                        },
-                        TreeOp::Move {
-                            target,
-                            parent,
-                            position,
-                        } => json::TreeOp::Move {
-                            target: register_tree_id(target, peer_register),
-                            parent: parent.map(|p| register_tree_id(&p, peer_register)),
-                            fractional_index: position.clone(),
-                        },
-                        TreeOp::Delete { target } => json::TreeOp::Delete {
-                            target: register_tree_id(target, peer_register),
-                        },
-                    }),
-                    _ => unreachable!(),
-                },
-                ContainerType::Unknown(_) => {
-                    let InnerContent::Future(FutureInnerContent::Unknown { prop, value }) = content
-                    else {
-                        unreachable!();
-                    };
+                _ => unreachable!(),
+            },
+
+            ContainerType::Tree => match content {
+                InnerContent::Tree(op) => JsonOpContent::Tree(match &**op {
+                    TreeOp::Create {
+                        target,
+                        parent,
+                        position,
+                    } => json::TreeOp::Create {
+                        target: register_tree_id(target, peer_register),
+                        parent: parent.map(|p| register_tree_id(&p, peer_register)),
+                        fractional_index: position.clone(),
+                    },
+                    TreeOp::Move {
+                        target,
+                        parent,
+                        position,
+                    } => json::TreeOp::Move {
+                        target: register_tree_id(target, peer_register),
+                        parent: parent.map(|p| register_tree_id(&p, peer_register)),
+                        fractional_index: position.clone(),
+                    },
+                    TreeOp::Delete { target } => json::TreeOp::Delete {
+                        target: register_tree_id(target, peer_register),
+                    },
+                }),
+                _ => unreachable!(),
+            },
+            ContainerType::Unknown(_) => match content {
+                InnerContent::Future(FutureInnerContent::Unknown { prop, value }) => {
                     JsonOpContent::Future(json::FutureOpWrapper {
                         prop: *prop,
                         value: json::FutureOp::Unknown((**value).clone()),
                     })
                 }
-                #[cfg(feature = "counter")]
-                ContainerType::Counter => {
-                    let InnerContent::Future(f) = content else {
-                        unreachable!()
-                    };
-                    match f {
-                        FutureInnerContent::Counter(x) => {
-                            JsonOpContent::Future(json::FutureOpWrapper {
-                                prop: 0,
-                                value: json::FutureOp::Counter(super::OwnedValue::F64(*x)),
-                            })
-                        }
-                        _ => unreachable!(),
+                // Only the length is kept. The json of the placeholders can't be imported.
+                InnerContent::Future(FutureInnerContent::Placeholder { len, .. }) => {
+                    JsonOpContent::Future(json::FutureOpWrapper {
+                        prop: 0,
+                        value: json::FutureOp::Unknown(super::OwnedValue::I64(*len as i64)),
+                    })
+                }
+                _ => unreachable!(),
+            },
+            #[cfg(feature = "counter")]
+            ContainerType::Counter => {
+                let InnerContent::Future(f) = content else {
+                    unreachable!()
+                };
+                match f {
+                    FutureInnerContent::Counter(x) => {
+                        JsonOpContent::Future(json::FutureOpWrapper {
+                            prop: 0,
+                            value: json::FutureOp::Counter(super::OwnedValue::F64(*x)),
+                        })
                     }
+                    _ => unreachable!(),
                 }
-            };
-            ops.push(json::JsonOp {
-                counter: *counter,
-                container,
-                content: op,
-            });
-        }
-        let c = json::JsonChange {
-            id: register_id(&change.id, peer_register),
-            ops,
-            deps: change
-                .deps
-                .iter()
-                .map(|id| register_id(&id, peer_register))
-                .collect(),
-            lamport: change.lamport,
-            timestamp: change.timestamp,
-            msg: change.message().map(|x| x.to_string()),
+            }
         };
-
-        changes.push(c);
+        ops.push(json::JsonOp {
+            counter: *counter,
+            container,
+            content: op,
+        });
+    }
+    json::JsonChange {
+        id: register_id(&change.id, peer_register),
+        ops,
+        deps: change
+            .deps
+            .iter()
+            .map(|id| register_id(&id, peer_register))
+            .collect(),
+        lamport: change.lamport,
+        timestamp: change.timestamp,
+        msg: change.message().map(|x| x.to_string()),
     }
-    changes
 }
 
 fn decode_changes(json: JsonSchema, arena: &SharedArena) -> LoroResult<Vec<Change>> {
@@ -472,6 +517,7 @@ fn decode_changes(json: JsonSchema, arena: &SharedArena) -> LoroResult<Vec<Chang
             lamport,
             ops,
             commit_msg: msg.map(|x| x.into()),
+            signature: None,
         };
         ans.push(change);
     }
@@ -485,6 +531,11 @@ fn decode_op(op: json::JsonOp, arena: &SharedArena, peers: &[PeerID]) -> LoroRes
         content,
     } = op;
     let container = convert_container_id(container, peers);
+    if is_hidden_container(&container) {
+        return Err(LoroError::DecodeError(
+            "The placeholders of a partial history can't be decoded from json".into(),
+        ));
+    }
     let idx = arena.register_container(&container);
     let content = match container.container_type() {
         ContainerType::Text => match content {
@@ -211,7 +211,9 @@ pub fn decode_import_blob_meta(parsed: ParsedHeaderAndBody) -> LoroResult<Import
             super::EncodeMode::OutdatedRle => super::EncodedBlobMode::OutdatedRle,
             super::EncodeMode::OutdatedSnapshot => super::EncodedBlobMode::OutdatedSnapshot,
             super::EncodeMode::FastSnapshot => super::EncodedBlobMode::Snapshot,
-            super::EncodeMode::FastUpdates => super::EncodedBlobMode::Updates,
+            super::EncodeMode::FastUpdates | super::EncodeMode::StreamedUpdates => {
+                super::EncodedBlobMode::Updates
+            }
             super::EncodeMode::Auto => unreachable!(),
         },
         start_frontiers: frontiers,
@@ -318,6 +320,7 @@ fn decode_changes<'a>(
                 let s = key.to_string();
                 Some(Arc::from(s))
             },
+            signature: None,
             timestamp,
         };
 
@@ -933,7 +936,7 @@ mod encode {
     use crate::encoding::value::FutureValue;
     use either::Either;
     use fxhash::FxHashMap;
-    use loro_common::{ContainerType, HasId, PeerID, ID};
+    use loro_common::{ContainerType, HasId, LoroValue, PeerID, ID};
     use rle::{HasLength, Sliceable};
     use std::{borrow::Cow, ops::Deref};
 
@@ -1160,6 +1163,7 @@ mod encode {
             #[cfg(feature = "counter")]
             FutureInnerContent::Counter(_) => 0,
             FutureInnerContent::Unknown { prop, .. } => *prop,
+            FutureInnerContent::Placeholder { .. } => 0,
         }
     }
 
@@ -1207,6 +1211,7 @@ mod encode {
                 #[cfg(feature = "counter")]
                 FutureInnerContent::Counter(_) => 0,
                 FutureInnerContent::Unknown { .. } => 0,
+                FutureInnerContent::Placeholder { .. } => 0,
             },
         }
     }
@@ -1298,6 +1303,19 @@ mod encode {
                     }
                 }
                 FutureInnerContent::Unknown { prop: _, value } => Value::from_owned(value),
+                FutureInnerContent::Placeholder { children, .. } => {
+                    if children.is_empty() {
+                        Value::Null
+                    } else {
+                        Value::LoroValue(
+                            children
+                                .iter()
+                                .map(|c| LoroValue::Container(c.clone()))
+                                .collect::<Vec<_>>()
+                                .into(),
+                        )
+                    }
+                }
             },
         };
         let (k, _) = value.encode(value_writer, registers);
@@ -1506,6 +1524,12 @@ pub(crate) fn decode_op(
             Value::I64(c) => crate::op::InnerContent::Future(FutureInnerContent::Counter(c as f64)),
             _ => unreachable!(),
         },
+        // The placeholders carry their length, which is only encoded in the change blocks
+        ContainerType::Unknown(kind) if kind == super::partition::HIDDEN_CONTAINER_KIND => {
+            return Err(LoroError::DecodeError(
+                "The placeholders of a partial history can't be decoded from this format".into(),
+            ));
+        }
         // NOTE: The future container type need also try to parse the unknown type
         ContainerType::Unknown(_) => crate::op::InnerContent::Future(FutureInnerContent::Unknown {
             prop,
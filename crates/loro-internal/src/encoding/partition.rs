@@ -0,0 +1,359 @@
+//! Exporting the history of a subset of the containers.
+//!
+//! [`ExportMode::UpdatesForContainers`](super::ExportMode::UpdatesForContainers) exports only the ops of the given containers and
+//! their descendants, so that the private sub-documents are never sent to unauthorized peers.
+//!
+//! Every change is still exported, so the ids, lamports and deps of the history stay intact.
+//! Each op on the other containers is replaced by a placeholder op of the same length on the
+//! [`hidden_container_id`] container. The receiver imports them like any other updates:
+//! nothing is missing, so nothing becomes pending. The placeholders carry no content but the
+//! ids of the visible containers created by the hidden ops, and the hidden container is never
+//! reachable from the root containers.
+//!
+//! Notes:
+//!
+//! - Only the content of the hidden ops is removed. The receiver still learns the number of
+//!   the hidden ops, their lengths, and the peers, lamports, timestamps and commit messages
+//!   of their changes.
+//! - The placeholders take the ids of the hidden ops. If they were passed on as updates, the
+//!   next receiver could never get the hidden ops and would diverge from the full history.
+//!   So a doc refuses to export the updates with placeholders
+//!   ([`LoroEncodeError::PartialHistory`]); it can still export its own changes. The
+//!   snapshots keep the placeholders, and the doc that imports them refuses to export them
+//!   in the same way. The json updates and the outdated formats with placeholders cannot be
+//!   imported.
+//! - The placeholders cannot be replaced by the real ops afterwards. Importing the real ops
+//!   of a placeholder fails with [`LoroError::PlaceholdersNotReplaceable`]. When a peer is
+//!   granted access to the hidden containers later, it should import the full history into
+//!   a new doc, and then import its own changes exported from the partial doc.
+//! - A whitelisted container created inside a hidden container is a child of the hidden
+//!   container for the receiver. So it's regarded as detached. It can still be accessed by its id.
+//! - The changes with hidden ops lose their signatures, because they're no longer the
+//!   changes that were signed.
+//! - The placeholders can only be imported by the versions of Loro that support partial histories.
+use fxhash::{FxHashMap, FxHashSet};
+use loro_common::{
+    ContainerID, ContainerType, Counter, HasCounterSpan, HasIdSpan, IdSpan, LoroEncodeError,
+    LoroError, LoroResult, LoroValue, ID,
+};
+use rle::{HasLength, RleVec};
+
+use super::{value::Value, ExportMode};
+use crate::{
+    arena::SharedArena,
+    change::Change,
+    container::idx::ContainerIdx,
+    op::{FutureInnerContent, InnerContent, Op},
+    OpLog, VersionVector,
+};
+use bytes::Bytes;
+
+/// The container type of the placeholder ops.
+///
+/// It's an unknown type to every version of Loro, so the placeholders are ignored by the state.
+pub(crate) const HIDDEN_CONTAINER_KIND: u8 = 15;
+
+/// The container that the placeholder ops of the hidden containers are applied to
+pub fn hidden_container_id() -> ContainerID {
+    ContainerID::Normal {
+        peer: 0,
+        counter: 0,
+        container_type: ContainerType::Unknown(HIDDEN_CONTAINER_KIND),
+    }
+}
+
+/// Whether the ops on the container are placeholders
+pub(crate) fn is_hidden_container(id: &ContainerID) -> bool {
+    id.container_type() == ContainerType::Unknown(HIDDEN_CONTAINER_KIND)
+}
+
+/// Decode a placeholder op of `len` atoms from the value encoded by [`ContainerPartition::mask`]
+pub(crate) fn decode_placeholder(value: Value<'_>, len: usize) -> LoroResult<InnerContent> {
+    if len == 0 {
+        return Err(LoroError::DecodeDataCorruptionError);
+    }
+
+    let children = match value {
+        Value::Null => Default::default(),
+        Value::LoroValue(LoroValue::List(list)) => list
+            .iter()
+            .map(|v| match v {
+                LoroValue::Container(c) => Ok(c.clone()),
+                _ => Err(LoroError::DecodeDataCorruptionError),
+            })
+            .collect::<LoroResult<_>>()?,
+        _ => return Err(LoroError::DecodeDataCorruptionError),
+    };
+    Ok(InnerContent::Future(FutureInnerContent::Placeholder {
+        len,
+        children,
+    }))
+}
+
+/// The containers visible in a partial history
+struct ContainerPartition<'a> {
+    /// The arena with the hidden container registered. It's a fork of the arena of the doc,
+    /// so that the export has no side effect on the doc.
+    arena: &'a SharedArena,
+    whitelist: FxHashSet<ContainerIdx>,
+    /// Cache of whether a container is in the whitelist or a descendant of it
+    visible: FxHashMap<ContainerIdx, bool>,
+    hidden: ContainerIdx,
+}
+
+impl<'a> ContainerPartition<'a> {
+    fn new(arena: &'a SharedArena, containers: &[ContainerID]) -> Self {
+        let hidden = arena.register_container(&hidden_container_id());
+        arena.set_parent(hidden, None);
+        Self {
+            arena,
+            // The containers not in the arena have no ops to export
+            whitelist: containers
+                .iter()
+                .filter_map(|c| arena.id_to_idx(c))
+                .collect(),
+            visible: Default::default(),
+            hidden,
+        }
+    }
+
+    fn is_visible(&mut self, idx: ContainerIdx) -> bool {
+        if let Some(&v) = self.visible.get(&idx) {
+            return v;
+        }
+
+        let mut path = vec![idx];
+        let mut ans = false;
+        let mut current = idx;
+        loop {
+            if self.whitelist.contains(&current) {
+                ans = true;
+                break;
+            }
+
+            if let Some(&v) = self.visible.get(&current) {
+                ans = v;
+                break;
+            }
+
+            match self.arena.get_parent(current) {
+                Some(parent) => {
+                    path.push(parent);
+                    current = parent;
+                }
+                None => break,
+            }
+        }
+
+        for c in path {
+            self.visible.insert(c, ans);
+        }
+        ans
+    }
+
+    /// Replace the ops on the hidden containers with placeholders
+    fn mask(&mut self, mut change: Change) -> Change {
+        if change.ops.iter().all(|op| self.is_visible(op.container)) {
+            return change;
+        }
+
+        let arena = self.arena;
+        let mut ops = RleVec::new();
+        for op in change.ops.iter() {
+            if self.is_visible(op.container) {
+                ops.push(op.clone());
+                continue;
+            }
+
+            // The receiver needs the parents of the visible containers created by the hidden op
+            let mut created = Vec::new();
+            op.content
+                .visit_created_children(arena, &mut |c| created.push(c.clone()));
+            let children = created
+                .into_iter()
+                .filter(|c| arena.id_to_idx(c).is_some_and(|idx| self.is_visible(idx)))
+                .collect();
+            // The adjacent placeholders are merged by the RleVec
+            ops.push(Op {
+                counter: op.counter,
+                container: self.hidden,
+                content: InnerContent::Future(FutureInnerContent::Placeholder {
+                    len: op.atom_len(),
+                    children,
+                }),
+            });
+        }
+
+        change.ops = ops;
+        change.signature = None;
+        change
+    }
+}
+
+impl OpLog {
+    /// Pass the encoded bytes of each block of the changes since `vv` to `f`, with only
+    /// the ops of the given containers and their descendants.
+    pub(crate) fn for_each_block_bytes_for_containers<E>(
+        &self,
+        vv: &VersionVector,
+        containers: &[ContainerID],
+        f: impl FnMut(Bytes) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let arena = self.arena.fork();
+        let mut partition = ContainerPartition::new(&arena, containers);
+        self.change_store().for_each_mapped_block_bytes_from(
+            &arena,
+            vv,
+            self.shallow_since_vv(),
+            self.vv(),
+            |c| partition.mask(c),
+            f,
+        )
+    }
+
+    /// Record the placeholders in the new change, so that they're never exported again
+    pub(crate) fn record_placeholders(&mut self, change: &Change) {
+        if change.ops.iter().any(is_placeholder) {
+            self.placeholder_end
+                .extend_to_include_end_id(change.id_end());
+        }
+    }
+
+    /// Return an error if the export contains placeholders
+    pub(crate) fn check_placeholders_not_exported(
+        &self,
+        mode: &ExportMode,
+    ) -> Result<(), LoroEncodeError> {
+        if self.placeholder_end.is_empty() {
+            return Ok(());
+        }
+
+        let has_placeholders = match mode {
+            ExportMode::Updates { from } | ExportMode::UpdatesForContainers { from, .. } => self
+                .placeholder_end
+                .iter()
+                .any(|(peer, end)| from.get(peer).copied().unwrap_or(0) < *end),
+            ExportMode::UpdatesInRange { spans } => spans.iter().any(|span| {
+                self.placeholder_end
+                    .get(&span.peer)
+                    .is_some_and(|end| span.counter.min() < *end)
+            }),
+            // The snapshots keep the placeholders, and the receiver records them again
+            ExportMode::Snapshot
+            | ExportMode::ShallowSnapshot(_)
+            | ExportMode::StateOnly(_)
+            | ExportMode::SnapshotAt { .. } => false,
+        };
+        if has_placeholders {
+            return Err(LoroEncodeError::PartialHistory);
+        }
+
+        Ok(())
+    }
+
+    /// Return an error if the doc has placeholders. It's for the outdated snapshot format,
+    /// which cannot record them.
+    pub(crate) fn check_no_placeholders(&self) -> Result<(), LoroEncodeError> {
+        if self.placeholder_end.is_empty() {
+            Ok(())
+        } else {
+            Err(LoroEncodeError::PartialHistory)
+        }
+    }
+
+    /// Return an error if the imported changes carry the real ops of the local placeholders.
+    ///
+    /// The placeholders share the ids with the real ops, so the real ops would be regarded
+    /// as known and dropped silently.
+    pub(crate) fn check_placeholders_not_replaced(&self, changes: &[Change]) -> LoroResult<()> {
+        if self.placeholder_end.is_empty() {
+            return Ok(());
+        }
+
+        for change in changes {
+            let peer = change.id.peer;
+            let Some(&placeholder_end) = self.placeholder_end.get(&peer) else {
+                continue;
+            };
+            // The ops before the shallow root are not in the change store anymore
+            let start = change
+                .id
+                .counter
+                .max(self.shallow_since_vv().get(&peer).copied().unwrap_or(0));
+            let end = placeholder_end
+                .min(self.vv().get(&peer).copied().unwrap_or(0))
+                .min(change.ctr_end());
+            if start >= end {
+                continue;
+            }
+
+            let real_ops: Vec<(Counter, Counter)> = change
+                .ops
+                .iter()
+                .filter(|op| !is_placeholder(op))
+                .map(|op| (op.counter, op.ctr_end()))
+                .filter(|&(op_start, op_end)| op_start < end && op_end > start)
+                .collect();
+            if real_ops.is_empty() {
+                continue;
+            }
+
+            let span = IdSpan::new(peer, start, end);
+            for local in self.change_store().iter_changes(span) {
+                for op in local.ops.iter().filter(|op| is_placeholder(op)) {
+                    if let Some(&(real_start, _)) =
+                        real_ops.iter().find(|&&(real_start, real_end)| {
+                            real_start < op.ctr_end() && op.counter < real_end
+                        })
+                    {
+                        return Err(LoroError::PlaceholdersNotReplaceable {
+                            id: ID::new(peer, real_start.max(op.counter)),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn is_placeholder(op: &Op) -> bool {
+    matches!(
+        op.content,
+        InnerContent::Future(FutureInnerContent::Placeholder { .. })
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{loro::ExportMode, LoroDoc};
+    use loro_common::ID;
+
+    #[test]
+    fn placeholders_are_coalesced_without_changing_the_doc() {
+        let doc = LoroDoc::new_auto_commit();
+        doc.set_peer_id(1).unwrap();
+        let private = doc.get_text("private");
+        private.insert(0, "secret").unwrap();
+        private.delete(0, 2).unwrap();
+        doc.get_text("public").insert(0, "a").unwrap();
+        doc.commit_then_renew();
+
+        let bytes = doc
+            .export(ExportMode::updates_for_containers(
+                &Default::default(),
+                vec![ContainerID::new_root("public", ContainerType::Text)],
+            ))
+            .unwrap();
+        assert!(doc.arena.id_to_idx(&hidden_container_id()).is_none());
+
+        let receiver = LoroDoc::new();
+        receiver.import(&bytes).unwrap();
+        let oplog = receiver.oplog().try_lock().unwrap();
+        let change = oplog.get_change_at(ID::new(1, 0)).unwrap();
+        let lens: Vec<_> = change.ops.iter().map(|op| op.atom_len()).collect();
+        assert_eq!(lens, vec![8, 1]);
+    }
+}
@@ -0,0 +1,427 @@
+//! Streaming import and export over [`std::io::Read`] and [`std::io::Write`].
+//!
+//! The updates are written in a streamed format, where each change block carries its own
+//! checksum:
+//!
+//! ```text
+//! header | block* | end
+//!
+//! header: magic bytes | xxh32 of the mode | mode (StreamedUpdates)
+//! block:  leb128 length (> 0) | bytes of the change block | xxh32 of the bytes (u32 LE)
+//! end:    leb128 length (= 0) | xxh32 of all the block checksums (u32 LE)
+//! ```
+//!
+//! On export, each block is encoded, written and dropped before the next one is encoded.
+//! On import, each block is verified and decoded as soon as it's read, so only the bytes
+//! of a single block are kept in memory besides the decoded changes. The changes are
+//! imported after the end mark is verified, so nothing is imported if any block is
+//! corrupted. [`LoroDoc::import`] accepts this format too.
+//!
+//! The snapshots have the same layout as the ones produced by [`LoroDoc::export`]. Their
+//! sections (the oplog, the state and the shallow root state) are encoded as a whole and
+//! the checksum covers all of them, so streaming a snapshot only saves the copies between
+//! the sections and the io buffers. The peak memory is still proportional to the size of
+//! the snapshot.
+//!
+//! The update blobs produced by [`LoroDoc::export`], the outdated formats and the
+//! encrypted blobs can be read too, but their blocks are buffered until the checksum of
+//! the whole body is verified.
+use std::io::{ErrorKind, Read, Write};
+
+use bytes::Bytes;
+use loro_common::{LoroEncodeError, LoroError, LoroResult};
+use xxhash_rust::xxh32::{xxh32, Xxh32};
+
+use super::{
+    fast_snapshot::{self, Snapshot, EMPTY_MARK},
+    shallow_snapshot, EncodeMode, ImportStatus, ENCRYPTED_MODE_BYTES, MAGIC_BYTES, MIN_HEADER_SIZE,
+    XXH_SEED,
+};
+use crate::{
+    version::{Frontiers, VersionRange},
+    LoroDoc, OpLog, VersionVector,
+};
+
+pub(crate) fn export_fast_snapshot<W: Write>(
+    doc: &LoroDoc,
+    w: &mut W,
+) -> Result<(), LoroEncodeError> {
+    let snapshot = fast_snapshot::encode_snapshot_inner(doc);
+    write_chunks(
+        EncodeMode::FastSnapshot,
+        &fast_snapshot::_encode_snapshot_chunks(snapshot),
+        w,
+    )
+}
+
+pub(crate) fn export_shallow_snapshot<W: Write>(
+    doc: &LoroDoc,
+    f: &Frontiers,
+    w: &mut W,
+) -> Result<(), LoroEncodeError> {
+    super::check_target_version_reachable(doc, f)?;
+    let (snapshot, _) = shallow_snapshot::export_shallow_snapshot_inner(doc, f)?;
+    write_chunks(
+        EncodeMode::FastSnapshot,
+        &fast_snapshot::_encode_snapshot_chunks(snapshot),
+        w,
+    )
+}
+
+pub(crate) fn export_fast_updates<W: Write>(
+    doc: &LoroDoc,
+    vv: &VersionVector,
+    w: &mut W,
+) -> Result<(), LoroEncodeError> {
+    let oplog = doc.oplog().try_lock().unwrap();
+    let mut writer = BlockWriter::new(w).map_err(write_error)?;
+    oplog
+        .for_each_block_bytes_from(vv, |block| writer.write_block(&block))
+        .map_err(write_error)?;
+    writer.finish().map_err(write_error)
+}
+
+pub(crate) fn export_fast_updates_in_range<W: Write>(
+    oplog: &OpLog,
+    spans: &[loro_common::IdSpan],
+    w: &mut W,
+) -> Result<(), LoroEncodeError> {
+    let mut writer = BlockWriter::new(w).map_err(write_error)?;
+    oplog
+        .for_each_block_bytes_in_range(spans, |block| writer.write_block(&block))
+        .map_err(write_error)?;
+    writer.finish().map_err(write_error)
+}
+
+pub(crate) fn export_fast_updates_for_containers<W: Write>(
+    oplog: &OpLog,
+    vv: &VersionVector,
+    containers: &[loro_common::ContainerID],
+    w: &mut W,
+) -> Result<(), LoroEncodeError> {
+    let mut writer = BlockWriter::new(w).map_err(write_error)?;
+    oplog
+        .for_each_block_bytes_for_containers(vv, containers, |block| writer.write_block(&block))
+        .map_err(write_error)?;
+    writer.finish().map_err(write_error)
+}
+
+/// Write an already encoded blob.
+///
+/// It's used by the export modes that need the whole body in memory anyway.
+pub(crate) fn write_blob<W: Write>(blob: &[u8], w: &mut W) -> Result<(), LoroEncodeError> {
+    w.write_all(blob)
+        .and_then(|_| w.flush())
+        .map_err(write_error)
+}
+
+fn write_chunks<W: Write>(
+    mode: EncodeMode,
+    chunks: &[Bytes],
+    w: &mut W,
+) -> Result<(), LoroEncodeError> {
+    let mode_bytes = mode.to_bytes();
+    let mut hasher = Xxh32::new(XXH_SEED);
+    hasher.update(&mode_bytes);
+    for chunk in chunks {
+        hasher.update(chunk);
+    }
+
+    let mut checksum = [0; 16];
+    checksum[12..16].copy_from_slice(&hasher.digest().to_le_bytes());
+    let mut write = || -> std::io::Result<()> {
+        w.write_all(&MAGIC_BYTES)?;
+        w.write_all(&checksum)?;
+        w.write_all(&mode_bytes)?;
+        for chunk in chunks {
+            w.write_all(chunk)?;
+        }
+        w.flush()
+    };
+
+    write().map_err(write_error)
+}
+
+fn write_error(e: std::io::Error) -> LoroEncodeError {
+    LoroEncodeError::WriteError(e.to_string())
+}
+
+/// The checksum in the header of a streamed update blob. It only covers the mode.
+pub(crate) fn streamed_updates_header_checksum() -> u32 {
+    xxh32(&EncodeMode::StreamedUpdates.to_bytes(), XXH_SEED)
+}
+
+/// Write the blocks of a streamed update blob
+struct BlockWriter<'a, W> {
+    w: &'a mut W,
+    /// The hasher of the checksums of the written blocks
+    checksums: Xxh32,
+}
+
+impl<'a, W: Write> BlockWriter<'a, W> {
+    fn new(w: &'a mut W) -> std::io::Result<Self> {
+        let mut checksum = [0; 16];
+        checksum[12..16].copy_from_slice(&streamed_updates_header_checksum().to_le_bytes());
+        w.write_all(&MAGIC_BYTES)?;
+        w.write_all(&checksum)?;
+        w.write_all(&EncodeMode::StreamedUpdates.to_bytes())?;
+        Ok(Self {
+            w,
+            checksums: Xxh32::new(XXH_SEED),
+        })
+    }
+
+    fn write_block(&mut self, block: &[u8]) -> std::io::Result<()> {
+        debug_assert!(!block.is_empty());
+        let checksum = xxh32(block, XXH_SEED).to_le_bytes();
+        leb128::write::unsigned(self.w, block.len() as u64)?;
+        self.w.write_all(block)?;
+        self.w.write_all(&checksum)?;
+        self.checksums.update(&checksum);
+        Ok(())
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        leb128::write::unsigned(self.w, 0)?;
+        self.w.write_all(&self.checksums.digest().to_le_bytes())?;
+        self.w.flush()
+    }
+}
+
+/// Read and verify the blocks of a streamed update blob, one block at a time.
+///
+/// The iterator stops after the first error.
+pub(crate) struct BlockReader<R> {
+    inner: R,
+    /// The hasher of the checksums of the blocks that have been read
+    checksums: Xxh32,
+    done: bool,
+}
+
+impl<R: Read> BlockReader<R> {
+    /// Create a reader of the body of a streamed update blob. The header is already read.
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            checksums: Xxh32::new(XXH_SEED),
+            done: false,
+        }
+    }
+
+    /// Return `None` after the end mark is read and verified
+    fn next_block(&mut self) -> LoroResult<Option<Bytes>> {
+        let len = read_block_len(&mut self.inner)?.ok_or_else(unexpected_end)?;
+        if len == 0 {
+            if read_u32(&mut self.inner)? != self.checksums.digest() {
+                return Err(LoroError::DecodeChecksumMismatchError);
+            }
+
+            let mut rest = [0; 1];
+            if self.inner.read(&mut rest).map_err(read_error)? != 0 {
+                return Err(LoroError::DecodeError(
+                    "Unexpected data after the end of the import data".into(),
+                ));
+            }
+
+            return Ok(None);
+        }
+
+        let block = read_chunk(&mut self.inner, len)?;
+        let checksum = read_u32(&mut self.inner)?;
+        if xxh32(&block, XXH_SEED) != checksum {
+            return Err(LoroError::DecodeChecksumMismatchError);
+        }
+
+        self.checksums.update(&checksum.to_le_bytes());
+        Ok(Some(block))
+    }
+}
+
+impl<R: Read> Iterator for BlockReader<R> {
+    type Item = LoroResult<Bytes>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let ans = self.next_block().transpose();
+        self.done = !matches!(ans, Some(Ok(_)));
+        ans
+    }
+}
+
+/// A blob read by [`read_blob`]
+pub(crate) enum StreamedBlob<R> {
+    /// The blob is in an outdated format or encrypted. It contains the header.
+    Whole(Vec<u8>),
+    /// The bytes of each change block in an update blob exported by [`LoroDoc::export`].
+    /// Its checksum has been verified.
+    Updates(Vec<Bytes>),
+    /// The blocks of a streamed update blob, which are verified while they're decoded
+    StreamedUpdates(BlockReader<R>),
+    /// A snapshot. Its checksum has been verified.
+    Snapshot(Snapshot),
+}
+
+/// A reader that feeds every byte it reads into the checksum hasher
+struct ChecksumReader<R> {
+    inner: R,
+    hasher: Xxh32,
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+pub(crate) fn read_blob<R: Read>(mut r: R) -> LoroResult<StreamedBlob<R>> {
+    let mut header = [0; MIN_HEADER_SIZE];
+    r.read_exact(&mut header)
+        .map_err(|_| LoroError::DecodeError("Invalid import data".into()))?;
+    if header[..4] != MAGIC_BYTES {
+        return Err(LoroError::DecodeError("Invalid magic bytes".into()));
+    }
+
+    let checksum: [u8; 16] = header[4..20].try_into().unwrap();
+    let expected = u32::from_le_bytes(checksum[12..16].try_into().unwrap());
+    // An encrypted blob is read as a whole, so that the import can report the key
+    // needed to decrypt it
+    if header[20..22] == ENCRYPTED_MODE_BYTES {
+        return read_whole(header, r);
+    }
+
+    let mode: EncodeMode = [header[20], header[21]].try_into()?;
+    match mode {
+        EncodeMode::StreamedUpdates => {
+            if expected != streamed_updates_header_checksum() {
+                return Err(LoroError::DecodeChecksumMismatchError);
+            }
+
+            return Ok(StreamedBlob::StreamedUpdates(BlockReader::new(r)));
+        }
+        EncodeMode::FastSnapshot | EncodeMode::FastUpdates => {}
+        _ => return read_whole(header, r),
+    }
+
+    let mut hasher = Xxh32::new(XXH_SEED);
+    hasher.update(&header[20..]);
+    let mut r = ChecksumReader { inner: r, hasher };
+    let blob = if mode == EncodeMode::FastUpdates {
+        let mut blocks = Vec::new();
+        while let Some(len) = read_block_len(&mut r)? {
+            blocks.push(read_chunk(&mut r, len)?);
+        }
+
+        StreamedBlob::Updates(blocks)
+    } else {
+        let oplog_bytes = read_section(&mut r)?;
+        let state_bytes = read_section(&mut r)?;
+        let shallow_root_state_bytes = read_section(&mut r)?;
+        // The checksum covers everything after the header
+        std::io::copy(&mut r, &mut std::io::sink()).map_err(read_error)?;
+        StreamedBlob::Snapshot(Snapshot {
+            oplog_bytes,
+            state_bytes: (state_bytes != EMPTY_MARK).then_some(state_bytes),
+            shallow_root_state_bytes,
+        })
+    };
+
+    if r.hasher.digest() != expected {
+        return Err(LoroError::DecodeChecksumMismatchError);
+    }
+
+    Ok(blob)
+}
+
+fn read_whole<R: Read>(header: [u8; MIN_HEADER_SIZE], mut r: R) -> LoroResult<StreamedBlob<R>> {
+    let mut bytes = header.to_vec();
+    r.read_to_end(&mut bytes).map_err(read_error)?;
+    Ok(StreamedBlob::Whole(bytes))
+}
+
+/// Read the leb128 length prefix of the next block.
+///
+/// Return `None` if the reader reaches the end.
+fn read_block_len<R: Read>(r: &mut R) -> LoroResult<Option<usize>> {
+    let mut first = [0; 1];
+    loop {
+        match r.read(&mut first) {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(read_error(e)),
+        }
+    }
+
+    let mut reader = (&first[..]).chain(r);
+    let len = leb128::read::unsigned(&mut reader)
+        .map_err(|_| LoroError::DecodeError("Invalid block length".into()))?;
+    Ok(Some(len as usize))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> LoroResult<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes).map_err(|_| unexpected_end())?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_section<R: Read>(r: &mut R) -> LoroResult<Bytes> {
+    let len = read_u32(r)?;
+    read_chunk(r, len as usize)
+}
+
+fn read_chunk<R: Read>(r: &mut R, len: usize) -> LoroResult<Bytes> {
+    // Don't trust `len` for the allocation, the data may be corrupted
+    let mut buf = Vec::new();
+    r.take(len as u64)
+        .read_to_end(&mut buf)
+        .map_err(read_error)?;
+    if buf.len() != len {
+        return Err(unexpected_end());
+    }
+
+    Ok(buf.into())
+}
+
+fn unexpected_end() -> LoroError {
+    LoroError::DecodeError("Unexpected end of the import data".into())
+}
+
+fn read_error(e: std::io::Error) -> LoroError {
+    LoroError::DecodeError(format!("Failed to read the import data: {}", e).into_boxed_str())
+}
+
+impl<R: Read> StreamedBlob<R> {
+    /// Decode the changes in the blob and import them into the oplog.
+    ///
+    /// Nothing is imported if the blob is invalid.
+    pub(crate) fn decode_oplog(self, oplog: &mut OpLog) -> Result<ImportStatus, LoroError> {
+        let changes = match self {
+            StreamedBlob::Whole(bytes) => {
+                return oplog.decode(super::parse_header_and_body(&bytes, false)?)
+            }
+            StreamedBlob::Updates(blocks) => {
+                fast_snapshot::decode_update_blocks(oplog, blocks.into_iter().map(Ok))?
+            }
+            StreamedBlob::StreamedUpdates(blocks) => {
+                fast_snapshot::decode_update_blocks(oplog, blocks)?
+            }
+            StreamedBlob::Snapshot(s) => fast_snapshot::decode_oplog_bytes(oplog, s.oplog_bytes)?,
+        };
+
+        super::import_decoded_changes(oplog, changes)
+    }
+}
+
+/// Init an empty doc with the snapshot
+pub(crate) fn decode_snapshot(doc: &LoroDoc, snapshot: Snapshot) -> LoroResult<ImportStatus> {
+    fast_snapshot::decode_snapshot_inner(snapshot, doc)?;
+    Ok(ImportStatus {
+        success: VersionRange::from_vv(&doc.oplog_vv()),
+        pending: None,
+    })
+}
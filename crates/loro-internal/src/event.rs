@@ -93,6 +93,36 @@ pub struct DocDiff {
     pub diff: Vec<ContainerDiff>,
 }
 
+impl DocDiff {
+    /// Compose a later diff into this one.
+    ///
+    /// The diffs of the same container are composed like [`DiffBatch::compose`](crate::undo::DiffBatch::compose),
+    /// and `to` moves to the end of the later diff. The trigger kind and the origin of the
+    /// later diff are kept.
+    pub fn compose(&mut self, other: DocDiff) {
+        self.to = other.to;
+        self.by = other.by;
+        self.origin = other.origin;
+        for diff in other.diff {
+            match self.diff.iter_mut().find(|x| x.id == diff.id) {
+                Some(existing) => {
+                    if !matches!(existing.diff, Diff::Unknown)
+                        && !matches!(diff.diff, Diff::Unknown)
+                    {
+                        existing.diff.compose_ref(&diff.diff);
+                    }
+                    existing.path = diff.path;
+                    existing.is_unknown = diff.is_unknown;
+                }
+                None => self.diff.push(diff),
+            }
+        }
+
+        // The parents should come before their children
+        self.diff.sort_by_key(|x| x.path.len());
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct InternalContainerDiff {
     pub(crate) idx: ContainerIdx,
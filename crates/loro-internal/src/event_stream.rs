@@ -0,0 +1,323 @@
+//! Async streams of the doc events and the local updates.
+//!
+//! The callbacks passed to [`LoroDoc::subscribe_root`], [`LoroDoc::subscribe`] and
+//! [`LoroDoc::subscribe_local_update`] are invoked synchronously inside commit and import.
+//! The streams here buffer the owned events instead, so an async task can consume them
+//! at its own pace.
+//!
+//! The buffers are bounded. When a buffer is full, the [`BackpressurePolicy`] decides whether
+//! the oldest item is dropped or the new item is merged into the newest buffered one.
+//!
+//! A stream ends after the doc is dropped. Dropping the stream unsubscribes it.
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use futures_core::Stream;
+use fxhash::FxHashMap;
+use loro_common::{ContainerID, Counter, IdSpan, PeerID};
+
+use crate::{encoding::export_fast_updates_in_range, DiffEvent, DocDiff, LoroDoc, Subscription};
+
+/// What to do with a new item when the buffer of the stream is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackpressurePolicy {
+    /// Drop the oldest buffered item.
+    ///
+    /// The number of the dropped items can be read from the stream.
+    DropOldest,
+    /// Merge the new item into the newest buffered item.
+    ///
+    /// The events are composed like [`DiffBatch::compose`](crate::undo::DiffBatch::compose),
+    /// and the local updates are re-exported as one blob. Nothing is lost, but the consumer
+    /// receives fewer and larger items.
+    #[default]
+    Coalesce,
+}
+
+/// The options of the event streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamOptions {
+    /// The max number of the buffered items. Zero is treated as one.
+    pub capacity: usize,
+    pub policy: BackpressurePolicy,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 64,
+            policy: BackpressurePolicy::Coalesce,
+        }
+    }
+}
+
+/// An owned [`DiffEvent`](crate::DiffEvent) yielded by [`EventStream`]
+#[derive(Debug, Clone)]
+pub struct OwnedDiffEvent {
+    /// The receiver of the event.
+    pub current_target: Option<ContainerID>,
+    /// The metadata of the event and the diffs received by the current target.
+    pub event: DocDiff,
+}
+
+impl OwnedDiffEvent {
+    /// Compose a later event into this one.
+    ///
+    /// The diffs of the same container are composed and `to` moves to the end of the later
+    /// event. The trigger kind and the origin of the later event are kept.
+    pub fn compose(&mut self, other: OwnedDiffEvent) {
+        self.event.compose(other.event);
+    }
+}
+
+struct Buffer<T> {
+    items: VecDeque<T>,
+    waker: Option<Waker>,
+    dropped: usize,
+    closed: bool,
+}
+
+struct Shared<T> {
+    buffer: Mutex<Buffer<T>>,
+    options: StreamOptions,
+}
+
+impl<T> Shared<T> {
+    fn new(options: StreamOptions) -> Arc<Self> {
+        Arc::new(Self {
+            buffer: Mutex::new(Buffer {
+                items: VecDeque::new(),
+                waker: None,
+                dropped: 0,
+                closed: false,
+            }),
+            options,
+        })
+    }
+
+    /// Push the item. When the buffer is full and the policy is [`BackpressurePolicy::Coalesce`],
+    /// `coalesce` merges the item into the newest buffered one. It returns the item back if
+    /// they cannot be merged, then the oldest item is dropped instead.
+    fn push(&self, item: T, coalesce: impl FnOnce(&mut T, T) -> Result<(), T>) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.items.len() < self.options.capacity.max(1) {
+            buffer.items.push_back(item);
+        } else {
+            let rest = match self.options.policy {
+                BackpressurePolicy::DropOldest => Some(item),
+                BackpressurePolicy::Coalesce => {
+                    coalesce(buffer.items.back_mut().unwrap(), item).err()
+                }
+            };
+            if let Some(item) = rest {
+                buffer.items.pop_front();
+                buffer.dropped += 1;
+                buffer.items.push_back(item);
+            }
+        }
+
+        let waker = buffer.waker.take();
+        drop(buffer);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    fn close(&self) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.closed = true;
+        let waker = buffer.waker.take();
+        drop(buffer);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut buffer = self.buffer.lock().unwrap();
+        if let Some(item) = buffer.items.pop_front() {
+            return Poll::Ready(Some(item));
+        }
+
+        if buffer.closed {
+            return Poll::Ready(None);
+        }
+
+        buffer.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn dropped_count(&self) -> usize {
+        self.buffer.lock().unwrap().dropped
+    }
+}
+
+/// The sending half owned by the subscriber.
+///
+/// The subscriber is dropped with the doc, which ends the stream.
+struct Sender<T>(Arc<Shared<T>>);
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// A [`Stream`] of the events of a doc or a container
+pub struct EventStream {
+    shared: Arc<Shared<OwnedDiffEvent>>,
+    _sub: Subscription,
+}
+
+impl EventStream {
+    /// The number of the events dropped because the buffer was full
+    pub fn dropped_count(&self) -> usize {
+        self.shared.dropped_count()
+    }
+}
+
+impl Stream for EventStream {
+    type Item = OwnedDiffEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.shared.poll_next(cx)
+    }
+}
+
+impl std::fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventStream").finish()
+    }
+}
+
+/// A [`Stream`] of the updates exported by the local commits
+pub struct LocalUpdateStream {
+    shared: Arc<Shared<Vec<u8>>>,
+    _sub: Subscription,
+}
+
+impl LocalUpdateStream {
+    /// The number of the updates dropped because the buffer was full
+    pub fn dropped_count(&self) -> usize {
+        self.shared.dropped_count()
+    }
+}
+
+impl Stream for LocalUpdateStream {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.shared.poll_next(cx)
+    }
+}
+
+impl std::fmt::Debug for LocalUpdateStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalUpdateStream").finish()
+    }
+}
+
+/// Get the op spans included in the updates blobs
+fn updates_spans(blobs: &[&[u8]]) -> Option<Vec<IdSpan>> {
+    let mut spans: FxHashMap<PeerID, (Counter, Counter)> = FxHashMap::default();
+    for blob in blobs {
+        let meta = LoroDoc::decode_import_blob_meta(blob, false).ok()?;
+        for (peer, &end) in meta.partial_end_vv.iter() {
+            let start = meta.partial_start_vv.get(peer).copied().unwrap_or(0);
+            let span = spans.entry(*peer).or_insert((start, end));
+            span.0 = span.0.min(start);
+            span.1 = span.1.max(end);
+        }
+    }
+
+    Some(
+        spans
+            .into_iter()
+            .map(|(peer, (start, end))| IdSpan::new(peer, start, end))
+            .collect(),
+    )
+}
+
+impl LoroDoc {
+    /// Subscribe to the events of all the containers as a [`Stream`].
+    ///
+    /// It's the stream version of [`LoroDoc::subscribe_root`].
+    pub fn subscribe_root_stream(&self, options: StreamOptions) -> EventStream {
+        let shared = Shared::new(options);
+        let sender = Sender(shared.clone());
+        let sub = self.subscribe_root(Arc::new(move |e| {
+            sender.0.push(to_owned_event(e), coalesce_events);
+        }));
+        EventStream { shared, _sub: sub }
+    }
+
+    /// Subscribe to the events of a container and its descendants as a [`Stream`].
+    ///
+    /// It's the stream version of [`LoroDoc::subscribe`].
+    pub fn subscribe_stream(
+        &self,
+        container_id: &ContainerID,
+        options: StreamOptions,
+    ) -> EventStream {
+        let shared = Shared::new(options);
+        let sender = Sender(shared.clone());
+        let sub = self.subscribe(
+            container_id,
+            Arc::new(move |e| {
+                sender.0.push(to_owned_event(e), coalesce_events);
+            }),
+        );
+        EventStream { shared, _sub: sub }
+    }
+
+    /// Subscribe to the updates of the local commits as a [`Stream`].
+    ///
+    /// It's the stream version of [`LoroDoc::subscribe_local_update`]. Each item can be
+    /// imported by the other peers. The coalesced updates are re-exported from the doc, so
+    /// they remain importable.
+    pub fn subscribe_local_update_stream(&self, options: StreamOptions) -> LocalUpdateStream {
+        let shared = Shared::new(options);
+        let sender = Sender(shared.clone());
+        let oplog = Arc::downgrade(&self.oplog);
+        let sub = self.subscribe_local_update(Box::new(move |bytes| {
+            sender.0.push(bytes.clone(), |last, bytes| {
+                let Some(oplog) = oplog.upgrade() else {
+                    return Err(bytes);
+                };
+                let Some(spans) = updates_spans(&[&last[..], &bytes[..]]) else {
+                    return Err(bytes);
+                };
+                // The oplog is not locked when the local updates are emitted
+                let Ok(oplog) = oplog.try_lock() else {
+                    return Err(bytes);
+                };
+                *last = export_fast_updates_in_range(&oplog, &spans);
+                Ok(())
+            });
+            true
+        }));
+        LocalUpdateStream { shared, _sub: sub }
+    }
+}
+
+fn to_owned_event(e: DiffEvent<'_>) -> OwnedDiffEvent {
+    OwnedDiffEvent {
+        current_target: e.current_target,
+        event: DocDiff {
+            from: e.event_meta.from.clone(),
+            to: e.event_meta.to.clone(),
+            origin: e.event_meta.origin.clone(),
+            by: e.event_meta.by,
+            diff: e.events.iter().map(|&x| x.clone()).collect(),
+        },
+    }
+}
+
+fn coalesce_events(last: &mut OwnedDiffEvent, event: OwnedDiffEvent) -> Result<(), OwnedDiffEvent> {
+    last.compose(event);
+    Ok(())
+}
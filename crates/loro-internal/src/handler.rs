@@ -4,16 +4,24 @@ use crate::{
     container::{
         idx::ContainerIdx,
         list::list_op::{DeleteSpan, DeleteSpanWithId, ListOp},
-        richtext::{richtext_state::PosType, RichtextState, StyleOp, TextStyleInfoFlag},
+        richtext::{
+            config::{annotation_key, parse_annotation_key},
+            richtext_state::{PosType, RichtextStateChunk},
+            ExpandType, RichtextState, StyleOp, TextStyleInfoFlag,
+        },
     },
-    cursor::{Cursor, Side},
+    cursor::{Cursor, Side, TextRange},
     delta::{DeltaItem, Meta, StyleMeta, TreeExternalDiff},
     diff::{diff, diff_impl::UpdateTimeoutError, OperateProxy},
     event::{Diff, TextDiffItem},
     op::ListSlice,
     state::{IndexType, State, TreeParentId},
     txn::EventHint,
-    utils::{string_slice::StringSlice, utf16::count_utf16_len},
+    utils::{
+        grapheme::{count_graphemes, grapheme_range_to_utf8, utf8_to_grapheme_index},
+        string_slice::StringSlice,
+        utf16::count_utf16_len,
+    },
 };
 use append_only_bytes::BytesSlice;
 use enum_as_inner::EnumAsInner;
@@ -29,14 +37,20 @@ use std::{
     cmp::Reverse,
     collections::BinaryHeap,
     fmt::Debug,
-    ops::Deref,
+    ops::{Deref, Range},
     sync::{Arc, Mutex, Weak},
 };
 use tracing::{error, info, instrument, trace};
 
-pub use crate::diff::diff_impl::UpdateOptions;
+pub use crate::container::richtext::config::{EMBED_CHAR, EMBED_KEY};
+pub use crate::diff::diff_impl::{UpdateGranularity, UpdateOptions};
+pub use text_html::HtmlMapping;
+pub use text_search::{TextMatch, TextSearchOptions};
 pub use tree::TreeHandler;
 mod movable_list_apply_delta;
+mod text_block;
+mod text_html;
+mod text_search;
 mod tree;
 
 const INSERT_CONTAINER_VALUE_ARG_ERROR: &str =
@@ -382,7 +396,123 @@ pub enum TextDelta {
     },
 }
 
+/// The value of a style key across a range of text. See [`TextHandler::get_styles_in`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StyleRangeValue {
+    /// All the text in the range has the style with the value
+    Uniform(LoroValue),
+    /// The style only covers a part of the range, or has different values in it
+    Mixed,
+}
+
+/// The unit of a position in the text. See [`TextHandler::convert_pos`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextPosType {
+    /// The index of a Unicode code point
+    Unicode,
+    /// The index of a UTF-16 code unit
+    Utf16,
+    /// The index of a UTF-8 byte
+    Utf8,
+    /// The index of an extended grapheme cluster, i.e. a user-perceived character
+    Grapheme,
+}
+
+impl TextPosType {
+    fn to_internal(self) -> Option<PosType> {
+        match self {
+            TextPosType::Unicode => Some(PosType::Unicode),
+            TextPosType::Utf16 => Some(PosType::Utf16),
+            TextPosType::Utf8 => Some(PosType::Bytes),
+            TextPosType::Grapheme => None,
+        }
+    }
+}
+
+/// A change of the text described by lines. See [`TextDelta::line_changes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineChange {
+    /// The lines (0-based) replaced in the old text
+    pub old: Range<usize>,
+    /// The lines (0-based) that replace them in the new text
+    pub new: Range<usize>,
+}
+
 impl TextDelta {
+    /// Describe the changes of the delta applied on `old_text` as line ranges.
+    ///
+    /// A line touched by an insertion, a deletion or a format change is included, and the
+    /// changes on the same line are merged. The lengths in the delta are Event Indexes.
+    pub fn line_changes(delta: &[TextDelta], old_text: &str) -> Vec<LineChange> {
+        fn take_event_len(chars: &mut std::str::Chars, len: usize) -> usize {
+            let mut taken = 0;
+            let mut line_breaks = 0;
+            while taken < len {
+                let Some(c) = chars.next() else {
+                    break;
+                };
+                taken += if cfg!(feature = "wasm") {
+                    c.len_utf16()
+                } else {
+                    1
+                };
+                if c == '\n' {
+                    line_breaks += 1;
+                }
+            }
+            line_breaks
+        }
+
+        let mut ans: Vec<LineChange> = Vec::new();
+        let mut push = |change: LineChange| {
+            if let Some(last) = ans.last_mut() {
+                if change.old.start < last.old.end {
+                    last.old.end = last.old.end.max(change.old.end);
+                    last.new.end = last.new.end.max(change.new.end);
+                    return;
+                }
+            }
+            ans.push(change);
+        };
+
+        let mut chars = old_text.chars();
+        let mut old_line = 0;
+        let mut new_line = 0;
+        for item in delta {
+            match item {
+                TextDelta::Retain { retain, attributes } => {
+                    let line_breaks = take_event_len(&mut chars, *retain);
+                    if attributes.is_some() {
+                        push(LineChange {
+                            old: old_line..old_line + line_breaks + 1,
+                            new: new_line..new_line + line_breaks + 1,
+                        });
+                    }
+                    old_line += line_breaks;
+                    new_line += line_breaks;
+                }
+                TextDelta::Insert { insert, .. } => {
+                    let line_breaks = insert.matches('\n').count();
+                    push(LineChange {
+                        old: old_line..old_line + 1,
+                        new: new_line..new_line + line_breaks + 1,
+                    });
+                    new_line += line_breaks;
+                }
+                TextDelta::Delete { delete } => {
+                    let line_breaks = take_event_len(&mut chars, *delete);
+                    push(LineChange {
+                        old: old_line..old_line + line_breaks + 1,
+                        new: new_line..new_line + 1,
+                    });
+                    old_line += line_breaks;
+                }
+            }
+        }
+
+        ans
+    }
+
     pub fn from_text_diff<'a>(diff: impl Iterator<Item = &'a TextDiffItem>) -> Vec<TextDelta> {
         let mut ans = Vec::with_capacity(diff.size_hint().0);
         for iter in diff {
@@ -1362,6 +1492,40 @@ impl TextHandler {
         }
     }
 
+    /// The number of extended grapheme clusters in the text
+    pub fn len_grapheme(&self) -> usize {
+        count_graphemes(|f| self.for_each_text_chunk(f))
+    }
+
+    /// The length of the text in the given unit
+    pub fn len_with_pos_type(&self, pos_type: TextPosType) -> usize {
+        match pos_type {
+            TextPosType::Unicode => self.len_unicode(),
+            TextPosType::Utf16 => self.len_utf16(),
+            TextPosType::Utf8 => self.len_utf8(),
+            TextPosType::Grapheme => self.len_grapheme(),
+        }
+    }
+
+    /// Visit the text chunk by chunk until `f` returns `false`
+    fn for_each_text_chunk(&self, f: &mut dyn FnMut(&str) -> bool) {
+        match &self.inner {
+            MaybeDetached::Detached(t) => {
+                let t = t.try_lock().unwrap();
+                for chunk in t.value.iter_chunk() {
+                    if let RichtextStateChunk::Text(chunk) = chunk {
+                        if !f(chunk.as_str()) {
+                            return;
+                        }
+                    }
+                }
+            }
+            MaybeDetached::Attached(a) => {
+                a.with_state(|state| state.as_richtext_state_mut().unwrap().iter(f))
+            }
+        }
+    }
+
     pub fn diagnose(&self) {
         match &self.inner {
             MaybeDetached::Detached(t) => {
@@ -1868,6 +2032,10 @@ impl TextHandler {
     /// - if feature="wasm", pos is a UTF-16 index
     /// - if feature!="wasm", pos is a Unicode index
     ///
+    /// If the key is configured as an annotation, a new annotation is added, see
+    /// [`TextHandler::annotate`]. If the key is a block attribute, only the line breaks in
+    /// the range are marked.
+    ///
     /// This method requires auto_commit to be enabled.
     pub fn mark(
         &self,
@@ -1876,6 +2044,15 @@ impl TextHandler {
         key: impl Into<InternalString>,
         value: LoroValue,
     ) -> LoroResult<()> {
+        let key: InternalString = key.into();
+        check_not_embed_key(&key)?;
+        if !key.contains(':') && self.is_annotation(&key) {
+            return self.annotate(start, end, &key, value).map(|_| ());
+        }
+        if self.is_block(&key) {
+            return self.mark_line_breaks(start, end, key, value, false);
+        }
+
         match &self.inner {
             MaybeDetached::Detached(t) => self.mark_for_detached(
                 &mut t.try_lock().unwrap().value,
@@ -1945,6 +2122,10 @@ impl TextHandler {
     /// - if feature="wasm", pos is a UTF-16 index
     /// - if feature!="wasm", pos is a Unicode index
     ///
+    /// If the key is configured as an annotation, all the annotations of the key are removed
+    /// from the range. If the key is a block attribute, it's removed from the line breaks in
+    /// the range.
+    ///
     /// This method requires auto_commit to be enabled.
     pub fn unmark(
         &self,
@@ -1952,6 +2133,30 @@ impl TextHandler {
         end: usize,
         key: impl Into<InternalString>,
     ) -> LoroResult<()> {
+        let key: InternalString = key.into();
+        check_not_embed_key(&key)?;
+        if self.is_block(&key) {
+            return self.mark_line_breaks(start, end, key, LoroValue::Null, true);
+        }
+        if !key.contains(':') && self.is_annotation(&key) {
+            if start >= end {
+                return Err(LoroError::ArgErr(
+                    "Start must be less than end".to_string().into_boxed_str(),
+                ));
+            }
+
+            let len = self.len_event();
+            if end > len {
+                return Err(LoroError::OutOfBound {
+                    pos: end,
+                    len,
+                    info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+                });
+            }
+
+            return self.unmark_annotations(start, end, &key, None);
+        }
+
         match &self.inner {
             MaybeDetached::Detached(t) => self.mark_for_detached(
                 &mut t.try_lock().unwrap().value,
@@ -1967,6 +2172,276 @@ impl TextHandler {
         }
     }
 
+    /// Add an annotation of the key to the range and return its ID.
+    ///
+    /// The key must be configured as an annotation, see
+    /// [`StyleConfig::annotation`](crate::configure::StyleConfig::annotation). Unlike the
+    /// normal marks, the annotations of the same key don't override each other: they overlap
+    /// freely and each one can be removed by [`TextHandler::remove_annotation`].
+    ///
+    /// `start` and `end` are [Event Index]s:
+    ///
+    /// - if feature="wasm", pos is a UTF-16 index
+    /// - if feature!="wasm", pos is a Unicode index
+    ///
+    /// This method requires auto_commit to be enabled.
+    pub fn annotate(
+        &self,
+        start: usize,
+        end: usize,
+        key: &str,
+        value: LoroValue,
+    ) -> LoroResult<ID> {
+        let inner = self.inner.try_attached_state()?;
+        if key.contains(':') || !self.is_annotation(key) {
+            return Err(LoroError::ArgErr(
+                format!(
+                    "The style key \"{}\" is not configured as an annotation",
+                    key
+                )
+                .into(),
+            ));
+        }
+
+        inner.with_txn(|txn| {
+            // The mark op is the next op of the txn
+            let id = txn.next_id();
+            self.mark_with_txn(txn, start, end, annotation_key(key, id), value, false)?;
+            Ok(id)
+        })
+    }
+
+    /// Remove the annotation of the key with the ID from the text.
+    ///
+    /// It's a no-op if the annotation doesn't exist or has been removed.
+    ///
+    /// This method requires auto_commit to be enabled.
+    pub fn remove_annotation(&self, key: &str, id: ID) -> LoroResult<()> {
+        self.unmark_annotations(0, usize::MAX, key, Some(id))
+    }
+
+    /// Get the annotations of the key with the [Event Index] ranges they cover.
+    ///
+    /// An annotation may cover several ranges if a part of it is removed. Only the styles of
+    /// the text are visited, the text itself is not read.
+    pub fn get_annotations(&self, key: &str) -> Vec<(ID, LoroValue, std::ops::Range<usize>)> {
+        match &self.inner {
+            MaybeDetached::Detached(_) => Vec::new(),
+            MaybeDetached::Attached(a) => {
+                a.with_state(|state| state.as_richtext_state_mut().unwrap().get_annotations(key))
+            }
+        }
+    }
+
+    /// Get the styles that the text inserted at `pos` would have.
+    ///
+    /// This is the style state of a cursor at `pos`. `pos` is an Event Index.
+    pub fn get_styles_at(&self, pos: usize) -> LoroResult<FxHashMap<String, LoroValue>> {
+        let len = self.len_event();
+        if pos > len {
+            return Err(LoroError::OutOfBound {
+                pos,
+                len,
+                info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+            });
+        }
+
+        let styles = match &self.inner {
+            MaybeDetached::Detached(t) => {
+                let mut t = t.try_lock().unwrap();
+                t.value.get_styles_at_event_index_for_insert(pos)?
+            }
+            MaybeDetached::Attached(a) => a.with_state(|state| {
+                state
+                    .as_richtext_state_mut()
+                    .unwrap()
+                    .get_styles_at_event_index(pos)
+            })?,
+        };
+
+        Ok(styles.to_map_without_null_value())
+    }
+
+    /// Get the value of each style key across the text in `start..end`.
+    ///
+    /// The keys that no text in the range has are absent from the result. An empty range
+    /// returns the styles at the position, as [`TextHandler::get_styles_at`] does.
+    ///
+    /// `start` and `end` are Event Indexes.
+    pub fn get_styles_in(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> LoroResult<FxHashMap<String, StyleRangeValue>> {
+        self.check_event_range(start, end)?;
+        if start == end {
+            return Ok(self
+                .get_styles_at(start)?
+                .into_iter()
+                .map(|(key, value)| (key, StyleRangeValue::Uniform(value)))
+                .collect());
+        }
+
+        let styles = match &self.inner {
+            MaybeDetached::Detached(t) => {
+                let mut t = t.try_lock().unwrap();
+                t.value.get_styles_in_event_range(start..end)
+            }
+            MaybeDetached::Attached(a) => a.with_state(|state| {
+                state
+                    .as_richtext_state_mut()
+                    .unwrap()
+                    .get_styles_in_event_range(start..end)
+            }),
+        };
+
+        let mut styles = styles.iter().map(|x| x.to_map_without_null_value());
+        let Some(first) = styles.next() else {
+            return Ok(Default::default());
+        };
+        let mut ans: FxHashMap<String, StyleRangeValue> = first
+            .into_iter()
+            .map(|(key, value)| (key, StyleRangeValue::Uniform(value)))
+            .collect();
+        for map in styles {
+            for (key, value) in ans.iter_mut() {
+                if let StyleRangeValue::Uniform(v) = value {
+                    if map.get(key) != Some(v) {
+                        *value = StyleRangeValue::Mixed;
+                    }
+                }
+            }
+
+            for key in map.into_keys() {
+                ans.entry(key).or_insert(StyleRangeValue::Mixed);
+            }
+        }
+
+        Ok(ans)
+    }
+
+    /// Iterate the text in `start..end` as insert spans with their styles, in the same form
+    /// as the delta of the whole text.
+    ///
+    /// The spans are read from the state one by one as the iterator advances, so only the
+    /// text of the consumed spans is visited. Each step reads the current text: an edit made
+    /// while iterating shifts the text that the remaining steps read.
+    ///
+    /// `start` and `end` are Event Indexes.
+    pub fn iter_spans(&self, start: usize, end: usize) -> LoroResult<TextSpans> {
+        self.check_event_range(start, end)?;
+        Ok(TextSpans {
+            text: self.clone(),
+            pos: start,
+            end,
+            next: None,
+        })
+    }
+
+    /// Get the first span of the text in `start..end` with the same styles
+    fn get_first_span_in(&self, start: usize, end: usize) -> Option<TextDelta> {
+        let span = match &self.inner {
+            MaybeDetached::Detached(t) => {
+                let mut t = t.try_lock().unwrap();
+                t.value.get_first_span_in_event_range(start..end)
+            }
+            MaybeDetached::Attached(a) => a.with_state(|state| {
+                state
+                    .as_richtext_state_mut()
+                    .unwrap()
+                    .get_first_span_in_event_range(start..end)
+            }),
+        }?;
+        Some(TextDelta::Insert {
+            insert: span.text.as_str().to_string(),
+            attributes: span.attributes.to_option_map(),
+        })
+    }
+
+    fn check_event_range(&self, start: usize, end: usize) -> LoroResult<()> {
+        if end < start {
+            return Err(LoroError::EndIndexLessThanStartIndex { start, end });
+        }
+
+        let len = self.len_event();
+        if end > len {
+            return Err(LoroError::OutOfBound {
+                pos: end,
+                len,
+                info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn unmark_annotations(
+        &self,
+        start: usize,
+        end: usize,
+        key: &str,
+        id: Option<ID>,
+    ) -> LoroResult<()> {
+        let inner = self.inner.try_attached_state()?;
+        let annotations = self.get_annotations(key);
+        inner.with_txn(|txn| {
+            for (annotation, _, range) in annotations {
+                if id.map_or(false, |id| id != annotation) {
+                    continue;
+                }
+
+                let range = range.start.max(start)..range.end.min(end);
+                if range.start < range.end {
+                    self.mark_with_txn(
+                        txn,
+                        range.start,
+                        range.end,
+                        annotation_key(key, annotation),
+                        LoroValue::Null,
+                        true,
+                    )?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Whether the marks of the key are annotations in the config of the doc
+    fn is_annotation(&self, key: &str) -> bool {
+        match &self.inner {
+            MaybeDetached::Detached(_) => false,
+            MaybeDetached::Attached(a) => {
+                let state = a.state.upgrade().unwrap();
+                let state = state.try_lock().unwrap();
+                let config = state.config.text_style_config.try_read().unwrap();
+                config.is_annotation(key)
+            }
+        }
+    }
+
+    /// Expand the annotations grouped under their keys into the style keys of the annotations
+    fn expand_annotations(
+        &self,
+        attributes: &FxHashMap<String, LoroValue>,
+    ) -> FxHashMap<String, LoroValue> {
+        let mut ans = FxHashMap::default();
+        for (key, value) in attributes {
+            match value {
+                LoroValue::Map(group) if !key.contains(':') && self.is_annotation(key) => {
+                    for (id, value) in group.iter() {
+                        ans.insert(format!("{}:{}", key, id), value.clone());
+                    }
+                }
+                _ => {
+                    ans.insert(key.clone(), value.clone());
+                }
+            }
+        }
+
+        ans
+    }
+
     /// `start` and `end` are [Event Index]s:
     ///
     /// - if feature="wasm", pos is a UTF-16 index
@@ -2032,6 +2507,10 @@ impl TextHandler {
                 .get_style_flag(&key)
                 .ok_or_else(|| LoroError::StyleConfigMissing(key.clone()))?
         };
+        let annotation = parse_annotation_key(&key)
+            .map(|(key, _)| key)
+            .filter(|key| style_config.is_annotation(key))
+            .map(InternalString::from);
 
         drop(style_config);
         drop(doc_state);
@@ -2048,6 +2527,7 @@ impl TextHandler {
                 start: start as u32,
                 end: end as u32,
                 style: crate::container::richtext::Style { key, data: value },
+                annotation,
             },
             &inner.state,
         )?;
@@ -2101,6 +2581,7 @@ impl TextHandler {
             match d {
                 TextDelta::Insert { insert, attributes } => {
                     let end = index + event_len(insert.as_str());
+                    let attributes = attributes.as_ref().map(|x| self.expand_annotations(x));
                     let override_styles = self.insert_with_txn_and_attr(
                         txn,
                         index,
@@ -2122,8 +2603,8 @@ impl TextHandler {
                     let end = index + *retain;
                     match attributes {
                         Some(attr) if !attr.is_empty() => {
-                            for (key, value) in attr {
-                                marks.push((index, end, key.deref().into(), value.clone()));
+                            for (key, value) in self.expand_annotations(attr) {
+                                marks.push((index, end, key.into(), value));
                             }
                         }
                         _ => {}
@@ -2140,13 +2621,23 @@ impl TextHandler {
                 len = start;
             }
 
-            self.mark_with_txn(txn, start, end, key.deref(), value, false)?;
+            if self.is_block(&key) {
+                for pos in self.line_breaks_in(start, end) {
+                    self.mark_with_txn(txn, pos, pos + 1, key.deref(), value.clone(), false)?;
+                }
+            } else {
+                self.mark_with_txn(txn, start, end, key.deref(), value, false)?;
+            }
         }
 
         Ok(())
     }
 
     pub fn update(&self, text: &str, options: UpdateOptions) -> Result<(), UpdateTimeoutError> {
+        if options.granularity != UpdateGranularity::Char || options.preserve_marks {
+            return self.update_by_tokens(text, options);
+        }
+
         let old_str = self.to_string();
         let new = text.chars().map(|x| x as u32).collect::<Vec<u32>>();
         let old = old_str.chars().map(|x| x as u32).collect::<Vec<u32>>();
@@ -2164,6 +2655,10 @@ impl TextHandler {
         text: &str,
         options: UpdateOptions,
     ) -> Result<(), UpdateTimeoutError> {
+        if options.preserve_marks {
+            return self.update_by_tokens(text, options.granularity(UpdateGranularity::Line));
+        }
+
         let hook = text_update::DiffHookForLine::new(self, text);
         let old_lines = hook.get_old_arr().to_vec();
         let new_lines = hook.get_new_arr().to_vec();
@@ -2175,6 +2670,53 @@ impl TextHandler {
         )
     }
 
+    /// Update the text by diffing the tokens of `options.granularity`, so the ops are aligned
+    /// to the token boundaries
+    fn update_by_tokens(
+        &self,
+        text: &str,
+        options: UpdateOptions,
+    ) -> Result<(), UpdateTimeoutError> {
+        let old_str = self.to_string();
+        let hook = text_update::DiffHookForTokens::new(&old_str, text, options.granularity);
+        let old_tokens = hook.get_old_arr().to_vec();
+        let new_tokens = hook.get_new_arr().to_vec();
+        let preserve_marks = options.preserve_marks;
+        let mut proxy = OperateProxy::new(hook);
+        diff(&mut proxy, options, &old_tokens, &new_tokens)?;
+        let mut delta = proxy.unwrap().into_delta();
+        if delta.is_empty() {
+            return Ok(());
+        }
+
+        if preserve_marks && self.is_attached() {
+            // `apply_delta` overrides the inherited styles of the inserted text with the
+            // given attributes, which are empty here
+            for d in delta.iter_mut() {
+                if let TextDelta::Insert { attributes, .. } = d {
+                    *attributes = Some(Default::default());
+                }
+            }
+
+            self.apply_delta(&delta)?;
+            return Ok(());
+        }
+
+        let mut index = 0;
+        for d in delta {
+            match d {
+                TextDelta::Retain { retain, .. } => index += retain,
+                TextDelta::Insert { insert, .. } => {
+                    self.insert(index, &insert)?;
+                    index += event_len(&insert);
+                }
+                TextDelta::Delete { delete } => self.delete(index, delete)?,
+            }
+        }
+
+        Ok(())
+    }
+
     #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         match &self.inner {
@@ -2247,6 +2789,231 @@ impl TextHandler {
         }
     }
 
+    /// Get a [`TextRange`] that keeps covering the text in `start..end` when the text is
+    /// edited.
+    ///
+    /// Like the expand type of a style, `expand` decides whether the text inserted at the
+    /// boundaries of the range is included. `start` and `end` are Event Indexes.
+    ///
+    /// Returns `None` if the range is out of bound or the text is detached.
+    pub fn get_range(&self, start: usize, end: usize, expand: ExpandType) -> Option<TextRange> {
+        let len = self.len_event();
+        if !self.is_attached() || start > end || end > len {
+            return None;
+        }
+
+        // The start is anchored to the char before the range if it expands, otherwise to the
+        // first char in the range
+        let start = if !expand.expand_before() {
+            self.get_cursor(start, Side::Left)?
+        } else if start > 0 {
+            self.get_cursor(start - 1, Side::Right)?
+        } else {
+            Cursor::new(None, self.id(), Side::Left, 0)
+        };
+        // The end is anchored to the char after the range if it expands, otherwise to the last
+        // char in the range
+        let end = if expand.expand_after() {
+            if end == len {
+                Cursor::new(None, self.id(), Side::Right, self.len_unicode())
+            } else {
+                self.get_cursor(end, Side::Left)?
+            }
+        } else if end > 0 {
+            self.get_cursor(end - 1, Side::Right)?
+        } else {
+            Cursor::new(None, self.id(), Side::Left, 0)
+        };
+
+        Some(TextRange { start, end })
+    }
+
+    pub(crate) fn convert_index(&self, index: usize, from: PosType, to: PosType) -> usize {
+        match &self.inner {
+            MaybeDetached::Detached(t) => {
+                t.try_lock().unwrap().value.convert_index(index, from, to)
+            }
+            MaybeDetached::Attached(a) => a.with_state(|state| {
+                state
+                    .as_richtext_state_mut()
+                    .unwrap()
+                    .convert_index(index, from, to)
+            }),
+        }
+    }
+
+    /// Convert a position of the `from` unit into the `to` unit.
+    ///
+    /// Returns `None` if the position is out of bound or it's inside a character of
+    /// the `from` unit, e.g. in the middle of a UTF-8 encoded code point or inside
+    /// an emoji ZWJ sequence.
+    pub fn convert_pos(&self, pos: usize, from: TextPosType, to: TextPosType) -> Option<usize> {
+        match (from.to_internal(), to.to_internal()) {
+            (Some(from), Some(to)) => self.convert_pos_internal(pos, from, to),
+            (from, to) => {
+                // Graphemes are not indexed by the state, so they are counted by walking the
+                // text from the start up to the target
+                let utf8 = match from {
+                    Some(from) => self.convert_pos_internal(pos, from, PosType::Bytes)?,
+                    None => {
+                        grapheme_range_to_utf8(|f| self.for_each_text_chunk(f), pos..pos)?.start
+                    }
+                };
+                match to {
+                    Some(to) => self.convert_pos_internal(utf8, PosType::Bytes, to),
+                    None => utf8_to_grapheme_index(|f| self.for_each_text_chunk(f), utf8),
+                }
+            }
+        }
+    }
+
+    fn convert_pos_internal(&self, pos: usize, from: PosType, to: PosType) -> Option<usize> {
+        match &self.inner {
+            MaybeDetached::Detached(t) => t.try_lock().unwrap().value.convert_pos(pos, from, to),
+            MaybeDetached::Attached(a) => a.with_state(|state| {
+                state
+                    .as_richtext_state_mut()
+                    .unwrap()
+                    .convert_pos(pos, from, to)
+            }),
+        }
+    }
+
+    /// Insert a string at the given position of the `pos_type` unit.
+    ///
+    /// Returns an error if the position is out of bound or inside a character of the unit.
+    pub fn insert_with_pos_type(
+        &self,
+        pos: usize,
+        s: &str,
+        pos_type: TextPosType,
+    ) -> LoroResult<()> {
+        let range = self.range_to_unicode(pos..pos, pos_type)?;
+        self.insert_unicode(range.start, s)
+    }
+
+    /// Delete `len` characters of the `pos_type` unit starting from `pos`.
+    ///
+    /// Returns an error if the range is out of bound or it splits a character of the unit.
+    pub fn delete_with_pos_type(
+        &self,
+        pos: usize,
+        len: usize,
+        pos_type: TextPosType,
+    ) -> LoroResult<()> {
+        let range = self.range_to_unicode(pos..pos + len, pos_type)?;
+        self.delete_unicode(range.start, range.len())
+    }
+
+    /// Get the string between the given positions of the `pos_type` unit.
+    ///
+    /// Returns an error if the range is out of bound or it splits a character of the unit.
+    pub fn slice_with_pos_type(
+        &self,
+        start: usize,
+        end: usize,
+        pos_type: TextPosType,
+    ) -> LoroResult<String> {
+        if end < start {
+            return Err(LoroError::EndIndexLessThanStartIndex { start, end });
+        }
+
+        let range = self.range_to_unicode(start..end, pos_type)?;
+        let start = self.convert_index(range.start, PosType::Unicode, PosType::Event);
+        let end = self.convert_index(range.end, PosType::Unicode, PosType::Event);
+        self.slice(start, end)
+    }
+
+    fn range_to_unicode(
+        &self,
+        range: Range<usize>,
+        pos_type: TextPosType,
+    ) -> LoroResult<Range<usize>> {
+        let convert = |pos| match pos_type.to_internal() {
+            Some(from) => self.convert_pos_internal(pos, from, PosType::Unicode),
+            None => self.convert_pos_internal(pos, PosType::Bytes, PosType::Unicode),
+        };
+        let utf8_range = match pos_type {
+            // Only the graphemes up to the end of the range are segmented
+            TextPosType::Grapheme => {
+                grapheme_range_to_utf8(|f| self.for_each_text_chunk(f), range.clone())
+            }
+            _ => Some(range.clone()),
+        };
+        utf8_range
+            .and_then(|r| Some(convert(r.start)?..convert(r.end)?))
+            .ok_or_else(|| LoroError::OutOfBound {
+                pos: range.end,
+                len: self.len_with_pos_type(pos_type),
+                info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+            })
+    }
+
+    /// The number of lines in the text. Lines are separated by `\n`, so an empty text has
+    /// one line.
+    pub fn line_count(&self) -> usize {
+        let line_breaks = match &self.inner {
+            MaybeDetached::Detached(t) => t.try_lock().unwrap().value.len_line_breaks(),
+            MaybeDetached::Attached(a) => {
+                a.with_state(|state| state.as_richtext_state_mut().unwrap().len_line_breaks())
+            }
+        };
+        line_breaks + 1
+    }
+
+    /// Get the Event Index of the start of the given line (0-based).
+    ///
+    /// Returns `None` if the line doesn't exist.
+    pub fn line_to_offset(&self, line: usize) -> Option<usize> {
+        match &self.inner {
+            MaybeDetached::Detached(t) => t.try_lock().unwrap().value.line_start_event_index(line),
+            MaybeDetached::Attached(a) => a.with_state(|state| {
+                state
+                    .as_richtext_state_mut()
+                    .unwrap()
+                    .line_start_event_index(line)
+            }),
+        }
+    }
+
+    /// Get the line and the column (both 0-based) of the given Event Index.
+    ///
+    /// Returns `None` if the position is out of bound.
+    pub fn offset_to_line_col(&self, pos: usize) -> Option<(usize, usize)> {
+        if pos > self.len_event() {
+            return None;
+        }
+
+        let line = match &self.inner {
+            MaybeDetached::Detached(t) => t
+                .try_lock()
+                .unwrap()
+                .value
+                .line_breaks_before_event_index(pos),
+            MaybeDetached::Attached(a) => a.with_state(|state| {
+                state
+                    .as_richtext_state_mut()
+                    .unwrap()
+                    .line_breaks_before_event_index(pos)
+            }),
+        };
+        let line_start = self.line_to_offset(line)?;
+        Some((line, pos - line_start))
+    }
+
+    /// Get the content of the given line (0-based), without the trailing `\n`.
+    ///
+    /// Returns `None` if the line doesn't exist.
+    pub fn get_line(&self, line: usize) -> Option<String> {
+        let start = self.line_to_offset(line)?;
+        let end = match self.line_to_offset(line + 1) {
+            // A line break is a single unit in all the encodings
+            Some(next) => next - 1,
+            None => self.len_event(),
+        };
+        self.slice(start, end).ok()
+    }
+
     pub(crate) fn convert_entity_index_to_event_index(&self, entity_index: usize) -> usize {
         match &self.inner {
             MaybeDetached::Detached(s) => s
@@ -2285,6 +3052,71 @@ impl TextHandler {
     }
 }
 
+/// The iterator of the styled spans of a text, see [`TextHandler::iter_spans`]
+#[derive(Debug)]
+pub struct TextSpans {
+    text: TextHandler,
+    pos: usize,
+    end: usize,
+    /// The span read ahead to merge it into the previous one
+    next: Option<TextDelta>,
+}
+
+impl TextSpans {
+    fn read_span(&mut self) -> Option<TextDelta> {
+        let span = self.text.get_first_span_in(self.pos, self.end)?;
+        let TextDelta::Insert { insert, .. } = &span else {
+            unreachable!()
+        };
+        self.pos += event_len(insert);
+        Some(span)
+    }
+}
+
+impl Iterator for TextSpans {
+    type Item = TextDelta;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut ans = self.next.take().or_else(|| self.read_span())?;
+        // The spans with different style ops may have the same resolved styles
+        while let Some(span) = self.read_span() {
+            let (
+                TextDelta::Insert { insert, attributes },
+                TextDelta::Insert {
+                    insert: next,
+                    attributes: next_attributes,
+                },
+            ) = (&mut ans, &span)
+            else {
+                unreachable!()
+            };
+            if attributes != next_attributes {
+                self.next = Some(span);
+                break;
+            }
+
+            insert.push_str(next);
+        }
+
+        Some(ans)
+    }
+}
+
+/// The embeds can only be created by [`TextHandler::insert_embed`]
+fn check_not_embed_key(key: &str) -> LoroResult<()> {
+    if key == EMBED_KEY {
+        return Err(LoroError::ArgErr(
+            format!(
+                "The style key \"{}\" is reserved for the embeds, use `insert_embed` instead",
+                EMBED_KEY
+            )
+            .into_boxed_str(),
+        ));
+    }
+
+    Ok(())
+}
+
 fn event_len(s: &str) -> usize {
     if cfg!(feature = "wasm") {
         count_utf16_len(s.as_bytes())
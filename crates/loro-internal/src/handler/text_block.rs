@@ -0,0 +1,214 @@
+use super::*;
+
+impl TextHandler {
+    /// Insert an embed with the value at the given [Event Index].
+    ///
+    /// The embed occupies one position. It's an [`EMBED_CHAR`] in the text with the value as
+    /// the [`EMBED_KEY`] style, and it's reported as an insert of the value by
+    /// [`TextHandler::get_richtext_value`]. It has the styles of the text inserted at the
+    /// position, like the bold style.
+    ///
+    /// The embed is stored as a mark, like the block attributes, so the encoding of the text
+    /// is unchanged. The child containers can't be embedded, because the containers are only
+    /// created in the maps, lists and trees.
+    ///
+    /// This method requires auto_commit to be enabled.
+    pub fn insert_embed(&self, pos: usize, value: LoroValue) -> LoroResult<()> {
+        match value {
+            LoroValue::Null => {
+                return Err(LoroError::ArgErr(
+                    "The value of an embed can't be null".into(),
+                ))
+            }
+            LoroValue::Container(_) => {
+                return Err(LoroError::ArgErr(
+                    "The child containers can't be embedded in the text".into(),
+                ))
+            }
+            _ => {}
+        }
+
+        let s = EMBED_CHAR.to_string();
+        match &self.inner {
+            MaybeDetached::Detached(t) => {
+                self.insert(pos, &s)?;
+                self.mark_for_detached(
+                    &mut t.try_lock().unwrap().value,
+                    EMBED_KEY,
+                    &value,
+                    pos,
+                    pos + 1,
+                    false,
+                )
+            }
+            MaybeDetached::Attached(a) => a.with_txn(|txn| {
+                self.insert_with_txn(txn, pos, &s)?;
+                self.mark_with_txn(txn, pos, pos + 1, EMBED_KEY, value, false)
+            }),
+        }
+    }
+
+    /// Set the block attribute of the given line (0-based).
+    ///
+    /// The key must be configured as a block attribute, see
+    /// [`StyleConfig::block`](crate::configure::StyleConfig::block). The attribute is set on
+    /// the line break that ends the line. If it's the last line and it doesn't end with a
+    /// line break, a line break is appended.
+    ///
+    /// This method requires auto_commit to be enabled.
+    pub fn set_block_attribute(&self, line: usize, key: &str, value: LoroValue) -> LoroResult<()> {
+        self.check_block_key(key)?;
+        let pos = match self.line_break_of_line(line)? {
+            Some(pos) => pos,
+            None => {
+                let len = self.len_event();
+                self.insert(len, "\n")?;
+                len
+            }
+        };
+
+        self.mark_line_breaks(pos, pos + 1, key.into(), value, false)
+    }
+
+    /// Remove the block attribute of the given line (0-based).
+    ///
+    /// This method requires auto_commit to be enabled.
+    pub fn remove_block_attribute(&self, line: usize, key: &str) -> LoroResult<()> {
+        self.check_block_key(key)?;
+        match self.line_break_of_line(line)? {
+            Some(pos) => self.mark_line_breaks(pos, pos + 1, key.into(), LoroValue::Null, true),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the block attributes of the given line (0-based).
+    ///
+    /// Returns `None` if the line doesn't exist. The last line without the trailing line
+    /// break has no block attributes.
+    pub fn get_block_attributes(&self, line: usize) -> Option<FxHashMap<String, LoroValue>> {
+        let Some(pos) = self.line_break_of_line(line).ok()? else {
+            return Some(FxHashMap::default());
+        };
+
+        let styles = self.get_styles_in(pos, pos + 1).ok()?;
+        Some(
+            styles
+                .into_iter()
+                .filter(|(key, _)| self.is_block(key))
+                .filter_map(|(key, value)| match value {
+                    StyleRangeValue::Uniform(value) => Some((key, value)),
+                    StyleRangeValue::Mixed => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// Whether the key is a block attribute in the config of the doc
+    pub(super) fn is_block(&self, key: &str) -> bool {
+        match &self.inner {
+            MaybeDetached::Detached(_) => false,
+            MaybeDetached::Attached(a) => {
+                let state = a.state.upgrade().unwrap();
+                let state = state.try_lock().unwrap();
+                let config = state.config.text_style_config.try_read().unwrap();
+                config.is_block(key)
+            }
+        }
+    }
+
+    fn check_block_key(&self, key: &str) -> LoroResult<()> {
+        if !self.is_block(key) {
+            return Err(LoroError::ArgErr(
+                format!(
+                    "The style key \"{}\" is not configured as a block attribute",
+                    key
+                )
+                .into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Get the [Event Index] of the line break that ends the line, or `None` if it's the last
+    /// line
+    fn line_break_of_line(&self, line: usize) -> LoroResult<Option<usize>> {
+        if self.line_to_offset(line).is_none() {
+            return Err(LoroError::OutOfBound {
+                pos: line,
+                len: self.line_count(),
+                info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+            });
+        }
+
+        // A line break is a single unit in all the encodings
+        Ok(self.line_to_offset(line + 1).map(|next| next - 1))
+    }
+
+    /// The [Event Index]es of the line breaks in `start..end`
+    pub(super) fn line_breaks_in(&self, start: usize, end: usize) -> Vec<usize> {
+        let first_line = match &self.inner {
+            MaybeDetached::Detached(t) => t
+                .try_lock()
+                .unwrap()
+                .value
+                .line_breaks_before_event_index(start),
+            MaybeDetached::Attached(a) => a.with_state(|state| {
+                state
+                    .as_richtext_state_mut()
+                    .unwrap()
+                    .line_breaks_before_event_index(start)
+            }),
+        };
+
+        let mut ans = Vec::new();
+        for line in first_line.. {
+            let Some(next_line_start) = self.line_to_offset(line + 1) else {
+                break;
+            };
+            let pos = next_line_start - 1;
+            if pos >= end {
+                break;
+            }
+
+            ans.push(pos);
+        }
+
+        ans
+    }
+
+    /// Mark the line breaks in the range with the block attribute
+    pub(super) fn mark_line_breaks(
+        &self,
+        start: usize,
+        end: usize,
+        key: InternalString,
+        value: LoroValue,
+        is_delete: bool,
+    ) -> LoroResult<()> {
+        self.check_event_range(start, end)?;
+        if start == end {
+            return Err(LoroError::ArgErr(
+                "Start must be less than end".to_string().into_boxed_str(),
+            ));
+        }
+
+        for pos in self.line_breaks_in(start, end) {
+            match &self.inner {
+                MaybeDetached::Detached(t) => self.mark_for_detached(
+                    &mut t.try_lock().unwrap().value,
+                    key.clone(),
+                    &value,
+                    pos,
+                    pos + 1,
+                    is_delete,
+                )?,
+                MaybeDetached::Attached(a) => a.with_txn(|txn| {
+                    self.mark_with_txn(txn, pos, pos + 1, key.clone(), value.clone(), is_delete)
+                })?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,786 @@
+use super::*;
+
+/// The tags without the closing tags
+const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// The tags that are dropped with their content
+const DROPPED_TAGS: &[&str] = &[
+    "head", "iframe", "math", "noscript", "object", "script", "select", "style", "svg", "template",
+    "textarea", "title",
+];
+
+/// The tags that start on a new line and end the line
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tr",
+    "ul",
+];
+
+/// The mapping between the style keys of a text and the HTML tags, used by
+/// [`TextHandler::to_html`] and [`TextHandler::insert_html`]
+///
+/// - A style is written as a tag around the styled text. The value of the style is either
+///   ignored, or written as an attribute of the tag, like the `href` of a link.
+/// - An embedded object, like an image, is an embed of the text, see
+///   [`TextHandler::insert_embed`]. The value of the embed is a map from the key of the rule
+///   to a map of the attributes of the tag, like `{ "image": { "src": "a.png" } }`. The
+///   Quill form `{ "image": "a.png" }` is written with the value as the first attribute.
+///
+/// The tags and the embeds without a rule are not written, and the tags are dropped with
+/// their attributes when the HTML is inserted, while their text is kept.
+#[derive(Debug, Clone, Default)]
+pub struct HtmlMapping {
+    /// The order of the rules is the nesting order of the tags, outermost first
+    styles: Vec<StyleRule>,
+    /// The other tags of the style keys that are accepted when the HTML is inserted
+    aliases: Vec<(String, String)>,
+    embeds: Vec<EmbedRule>,
+}
+
+#[derive(Debug, Clone)]
+struct StyleRule {
+    key: String,
+    tag: String,
+    attribute: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct EmbedRule {
+    key: String,
+    tag: String,
+    attributes: Vec<String>,
+}
+
+impl HtmlMapping {
+    /// An empty mapping that writes and keeps only the plain text
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The mapping of the keys in [`StyleConfigMap::default_rich_text_config`], and the
+    /// `image` embeds for `<img>`
+    ///
+    /// `comment` isn't mapped.
+    ///
+    /// [`StyleConfigMap::default_rich_text_config`]: crate::configure::StyleConfigMap::default_rich_text_config
+    pub fn default_rich_text() -> Self {
+        Self::new()
+            .style_with_attribute("link", "a", "href")
+            .style("bold", "strong")
+            .alias("b", "bold")
+            .style("italic", "em")
+            .alias("i", "italic")
+            .style("underline", "u")
+            .style("highlight", "mark")
+            .style("code", "code")
+            .embed("image", "img", &["src", "alt", "title", "width", "height"])
+    }
+
+    /// Write the style as the tag. The inserted tag sets the style to `true`.
+    pub fn style(mut self, key: &str, tag: &str) -> Self {
+        self.styles.push(StyleRule {
+            key: key.to_string(),
+            tag: tag.to_ascii_lowercase(),
+            attribute: None,
+        });
+        self
+    }
+
+    /// Write the style as the tag with the value of the style as the attribute.
+    /// The inserted tag without the attribute is ignored.
+    pub fn style_with_attribute(mut self, key: &str, tag: &str, attribute: &str) -> Self {
+        self.styles.push(StyleRule {
+            key: key.to_string(),
+            tag: tag.to_ascii_lowercase(),
+            attribute: Some(attribute.to_ascii_lowercase()),
+        });
+        self
+    }
+
+    /// Accept another tag for the style key when the HTML is inserted, like `<b>` for `bold`
+    pub fn alias(mut self, tag: &str, key: &str) -> Self {
+        self.aliases
+            .push((tag.to_ascii_lowercase(), key.to_string()));
+        self
+    }
+
+    /// Write the embeds of the key as the tag. The value of the key in the embed is a map from
+    /// the attributes to their values, and only the listed attributes are kept.
+    pub fn embed(mut self, key: &str, tag: &str, attributes: &[&str]) -> Self {
+        self.embeds.push(EmbedRule {
+            key: key.to_string(),
+            tag: tag.to_ascii_lowercase(),
+            attributes: attributes.iter().map(|a| a.to_ascii_lowercase()).collect(),
+        });
+        self
+    }
+
+    fn style_for_tag(&self, tag: &str) -> Option<&StyleRule> {
+        self.styles.iter().find(|r| r.tag == tag).or_else(|| {
+            let (_, key) = self.aliases.iter().find(|(t, _)| t == tag)?;
+            self.styles.iter().find(|r| &r.key == key)
+        })
+    }
+
+    fn embed_for_tag(&self, tag: &str) -> Option<&EmbedRule> {
+        self.embeds.iter().find(|r| r.tag == tag)
+    }
+}
+
+impl TextHandler {
+    /// Write the text as HTML with the mapping.
+    ///
+    /// The line breaks are written as `<br>`, and the spaces that would be collapsed by the
+    /// browsers are written as `&nbsp;`.
+    pub fn to_html(&self, mapping: &HtmlMapping) -> String {
+        // The embeds are kept as the styled `EMBED_CHAR`s in the spans
+        let spans: Vec<TextDelta> = self.iter_spans(0, self.len_event()).unwrap().collect();
+        let spans: Vec<(&str, Option<&FxHashMap<String, LoroValue>>)> = spans
+            .iter()
+            .map(|span| match span {
+                TextDelta::Insert { insert, attributes } => (insert.as_str(), attributes.as_ref()),
+                _ => unreachable!(),
+            })
+            .collect();
+        let chars: Vec<char> = spans.iter().flat_map(|(text, _)| text.chars()).collect();
+
+        let mut html = String::new();
+        // The open tags with their rules and values
+        let mut open: Vec<(&StyleRule, &LoroValue)> = Vec::new();
+        let mut index = 0;
+        for (text, attributes) in spans {
+            let styles: Vec<(&StyleRule, &LoroValue)> = mapping
+                .styles
+                .iter()
+                .filter_map(|rule| {
+                    let value = attributes?.get(&rule.key)?;
+                    match &rule.attribute {
+                        None => value_is_set(value).then_some((rule, value)),
+                        Some(_) => attribute_value(value)
+                            .filter(|v| is_safe_value(v))
+                            .map(|_| (rule, value)),
+                    }
+                })
+                .collect();
+            let common = open
+                .iter()
+                .zip(styles.iter())
+                .take_while(|((a, a_value), (b, b_value))| {
+                    std::ptr::eq(*a, *b) && a_value == b_value
+                })
+                .count();
+            for (rule, _) in open.drain(common..).rev() {
+                write_close_tag(&mut html, &rule.tag);
+            }
+            for &(rule, value) in &styles[common..] {
+                let attribute = rule
+                    .attribute
+                    .as_deref()
+                    .map(|name| (name, attribute_value(value).unwrap()));
+                write_open_tag(&mut html, &rule.tag, attribute);
+                open.push((rule, value));
+            }
+
+            let embed = attributes.and_then(|a| a.get(EMBED_KEY));
+            for c in text.chars() {
+                match (c, embed) {
+                    (EMBED_CHAR, Some(embed)) => write_embed(&mut html, mapping, embed),
+                    ('\n', _) => html.push_str("<br>"),
+                    ('\u{a0}', _) => html.push_str("&nbsp;"),
+                    (' ', _) => {
+                        let prev = index.checked_sub(1).map(|i| chars[i]);
+                        let next = chars.get(index + 1).copied();
+                        if matches!(prev, None | Some(' ') | Some('\n'))
+                            || matches!(next, None | Some('\n'))
+                        {
+                            html.push_str("&nbsp;");
+                        } else {
+                            html.push(' ');
+                        }
+                    }
+                    _ => escape_into(&mut html, c, false),
+                }
+                index += 1;
+            }
+        }
+
+        for (rule, _) in open.into_iter().rev() {
+            write_close_tag(&mut html, &rule.tag);
+        }
+
+        html
+    }
+
+    /// Insert the HTML at the unicode position with the mapping.
+    ///
+    /// The inserted text has exactly the styles of its tags, so it doesn't inherit the
+    /// styles around the position. The rules whose keys are not configured in the doc are
+    /// ignored, so their tags are dropped like the unknown tags.
+    ///
+    /// The whitespaces are collapsed like the browsers do except in `<pre>`, `&nbsp;` is
+    /// inserted as a space, and the block tags like `<p>` are separated by line breaks.
+    pub fn insert_html(&self, pos: usize, html: &str, mapping: &HtmlMapping) -> LoroResult<()> {
+        let index = self
+            .convert_pos_internal(pos, PosType::Unicode, PosType::Event)
+            .ok_or_else(|| LoroError::OutOfBound {
+                pos,
+                len: self.len_unicode(),
+                info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+            })?;
+        let segments = HtmlReader::new(mapping, |key| self.is_style_configured(key)).read(html);
+        if segments.is_empty() {
+            return Ok(());
+        }
+
+        let mut delta = Vec::with_capacity(segments.len() + 1);
+        if index > 0 {
+            delta.push(TextDelta::Retain {
+                retain: index,
+                attributes: None,
+            });
+        }
+        delta.extend(
+            segments
+                .into_iter()
+                .map(|(insert, attributes)| TextDelta::Insert {
+                    insert,
+                    attributes: Some(attributes),
+                }),
+        );
+        self.apply_delta(&delta)
+    }
+
+    fn is_style_configured(&self, key: &str) -> bool {
+        match &self.inner {
+            MaybeDetached::Detached(_) => true,
+            MaybeDetached::Attached(a) => {
+                let state = a.state.upgrade().unwrap();
+                let state = state.try_lock().unwrap();
+                let config = state.config.text_style_config.try_read().unwrap();
+                config.get(&key.into()).is_some()
+            }
+        }
+    }
+}
+
+/// Write the embed with the first rule whose key is in the value of the embed. The embed
+/// without a rule is not written.
+fn write_embed(html: &mut String, mapping: &HtmlMapping, embed: &LoroValue) {
+    let Some((rule, value)) = embed.as_map().and_then(|embed| {
+        mapping
+            .embeds
+            .iter()
+            .find_map(|rule| Some((rule, embed.get(&rule.key)?)))
+    }) else {
+        return;
+    };
+
+    let attributes: Vec<(&str, String)> = match value {
+        LoroValue::Map(map) => rule
+            .attributes
+            .iter()
+            .filter_map(|name| Some((name.as_str(), attribute_value(map.get(name)?)?)))
+            .collect(),
+        // The Quill form, like `{ "image": "a.png" }`
+        value => rule
+            .attributes
+            .first()
+            .and_then(|name| Some((name.as_str(), attribute_value(value)?)))
+            .into_iter()
+            .collect(),
+    };
+    write_open_tag(
+        html,
+        &rule.tag,
+        attributes
+            .into_iter()
+            .filter(|(_, value)| is_safe_value(value)),
+    );
+    if !VOID_TAGS.contains(&rule.tag.as_str()) {
+        write_close_tag(html, &rule.tag);
+    }
+}
+
+/// Whether the style is set for the tag without the attribute
+fn value_is_set(value: &LoroValue) -> bool {
+    !matches!(value, LoroValue::Null | LoroValue::Bool(false))
+}
+
+fn attribute_value(value: &LoroValue) -> Option<String> {
+    match value {
+        LoroValue::String(s) => Some(s.to_string()),
+        LoroValue::Bool(b) => Some(b.to_string()),
+        LoroValue::I64(i) => Some(i.to_string()),
+        LoroValue::Double(d) => Some(d.to_string()),
+        _ => None,
+    }
+}
+
+/// Whether the attribute value is kept. The values with a script scheme, or with a data
+/// scheme other than the images, are dropped.
+fn is_safe_value(value: &str) -> bool {
+    // The browsers ignore the whitespaces and the control chars in the scheme
+    let scheme: String = value
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .take(16)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    !(scheme.starts_with("javascript:")
+        || scheme.starts_with("vbscript:")
+        || (scheme.starts_with("data:") && !scheme.starts_with("data:image/")))
+}
+
+fn escape_into(html: &mut String, c: char, in_attribute: bool) {
+    match c {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' if in_attribute => html.push_str("&quot;"),
+        c => html.push(c),
+    }
+}
+
+fn write_open_tag<'a, V: AsRef<str>>(
+    html: &mut String,
+    tag: &str,
+    attributes: impl IntoIterator<Item = (&'a str, V)>,
+) {
+    html.push('<');
+    html.push_str(tag);
+    for (name, value) in attributes {
+        html.push(' ');
+        html.push_str(name);
+        html.push_str("=\"");
+        for c in value.as_ref().chars() {
+            escape_into(html, c, true);
+        }
+        html.push('"');
+    }
+    html.push('>');
+}
+
+fn write_close_tag(html: &mut String, tag: &str) {
+    html.push_str("</");
+    html.push_str(tag);
+    html.push('>');
+}
+
+enum Tag {
+    Open {
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    Close {
+        name: String,
+    },
+    /// Comments, doctypes and processing instructions
+    Other,
+}
+
+/// Parse the tag at the start of `s`. Returns the tag and its length, or `None` if the `<`
+/// doesn't start a tag.
+fn parse_tag(s: &str) -> Option<(Tag, usize)> {
+    let bytes = s.as_bytes();
+    debug_assert_eq!(bytes[0], b'<');
+    if s.starts_with("<!--") {
+        let len = s[4..].find("-->").map_or(s.len(), |i| i + 7);
+        return Some((Tag::Other, len));
+    }
+
+    match bytes.get(1)? {
+        b'!' | b'?' => {
+            let len = s.find('>').map_or(s.len(), |i| i + 1);
+            Some((Tag::Other, len))
+        }
+        b'/' => {
+            let name_len = bytes[2..]
+                .iter()
+                .take_while(|b| b.is_ascii_alphanumeric())
+                .count();
+            if name_len == 0 {
+                return None;
+            }
+
+            let name = s[2..2 + name_len].to_ascii_lowercase();
+            let len = s.find('>').map_or(s.len(), |i| i + 1);
+            Some((Tag::Close { name }, len))
+        }
+        b if b.is_ascii_alphabetic() => {
+            let name_len = bytes[1..]
+                .iter()
+                .take_while(|b| b.is_ascii_alphanumeric())
+                .count();
+            let name = s[1..1 + name_len].to_ascii_lowercase();
+            let mut attributes = Vec::new();
+            let mut i = 1 + name_len;
+            let is_name_end = |b: u8| b.is_ascii_whitespace() || matches!(b, b'=' | b'>' | b'/');
+            loop {
+                while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                    i += 1;
+                }
+                match bytes.get(i) {
+                    None => {
+                        return Some((
+                            Tag::Open {
+                                name,
+                                attributes,
+                                self_closing: false,
+                            },
+                            s.len(),
+                        ))
+                    }
+                    Some(b'>') => {
+                        return Some((
+                            Tag::Open {
+                                name,
+                                attributes,
+                                self_closing: false,
+                            },
+                            i + 1,
+                        ))
+                    }
+                    Some(b'/') if bytes.get(i + 1) == Some(&b'>') => {
+                        return Some((
+                            Tag::Open {
+                                name,
+                                attributes,
+                                self_closing: true,
+                            },
+                            i + 2,
+                        ))
+                    }
+                    Some(b'/') => {
+                        i += 1;
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                let start = i;
+                while i < bytes.len() && !is_name_end(bytes[i]) {
+                    i += 1;
+                }
+                let attr_name = s[start..i].to_ascii_lowercase();
+                while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                    i += 1;
+                }
+                if bytes.get(i) != Some(&b'=') {
+                    attributes.push((attr_name, String::new()));
+                    continue;
+                }
+
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                    i += 1;
+                }
+                let value = match bytes.get(i) {
+                    Some(&quote @ (b'"' | b'\'')) => {
+                        let start = i + 1;
+                        let end = s[start..]
+                            .find(quote as char)
+                            .map_or(s.len(), |len| start + len);
+                        i = (end + 1).min(s.len());
+                        &s[start..end]
+                    }
+                    _ => {
+                        let start = i;
+                        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>'
+                        {
+                            i += 1;
+                        }
+                        &s[start..i]
+                    }
+                };
+                attributes.push((attr_name, decode_entities(value).into_owned()));
+            }
+        }
+        _ => None,
+    }
+}
+
+fn decode_entities(s: &str) -> Cow<'_, str> {
+    if !s.contains('&') {
+        return Cow::Borrowed(s);
+    }
+
+    let mut ans = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        ans.push_str(&rest[..i]);
+        rest = &rest[i..];
+        match decode_entity(rest) {
+            Some((c, len)) => {
+                ans.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                ans.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    ans.push_str(rest);
+    Cow::Owned(ans)
+}
+
+/// Decode the entity at the start of `s`. Returns the char and the length of the entity.
+fn decode_entity(s: &str) -> Option<(char, usize)> {
+    let end = s.bytes().take(32).position(|b| b == b';')?;
+    let name = &s[1..end];
+    let c = match name.strip_prefix('#') {
+        Some(num) => {
+            let code = match num.strip_prefix(|c| c == 'x' || c == 'X') {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => num.parse().ok()?,
+            };
+            char::from_u32(code)
+                .filter(|c| *c != '\0')
+                .unwrap_or('\u{FFFD}')
+        }
+        None => match name {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            "nbsp" => '\u{a0}',
+            _ => return None,
+        },
+    };
+    Some((c, end + 1))
+}
+
+/// Read the HTML into the text segments with their styles
+struct HtmlReader<'a, F> {
+    mapping: &'a HtmlMapping,
+    is_configured: F,
+    segments: Vec<(String, FxHashMap<String, LoroValue>)>,
+    /// The open elements and the styles set by them
+    stack: Vec<(String, Option<(String, LoroValue)>)>,
+    /// Whether the last char is a space that can be collapsed
+    collapsible_space: bool,
+    /// Whether a block has ended or started, so the next text starts on a new line
+    pending_line_break: bool,
+}
+
+impl<'a, F: Fn(&str) -> bool> HtmlReader<'a, F> {
+    fn new(mapping: &'a HtmlMapping, is_configured: F) -> Self {
+        Self {
+            mapping,
+            is_configured,
+            segments: Vec::new(),
+            stack: Vec::new(),
+            collapsible_space: false,
+            pending_line_break: false,
+        }
+    }
+
+    fn read(mut self, html: &str) -> Vec<(String, FxHashMap<String, LoroValue>)> {
+        let mapping = self.mapping;
+        let mut rest = html;
+        while !rest.is_empty() {
+            if !rest.starts_with('<') {
+                let end = rest.find('<').unwrap_or(rest.len());
+                self.push_text(&decode_entities(&rest[..end]));
+                rest = &rest[end..];
+                continue;
+            }
+
+            let Some((tag, len)) = parse_tag(rest) else {
+                self.push_text("<");
+                rest = &rest[1..];
+                continue;
+            };
+            rest = &rest[len..];
+            match tag {
+                Tag::Open {
+                    name,
+                    attributes,
+                    self_closing,
+                } => {
+                    let has_content = !self_closing && !VOID_TAGS.contains(&name.as_str());
+                    if DROPPED_TAGS.contains(&name.as_str()) {
+                        if has_content {
+                            rest = skip_element(rest, &name);
+                        }
+                        continue;
+                    }
+
+                    if let Some(rule) = mapping.embed_for_tag(&name) {
+                        let value: FxHashMap<String, LoroValue> = attributes
+                            .into_iter()
+                            .filter(|(name, value)| {
+                                rule.attributes.contains(name) && is_safe_value(value)
+                            })
+                            .map(|(name, value)| (name, LoroValue::from(value)))
+                            .collect();
+                        let mut embed = FxHashMap::default();
+                        embed.insert(rule.key.clone(), LoroValue::Map(value.into()));
+                        self.push_char(
+                            EMBED_CHAR,
+                            Some((EMBED_KEY.to_string(), LoroValue::Map(embed.into()))),
+                        );
+                        if has_content {
+                            rest = skip_element(rest, &name);
+                        }
+                        continue;
+                    }
+
+                    if name == "br" {
+                        self.trim_collapsible_space();
+                        self.push_char('\n', None);
+                        continue;
+                    }
+
+                    if BLOCK_TAGS.contains(&name.as_str()) {
+                        self.start_block();
+                    }
+
+                    if has_content {
+                        let style = mapping
+                            .style_for_tag(&name)
+                            .filter(|rule| (self.is_configured)(&rule.key))
+                            .and_then(|rule| {
+                                let value = match &rule.attribute {
+                                    None => LoroValue::Bool(true),
+                                    Some(attribute) => {
+                                        let (_, value) =
+                                            attributes.iter().find(|(n, _)| n == attribute)?;
+                                        if !is_safe_value(value) {
+                                            return None;
+                                        }
+                                        LoroValue::from(value.as_str())
+                                    }
+                                };
+                                Some((rule.key.clone(), value))
+                            });
+                        self.stack.push((name, style));
+                    }
+                }
+                Tag::Close { name } => {
+                    if let Some(index) = self.stack.iter().rposition(|(n, _)| n == &name) {
+                        self.stack.truncate(index);
+                        if BLOCK_TAGS.contains(&name.as_str()) {
+                            self.start_block();
+                        }
+                    }
+                }
+                Tag::Other => {}
+            }
+        }
+
+        self.trim_collapsible_space();
+        self.segments
+    }
+
+    fn in_pre(&self) -> bool {
+        self.stack.iter().any(|(name, _)| name == "pre")
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.segments
+            .last()
+            .map_or(true, |(text, _)| text.ends_with('\n'))
+    }
+
+    fn start_block(&mut self) {
+        self.trim_collapsible_space();
+        self.pending_line_break = true;
+    }
+
+    fn trim_collapsible_space(&mut self) {
+        if !self.collapsible_space {
+            return;
+        }
+
+        self.collapsible_space = false;
+        let (text, _) = self.segments.last_mut().unwrap();
+        text.pop();
+        if text.is_empty() {
+            self.segments.pop();
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        let in_pre = self.in_pre();
+        for c in text.chars() {
+            match c {
+                '\r' if in_pre => {}
+                ' ' | '\t' | '\n' | '\r' | '\x0c' if !in_pre => {
+                    if !self.collapsible_space && !self.pending_line_break && !self.at_line_start()
+                    {
+                        self.push_char(' ', None);
+                        self.collapsible_space = true;
+                    }
+                }
+                '\u{a0}' => self.push_char(' ', None),
+                c => self.push_char(c, None),
+            }
+        }
+    }
+
+    fn push_char(&mut self, c: char, extra_style: Option<(String, LoroValue)>) {
+        if std::mem::take(&mut self.pending_line_break) && !self.at_line_start() {
+            self.push_char('\n', None);
+        }
+
+        self.collapsible_space = false;
+        let mut styles = FxHashMap::default();
+        for (_, style) in self.stack.iter() {
+            if let Some((key, value)) = style {
+                styles.insert(key.clone(), value.clone());
+            }
+        }
+        if let Some((key, value)) = extra_style {
+            styles.insert(key, value);
+        }
+
+        match self.segments.last_mut() {
+            Some((text, last_styles)) if *last_styles == styles => text.push(c),
+            _ => self.segments.push((c.to_string(), styles)),
+        }
+    }
+}
+
+/// Skip the content of the element and its closing tag
+fn skip_element<'s>(rest: &'s str, name: &str) -> &'s str {
+    let closing = format!("</{}", name);
+    match rest.to_ascii_lowercase().find(&closing) {
+        Some(i) => {
+            let rest = &rest[i..];
+            rest.find('>').map_or("", |end| &rest[end + 1..])
+        }
+        None => "",
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::VecDeque;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::*;
+
+/// The options of [`TextHandler::find`] and [`TextHandler::replace_all`]
+#[derive(Debug, Clone)]
+pub struct TextSearchOptions {
+    /// Whether the letter case matters. Defaults to `true`.
+    ///
+    /// The case-insensitive search of a plain pattern uses the simple case folding, i.e. each
+    /// char is compared by its first lowercase char.
+    pub case_sensitive: bool,
+    /// Only match the text that is not preceded or followed by a letter, a digit or `_`.
+    /// Defaults to `false`.
+    pub whole_word: bool,
+    /// The unit of [`TextMatch::range`]. Defaults to [`TextPosType::Unicode`].
+    ///
+    /// The matches that don't start or end on a grapheme boundary are skipped when it's
+    /// [`TextPosType::Grapheme`].
+    pub pos_type: TextPosType,
+    /// Whether to create the [`TextMatch::text_range`] of each match. Defaults to `false`.
+    pub with_text_ranges: bool,
+}
+
+impl Default for TextSearchOptions {
+    fn default() -> Self {
+        Self {
+            case_sensitive: true,
+            whole_word: false,
+            pos_type: TextPosType::Unicode,
+            with_text_ranges: false,
+        }
+    }
+}
+
+/// A match of the search on the text
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMatch {
+    /// The range of the match in the unit of [`TextSearchOptions::pos_type`]
+    pub range: Range<usize>,
+    /// The [`TextRange`] that keeps covering the matched text when the text is edited.
+    ///
+    /// It's `None` unless [`TextSearchOptions::with_text_ranges`] is set and the text is
+    /// attached.
+    pub text_range: Option<TextRange>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Pos {
+    unicode: usize,
+    utf16: usize,
+    utf8: usize,
+}
+
+impl Pos {
+    fn advance(&mut self, c: char) {
+        self.unicode += 1;
+        self.utf16 += c.len_utf16();
+        self.utf8 += c.len_utf8();
+    }
+
+    fn event(&self) -> usize {
+        if cfg!(feature = "wasm") {
+            self.utf16
+        } else {
+            self.unicode
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RawMatch {
+    start: Pos,
+    end: Pos,
+    text: String,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn fold_case(c: char, case_sensitive: bool) -> char {
+    if case_sensitive {
+        c
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+fn event_len(s: &str) -> usize {
+    if cfg!(feature = "wasm") {
+        count_utf16_len(s.as_bytes())
+    } else {
+        s.chars().count()
+    }
+}
+
+/// Finds the non-overlapping matches of a plain pattern with the KMP algorithm, so the text
+/// can be fed chunk by chunk.
+struct PlainMatcher {
+    pattern: Vec<char>,
+    fail: Vec<usize>,
+    case_sensitive: bool,
+    whole_word: bool,
+    matched: usize,
+    pos: Pos,
+    last_char: Option<char>,
+    /// The recent chars with the positions before them and the chars preceding them
+    window: VecDeque<(Pos, char, Option<char>)>,
+    /// The match that waits for the next char to check the word boundary
+    pending: Option<RawMatch>,
+    matches: Vec<RawMatch>,
+}
+
+impl PlainMatcher {
+    fn new(pattern: &str, options: &TextSearchOptions) -> Self {
+        let pattern: Vec<char> = pattern
+            .chars()
+            .map(|c| fold_case(c, options.case_sensitive))
+            .collect();
+        let mut fail = vec![0; pattern.len()];
+        let mut k = 0;
+        for i in 1..pattern.len() {
+            while k > 0 && pattern[i] != pattern[k] {
+                k = fail[k - 1];
+            }
+            if pattern[i] == pattern[k] {
+                k += 1;
+            }
+            fail[i] = k;
+        }
+
+        Self {
+            fail,
+            window: VecDeque::with_capacity(pattern.len()),
+            pattern,
+            case_sensitive: options.case_sensitive,
+            whole_word: options.whole_word,
+            matched: 0,
+            pos: Pos::default(),
+            last_char: None,
+            pending: None,
+            matches: Vec::new(),
+        }
+    }
+
+    fn feed(&mut self, s: &str) {
+        for c in s.chars() {
+            self.feed_char(c);
+        }
+    }
+
+    fn feed_char(&mut self, c: char) {
+        if let Some(pending) = self.pending.take() {
+            if !is_word_char(c) {
+                self.matches.push(pending);
+            }
+        }
+
+        if self.window.len() == self.pattern.len() {
+            self.window.pop_front();
+        }
+        self.window.push_back((self.pos, c, self.last_char));
+        self.last_char = Some(c);
+        self.pos.advance(c);
+
+        let folded = fold_case(c, self.case_sensitive);
+        while self.matched > 0 && self.pattern[self.matched] != folded {
+            self.matched = self.fail[self.matched - 1];
+        }
+        if self.pattern[self.matched] == folded {
+            self.matched += 1;
+        }
+        if self.matched < self.pattern.len() {
+            return;
+        }
+
+        let (start, _, before) = self.window[0];
+        if self.whole_word && before.is_some_and(is_word_char) {
+            self.matched = self.fail[self.matched - 1];
+            return;
+        }
+
+        let m = RawMatch {
+            start,
+            end: self.pos,
+            text: self.window.iter().map(|(_, c, _)| *c).collect(),
+        };
+        self.matched = 0;
+        if self.whole_word {
+            self.pending = Some(m);
+        } else {
+            self.matches.push(m);
+        }
+    }
+
+    fn finish(mut self) -> Vec<RawMatch> {
+        if let Some(pending) = self.pending.take() {
+            self.matches.push(pending);
+        }
+        self.matches
+    }
+}
+
+impl TextHandler {
+    /// Find the non-overlapping occurrences of `pattern` in the text.
+    ///
+    /// The text is scanned chunk by chunk without being copied, unless the ranges are
+    /// requested in [`TextPosType::Grapheme`].
+    pub fn find(&self, pattern: &str, options: &TextSearchOptions) -> Vec<TextMatch> {
+        let matches = self.find_raw(pattern, options);
+        self.to_text_matches(matches, options)
+    }
+
+    /// Replace all the non-overlapping occurrences of `pattern` with `replacement`.
+    ///
+    /// Only the matched text is edited, and the chars a match shares with the replacement at
+    /// its both ends are kept. All the edits are in the same transaction.
+    ///
+    /// Returns the number of the replaced matches.
+    pub fn replace_all(
+        &self,
+        pattern: &str,
+        replacement: &str,
+        options: &TextSearchOptions,
+    ) -> LoroResult<usize> {
+        let matches = self.find_raw(pattern, options);
+        self.replace_matches(
+            matches
+                .into_iter()
+                .map(|m| (m, replacement.to_string()))
+                .collect(),
+        )
+    }
+
+    /// Find the non-overlapping matches of the regular expression in the text.
+    ///
+    /// [`TextSearchOptions::case_sensitive`] and [`TextSearchOptions::whole_word`] are
+    /// applied to the regex. Unlike [`TextHandler::find`], the text is copied to be searched.
+    #[cfg(feature = "text-regex")]
+    pub fn find_regex(
+        &self,
+        pattern: &str,
+        options: &TextSearchOptions,
+    ) -> LoroResult<Vec<TextMatch>> {
+        let regex = build_regex(pattern, options)?;
+        let text = self.to_string();
+        let matches = regex_matches(&text, regex.find_iter(&text).map(|m| m.range()));
+        Ok(self.to_text_matches(matches, options))
+    }
+
+    /// Replace all the non-overlapping matches of the regular expression with `replacement`.
+    ///
+    /// The replacement can refer to the capture groups like `$1` or `${name}`. Only the
+    /// matched text is edited, and all the edits are in the same transaction.
+    ///
+    /// Returns the number of the replaced matches.
+    #[cfg(feature = "text-regex")]
+    pub fn replace_all_regex(
+        &self,
+        pattern: &str,
+        replacement: &str,
+        options: &TextSearchOptions,
+    ) -> LoroResult<usize> {
+        let regex = build_regex(pattern, options)?;
+        let text = self.to_string();
+        let mut ranges = Vec::new();
+        let mut replacements = Vec::new();
+        for caps in regex.captures_iter(&text) {
+            let mut expanded = String::new();
+            caps.expand(replacement, &mut expanded);
+            ranges.push(caps.get(0).unwrap().range());
+            replacements.push(expanded);
+        }
+
+        let matches = regex_matches(&text, ranges.into_iter());
+        self.replace_matches(matches.into_iter().zip(replacements).collect())
+    }
+
+    fn find_raw(&self, pattern: &str, options: &TextSearchOptions) -> Vec<RawMatch> {
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matcher = PlainMatcher::new(pattern, options);
+        self.iter(|chunk| {
+            matcher.feed(chunk);
+            true
+        });
+        matcher.finish()
+    }
+
+    fn to_text_matches(
+        &self,
+        matches: Vec<RawMatch>,
+        options: &TextSearchOptions,
+    ) -> Vec<TextMatch> {
+        let graphemes: Vec<usize> = if options.pos_type == TextPosType::Grapheme {
+            let text = self.to_string();
+            text.grapheme_indices(true)
+                .map(|(i, _)| i)
+                .chain(std::iter::once(text.len()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        matches
+            .into_iter()
+            .filter_map(|m| {
+                let range = match options.pos_type {
+                    TextPosType::Unicode => m.start.unicode..m.end.unicode,
+                    TextPosType::Utf16 => m.start.utf16..m.end.utf16,
+                    TextPosType::Utf8 => m.start.utf8..m.end.utf8,
+                    TextPosType::Grapheme => {
+                        let start = graphemes.binary_search(&m.start.utf8).ok()?;
+                        let end = graphemes.binary_search(&m.end.utf8).ok()?;
+                        start..end
+                    }
+                };
+                let text_range = if options.with_text_ranges {
+                    self.get_range(m.start.event(), m.end.event(), ExpandType::None)
+                } else {
+                    None
+                };
+                Some(TextMatch { range, text_range })
+            })
+            .collect()
+    }
+
+    fn replace_matches(&self, matches: Vec<(RawMatch, String)>) -> LoroResult<usize> {
+        let count = matches.len();
+        let mut splices = Vec::with_capacity(count);
+        // Splice from the end so that the positions of the other matches are not shifted
+        for (m, replacement) in matches.into_iter().rev() {
+            let prefix = m
+                .text
+                .chars()
+                .zip(replacement.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a.len_utf8())
+                .sum::<usize>();
+            let suffix = m.text[prefix..]
+                .chars()
+                .rev()
+                .zip(replacement[prefix..].chars().rev())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a.len_utf8())
+                .sum::<usize>();
+            let deleted = &m.text[prefix..m.text.len() - suffix];
+            let inserted = &replacement[prefix..replacement.len() - suffix];
+            if deleted.is_empty() && inserted.is_empty() {
+                continue;
+            }
+
+            let pos = m.start.event() + event_len(&m.text[..prefix]);
+            splices.push((pos, event_len(deleted), inserted.to_string()));
+        }
+
+        match &self.inner {
+            MaybeDetached::Detached(_) => {
+                for (pos, len, s) in splices {
+                    if len > 0 {
+                        self.delete(pos, len)?;
+                    }
+                    if !s.is_empty() {
+                        self.insert(pos, &s)?;
+                    }
+                }
+            }
+            MaybeDetached::Attached(a) => a.with_txn(|txn| {
+                for (pos, len, s) in splices {
+                    if len > 0 {
+                        self.delete_with_txn(txn, pos, len)?;
+                    }
+                    if !s.is_empty() {
+                        self.insert_with_txn(txn, pos, &s)?;
+                    }
+                }
+                Ok(())
+            })?,
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(feature = "text-regex")]
+fn build_regex(pattern: &str, options: &TextSearchOptions) -> LoroResult<regex::Regex> {
+    let pattern = if options.whole_word {
+        format!(r"\b(?:{})\b", pattern)
+    } else {
+        pattern.to_string()
+    };
+    regex::RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .map_err(|e| LoroError::ArgErr(format!("Invalid regex: {}", e).into_boxed_str()))
+}
+
+/// Convert the sorted byte ranges of the matches into [`RawMatch`]es in one pass
+#[cfg(feature = "text-regex")]
+fn regex_matches(text: &str, ranges: impl Iterator<Item = Range<usize>>) -> Vec<RawMatch> {
+    let mut chars = text.chars();
+    let mut pos = Pos::default();
+    let mut advance_to = |pos: &mut Pos, utf8: usize| {
+        while pos.utf8 < utf8 {
+            pos.advance(chars.next().unwrap());
+        }
+    };
+
+    let mut ans = Vec::new();
+    for range in ranges {
+        // Skip the empty matches, which don't select any text
+        if range.is_empty() {
+            continue;
+        }
+
+        advance_to(&mut pos, range.start);
+        let start = pos;
+        advance_to(&mut pos, range.end);
+        ans.push(RawMatch {
+            start,
+            end: pos,
+            text: text[range].to_string(),
+        });
+    }
+    ans
+}
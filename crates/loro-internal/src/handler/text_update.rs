@@ -3,10 +3,11 @@ use std::{char, sync::Arc};
 use fxhash::FxHashMap;
 use itertools::Itertools;
 use tracing::trace;
+use unicode_segmentation::UnicodeSegmentation;
 
-use crate::diff::DiffHandler;
+use crate::diff::{diff_impl::UpdateGranularity, DiffHandler};
 
-use super::TextHandler;
+use super::{event_len, TextDelta, TextHandler};
 
 pub(super) struct DiffHook<'a> {
     text: &'a TextHandler,
@@ -160,3 +161,115 @@ impl DiffHandler for DiffHookForLine<'_> {
             .unwrap();
     }
 }
+
+/// Split the text into the tokens of the granularity. The tokens cover the whole text.
+fn tokenize(s: &str, granularity: UpdateGranularity) -> Vec<&str> {
+    match granularity {
+        UpdateGranularity::Char => s
+            .char_indices()
+            .map(|(i, c)| &s[i..i + c.len_utf8()])
+            .collect(),
+        UpdateGranularity::Word => s.split_word_bounds().collect(),
+        UpdateGranularity::Line => s.split_inclusive('\n').collect(),
+        UpdateGranularity::Sentence => s.split_sentence_bounds().collect(),
+    }
+}
+
+/// Records the diff of the tokens as a [`TextDelta`] in Event Indexes, so the ops are
+/// aligned to the token boundaries
+pub(super) struct DiffHookForTokens<'a> {
+    old: Vec<u32>,
+    new: Vec<u32>,
+    tokens: Vec<&'a str>,
+    tokens_lookup: FxHashMap<&'a str, u32>,
+    delta: Vec<TextDelta>,
+    last_old_index: usize,
+}
+
+impl<'a> DiffHookForTokens<'a> {
+    pub(crate) fn new(old_str: &'a str, new_str: &'a str, granularity: UpdateGranularity) -> Self {
+        let mut this = Self {
+            old: Vec::new(),
+            new: Vec::new(),
+            tokens: Vec::new(),
+            tokens_lookup: FxHashMap::default(),
+            delta: Vec::new(),
+            last_old_index: 0,
+        };
+
+        for token in tokenize(old_str, granularity) {
+            let id = this.register_token(token);
+            this.old.push(id);
+        }
+
+        for token in tokenize(new_str, granularity) {
+            let id = this.register_token(token);
+            this.new.push(id);
+        }
+
+        this
+    }
+
+    fn register_token(&mut self, token: &'a str) -> u32 {
+        if let Some(&id) = self.tokens_lookup.get(token) {
+            return id;
+        }
+
+        let id = self.tokens.len() as u32;
+        self.tokens.push(token);
+        self.tokens_lookup.insert(token, id);
+        id
+    }
+
+    pub fn get_old_arr(&self) -> &[u32] {
+        &self.old
+    }
+
+    pub fn get_new_arr(&self) -> &[u32] {
+        &self.new
+    }
+
+    pub fn into_delta(self) -> Vec<TextDelta> {
+        self.delta
+    }
+
+    fn event_len_of(&self, ids: &[u32]) -> usize {
+        ids.iter()
+            .map(|id| event_len(self.tokens[*id as usize]))
+            .sum()
+    }
+
+    fn retain_to(&mut self, old_index: usize) {
+        if self.last_old_index < old_index {
+            let retain = self.event_len_of(&self.old[self.last_old_index..old_index]);
+            self.delta.push(TextDelta::Retain {
+                retain,
+                attributes: None,
+            });
+            self.last_old_index = old_index;
+        }
+    }
+}
+
+impl DiffHandler for DiffHookForTokens<'_> {
+    fn insert(&mut self, old_index: usize, new_index: usize, new_len: usize) {
+        trace!("insert tokens {old_index} {new_index} {new_len}");
+        self.retain_to(old_index);
+        let s = self.new[new_index..new_index + new_len]
+            .iter()
+            .map(|id| self.tokens[*id as usize])
+            .join("");
+        self.delta.push(TextDelta::Insert {
+            insert: s,
+            attributes: None,
+        });
+    }
+
+    fn delete(&mut self, old_index: usize, old_len: usize) {
+        trace!("delete tokens {old_index} {old_len}");
+        self.retain_to(old_index);
+        let delete = self.event_len_of(&self.old[old_index..old_index + old_len]);
+        self.delta.push(TextDelta::Delete { delete });
+        self.last_old_index = old_index + old_len;
+    }
+}
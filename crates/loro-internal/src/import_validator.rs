@@ -0,0 +1,188 @@
+//! Validating the imported changes before they are applied.
+//!
+//! [`LoroDoc::import`] applies every causally-ready change. When the updates come from
+//! untrusted clients, an [`ImportValidator`] can inspect each decoded change, with its ops
+//! resolved to [`ContainerID`](loro_common::ContainerID)s, and reject it with a reason.
+//! This is how a server enforces read-only containers, size limits or schemas.
+//!
+//! A rejected change is dropped, and so is every change depending on it, including the
+//! later changes of the same peer. The rejections are remembered by the doc, so the
+//! dependents arriving in later imports are rejected as well. The changes that were already
+//! pending before the rejection stay pending.
+//!
+//! The rejected changes are not included in the returned [`ImportStatus`](crate::encoding::ImportStatus).
+//! They can be inspected by [`LoroDoc::rejected_changes`].
+use std::sync::Arc;
+
+use fxhash::FxHashMap;
+use loro_common::{Counter, HasIdSpan, PeerID, ID};
+
+use crate::{
+    change::Change, encoding::json_schema::change_to_json, json::JsonChange, ChangeMeta, LoroDoc,
+    OpLog,
+};
+
+/// The hook called with each imported change before it is applied.
+///
+/// The doc is locked while the validator runs, so it must not access the doc.
+pub trait ImportValidator: Send + Sync {
+    /// Return `Err(reason)` to reject the change.
+    ///
+    /// The ids in the change are not compressed, they carry the original peers.
+    fn validate(&self, change: &JsonChange) -> Result<(), String>;
+}
+
+impl<F> ImportValidator for F
+where
+    F: Fn(&JsonChange) -> Result<(), String> + Send + Sync,
+{
+    fn validate(&self, change: &JsonChange) -> Result<(), String> {
+        self(change)
+    }
+}
+
+/// A change rejected by the [`ImportValidator`] or because it depends on a rejected change
+#[derive(Debug, Clone)]
+pub struct RejectedChange {
+    pub change: ChangeMeta,
+    pub reason: String,
+}
+
+/// The import validation states of an [`OpLog`]
+#[derive(Default)]
+pub(crate) struct ImportValidation {
+    validator: Option<Arc<dyn ImportValidator>>,
+    /// The first rejected counter of each peer.
+    ///
+    /// All the later changes of the peer depend on it, so they are rejected too.
+    rejected_from: FxHashMap<PeerID, Counter>,
+    rejected: Vec<RejectedChange>,
+}
+
+impl std::fmt::Debug for ImportValidation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImportValidation")
+            .field("has_validator", &self.validator.is_some())
+            .field("rejected_from", &self.rejected_from)
+            .field("rejected", &self.rejected.len())
+            .finish()
+    }
+}
+
+impl ImportValidation {
+    fn rejected_dep(&self, change: &Change) -> Option<ID> {
+        let peer = change.peer();
+        if let Some(&counter) = self.rejected_from.get(&peer) {
+            if change.id_last().counter >= counter {
+                return Some(ID::new(peer, counter));
+            }
+        }
+
+        change.deps.iter().find(|dep| {
+            self.rejected_from
+                .get(&dep.peer)
+                .map_or(false, |&counter| dep.counter >= counter)
+        })
+    }
+
+    fn reject(&mut self, change: Change, reason: String) {
+        let counter = self
+            .rejected_from
+            .entry(change.peer())
+            .or_insert(Counter::MAX);
+        *counter = (*counter).min(change.id.counter);
+        self.rejected.push(RejectedChange {
+            change: ChangeMeta::from_change(&change),
+            reason,
+        });
+    }
+}
+
+impl OpLog {
+    pub(crate) fn has_import_validator(&self) -> bool {
+        self.validation.validator.is_some()
+    }
+
+    /// Validate the imported changes.
+    ///
+    /// Return the changes that can be imported. The changes that are already included
+    /// in the oplog are not validated, they will be skipped by the import anyway.
+    pub(crate) fn validate_imported_changes(&mut self, changes: Vec<Change>) -> Vec<Change> {
+        if self.validation.validator.is_none() && self.validation.rejected_from.is_empty() {
+            return changes;
+        }
+
+        let rejected_before = self.validation.rejected.len();
+        let vv = self.vv();
+        let mut candidates = Vec::with_capacity(changes.len());
+        let mut accepted = Vec::with_capacity(changes.len());
+        for change in changes {
+            if vv.includes_id(change.id_last()) {
+                accepted.push(change);
+            } else {
+                candidates.push(change);
+            }
+        }
+
+        if let Some(validator) = self.validation.validator.clone() {
+            let mut valid = Vec::with_capacity(candidates.len());
+            for change in candidates {
+                match validator.validate(&change_to_json(&change, &self.arena)) {
+                    Ok(()) => valid.push(change),
+                    Err(reason) => self.validation.reject(change, reason),
+                }
+            }
+            candidates = valid;
+        }
+
+        // The changes are not in causal order, so propagate the rejections until nothing changes
+        loop {
+            let mut rest = Vec::with_capacity(candidates.len());
+            let len = candidates.len();
+            for change in candidates {
+                match self.validation.rejected_dep(&change) {
+                    Some(dep) => {
+                        let reason = format!("It depends on the rejected change {}", dep);
+                        self.validation.reject(change, reason);
+                    }
+                    None => rest.push(change),
+                }
+            }
+
+            candidates = rest;
+            if candidates.len() == len {
+                break;
+            }
+        }
+
+        let rejected = self.validation.rejected.len() - rejected_before;
+        if rejected > 0 {
+            tracing::warn!("Rejected {} changes", rejected);
+        }
+
+        accepted.extend(candidates);
+        accepted
+    }
+}
+
+impl LoroDoc {
+    /// Set the validator of the imported changes.
+    ///
+    /// Pass `None` to import the changes without validating them. The changes depending on
+    /// the previously rejected changes are still rejected.
+    pub fn set_import_validator(&self, validator: Option<Arc<dyn ImportValidator>>) {
+        self.oplog.try_lock().unwrap().validation.validator = validator;
+    }
+
+    /// Get the changes rejected by the [`ImportValidator`] and their dependents
+    pub fn rejected_changes(&self) -> Vec<RejectedChange> {
+        self.oplog.try_lock().unwrap().validation.rejected.clone()
+    }
+
+    /// Drop the records of the rejected changes.
+    ///
+    /// The changes depending on them are still rejected.
+    pub fn clear_rejected_changes(&self) {
+        self.oplog.try_lock().unwrap().validation.rejected.clear();
+    }
+}
@@ -0,0 +1,1851 @@
+//! Converting the events to [JSON Patch](https://datatracker.ietf.org/doc/html/rfc6902) and
+//! applying JSON Patch to the doc.
+//!
+//! The patches work on the JSON shape of [`LoroDoc::get_deep_value`]:
+//!
+//! - The root containers are the members of the root object.
+//! - A text is a string. Its edits are either the `replace` of the whole string or the
+//!   non-standard `splice` op, see [`TextPatchStyle`].
+//! - A tree is an array of its root nodes. Each node is an object with the `id`, `parent`,
+//!   `meta`, `index`, `fractional_index` and `children` members.
+//! - A counter is a number.
+//!
+//! The patches are built from the diffs of the events and a copy of the JSON value kept by
+//! [`JsonPatchEncoder`]. A new container is added as an empty value, and its content follows
+//! in its own patches.
+//!
+//! When a patch is applied, the new objects and arrays become maps and lists.
+use std::sync::{Arc, Mutex};
+
+use fxhash::{FxHashMap, FxHashSet};
+use generic_btree::rle::HasLength as _;
+use loro_common::{ContainerID, ContainerType, InternalString, LoroError, LoroValue, TreeID};
+use loro_delta::DeltaItem;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    container::richtext::richtext_state::{unicode_to_utf8_index, utf16_to_utf8_index},
+    delta::{ResolvedMapDelta, TreeDiffItem, TreeExternalDiff},
+    diff::diff_impl::UpdateOptions,
+    event::{Diff, Index, ListDiff, TextDiff},
+    handler::{Handler, HandlerTrait, MapHandler, TextHandler, TreeHandler, ValueOrHandler},
+    DocDiff, LoroDoc, Subscription, TreeParentId,
+};
+
+/// A JSON Patch operation.
+///
+/// Besides the operations of RFC 6902, it has the `splice` extension for the text edits.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum JsonPatchOp {
+    Add {
+        path: String,
+        value: LoroValue,
+    },
+    Remove {
+        path: String,
+    },
+    Replace {
+        path: String,
+        value: LoroValue,
+    },
+    Move {
+        from: String,
+        path: String,
+    },
+    Copy {
+        from: String,
+        path: String,
+    },
+    Test {
+        path: String,
+        value: LoroValue,
+    },
+    /// Delete `delete` characters at `pos` of the string, then insert `insert` there.
+    ///
+    /// - if feature="wasm", `pos` and `delete` are UTF-16 indexes
+    /// - if feature!="wasm", `pos` and `delete` are Unicode indexes
+    Splice {
+        path: String,
+        pos: usize,
+        delete: usize,
+        insert: String,
+    },
+}
+
+/// How the text edits are converted to JSON Patch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextPatchStyle {
+    /// `replace` the whole string. It works with every JSON Patch consumer.
+    #[default]
+    Replace,
+    /// Use the non-standard `splice` op for each edit
+    Splice,
+}
+
+#[derive(Error, Debug)]
+pub enum JsonPatchError {
+    #[error("Invalid JSON pointer: {0}")]
+    InvalidPointer(String),
+    #[error("Path not found: {0}")]
+    PathNotFound(String),
+    #[error("Test failed at {0}")]
+    TestFailed(String),
+    #[error("Cannot apply `{op}` at {path}: {reason}")]
+    InvalidOp {
+        op: &'static str,
+        path: String,
+        reason: String,
+    },
+    #[error(transparent)]
+    Loro(#[from] LoroError),
+}
+
+pub type JsonPatchCallback = Arc<dyn Fn(Vec<JsonPatchOp>) + Send + Sync>;
+
+fn push_segment(pointer: &mut String, segment: &str) {
+    pointer.push('/');
+    pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+}
+
+fn child_pointer(pointer: &str, segment: &str) -> String {
+    let mut ans = pointer.to_string();
+    push_segment(&mut ans, segment);
+    ans
+}
+
+fn parse_pointer(pointer: &str) -> Result<Vec<String>, JsonPatchError> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(JsonPatchError::InvalidPointer(pointer.to_string()));
+    };
+
+    rest.split('/')
+        .map(|segment| {
+            let mut ans = String::with_capacity(segment.len());
+            let mut chars = segment.chars();
+            while let Some(c) = chars.next() {
+                if c != '~' {
+                    ans.push(c);
+                    continue;
+                }
+
+                match chars.next() {
+                    Some('0') => ans.push('~'),
+                    Some('1') => ans.push('/'),
+                    _ => return Err(JsonPatchError::InvalidPointer(pointer.to_string())),
+                }
+            }
+            Ok(ans)
+        })
+        .collect()
+}
+
+/// Whether `pointer` is `prefix` or a location inside it
+fn is_under(pointer: &str, prefix: &str) -> bool {
+    pointer
+        .strip_prefix(prefix)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The path segments of a tree node, relative to the tree
+fn node_segments(tree: &TreeHandler, mut node: TreeID) -> Option<Vec<String>> {
+    let mut ans = Vec::new();
+    loop {
+        ans.push(tree.get_index_by_tree_id(&node)?.to_string());
+        match tree.get_node_parent(&node)? {
+            TreeParentId::Node(parent) => {
+                ans.push("children".to_string());
+                node = parent;
+            }
+            TreeParentId::Root => break,
+            TreeParentId::Deleted | TreeParentId::Unexist => return None,
+        }
+    }
+
+    ans.reverse();
+    Some(ans)
+}
+
+fn value_at<'a>(mut value: &'a LoroValue, segments: &[String]) -> Option<&'a LoroValue> {
+    for segment in segments {
+        value = match value {
+            LoroValue::List(list) => list.get(segment.parse::<usize>().ok()?)?,
+            LoroValue::Map(map) => map.get(segment.as_str())?,
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+/// The value of a container kept by [`JsonPatchEncoder`]
+enum Mirror {
+    /// The shallow value, where the child containers are [`LoroValue::Container`]
+    Value(LoroValue),
+    Tree(TreeMirror),
+}
+
+/// The structure of a tree kept by [`JsonPatchEncoder`]
+#[derive(Default)]
+struct TreeMirror {
+    /// The children of each node. The roots are the children of `None`.
+    children: FxHashMap<Option<TreeID>, Vec<TreeID>>,
+    /// The parent and the fractional index of each node
+    nodes: FxHashMap<TreeID, (Option<TreeID>, String)>,
+}
+
+impl TreeMirror {
+    /// Load the nodes of the shallow value of a tree
+    fn load(&mut self, parent: Option<TreeID>, value: &LoroValue) {
+        let Some(nodes) = value.as_list() else {
+            return;
+        };
+
+        for node in nodes.iter() {
+            let Some(obj) = node.as_map() else {
+                continue;
+            };
+            let Some(id) = obj
+                .get("id")
+                .and_then(|id| id.as_string())
+                .and_then(|id| TreeID::try_from(id.as_str()).ok())
+            else {
+                continue;
+            };
+
+            let fractional_index = obj
+                .get("fractional_index")
+                .and_then(|f| f.as_string())
+                .map(|f| f.as_str().to_string())
+                .unwrap_or_default();
+            self.insert(id, parent, usize::MAX, fractional_index);
+            if let Some(children) = obj.get("children") {
+                self.load(Some(id), children);
+            }
+        }
+    }
+
+    fn insert(
+        &mut self,
+        node: TreeID,
+        parent: Option<TreeID>,
+        index: usize,
+        fractional_index: String,
+    ) {
+        self.detach(node);
+        let children = self.children.entry(parent).or_default();
+        children.insert(index.min(children.len()), node);
+        self.nodes.insert(node, (parent, fractional_index));
+    }
+
+    fn detach(&mut self, node: TreeID) {
+        if let Some((parent, _)) = self.nodes.get(&node) {
+            if let Some(children) = self.children.get_mut(parent) {
+                children.retain(|&c| c != node);
+            }
+        }
+    }
+
+    /// Remove the node and its descendants. Return the removed nodes.
+    fn remove(&mut self, node: TreeID) -> Vec<TreeID> {
+        self.detach(node);
+        let mut removed = Vec::new();
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            if self.nodes.remove(&node).is_some() {
+                removed.push(node);
+            }
+            stack.extend(self.children.remove(&Some(node)).unwrap_or_default());
+        }
+        removed
+    }
+
+    /// The path segments of a node, relative to the tree
+    fn segments(&self, mut node: TreeID) -> Option<Vec<String>> {
+        let mut ans = Vec::new();
+        loop {
+            let (parent, _) = self.nodes.get(&node)?;
+            let index = self.children.get(parent)?.iter().position(|&c| c == node)?;
+            ans.push(index.to_string());
+            match parent {
+                Some(parent) => {
+                    ans.push("children".to_string());
+                    node = *parent;
+                }
+                None => break,
+            }
+        }
+
+        ans.reverse();
+        Some(ans)
+    }
+
+    /// The JSON value of the children of `parent`, the same as [`LoroDoc::get_deep_value`]
+    fn children_value(
+        &self,
+        parent: Option<TreeID>,
+        meta: &dyn Fn(&ContainerID) -> LoroValue,
+    ) -> LoroValue {
+        let children = self.children.get(&parent).map_or(&[][..], |c| c.as_slice());
+        children
+            .iter()
+            .enumerate()
+            .map(|(index, &node)| {
+                let mut obj = FxHashMap::default();
+                obj.insert("id".to_string(), node.to_string().into());
+                obj.insert(
+                    "parent".to_string(),
+                    parent.map_or(LoroValue::Null, |p| p.to_string().into()),
+                );
+                obj.insert("meta".to_string(), meta(&node.associated_meta_container()));
+                obj.insert("index".to_string(), (index as i64).into());
+                let fractional_index = self
+                    .nodes
+                    .get(&node)
+                    .map(|(_, f)| f.clone())
+                    .unwrap_or_default();
+                obj.insert("fractional_index".to_string(), fractional_index.into());
+                obj.insert(
+                    "children".to_string(),
+                    self.children_value(Some(node), meta),
+                );
+                obj.into()
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+fn tree_parent(parent: &TreeParentId) -> Option<Option<TreeID>> {
+    match parent {
+        TreeParentId::Node(node) => Some(Some(*node)),
+        TreeParentId::Root => Some(None),
+        TreeParentId::Deleted | TreeParentId::Unexist => None,
+    }
+}
+
+/// The JSON value of a new container
+fn empty_value(container_type: ContainerType) -> LoroValue {
+    match container_type {
+        ContainerType::Map => LoroValue::Map(Default::default()),
+        ContainerType::List | ContainerType::MovableList | ContainerType::Tree => {
+            LoroValue::List(Default::default())
+        }
+        ContainerType::Text => LoroValue::String(Default::default()),
+        #[cfg(feature = "counter")]
+        ContainerType::Counter => LoroValue::Double(0.),
+        ContainerType::Unknown(_) => LoroValue::Null,
+    }
+}
+
+/// Converts the events of a doc to JSON Patch.
+///
+/// It's created by [`LoroDoc::json_patch_encoder`]. It keeps a copy of the JSON value of the
+/// doc, and updates it by the diffs of the events. The values in the patches are read from the
+/// copy and the diffs only, so the events can be converted at any time, as long as they're
+/// converted in order. The copy takes about as much memory as [`LoroDoc::get_deep_value`].
+///
+/// It also remembers the root containers that have been included in the patches, so the first
+/// patch of a new root container adds it.
+pub struct JsonPatchEncoder {
+    style: TextPatchStyle,
+    roots: FxHashSet<InternalString>,
+    /// The values of the containers before the next event
+    containers: FxHashMap<ContainerID, Mirror>,
+}
+
+impl JsonPatchEncoder {
+    /// The JSON value of a container. The containers that are not kept are new, so they're empty.
+    fn deep_value(&self, id: &ContainerID) -> LoroValue {
+        match self.containers.get(id) {
+            Some(Mirror::Value(value)) => self.resolve(value),
+            Some(Mirror::Tree(tree)) => tree.children_value(None, &|meta| self.deep_value(meta)),
+            None => empty_value(id.container_type()),
+        }
+    }
+
+    /// Replace the child containers in a shallow value with their JSON values
+    fn resolve(&self, value: &LoroValue) -> LoroValue {
+        match value {
+            LoroValue::Container(id) => self.deep_value(id),
+            LoroValue::List(list) => list
+                .iter()
+                .map(|v| self.resolve(v))
+                .collect::<Vec<_>>()
+                .into(),
+            LoroValue::Map(map) => map
+                .iter()
+                .map(|(k, v)| (k.clone(), self.resolve(v)))
+                .collect::<FxHashMap<_, _>>()
+                .into(),
+            v => v.clone(),
+        }
+    }
+
+    /// The shallow value of a map, a list, a text or a counter
+    fn value_mut(&mut self, id: &ContainerID) -> Option<&mut LoroValue> {
+        match self
+            .containers
+            .entry(id.clone())
+            .or_insert_with(|| Mirror::Value(empty_value(id.container_type())))
+        {
+            Mirror::Value(value) => Some(value),
+            Mirror::Tree(_) => None,
+        }
+    }
+
+    /// Drop the kept value of a container and its descendants
+    fn forget(&mut self, id: &ContainerID) {
+        let mut stack = vec![id.clone()];
+        while let Some(id) = stack.pop() {
+            match self.containers.remove(&id) {
+                Some(Mirror::Value(LoroValue::Map(map))) => {
+                    stack.extend(map.values().filter_map(|v| v.as_container().cloned()))
+                }
+                Some(Mirror::Value(LoroValue::List(list))) => {
+                    stack.extend(list.iter().filter_map(|v| v.as_container().cloned()))
+                }
+                Some(Mirror::Tree(tree)) => stack.extend(
+                    tree.nodes
+                        .keys()
+                        .map(|node| node.associated_meta_container()),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    /// The shallow value of an inserted child.
+    ///
+    /// An inserted container is new or revived, and its content is in its own diff, unless
+    /// it's moved.
+    fn insert_child(&mut self, value: &ValueOrHandler, from_move: bool) -> LoroValue {
+        match value {
+            ValueOrHandler::Value(v) => v.clone(),
+            ValueOrHandler::Handler(h) => {
+                let id = h.id();
+                if !from_move {
+                    self.forget(&id);
+                }
+                LoroValue::Container(id)
+            }
+        }
+    }
+
+    fn pointer(&self, path: &[(ContainerID, Index)]) -> Option<String> {
+        let mut ans = String::new();
+        let mut parent: Option<&ContainerID> = None;
+        for (id, index) in path {
+            match index {
+                Index::Key(key) => push_segment(&mut ans, key),
+                Index::Seq(i) => push_segment(&mut ans, &i.to_string()),
+                Index::Node(node) => {
+                    let Some(Mirror::Tree(tree)) = self.containers.get(parent?) else {
+                        return None;
+                    };
+                    for segment in tree.segments(*node)? {
+                        push_segment(&mut ans, &segment);
+                    }
+                    push_segment(&mut ans, "meta");
+                }
+            }
+            parent = Some(id);
+        }
+
+        Some(ans)
+    }
+
+    /// Convert the diff of an event.
+    ///
+    /// The events must be passed in the order they're emitted.
+    pub fn encode(&mut self, diff: &DocDiff) -> Vec<JsonPatchOp> {
+        let mut ans = Vec::new();
+        for container_diff in diff.diff.iter() {
+            if container_diff.is_unknown {
+                continue;
+            }
+
+            if let Some((ContainerID::Root { name, .. }, _)) = container_diff.path.first() {
+                if self.roots.insert(name.clone()) {
+                    ans.push(JsonPatchOp::Add {
+                        path: child_pointer("", name),
+                        value: empty_value(container_diff.path[0].0.container_type()),
+                    });
+                }
+            }
+
+            // The kept values are updated even if the container cannot be located
+            let pointer = self.pointer(&container_diff.path);
+            let mut ops = Vec::new();
+            let path = pointer.as_deref().unwrap_or_default();
+            let id = &container_diff.id;
+            match &container_diff.diff {
+                Diff::Map(map) => self.encode_map(id, map, path, &mut ops),
+                Diff::List(list) => self.encode_list(id, list, path, &mut ops),
+                Diff::Text(text) => self.encode_text(id, text, path, &mut ops),
+                Diff::Tree(tree_diff) => self.encode_tree(id, &tree_diff.diff, path, &mut ops),
+                #[cfg(feature = "counter")]
+                Diff::Counter(delta) => {
+                    if let Some(LoroValue::Double(value)) = self.value_mut(id) {
+                        *value += delta;
+                        ops.push(JsonPatchOp::Replace {
+                            path: path.to_string(),
+                            value: LoroValue::Double(*value),
+                        });
+                    }
+                }
+                Diff::Unknown => {}
+            }
+
+            if pointer.is_some() {
+                ans.extend(ops);
+            }
+        }
+
+        ans
+    }
+
+    fn encode_map(
+        &mut self,
+        id: &ContainerID,
+        map: &ResolvedMapDelta,
+        pointer: &str,
+        ans: &mut Vec<JsonPatchOp>,
+    ) {
+        let mut updated: Vec<_> = map.updated.iter().collect();
+        updated.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        for (key, v) in updated {
+            let new = v.value.as_ref().map(|v| self.insert_child(v, false));
+            let old = match self.value_mut(id) {
+                Some(LoroValue::Map(map)) => match &new {
+                    Some(new) => map.make_mut().insert(key.to_string(), new.clone()),
+                    None => map.make_mut().remove(key.as_str()),
+                },
+                _ => None,
+            };
+            if let Some(LoroValue::Container(old)) = old {
+                if new.as_ref().and_then(|v| v.as_container()) != Some(&old) {
+                    self.forget(&old);
+                }
+            }
+
+            let path = child_pointer(pointer, key);
+            match new {
+                Some(value) => ans.push(JsonPatchOp::Add {
+                    path,
+                    value: self.resolve(&value),
+                }),
+                None => ans.push(JsonPatchOp::Remove { path }),
+            }
+        }
+    }
+
+    fn encode_list(
+        &mut self,
+        id: &ContainerID,
+        list: &ListDiff,
+        pointer: &str,
+        ans: &mut Vec<JsonPatchOp>,
+    ) {
+        let mut index = 0;
+        let mut removed = Vec::new();
+        let mut moved = FxHashSet::default();
+        for item in list.iter() {
+            match item {
+                DeltaItem::Retain { len, .. } => index += len,
+                DeltaItem::Replace {
+                    value,
+                    attr,
+                    delete,
+                } => {
+                    for v in value.iter() {
+                        let v = self.insert_child(v, attr.from_move);
+                        if let Some(c) = v.as_container().filter(|_| attr.from_move) {
+                            moved.insert(c.clone());
+                        }
+                        ans.push(JsonPatchOp::Add {
+                            path: child_pointer(pointer, &index.to_string()),
+                            value: self.resolve(&v),
+                        });
+                        if let Some(LoroValue::List(list)) = self.value_mut(id) {
+                            let list = list.make_mut();
+                            list.insert(index.min(list.len()), v);
+                        }
+                        index += 1;
+                    }
+                    for _ in 0..*delete {
+                        ans.push(JsonPatchOp::Remove {
+                            path: child_pointer(pointer, &index.to_string()),
+                        });
+                        if let Some(LoroValue::List(list)) = self.value_mut(id) {
+                            let list = list.make_mut();
+                            if index < list.len() {
+                                if let LoroValue::Container(c) = list.remove(index) {
+                                    removed.push(c);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // The moved containers are removed and inserted in the same delta
+        for c in removed {
+            if !moved.contains(&c) {
+                self.forget(&c);
+            }
+        }
+    }
+
+    fn encode_text(
+        &mut self,
+        id: &ContainerID,
+        text: &TextDiff,
+        pointer: &str,
+        ans: &mut Vec<JsonPatchOp>,
+    ) {
+        let style = self.style;
+        let Some(value) = self.value_mut(id) else {
+            return;
+        };
+
+        let mut pos = 0;
+        let mut changed = false;
+        for item in text.iter() {
+            match item {
+                DeltaItem::Retain { len, .. } => pos += len,
+                DeltaItem::Replace {
+                    value: v, delete, ..
+                } => {
+                    let insert = v.to_string();
+                    // The kept string always matches the diffs
+                    let _ = splice_string(value, pos, *delete, &insert, pointer);
+                    changed = true;
+                    if style == TextPatchStyle::Splice {
+                        ans.push(JsonPatchOp::Splice {
+                            path: pointer.to_string(),
+                            pos,
+                            delete: *delete,
+                            insert,
+                        });
+                    }
+                    pos += v.rle_len();
+                }
+            }
+        }
+
+        // The changes of the styles are skipped
+        if style == TextPatchStyle::Replace && changed {
+            ans.push(JsonPatchOp::Replace {
+                path: pointer.to_string(),
+                value: value.clone(),
+            });
+        }
+    }
+
+    /// Replace the children array that contains the parents affected by the diff
+    fn encode_tree(
+        &mut self,
+        id: &ContainerID,
+        diff: &[TreeDiffItem],
+        pointer: &str,
+        ans: &mut Vec<JsonPatchOp>,
+    ) {
+        let Mirror::Tree(tree) = self
+            .containers
+            .entry(id.clone())
+            .or_insert_with(|| Mirror::Tree(Default::default()))
+        else {
+            return;
+        };
+
+        // The meta of the created nodes is new, and the meta of the deleted nodes is dropped
+        let mut reset = Vec::new();
+        let mut parents = Vec::new();
+        for item in diff {
+            match &item.action {
+                TreeExternalDiff::Create {
+                    parent,
+                    index,
+                    position,
+                } => {
+                    if let Some(p) = tree_parent(parent) {
+                        tree.insert(item.target, p, *index, position.to_string());
+                    }
+                    reset.push(item.target);
+                    parents.push(*parent);
+                }
+                TreeExternalDiff::Move {
+                    parent,
+                    index,
+                    position,
+                    old_parent,
+                    ..
+                } => {
+                    match tree_parent(parent) {
+                        Some(p) => tree.insert(item.target, p, *index, position.to_string()),
+                        None => reset.extend(tree.remove(item.target)),
+                    }
+                    parents.extend([*parent, *old_parent]);
+                }
+                TreeExternalDiff::Delete { old_parent, .. } => {
+                    reset.extend(tree.remove(item.target));
+                    parents.push(*old_parent);
+                }
+            }
+        }
+
+        let mut targets: Vec<Vec<String>> = Vec::new();
+        let mut whole_tree = false;
+        for parent in parents {
+            match parent {
+                TreeParentId::Node(node) => match tree.segments(node) {
+                    Some(mut segments) => {
+                        segments.push("children".to_string());
+                        targets.push(segments);
+                    }
+                    // The parent is deleted too
+                    None => whole_tree = true,
+                },
+                TreeParentId::Root => whole_tree = true,
+                TreeParentId::Deleted | TreeParentId::Unexist => {}
+            }
+        }
+
+        for node in reset {
+            self.forget(&node.associated_meta_container());
+        }
+
+        // Replace the innermost children array that contains all the affected parents, so
+        // that a moved node is removed and added by the same op
+        let mut common: Option<Vec<String>> = None;
+        for target in targets {
+            common = Some(match common {
+                None => target,
+                Some(common) => common
+                    .into_iter()
+                    .zip(target)
+                    .take_while(|(a, b)| a == b)
+                    .map(|(a, _)| a)
+                    .collect(),
+            });
+        }
+
+        let mut path = pointer.to_string();
+        let target = match common {
+            Some(mut common) if !whole_tree => {
+                common.truncate(common.len() / 2 * 2);
+                for segment in common.iter() {
+                    push_segment(&mut path, segment);
+                }
+                common
+            }
+            None if !whole_tree => return,
+            _ => Vec::new(),
+        };
+
+        let value = self.deep_value(id);
+        ans.push(JsonPatchOp::Replace {
+            path,
+            value: value_at(&value, &target)
+                .cloned()
+                .unwrap_or(LoroValue::Null),
+        });
+    }
+}
+
+/// The location a JSON pointer refers to
+enum Target {
+    /// A member of the root object, i.e. a root container
+    Root(String),
+    /// A member of a map or an element of a list
+    Child { parent: Handler, key: String },
+    /// A node in the roots of a tree or in the children of a tree node
+    TreeNode {
+        tree: TreeHandler,
+        parent: TreeParentId,
+        key: String,
+    },
+    /// A member of a tree node object
+    TreeNodeField {
+        tree: TreeHandler,
+        node: TreeID,
+        key: String,
+    },
+    /// A location inside a plain value stored in a container
+    Value {
+        owner: Box<Target>,
+        path: Vec<String>,
+    },
+}
+
+/// What a [`Target`] holds
+enum Resolved {
+    Handler(Handler),
+    TreeChildren(TreeHandler, TreeParentId),
+    TreeNode(TreeHandler, TreeID),
+    Value(LoroValue),
+}
+
+enum Edit<'a> {
+    Add(LoroValue),
+    Remove,
+    Replace(LoroValue),
+    Splice {
+        pos: usize,
+        delete: usize,
+        insert: &'a str,
+    },
+}
+
+impl Edit<'_> {
+    fn name(&self) -> &'static str {
+        match self {
+            Edit::Add(_) => "add",
+            Edit::Remove => "remove",
+            Edit::Replace(_) => "replace",
+            Edit::Splice { .. } => "splice",
+        }
+    }
+}
+
+fn invalid(op: &'static str, path: &str, reason: impl Into<String>) -> JsonPatchError {
+    JsonPatchError::InvalidOp {
+        op,
+        path: path.to_string(),
+        reason: reason.into(),
+    }
+}
+
+fn parse_index(key: &str, len: usize, pointer: &str) -> Result<usize, JsonPatchError> {
+    match key.parse::<usize>() {
+        Ok(i) if i < len => Ok(i),
+        _ => Err(JsonPatchError::PathNotFound(pointer.to_string())),
+    }
+}
+
+/// Parse the index of an `add`, where `-` is the end of the array
+fn parse_insert_index(key: &str, len: usize, pointer: &str) -> Result<usize, JsonPatchError> {
+    if key == "-" {
+        return Ok(len);
+    }
+
+    match key.parse::<usize>() {
+        Ok(i) if i <= len => Ok(i),
+        _ => Err(JsonPatchError::PathNotFound(pointer.to_string())),
+    }
+}
+
+fn event_index_to_utf8(s: &str, index: usize) -> Option<usize> {
+    if cfg!(feature = "wasm") {
+        utf16_to_utf8_index(s, index)
+    } else {
+        unicode_to_utf8_index(s, index)
+    }
+}
+
+fn splice_string(
+    value: &mut LoroValue,
+    pos: usize,
+    delete: usize,
+    insert: &str,
+    pointer: &str,
+) -> Result<(), JsonPatchError> {
+    let LoroValue::String(s) = value else {
+        return Err(invalid("splice", pointer, "the target is not a string"));
+    };
+
+    let s = s.make_mut();
+    let (Some(start), Some(end)) = (
+        event_index_to_utf8(s, pos),
+        event_index_to_utf8(s, pos + delete),
+    ) else {
+        return Err(invalid("splice", pointer, "the range is out of bound"));
+    };
+    s.replace_range(start..end, insert);
+    Ok(())
+}
+
+/// Apply the edit to a location inside a plain value. Return the old value.
+fn edit_value(
+    value: &mut LoroValue,
+    path: &[String],
+    edit: Edit,
+    pointer: &str,
+) -> Result<LoroValue, JsonPatchError> {
+    let not_found = || JsonPatchError::PathNotFound(pointer.to_string());
+    let (last, parents) = path.split_last().ok_or_else(not_found)?;
+    let mut parent = value;
+    for segment in parents {
+        parent = match parent {
+            LoroValue::Map(map) => map.make_mut().get_mut(segment.as_str()),
+            LoroValue::List(list) => {
+                let list = list.make_mut();
+                let i = parse_index(segment, list.len(), pointer)?;
+                list.get_mut(i)
+            }
+            _ => None,
+        }
+        .ok_or_else(not_found)?;
+    }
+
+    match parent {
+        LoroValue::Map(map) => {
+            let map = map.make_mut();
+            match edit {
+                Edit::Add(v) => Ok(map.insert(last.clone(), v).unwrap_or_default()),
+                Edit::Remove => map.remove(last.as_str()).ok_or_else(not_found),
+                Edit::Replace(v) => {
+                    let slot = map.get_mut(last.as_str()).ok_or_else(not_found)?;
+                    Ok(std::mem::replace(slot, v))
+                }
+                Edit::Splice {
+                    pos,
+                    delete,
+                    insert,
+                } => {
+                    let slot = map.get_mut(last.as_str()).ok_or_else(not_found)?;
+                    let old = slot.clone();
+                    splice_string(slot, pos, delete, insert, pointer)?;
+                    Ok(old)
+                }
+            }
+        }
+        LoroValue::List(list) => {
+            let list = list.make_mut();
+            match edit {
+                Edit::Add(v) => {
+                    let i = parse_insert_index(last, list.len(), pointer)?;
+                    list.insert(i, v);
+                    Ok(LoroValue::Null)
+                }
+                Edit::Remove => {
+                    let i = parse_index(last, list.len(), pointer)?;
+                    Ok(list.remove(i))
+                }
+                Edit::Replace(v) => {
+                    let i = parse_index(last, list.len(), pointer)?;
+                    Ok(std::mem::replace(&mut list[i], v))
+                }
+                Edit::Splice {
+                    pos,
+                    delete,
+                    insert,
+                } => {
+                    let i = parse_index(last, list.len(), pointer)?;
+                    let old = list[i].clone();
+                    splice_string(&mut list[i], pos, delete, insert, pointer)?;
+                    Ok(old)
+                }
+            }
+        }
+        _ => Err(not_found()),
+    }
+}
+
+/// Whether a container can be updated in place to hold the value
+fn is_compatible(handler: &Handler, value: &LoroValue) -> bool {
+    match (handler.c_type(), value) {
+        (ContainerType::Map, LoroValue::Map(_)) => true,
+        (
+            ContainerType::List | ContainerType::MovableList | ContainerType::Tree,
+            LoroValue::List(_),
+        ) => true,
+        (ContainerType::Text, LoroValue::String(_)) => true,
+        #[cfg(feature = "counter")]
+        (ContainerType::Counter, LoroValue::Double(_) | LoroValue::I64(_)) => true,
+        _ => false,
+    }
+}
+
+#[cfg(feature = "counter")]
+fn as_f64(value: &LoroValue) -> Option<f64> {
+    match value {
+        LoroValue::Double(d) => Some(*d),
+        LoroValue::I64(i) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+/// Update the container so that its deep value becomes `value`
+fn set_container_value(
+    handler: &Handler,
+    value: LoroValue,
+    pointer: &str,
+) -> Result<(), JsonPatchError> {
+    if handler.get_deep_value() == value {
+        return Ok(());
+    }
+
+    match (handler, value) {
+        (Handler::Map(map), LoroValue::Map(value)) => {
+            let removed: Vec<InternalString> = map
+                .keys()
+                .filter(|k| !value.contains_key(k.as_str()))
+                .collect();
+            for key in removed {
+                map.delete(key.as_str())?;
+            }
+            for (key, v) in value.iter() {
+                set_map_value(map, key, v.clone(), pointer)?;
+            }
+        }
+        (Handler::List(list), LoroValue::List(value)) => {
+            list.clear()?;
+            for (i, v) in value.iter().enumerate() {
+                insert_list_value(
+                    handler,
+                    i,
+                    v.clone(),
+                    &child_pointer(pointer, &i.to_string()),
+                )?;
+            }
+        }
+        (Handler::MovableList(list), LoroValue::List(value)) => {
+            list.clear()?;
+            for (i, v) in value.iter().enumerate() {
+                insert_list_value(
+                    handler,
+                    i,
+                    v.clone(),
+                    &child_pointer(pointer, &i.to_string()),
+                )?;
+            }
+        }
+        (Handler::Text(text), LoroValue::String(value)) => {
+            text.update(&value, UpdateOptions::default())
+                .map_err(|e| invalid("replace", pointer, e.to_string()))?;
+        }
+        (Handler::Tree(tree), value @ LoroValue::List(_)) => {
+            reconcile_tree_children(tree, TreeParentId::Root, &value, pointer)?;
+        }
+        #[cfg(feature = "counter")]
+        (Handler::Counter(counter), value) => {
+            let (Some(v), Some(current)) = (as_f64(&value), as_f64(&counter.get_value())) else {
+                return Err(invalid(
+                    "replace",
+                    pointer,
+                    "a counter can only be a number",
+                ));
+            };
+            counter.increment(v - current)?;
+        }
+        (handler, value) => {
+            return Err(invalid(
+                "replace",
+                pointer,
+                format!("{:?} cannot be set to {:?}", handler.c_type(), value),
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// Set the value of a map member. The child container is updated in place if possible.
+fn set_map_value(
+    map: &MapHandler,
+    key: &str,
+    value: LoroValue,
+    pointer: &str,
+) -> Result<(), JsonPatchError> {
+    match map.get_(key) {
+        Some(ValueOrHandler::Handler(h)) if is_compatible(&h, &value) => {
+            return set_container_value(&h, value, &child_pointer(pointer, key));
+        }
+        Some(ValueOrHandler::Value(v)) if v == value => return Ok(()),
+        _ => {}
+    }
+
+    match new_container_type(&value) {
+        Some(kind) => {
+            let child = map.insert_container(key, Handler::new_unattached(kind))?;
+            set_container_value(&child, value, &child_pointer(pointer, key))
+        }
+        None => {
+            map.insert(key, value)?;
+            Ok(())
+        }
+    }
+}
+
+/// The type of the container created for a new value. The objects and the arrays become
+/// containers, the other values are inserted as they are.
+fn new_container_type(value: &LoroValue) -> Option<ContainerType> {
+    match value {
+        LoroValue::Map(_) => Some(ContainerType::Map),
+        LoroValue::List(_) => Some(ContainerType::List),
+        _ => None,
+    }
+}
+
+/// Insert a new value into a list or a movable list
+fn insert_list_value(
+    list: &Handler,
+    index: usize,
+    value: LoroValue,
+    pointer: &str,
+) -> Result<(), JsonPatchError> {
+    let Some(kind) = new_container_type(&value) else {
+        match list {
+            Handler::List(list) => list.insert(index, value)?,
+            Handler::MovableList(list) => list.insert(index, value)?,
+            _ => unreachable!(),
+        }
+        return Ok(());
+    };
+
+    let child = Handler::new_unattached(kind);
+    let child = match list {
+        Handler::List(list) => list.insert_container(index, child)?,
+        Handler::MovableList(list) => list.insert_container(index, child)?,
+        _ => unreachable!(),
+    };
+    set_container_value(&child, value, pointer)
+}
+
+fn tree_value_at(tree: &TreeHandler, node: Option<TreeID>) -> Option<LoroValue> {
+    let value = tree.get_deep_value();
+    let segments = match node {
+        Some(node) => node_segments(tree, node)?,
+        None => Vec::new(),
+    };
+    value_at(&value, &segments).cloned()
+}
+
+fn is_alive(tree: &TreeHandler, node: TreeID) -> bool {
+    tree.contains(node) && !tree.is_node_deleted(&node).unwrap_or(true)
+}
+
+/// Makes the nodes of a tree match their JSON values.
+///
+/// The nodes are matched by their `id`, and the other nodes are created. All the nodes are
+/// placed before the unmatched ones are deleted, so a node moved to another parent keeps its id.
+struct TreeReconciler<'a> {
+    tree: &'a TreeHandler,
+    placed: FxHashSet<TreeID>,
+    /// The nodes to delete unless they are placed
+    stale: Vec<TreeID>,
+}
+
+impl<'a> TreeReconciler<'a> {
+    fn new(tree: &'a TreeHandler) -> Self {
+        Self {
+            tree,
+            placed: Default::default(),
+            stale: Vec::new(),
+        }
+    }
+
+    /// Place a node at `index` of `parent`
+    fn node(
+        &mut self,
+        parent: TreeParentId,
+        index: usize,
+        value: &LoroValue,
+        pointer: &str,
+    ) -> Result<TreeID, JsonPatchError> {
+        let tree = self.tree;
+        let LoroValue::Map(obj) = value else {
+            return Err(invalid("add", pointer, "a tree node must be an object"));
+        };
+
+        let existing = obj
+            .get("id")
+            .and_then(|id| id.as_string())
+            .and_then(|id| TreeID::try_from(id.as_str()).ok())
+            .filter(|&id| is_alive(tree, id));
+        let node = match existing {
+            Some(node) => {
+                if tree.is_fractional_index_enabled() {
+                    tree.move_to(node, parent, index)?;
+                } else if !tree.is_parent(&node, &parent) {
+                    tree.mov(node, parent)?;
+                }
+                node
+            }
+            None if tree.is_fractional_index_enabled() => tree.create_at(parent, index)?,
+            None => tree.create(parent)?,
+        };
+
+        self.placed.insert(node);
+        self.update(node, value, pointer)?;
+        Ok(node)
+    }
+
+    /// Update the meta and the children of a node
+    fn update(
+        &mut self,
+        node: TreeID,
+        value: &LoroValue,
+        pointer: &str,
+    ) -> Result<(), JsonPatchError> {
+        let LoroValue::Map(obj) = value else {
+            return Err(invalid("replace", pointer, "a tree node must be an object"));
+        };
+
+        if let Some(meta) = obj.get("meta") {
+            let meta_pointer = child_pointer(pointer, "meta");
+            let meta_map = Handler::Map(self.tree.get_meta(node)?);
+            set_container_value(&meta_map, meta.clone(), &meta_pointer)?;
+        }
+
+        if let Some(children) = obj.get("children") {
+            let children_pointer = child_pointer(pointer, "children");
+            self.children(TreeParentId::Node(node), children, &children_pointer)?;
+        }
+
+        Ok(())
+    }
+
+    fn children(
+        &mut self,
+        parent: TreeParentId,
+        value: &LoroValue,
+        pointer: &str,
+    ) -> Result<(), JsonPatchError> {
+        let LoroValue::List(children) = value else {
+            return Err(invalid("replace", pointer, "the children must be an array"));
+        };
+
+        for (i, child) in children.iter().enumerate() {
+            self.node(parent, i, child, &child_pointer(pointer, &i.to_string()))?;
+        }
+
+        self.stale
+            .extend(self.tree.children(&parent).unwrap_or_default());
+        Ok(())
+    }
+
+    /// Replace the node with the node of the value
+    fn replace(
+        &mut self,
+        old: TreeID,
+        parent: TreeParentId,
+        index: usize,
+        value: &LoroValue,
+        pointer: &str,
+    ) -> Result<(), JsonPatchError> {
+        let node = self.node(parent, index, value, pointer)?;
+        if node != old {
+            self.stale.push(old);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), JsonPatchError> {
+        for node in self.stale {
+            if !self.placed.contains(&node) && is_alive(self.tree, node) {
+                self.tree.delete(node)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn reconcile_tree_children(
+    tree: &TreeHandler,
+    parent: TreeParentId,
+    value: &LoroValue,
+    pointer: &str,
+) -> Result<(), JsonPatchError> {
+    let mut reconciler = TreeReconciler::new(tree);
+    reconciler.children(parent, value, pointer)?;
+    reconciler.finish()
+}
+
+impl LoroDoc {
+    /// Create an encoder that converts the events of the doc to JSON Patch.
+    ///
+    /// The patches apply to the deep value of the doc at the time the encoder is created.
+    pub fn json_patch_encoder(&self, style: TextPatchStyle) -> JsonPatchEncoder {
+        let roots: Vec<ContainerID> = self
+            .arena
+            .root_containers()
+            .into_iter()
+            .filter_map(|idx| self.arena.idx_to_id(idx))
+            .collect();
+        let names = roots
+            .iter()
+            .filter_map(|id| match id {
+                ContainerID::Root { name, .. } => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+
+        // Keep the shallow values of all the containers reachable from the roots
+        let mut containers = FxHashMap::default();
+        let mut stack = roots;
+        while let Some(id) = stack.pop() {
+            let handler = self.get_handler(id.clone());
+            let value = handler.get_value();
+            let mirror = match handler {
+                Handler::Tree(_) => {
+                    let mut tree = TreeMirror::default();
+                    tree.load(None, &value);
+                    stack.extend(
+                        tree.nodes
+                            .keys()
+                            .map(|node| node.associated_meta_container()),
+                    );
+                    Mirror::Tree(tree)
+                }
+                Handler::Unknown(_) => continue,
+                _ => {
+                    match &value {
+                        LoroValue::Map(map) => {
+                            stack.extend(map.values().filter_map(|v| v.as_container().cloned()))
+                        }
+                        LoroValue::List(list) => {
+                            stack.extend(list.iter().filter_map(|v| v.as_container().cloned()))
+                        }
+                        _ => {}
+                    }
+                    Mirror::Value(value)
+                }
+            };
+            containers.insert(id, mirror);
+        }
+
+        JsonPatchEncoder {
+            style,
+            roots: names,
+            containers,
+        }
+    }
+
+    /// Subscribe the changes of the doc as JSON Patch.
+    ///
+    /// The patches apply to [`LoroDoc::get_deep_value`] at the time of subscribing.
+    pub fn subscribe_json_patch(
+        &self,
+        style: TextPatchStyle,
+        callback: JsonPatchCallback,
+    ) -> Subscription {
+        let encoder = Mutex::new(self.json_patch_encoder(style));
+        self.subscribe_root(Arc::new(move |event| {
+            let patch = encoder.try_lock().unwrap().encode(event.event_meta);
+            if !patch.is_empty() {
+                callback(patch);
+            }
+        }))
+    }
+
+    /// Apply the JSON Patch to the doc in one transaction.
+    ///
+    /// The patch works on the shape of [`LoroDoc::get_deep_value`]. The existing containers
+    /// are edited in place. The new objects and arrays become maps and lists, and a new root
+    /// member becomes a root container of the matching type.
+    ///
+    /// Removing a root member clears the root container, because the root containers cannot
+    /// be deleted. The fields of the tree nodes other than `meta` and `children` are read-only.
+    ///
+    /// The patch is atomic. If an operation fails, the whole transaction is rolled back.
+    pub fn apply_json_patch(&self, patch: &[JsonPatchOp]) -> Result<(), JsonPatchError> {
+        self.transact(|doc| patch.iter().try_for_each(|op| doc.apply_json_patch_op(op)))
+    }
+
+    fn apply_json_patch_op(&self, op: &JsonPatchOp) -> Result<(), JsonPatchError> {
+        match op {
+            JsonPatchOp::Add { path, value } => {
+                self.edit_target(&self.patch_target(path)?, Edit::Add(value.clone()), path)?;
+            }
+            JsonPatchOp::Remove { path } => {
+                self.edit_target(&self.patch_target(path)?, Edit::Remove, path)?;
+            }
+            JsonPatchOp::Replace { path, value } => {
+                self.edit_target(
+                    &self.patch_target(path)?,
+                    Edit::Replace(value.clone()),
+                    path,
+                )?;
+            }
+            JsonPatchOp::Move { from, path } => {
+                if from == path {
+                    return Ok(());
+                }
+                if is_under(path, from) {
+                    return Err(invalid("move", path, "cannot move a value into itself"));
+                }
+
+                let source = self.patch_target(from)?;
+                if let Target::TreeNode { tree, parent, key } = &source {
+                    return self.move_tree_node(tree, *parent, key, from, path);
+                }
+
+                let value = self.edit_target(&source, Edit::Remove, from)?;
+                self.edit_target(&self.patch_target(path)?, Edit::Add(value), path)?;
+            }
+            JsonPatchOp::Copy { from, path } => {
+                let value = self.target_value(&self.patch_target(from)?, from)?;
+                self.edit_target(&self.patch_target(path)?, Edit::Add(value), path)?;
+            }
+            JsonPatchOp::Test { path, value } => {
+                if &self.target_value(&self.patch_target(path)?, path)? != value {
+                    return Err(JsonPatchError::TestFailed(path.clone()));
+                }
+            }
+            JsonPatchOp::Splice {
+                path,
+                pos,
+                delete,
+                insert,
+            } => {
+                let edit = Edit::Splice {
+                    pos: *pos,
+                    delete: *delete,
+                    insert,
+                };
+                self.edit_target(&self.patch_target(path)?, edit, path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move a tree node, keeping its id and its descendants
+    fn move_tree_node(
+        &self,
+        tree: &TreeHandler,
+        parent: TreeParentId,
+        key: &str,
+        from: &str,
+        path: &str,
+    ) -> Result<(), JsonPatchError> {
+        let len = tree.children_num(&parent).unwrap_or(0);
+        let node = tree
+            .get_child_at(&parent, parse_index(key, len, from)?)
+            .ok_or_else(|| JsonPatchError::PathNotFound(from.to_string()))?;
+
+        // `path` is resolved as if the node were removed, so shift the indexes after it
+        let mut segments = parse_pointer(path)?;
+        let source = parse_pointer(from)?;
+        let (removed, array) = source.split_last().unwrap();
+        if segments.len() > source.len() && segments.starts_with(array) {
+            let removed: usize = removed.parse().unwrap();
+            if let Ok(i) = segments[array.len()].parse::<usize>() {
+                if i >= removed {
+                    segments[array.len()] = (i + 1).to_string();
+                }
+            }
+        }
+
+        let mut dest = String::new();
+        for segment in segments.iter() {
+            push_segment(&mut dest, segment);
+        }
+        match self.patch_target(&dest)? {
+            Target::TreeNode {
+                tree: dest_tree,
+                parent: dest_parent,
+                key,
+            } if dest_tree.id() == tree.id() => {
+                if tree.is_fractional_index_enabled() {
+                    let mut len = tree.children_num(&dest_parent).unwrap_or(0);
+                    if tree.is_parent(&node, &dest_parent) {
+                        len -= 1;
+                    }
+                    tree.move_to(node, dest_parent, parse_insert_index(&key, len, path)?)?;
+                } else if !tree.is_parent(&node, &dest_parent) {
+                    tree.mov(node, dest_parent)?;
+                }
+                Ok(())
+            }
+            _ => Err(invalid(
+                "move",
+                path,
+                "a tree node can only be moved inside its tree",
+            )),
+        }
+    }
+
+    fn root_container(&self, name: &str) -> Option<ContainerID> {
+        self.arena
+            .root_containers()
+            .into_iter()
+            .filter_map(|idx| self.arena.idx_to_id(idx))
+            .find(|id| matches!(id, ContainerID::Root { name: n, .. } if n.as_str() == name))
+    }
+
+    fn patch_target(&self, pointer: &str) -> Result<Target, JsonPatchError> {
+        let segments = parse_pointer(pointer)?;
+        let Some((first, rest)) = segments.split_first() else {
+            return Err(invalid("patch", pointer, "the doc cannot be replaced"));
+        };
+
+        let mut target = Target::Root(first.clone());
+        for segment in rest {
+            let key = segment.clone();
+            target = match target {
+                Target::Value { owner, mut path } => {
+                    path.push(key);
+                    Target::Value { owner, path }
+                }
+                target => match self.resolve(&target, pointer)? {
+                    Resolved::Handler(Handler::Tree(tree)) => Target::TreeNode {
+                        tree,
+                        parent: TreeParentId::Root,
+                        key,
+                    },
+                    Resolved::Handler(
+                        parent @ (Handler::Map(_) | Handler::List(_) | Handler::MovableList(_)),
+                    ) => Target::Child { parent, key },
+                    Resolved::Handler(_) => {
+                        return Err(JsonPatchError::PathNotFound(pointer.to_string()))
+                    }
+                    Resolved::TreeChildren(tree, parent) => Target::TreeNode { tree, parent, key },
+                    Resolved::TreeNode(tree, node) => Target::TreeNodeField { tree, node, key },
+                    Resolved::Value(_) => Target::Value {
+                        owner: Box::new(target),
+                        path: vec![key],
+                    },
+                },
+            };
+        }
+
+        Ok(target)
+    }
+
+    fn resolve(&self, target: &Target, pointer: &str) -> Result<Resolved, JsonPatchError> {
+        let not_found = || JsonPatchError::PathNotFound(pointer.to_string());
+        let value_or_handler = |v: ValueOrHandler| match v {
+            ValueOrHandler::Value(v) => Resolved::Value(v),
+            ValueOrHandler::Handler(h) => Resolved::Handler(h),
+        };
+        match target {
+            Target::Root(name) => {
+                let id = self.root_container(name).ok_or_else(not_found)?;
+                Ok(Resolved::Handler(self.get_handler(id)))
+            }
+            Target::Child { parent, key } => {
+                let child = match parent {
+                    Handler::Map(map) => map.get_(key),
+                    Handler::List(list) => list.get_(parse_index(key, list.len(), pointer)?),
+                    Handler::MovableList(list) => list.get_(parse_index(key, list.len(), pointer)?),
+                    _ => None,
+                };
+                child.map(value_or_handler).ok_or_else(not_found)
+            }
+            Target::TreeNode { tree, parent, key } => {
+                let len = tree.children_num(parent).unwrap_or(0);
+                let node = tree
+                    .get_child_at(parent, parse_index(key, len, pointer)?)
+                    .ok_or_else(not_found)?;
+                Ok(Resolved::TreeNode(tree.clone(), node))
+            }
+            Target::TreeNodeField { tree, node, key } => match key.as_str() {
+                "meta" => Ok(Resolved::Handler(Handler::Map(tree.get_meta(*node)?))),
+                "children" => Ok(Resolved::TreeChildren(
+                    tree.clone(),
+                    TreeParentId::Node(*node),
+                )),
+                _ => tree_value_at(tree, Some(*node))
+                    .as_ref()
+                    .and_then(|v| value_at(v, std::slice::from_ref(key)))
+                    .cloned()
+                    .map(Resolved::Value)
+                    .ok_or_else(not_found),
+            },
+            Target::Value { owner, path } => {
+                let owner = self.target_value(owner, pointer)?;
+                value_at(&owner, path)
+                    .cloned()
+                    .map(Resolved::Value)
+                    .ok_or_else(not_found)
+            }
+        }
+    }
+
+    fn target_value(&self, target: &Target, pointer: &str) -> Result<LoroValue, JsonPatchError> {
+        Ok(match self.resolve(target, pointer)? {
+            Resolved::Handler(h) => h.get_deep_value(),
+            Resolved::TreeChildren(tree, parent) => {
+                let node = parent.tree_id();
+                tree_value_at(&tree, node)
+                    .and_then(|v| match node {
+                        Some(_) => v.as_map().and_then(|m| m.get("children")).cloned(),
+                        None => Some(v),
+                    })
+                    .ok_or_else(|| JsonPatchError::PathNotFound(pointer.to_string()))?
+            }
+            Resolved::TreeNode(tree, node) => tree_value_at(&tree, Some(node))
+                .ok_or_else(|| JsonPatchError::PathNotFound(pointer.to_string()))?,
+            Resolved::Value(v) => v,
+        })
+    }
+
+    /// Apply the edit to the target. Return the removed value for [`Edit::Remove`].
+    fn edit_target(
+        &self,
+        target: &Target,
+        edit: Edit,
+        pointer: &str,
+    ) -> Result<LoroValue, JsonPatchError> {
+        let op = edit.name();
+        if let Edit::Splice {
+            pos,
+            delete,
+            insert,
+        } = edit
+        {
+            match self.resolve(target, pointer)? {
+                Resolved::Handler(Handler::Text(text)) => {
+                    return Ok(text.splice(pos, delete, insert)?.into());
+                }
+                Resolved::Value(mut value) => {
+                    let old = value.clone();
+                    splice_string(&mut value, pos, delete, insert, pointer)?;
+                    self.edit_target(target, Edit::Replace(value), pointer)?;
+                    return Ok(old);
+                }
+                _ => return Err(invalid(op, pointer, "the target is not a string")),
+            }
+        }
+
+        match target {
+            Target::Root(name) => match edit {
+                Edit::Add(value) | Edit::Replace(value) => {
+                    self.set_root_value(name, value, pointer)?;
+                    Ok(LoroValue::Null)
+                }
+                Edit::Remove => {
+                    let id = self
+                        .root_container(name)
+                        .ok_or_else(|| JsonPatchError::PathNotFound(pointer.to_string()))?;
+                    let handler = self.get_handler(id);
+                    let old = handler.get_deep_value();
+                    clear_container(&handler, pointer)?;
+                    Ok(old)
+                }
+                Edit::Splice { .. } => unreachable!(),
+            },
+            Target::Child { parent, key } => self.edit_container_child(parent, key, edit, pointer),
+            Target::TreeNode { tree, parent, key } => {
+                let len = tree.children_num(parent).unwrap_or(0);
+                match edit {
+                    Edit::Add(value) => {
+                        let index = parse_insert_index(key, len, pointer)?;
+                        let mut reconciler = TreeReconciler::new(tree);
+                        reconciler.node(*parent, index, &value, pointer)?;
+                        reconciler.finish()?;
+                        Ok(LoroValue::Null)
+                    }
+                    Edit::Remove => {
+                        let node = tree
+                            .get_child_at(parent, parse_index(key, len, pointer)?)
+                            .ok_or_else(|| JsonPatchError::PathNotFound(pointer.to_string()))?;
+                        let old = tree_value_at(tree, Some(node)).unwrap_or_default();
+                        tree.delete(node)?;
+                        Ok(old)
+                    }
+                    Edit::Replace(value) => {
+                        let index = parse_index(key, len, pointer)?;
+                        let node = tree
+                            .get_child_at(parent, index)
+                            .ok_or_else(|| JsonPatchError::PathNotFound(pointer.to_string()))?;
+                        let old = tree_value_at(tree, Some(node)).unwrap_or_default();
+                        let mut reconciler = TreeReconciler::new(tree);
+                        // A node without `id` replaces the content of the existing node
+                        if value.as_map().map_or(false, |m| m.contains_key("id")) {
+                            reconciler.replace(node, *parent, index, &value, pointer)?;
+                        } else {
+                            reconciler.update(node, &value, pointer)?;
+                        }
+                        reconciler.finish()?;
+                        Ok(old)
+                    }
+                    Edit::Splice { .. } => unreachable!(),
+                }
+            }
+            Target::TreeNodeField { tree, node, key } => {
+                let value = match edit {
+                    Edit::Add(value) | Edit::Replace(value) => value,
+                    _ => {
+                        return Err(invalid(
+                            op,
+                            pointer,
+                            "the tree node fields cannot be removed",
+                        ))
+                    }
+                };
+                let old = self.target_value(target, pointer)?;
+                match key.as_str() {
+                    "meta" => {
+                        set_container_value(&Handler::Map(tree.get_meta(*node)?), value, pointer)?
+                    }
+                    "children" => {
+                        reconcile_tree_children(tree, TreeParentId::Node(*node), &value, pointer)?
+                    }
+                    _ => return Err(invalid(op, pointer, format!("`{}` is read-only", key))),
+                }
+                Ok(old)
+            }
+            Target::Value { owner, path } => {
+                let mut value = self.target_value(owner, pointer)?;
+                let old = edit_value(&mut value, path, edit, pointer)?;
+                self.edit_target(owner, Edit::Replace(value), pointer)?;
+                Ok(old)
+            }
+        }
+    }
+
+    fn edit_container_child(
+        &self,
+        parent: &Handler,
+        key: &str,
+        edit: Edit,
+        pointer: &str,
+    ) -> Result<LoroValue, JsonPatchError> {
+        let not_found = || JsonPatchError::PathNotFound(pointer.to_string());
+        let old = |child: Option<ValueOrHandler>| child.map(|v| v.to_deep_value());
+        match parent {
+            Handler::Map(map) => {
+                let child = old(map.get_(key));
+                match edit {
+                    Edit::Add(value) => set_map_value(map, key, value, pointer)?,
+                    Edit::Replace(value) => {
+                        if child.is_none() {
+                            return Err(not_found());
+                        }
+                        set_map_value(map, key, value, pointer)?;
+                    }
+                    Edit::Remove => {
+                        if child.is_none() {
+                            return Err(not_found());
+                        }
+                        map.delete(key)?;
+                    }
+                    Edit::Splice { .. } => unreachable!(),
+                }
+                Ok(child.unwrap_or_default())
+            }
+            Handler::List(_) | Handler::MovableList(_) => {
+                let len = match parent {
+                    Handler::List(list) => list.len(),
+                    Handler::MovableList(list) => list.len(),
+                    _ => unreachable!(),
+                };
+                let get = |i: usize| match parent {
+                    Handler::List(list) => list.get_(i),
+                    Handler::MovableList(list) => list.get_(i),
+                    _ => unreachable!(),
+                };
+                let insert =
+                    |i: usize, value: LoroValue| insert_list_value(parent, i, value, pointer);
+                let delete = |i: usize| -> Result<(), JsonPatchError> {
+                    match parent {
+                        Handler::List(list) => list.delete(i, 1)?,
+                        Handler::MovableList(list) => list.delete(i, 1)?,
+                        _ => unreachable!(),
+                    }
+                    Ok(())
+                };
+
+                match edit {
+                    Edit::Add(value) => {
+                        insert(parse_insert_index(key, len, pointer)?, value)?;
+                        Ok(LoroValue::Null)
+                    }
+                    Edit::Remove => {
+                        let i = parse_index(key, len, pointer)?;
+                        let child = old(get(i)).ok_or_else(not_found)?;
+                        delete(i)?;
+                        Ok(child)
+                    }
+                    Edit::Replace(value) => {
+                        let i = parse_index(key, len, pointer)?;
+                        let child = get(i).ok_or_else(not_found)?;
+                        let old = child.to_deep_value();
+                        match child {
+                            ValueOrHandler::Handler(h) if is_compatible(&h, &value) => {
+                                set_container_value(&h, value, pointer)?
+                            }
+                            _ => match (parent, new_container_type(&value)) {
+                                (Handler::MovableList(list), Some(kind)) => {
+                                    let child =
+                                        list.set_container(i, Handler::new_unattached(kind))?;
+                                    set_container_value(&child, value, pointer)?;
+                                }
+                                (Handler::MovableList(list), None) => list.set(i, value)?,
+                                _ => {
+                                    delete(i)?;
+                                    insert(i, value)?;
+                                }
+                            },
+                        }
+                        Ok(old)
+                    }
+                    Edit::Splice { .. } => unreachable!(),
+                }
+            }
+            _ => Err(not_found()),
+        }
+    }
+
+    fn set_root_value(
+        &self,
+        name: &str,
+        value: LoroValue,
+        pointer: &str,
+    ) -> Result<(), JsonPatchError> {
+        let handler = match self.root_container(name) {
+            Some(id) => self.get_handler(id),
+            None => match &value {
+                LoroValue::Map(_) => Handler::Map(self.get_map(name)),
+                LoroValue::List(_) => Handler::List(self.get_list(name)),
+                LoroValue::String(_) => Handler::Text(self.get_text(name)),
+                #[cfg(feature = "counter")]
+                LoroValue::Double(_) | LoroValue::I64(_) => {
+                    Handler::Counter(self.get_counter(name))
+                }
+                _ => {
+                    return Err(invalid(
+                        "add",
+                        pointer,
+                        "a root member must be an object, an array or a string",
+                    ))
+                }
+            },
+        };
+
+        set_container_value(&handler, value, pointer)
+    }
+}
+
+fn clear_container(handler: &Handler, pointer: &str) -> Result<(), JsonPatchError> {
+    match handler {
+        Handler::Map(map) => map.clear()?,
+        Handler::List(list) => list.clear()?,
+        Handler::MovableList(list) => list.clear()?,
+        Handler::Text(text) => clear_text(text)?,
+        Handler::Tree(tree) => {
+            for node in tree.roots() {
+                tree.delete(node)?;
+            }
+        }
+        #[cfg(feature = "counter")]
+        Handler::Counter(counter) => {
+            let current = as_f64(&counter.get_value()).unwrap_or_default();
+            counter.decrement(current)?;
+        }
+        Handler::Unknown(_) => {
+            return Err(invalid("remove", pointer, "unknown container"));
+        }
+    }
+
+    Ok(())
+}
+
+fn clear_text(text: &TextHandler) -> Result<(), JsonPatchError> {
+    let len = text.len_event();
+    if len > 0 {
+        text.delete(0, len)?;
+    }
+    Ok(())
+}
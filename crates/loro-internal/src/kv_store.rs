@@ -249,3 +249,273 @@ impl KvStore for BTreeMap<Bytes, Bytes> {
         Arc::new(Mutex::new(self.clone()))
     }
 }
+
+#[cfg(feature = "encryption")]
+pub use encrypted::EncryptedKvStore;
+
+#[cfg(feature = "encryption")]
+mod encrypted {
+    use std::{
+        ops::Bound,
+        sync::{Arc, Mutex},
+    };
+
+    use bytes::Bytes;
+    use loro_common::LoroResult;
+
+    use super::KvStore;
+    use crate::encoding::encryption::EncryptionKey;
+
+    /// A kv store that encrypts every value with XChaCha20-Poly1305 before passing it
+    /// to the inner store.
+    ///
+    /// The keys are kept in plaintext so that the order and the scans still work.
+    /// Each value is bound to its key, so the values cannot be swapped between keys.
+    /// The empty value is regarded as deleted, so it's kept empty.
+    ///
+    /// The methods of [`KvStore`] can't report errors, so they treat a value that fails to
+    /// be decrypted, because it was tampered with or encrypted by another key, as missing.
+    /// Use [`EncryptedKvStore::try_get`], [`EncryptedKvStore::try_remove`] and
+    /// [`EncryptedKvStore::try_scan`] to get a [`LoroError::DecryptionError`] instead.
+    ///
+    /// [`LoroError::DecryptionError`]: loro_common::LoroError::DecryptionError
+    #[derive(Debug, Clone)]
+    pub struct EncryptedKvStore<S> {
+        inner: S,
+        key: EncryptionKey,
+    }
+
+    impl<S: KvStore> EncryptedKvStore<S> {
+        pub fn new(inner: S, key: EncryptionKey) -> Self {
+            Self { inner, key }
+        }
+
+        pub fn into_inner(self) -> S {
+            self.inner
+        }
+
+        fn encrypt(&self, key: &[u8], value: &Bytes) -> Bytes {
+            if value.is_empty() {
+                return Bytes::new();
+            }
+
+            self.key.seal(key, value).into()
+        }
+
+        /// Get the value of the key, or an error if it fails to be decrypted
+        pub fn try_get(&self, key: &[u8]) -> LoroResult<Option<Bytes>> {
+            self.inner
+                .get(key)
+                .map(|v| self.decrypt(key, v))
+                .transpose()
+        }
+
+        /// Remove the key and return its value, or an error if the value fails to be
+        /// decrypted. The key is removed in both cases.
+        pub fn try_remove(&mut self, key: &[u8]) -> LoroResult<Option<Bytes>> {
+            let value = self.inner.remove(key);
+            value.map(|v| self.decrypt(key, v)).transpose()
+        }
+
+        /// Scan the entries in the range. An entry whose value fails to be decrypted is
+        /// yielded as an error.
+        pub fn try_scan(
+            &self,
+            start: Bound<&[u8]>,
+            end: Bound<&[u8]>,
+        ) -> impl DoubleEndedIterator<Item = LoroResult<(Bytes, Bytes)>> + '_ {
+            self.inner
+                .scan(start, end)
+                .map(|(k, v)| self.decrypt(&k, v).map(|v| (k, v)))
+        }
+
+        /// Decrypt the value. It fails if the value was tampered with or was not encrypted
+        /// by this key. [`KvStore::import_all`] checks all the values, so it only happens
+        /// if the inner store is modified directly.
+        fn decrypt(&self, key: &[u8], value: Bytes) -> LoroResult<Bytes> {
+            if value.is_empty() {
+                return Ok(value);
+            }
+
+            self.key.open(key, &value).map(Bytes::from)
+        }
+
+        /// Decrypt the value for the [`KvStore`] methods, which treat the value that fails
+        /// to be decrypted as missing
+        fn decrypt_or_log(&self, key: &[u8], value: Bytes) -> Option<Bytes> {
+            match self.decrypt(key, value) {
+                Ok(v) => Some(v),
+                Err(_) => {
+                    tracing::error!("Failed to decrypt the kv store value of key {:?}", key);
+                    None
+                }
+            }
+        }
+    }
+
+    impl<S: KvStore + Clone + 'static> KvStore for EncryptedKvStore<S> {
+        fn get(&self, key: &[u8]) -> Option<Bytes> {
+            self.inner
+                .get(key)
+                .and_then(|v| self.decrypt_or_log(key, v))
+        }
+
+        fn set(&mut self, key: &[u8], value: Bytes) {
+            let value = self.encrypt(key, &value);
+            self.inner.set(key, value)
+        }
+
+        fn compare_and_swap(&mut self, key: &[u8], old: Option<Bytes>, new: Bytes) -> bool {
+            // The ciphertexts of the same value are different, so we need to compare the plaintext
+            let current = self.inner.get(key);
+            let current_value = match current.clone() {
+                Some(v) => match self.decrypt(key, v) {
+                    Ok(v) => Some(v),
+                    Err(_) => return false,
+                },
+                None => None,
+            };
+            if current_value != old {
+                return false;
+            }
+
+            let new = self.encrypt(key, &new);
+            self.inner.compare_and_swap(key, current, new)
+        }
+
+        fn remove(&mut self, key: &[u8]) -> Option<Bytes> {
+            self.inner
+                .remove(key)
+                .and_then(|v| self.decrypt_or_log(key, v))
+        }
+
+        fn contains_key(&self, key: &[u8]) -> bool {
+            self.inner.contains_key(key)
+        }
+
+        fn scan(
+            &self,
+            start: Bound<&[u8]>,
+            end: Bound<&[u8]>,
+        ) -> Box<dyn DoubleEndedIterator<Item = (Bytes, Bytes)> + '_> {
+            Box::new(
+                self.inner
+                    .scan(start, end)
+                    .filter_map(|(k, v)| self.decrypt_or_log(&k, v).map(|v| (k, v))),
+            )
+        }
+
+        fn len(&self) -> usize {
+            self.inner.len()
+        }
+
+        fn is_empty(&self) -> bool {
+            self.inner.is_empty()
+        }
+
+        fn size(&self) -> usize {
+            self.inner.size()
+        }
+
+        fn export_all(&mut self) -> Bytes {
+            self.inner.export_all()
+        }
+
+        fn import_all(&mut self, bytes: Bytes) -> Result<(), String> {
+            self.inner.import_all(bytes)?;
+            for (k, v) in self.inner.scan(Bound::Unbounded, Bound::Unbounded) {
+                if !v.is_empty() && self.key.open(&k, &v).is_err() {
+                    return Err(format!(
+                        "Failed to decrypt the value of key {:?} with the key \"{}\"",
+                        k,
+                        self.key.id()
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+
+        fn clone_store(&self) -> Arc<Mutex<dyn KvStore>> {
+            Arc::new(Mutex::new(self.clone()))
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::collections::BTreeMap;
+
+        use loro_common::LoroError;
+
+        use super::*;
+
+        #[test]
+        fn values_are_encrypted() {
+            let key = EncryptionKey::generate("kv");
+            let mut store = EncryptedKvStore::new(BTreeMap::<Bytes, Bytes>::new(), key.clone());
+            store.set(b"a", Bytes::from_static(b"hello"));
+            store.set(b"b", Bytes::from_static(b"world"));
+            assert_eq!(store.get(b"a").unwrap(), Bytes::from_static(b"hello"));
+            assert!(!store.compare_and_swap(b"a", None, Bytes::from_static(b"x")));
+            assert!(store.compare_and_swap(
+                b"a",
+                Some(Bytes::from_static(b"hello")),
+                Bytes::from_static(b"hi")
+            ));
+            assert_eq!(
+                store
+                    .scan(Bound::Unbounded, Bound::Unbounded)
+                    .map(|(_, v)| v)
+                    .collect::<Vec<_>>(),
+                vec![Bytes::from_static(b"hi"), Bytes::from_static(b"world")]
+            );
+
+            let bytes = store.export_all();
+            assert!(!bytes.windows(5).any(|w| w == b"world"));
+            let mut new_store = EncryptedKvStore::new(BTreeMap::<Bytes, Bytes>::new(), key);
+            new_store.import_all(bytes.clone()).unwrap();
+            assert_eq!(new_store.get(b"b").unwrap(), Bytes::from_static(b"world"));
+
+            let mut wrong_key = EncryptedKvStore::new(
+                BTreeMap::<Bytes, Bytes>::new(),
+                EncryptionKey::generate("kv"),
+            );
+            assert!(wrong_key.import_all(bytes).is_err());
+        }
+
+        #[test]
+        fn tampered_values_are_errors() {
+            let key = EncryptionKey::generate("kv");
+            let mut store = EncryptedKvStore::new(BTreeMap::<Bytes, Bytes>::new(), key.clone());
+            store.set(b"a", Bytes::from_static(b"hello"));
+            store.set(b"b", Bytes::from_static(b"world"));
+            let mut inner = store.into_inner();
+            let mut tampered = inner.get(b"a".as_slice()).unwrap().to_vec();
+            *tampered.last_mut().unwrap() ^= 1;
+            inner.insert(Bytes::from_static(b"a"), tampered.into());
+
+            let mut store = EncryptedKvStore::new(inner.clone(), key);
+            assert_eq!(store.try_get(b"a"), Err(LoroError::DecryptionError));
+            assert_eq!(store.get(b"a"), None);
+            assert_eq!(
+                store.try_get(b"b").unwrap(),
+                Some(Bytes::from_static(b"world"))
+            );
+            assert_eq!(
+                store
+                    .try_scan(Bound::Unbounded, Bound::Unbounded)
+                    .filter(|x| x.is_err())
+                    .count(),
+                1
+            );
+            assert_eq!(store.scan(Bound::Unbounded, Bound::Unbounded).count(), 1);
+            assert!(!store.compare_and_swap(b"a", None, Bytes::from_static(b"x")));
+            assert_eq!(store.try_remove(b"a"), Err(LoroError::DecryptionError));
+            assert!(!store.contains_key(b"a"));
+
+            // The values of another key can't be decrypted either
+            let store = EncryptedKvStore::new(inner, EncryptionKey::generate("kv"));
+            assert_eq!(store.try_get(b"b"), Err(LoroError::DecryptionError));
+        }
+    }
+}
@@ -28,9 +28,10 @@ pub use handler::{
 };
 pub use loro_common;
 pub use oplog::OpLog;
+use pre_commit::PreCommitHooks;
 pub use state::DocState;
 pub use state::{TreeNode, TreeNodeWithChildren, TreeParentId};
-use subscription::{LocalUpdateCallback, Observer, PeerIdUpdateCallback};
+use subscription::{LocalUpdateCallback, Observer, PeerIdUpdateCallback, ThrottledSubscribers};
 use txn::Transaction;
 pub use undo::UndoManager;
 use utils::subscription::SubscriberSetWithQueue;
@@ -43,15 +44,24 @@ pub mod container;
 pub mod cursor;
 pub mod dag;
 pub mod encoding;
+#[cfg(feature = "event-stream")]
+pub mod event_stream;
 pub(crate) mod fork;
 pub mod id;
+pub mod import_validator;
+pub mod json_patch;
 #[cfg(feature = "jsonpath")]
 pub mod jsonpath;
 pub mod kv_store;
 pub mod loro;
 pub mod op;
 pub mod oplog;
+pub mod path_subscription;
+pub mod pre_commit;
+#[cfg(feature = "signature")]
+pub mod signature;
 pub mod subscription;
+pub mod text_patch;
 pub mod txn;
 pub mod version;
 
@@ -82,10 +92,11 @@ pub(crate) use loro_common::InternalString;
 pub use container::ContainerType;
 pub use encoding::json_schema::json;
 pub use fractional_index::FractionalIndex;
+pub use loro_common::{from_loro_value, to_loro_value};
 pub use loro_common::{loro_value, to_value};
 pub use loro_common::{
     Counter, CounterSpan, IdLp, IdSpan, Lamport, LoroEncodeError, LoroError, LoroResult,
-    LoroTreeError, PeerID, TreeID, ID,
+    LoroSchemaError, LoroSerdeError, LoroTreeError, PeerID, TreeID, ID,
 };
 pub use loro_common::{LoroBinaryValue, LoroListValue, LoroMapValue, LoroStringValue};
 #[cfg(feature = "wasm")]
@@ -120,7 +131,11 @@ pub struct LoroDoc {
     // when dropping the doc, the txn will be committed
     txn: Arc<Mutex<Option<Transaction>>>,
     auto_commit: AtomicBool,
+    /// Whether a [`LoroDoc::transact`] closure is running
+    in_transact: AtomicBool,
     detached: AtomicBool,
     local_update_subs: SubscriberSetWithQueue<(), LocalUpdateCallback, Vec<u8>>,
     peer_id_change_subs: SubscriberSetWithQueue<(), PeerIdUpdateCallback, ID>,
+    throttled_subs: ThrottledSubscribers,
+    pre_commit_subs: PreCommitHooks,
 }
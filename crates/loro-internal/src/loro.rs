@@ -26,16 +26,23 @@ use crate::{
     change::Timestamp,
     configure::{Configure, DefaultRandom, SecureRandomGenerator},
     container::{
-        idx::ContainerIdx, list::list_op::InnerListOp, richtext::config::StyleConfigMap,
+        idx::ContainerIdx,
+        list::list_op::InnerListOp,
+        richtext::{config::StyleConfigMap, richtext_state::PosType},
         IntoContainerId,
     },
-    cursor::{AbsolutePosition, CannotFindRelativePosition, Cursor, PosQueryResult},
+    cursor::{
+        AbsolutePosition, CannotFindRelativePosition, Cursor, PosQueryResult, ResolvedTextRange,
+        Side, TextRange,
+    },
     dag::Dag,
     diff_calc::DiffCalculator,
     encoding::{
         self, decode_snapshot, export_fast_snapshot, export_fast_updates,
-        export_fast_updates_in_range, export_shallow_snapshot, export_snapshot, export_snapshot_at,
-        export_state_only_snapshot, json_schema::json::JsonSchema, parse_header_and_body,
+        export_fast_updates_for_containers, export_fast_updates_in_range, export_shallow_snapshot,
+        export_snapshot, export_snapshot_at, export_state_only_snapshot,
+        json_schema::json::JsonSchema, parse_header_and_body,
+        stream::{self, StreamedBlob},
         EncodeMode, ImportBlobMetadata, ImportStatus, ParsedHeaderAndBody,
     },
     event::{str_to_path, EventTriggerKind, Index, InternalDocDiff},
@@ -47,7 +54,7 @@ use crate::{
     subscription::{LocalUpdateCallback, Observer, Subscriber},
     txn::Transaction,
     undo::DiffBatch,
-    utils::subscription::{SubscriberSetWithQueue, Subscription},
+    utils::subscription::{SubscriberSet, SubscriberSetWithQueue, Subscription},
     version::{shrink_frontiers, Frontiers, ImVersionVector, VersionRange},
     ChangeMeta, DocDiff, HandlerTrait, InternalString, ListHandler, LoroError, MapHandler,
     VersionVector,
@@ -87,12 +94,15 @@ impl LoroDoc {
             config,
             detached: AtomicBool::new(false),
             auto_commit: AtomicBool::new(false),
+            in_transact: AtomicBool::new(false),
             observer: Arc::new(Observer::new(arena.clone())),
             diff_calculator: Arc::new(Mutex::new(DiffCalculator::new(true))),
             txn: global_txn,
             arena,
             local_update_subs: SubscriberSetWithQueue::new(),
             peer_id_change_subs: SubscriberSetWithQueue::new(),
+            throttled_subs: Default::default(),
+            pre_commit_subs: SubscriberSet::new(),
         }
     }
 
@@ -209,6 +219,17 @@ impl LoroDoc {
             return false;
         }
 
+        // Replay the history of the snapshot, so that every change is verified
+        #[cfg(feature = "signature")]
+        if oplog.has_change_verifier() {
+            return false;
+        }
+
+        // Replay the history of the snapshot, so that every change is validated
+        if oplog.has_import_validator() {
+            return false;
+        }
+
         if self.is_detached() {
             return false;
         }
@@ -244,6 +265,7 @@ impl LoroDoc {
         if peer == PeerID::MAX {
             return Err(LoroError::InvalidPeerID);
         }
+        self.check_not_in_transact()?;
         let next_id = self.oplog.try_lock().unwrap().next_id(peer);
         if self.auto_commit.load(Acquire) {
             let doc_state = self.state.try_lock().unwrap();
@@ -317,6 +339,9 @@ impl LoroDoc {
     /// Commit the cumulative auto commit transaction.
     /// This method only has effect when `auto_commit` is true.
     /// If `immediate_renew` is true, a new transaction will be created after the old one is committed
+    ///
+    /// It does nothing inside [`LoroDoc::transact`], whose transaction is committed when the
+    /// closure returns.
     #[instrument(skip_all)]
     pub fn commit_with(&self, config: CommitOptions) {
         if !self.auto_commit.load(Acquire) {
@@ -325,6 +350,10 @@ impl LoroDoc {
             return;
         }
 
+        if self.is_in_transact() {
+            return;
+        }
+
         let mut txn_guard = self.txn.try_lock().unwrap();
         let txn = txn_guard.take();
         drop(txn_guard);
@@ -345,8 +374,7 @@ impl LoroDoc {
             txn.set_msg(Some(msg.clone()));
         }
 
-        let id_span = txn.id_span();
-        txn.commit().unwrap();
+        let id_span = txn.commit_and_get_id_span().unwrap();
         if config.immediate_renew {
             let mut txn_guard = self.txn.try_lock().unwrap();
             assert!(self.can_edit());
@@ -412,6 +440,7 @@ impl LoroDoc {
         bytes: &[u8],
         origin: InternalString,
     ) -> Result<ImportStatus, LoroError> {
+        self.check_not_in_transact()?;
         self.commit_then_stop();
         let ans = self._import_with(bytes, origin);
         self.renew_txn_if_auto_commit();
@@ -472,10 +501,11 @@ impl LoroDoc {
                     // return self.import_with(updates.as_slice(), origin);
                 }
             }
-            EncodeMode::FastUpdates => self.update_oplog_and_apply_delta_to_state_if_needed(
-                |oplog| oplog.decode(parsed),
-                origin,
-            ),
+            EncodeMode::FastUpdates | EncodeMode::StreamedUpdates => self
+                .update_oplog_and_apply_delta_to_state_if_needed(
+                    |oplog| oplog.decode(parsed),
+                    origin,
+                ),
             EncodeMode::Auto => {
                 unreachable!()
             }
@@ -485,6 +515,63 @@ impl LoroDoc {
         result
     }
 
+    /// Import updates or a snapshot from a reader.
+    ///
+    /// Unlike [`LoroDoc::import`], the encoded blob doesn't need to be read into a single
+    /// buffer first. The checksum is validated while reading, and nothing is imported if
+    /// it doesn't match.
+    ///
+    /// The updates written by [`LoroDoc::export_to_writer`] are verified and decoded block
+    /// by block, so only one block of bytes is kept in memory besides the decoded changes.
+    /// The other blobs are buffered until the checksum of the whole body is verified.
+    #[inline]
+    pub fn import_from_reader<R: std::io::Read>(
+        &self,
+        reader: R,
+    ) -> Result<ImportStatus, LoroError> {
+        self.import_from_reader_with(reader, Default::default())
+    }
+
+    #[inline]
+    pub fn import_from_reader_with<R: std::io::Read>(
+        &self,
+        reader: R,
+        origin: InternalString,
+    ) -> Result<ImportStatus, LoroError> {
+        self.check_not_in_transact()?;
+        self.commit_then_stop();
+        let ans = self._import_from_reader_with(reader, origin);
+        self.renew_txn_if_auto_commit();
+        ans
+    }
+
+    #[tracing::instrument(skip_all)]
+    fn _import_from_reader_with<R: std::io::Read>(
+        &self,
+        reader: R,
+        origin: InternalString,
+    ) -> Result<ImportStatus, LoroError> {
+        if self.state.try_lock().unwrap().is_in_txn() {
+            return Err(LoroError::ImportWhenInTxn);
+        }
+
+        let blob = stream::read_blob(reader)?;
+        let result = match blob {
+            StreamedBlob::Whole(bytes) => return self._import_with(&bytes, origin),
+            StreamedBlob::Snapshot(snapshot) if self.can_reset_with_snapshot() => {
+                tracing::info!("Init by streamed snapshot {}", self.peer_id());
+                stream::decode_snapshot(self, snapshot)
+            }
+            blob => self.update_oplog_and_apply_delta_to_state_if_needed(
+                |oplog| blob.decode_oplog(oplog),
+                origin,
+            ),
+        };
+
+        self.emit_events();
+        result
+    }
+
     #[tracing::instrument(skip_all)]
     pub(crate) fn update_oplog_and_apply_delta_to_state_if_needed(
         &self,
@@ -544,6 +631,7 @@ impl LoroDoc {
         if self.is_shallow() {
             return Err(LoroEncodeError::ShallowSnapshotIncompatibleWithOldFormat);
         }
+        self.oplog.try_lock().unwrap().check_no_placeholders()?;
         self.commit_then_stop();
         let ans = export_snapshot(self);
         self.renew_txn_if_auto_commit();
@@ -556,6 +644,7 @@ impl LoroDoc {
     #[tracing::instrument(skip_all)]
     pub fn import_json_updates<T: TryInto<JsonSchema>>(&self, json: T) -> LoroResult<ImportStatus> {
         let json = json.try_into().map_err(|_| LoroError::InvalidJsonSchema)?;
+        self.check_not_in_transact()?;
         self.commit_then_stop();
         let result = self.update_oplog_and_apply_delta_to_state_if_needed(
             |oplog| crate::encoding::json_schema::import_json(oplog, json),
@@ -988,6 +1077,7 @@ impl LoroDoc {
     // PERF: opt
     #[tracing::instrument(skip_all)]
     pub fn import_batch(&self, bytes: &[Vec<u8>]) -> LoroResult<ImportStatus> {
+        self.check_not_in_transact()?;
         if bytes.is_empty() {
             return Ok(ImportStatus::default());
         }
@@ -1116,6 +1206,7 @@ impl LoroDoc {
     /// This will make the current [DocState] detached from the latest version of [OpLog].
     /// Any further import will not be reflected on the [DocState], until user call [LoroDoc::attach()]
     pub fn checkout(&self, frontiers: &Frontiers) -> LoroResult<()> {
+        self.check_not_in_transact()?;
         self.checkout_without_emitting(frontiers, true)?;
         self.emit_events();
         if self.config.detached_editing() {
@@ -1354,6 +1445,53 @@ impl LoroDoc {
         self.query_pos_internal(pos, true)
     }
 
+    /// Get the current position of a [`TextRange`].
+    ///
+    /// The range is resolved at the version of the state, so it follows the checkouts. It
+    /// returns [`CannotFindRelativePosition::IdNotFound`] if the range is created after the
+    /// version. A deleted anchor is located through the history, which is not available if it's
+    /// deleted before the shallow root of the doc.
+    pub fn resolve_range(
+        &self,
+        range: &TextRange,
+    ) -> Result<ResolvedTextRange, CannotFindRelativePosition> {
+        let (start, start_deleted) = self.resolve_range_anchor(&range.start)?;
+        let (end, end_deleted) = self.resolve_range_anchor(&range.end)?;
+        // The anchors of a collapsed range can be crossed by the text inserted between them
+        let end = end.max(start);
+        let text = self.get_text(&range.start.container);
+        let convert = |pos_type| {
+            text.convert_index(start, PosType::Event, pos_type)
+                ..text.convert_index(end, PosType::Event, pos_type)
+        };
+        Ok(ResolvedTextRange {
+            unicode: convert(PosType::Unicode),
+            utf16: convert(PosType::Utf16),
+            utf8: convert(PosType::Bytes),
+            collapsed: start == end,
+            deleted: start == end && (start_deleted || end_deleted),
+        })
+    }
+
+    /// Get the event index of the boundary that a cursor of a [`TextRange`] marks, and whether
+    /// the char it's anchored to is deleted
+    fn resolve_range_anchor(
+        &self,
+        cursor: &Cursor,
+    ) -> Result<(usize, bool), CannotFindRelativePosition> {
+        let pos = self
+            .state
+            .try_lock()
+            .unwrap()
+            .get_relative_position(cursor, true);
+        match pos {
+            // The boundary is after the char if the cursor is on its right side
+            Some(pos) if cursor.id.is_some() && cursor.side == Side::Right => Ok((pos + 1, false)),
+            Some(pos) => Ok((pos, false)),
+            None => Ok((self.query_pos_internal(cursor, true)?.current.pos, true)),
+        }
+    }
+
     /// Get position in a seq container
     pub(crate) fn query_pos_internal(
         &self,
@@ -1383,15 +1521,22 @@ impl LoroDoc {
             // commit the txn to make sure we can query the history correctly
             drop(state);
             self.commit_then_renew();
+            // The position is resolved at the version of the state, which is not the latest
+            // version when the doc is detached
+            let frontiers = self.state_frontiers();
             let oplog = self.oplog().try_lock().unwrap();
-            // TODO: assert pos.id is not unknown
+            let vv = oplog.dag.frontiers_to_vv(&frontiers).unwrap();
             if let Some(id) = pos.id {
+                if !vv.includes_id(id) {
+                    return Err(CannotFindRelativePosition::IdNotFound);
+                }
+
                 let idx = oplog
                     .arena
                     .id_to_idx(&pos.container)
                     .ok_or(CannotFindRelativePosition::ContainerDeleted)?;
                 // We know where the target id is when we trace back to the delete_op_id.
-                let Some(delete_op_id) = find_last_delete_op(&oplog, id, idx) else {
+                let Some(delete_op_id) = find_last_delete_op(&oplog, id, idx, &vv) else {
                     if oplog.shallow_since_vv().includes_id(id) {
                         return Err(CannotFindRelativePosition::HistoryCleared);
                     }
@@ -1408,8 +1553,8 @@ impl LoroDoc {
                     &oplog,
                     before,
                     &before_frontiers,
-                    oplog.vv(),
-                    oplog.frontiers(),
+                    &vv,
+                    &frontiers,
                     Some(&|target| idx == target),
                 );
                 // TODO: remove depth info
@@ -1562,6 +1707,10 @@ impl LoroDoc {
 
     #[instrument(skip(self))]
     pub fn export(&self, mode: ExportMode) -> Result<Vec<u8>, LoroEncodeError> {
+        self.oplog
+            .try_lock()
+            .unwrap()
+            .check_placeholders_not_exported(&mode)?;
         self.commit_then_stop();
         let ans = match mode {
             ExportMode::Snapshot => export_fast_snapshot(self),
@@ -1569,6 +1718,13 @@ impl LoroDoc {
             ExportMode::UpdatesInRange { spans } => {
                 export_fast_updates_in_range(&self.oplog.try_lock().unwrap(), spans.as_ref())
             }
+            ExportMode::UpdatesForContainers { from, containers } => {
+                export_fast_updates_for_containers(
+                    &self.oplog.try_lock().unwrap(),
+                    &from,
+                    containers.as_ref(),
+                )
+            }
             ExportMode::ShallowSnapshot(f) => export_shallow_snapshot(self, &f)?,
             ExportMode::StateOnly(f) => match f {
                 Some(f) => export_state_only_snapshot(self, &f)?,
@@ -1581,6 +1737,56 @@ impl LoroDoc {
         Ok(ans)
     }
 
+    /// Export the document in the given mode into a writer.
+    ///
+    /// In the updates modes, the changes are written in a streamed format where each block
+    /// carries its own checksum. Each block is encoded and written before the next one is
+    /// encoded, so only one block is kept in memory. The blob can be imported by both
+    /// [`LoroDoc::import`] and [`LoroDoc::import_from_reader`].
+    ///
+    /// The other modes write the same bytes as [`LoroDoc::export`]. The sections of a
+    /// snapshot are written one by one instead of being concatenated into a single buffer
+    /// first, but they are still encoded as a whole.
+    #[instrument(skip(self, w))]
+    pub fn export_to_writer<W: std::io::Write>(
+        &self,
+        mode: ExportMode,
+        mut w: W,
+    ) -> Result<(), LoroEncodeError> {
+        self.oplog
+            .try_lock()
+            .unwrap()
+            .check_placeholders_not_exported(&mode)?;
+        self.commit_then_stop();
+        let ans = match mode {
+            ExportMode::Snapshot => stream::export_fast_snapshot(self, &mut w),
+            ExportMode::Updates { from } => stream::export_fast_updates(self, &from, &mut w),
+            ExportMode::UpdatesInRange { spans } => stream::export_fast_updates_in_range(
+                &self.oplog.try_lock().unwrap(),
+                spans.as_ref(),
+                &mut w,
+            ),
+            ExportMode::UpdatesForContainers { from, containers } => {
+                stream::export_fast_updates_for_containers(
+                    &self.oplog.try_lock().unwrap(),
+                    &from,
+                    containers.as_ref(),
+                    &mut w,
+                )
+            }
+            ExportMode::ShallowSnapshot(f) => stream::export_shallow_snapshot(self, &f, &mut w),
+            ExportMode::StateOnly(f) => {
+                let f = f.map(Cow::into_owned).unwrap_or_else(|| self.oplog_frontiers());
+                export_state_only_snapshot(self, &f).and_then(|b| stream::write_blob(&b, &mut w))
+            }
+            ExportMode::SnapshotAt { version } => export_snapshot_at(self, &version)
+                .and_then(|b| stream::write_blob(&b, &mut w)),
+        };
+
+        self.renew_txn_if_auto_commit();
+        ans
+    }
+
     /// The doc only contains the history since the shallow history start version vector.
     ///
     /// This is empty if the doc is not shallow.
@@ -1711,12 +1917,13 @@ impl LoroDoc {
 }
 
 // FIXME: PERF: This method is quite slow because it iterates all the changes
-fn find_last_delete_op(oplog: &OpLog, id: ID, idx: ContainerIdx) -> Option<ID> {
+/// Find the last op that deletes the id in the container before the version `to`
+fn find_last_delete_op(oplog: &OpLog, id: ID, idx: ContainerIdx, to: &VersionVector) -> Option<ID> {
     let start_vv = oplog
         .dag
         .frontiers_to_vv(&id.into())
         .unwrap_or_else(|| oplog.shallow_since_vv().to_vv());
-    for change in oplog.iter_changes_causally_rev(&start_vv, oplog.vv()) {
+    for change in oplog.iter_changes_causally_rev(&start_vv, to) {
         for op in change.ops.iter().rev() {
             if op.container != idx {
                 continue;
@@ -61,6 +61,11 @@ impl InnerContent {
                 #[cfg(feature = "counter")]
                 crate::op::FutureInnerContent::Counter(_) => {}
                 crate::op::FutureInnerContent::Unknown { .. } => {}
+                crate::op::FutureInnerContent::Placeholder { children, .. } => {
+                    for c in children.iter() {
+                        f(c);
+                    }
+                }
             },
         }
     }
@@ -85,6 +90,13 @@ pub enum FutureInnerContent {
         prop: i32,
         value: Box<OwnedValue>,
     },
+    /// The ops left out of a partial history, see [`crate::encoding::partition`].
+    ///
+    /// `children` are the visible containers created by the hidden ops.
+    Placeholder {
+        len: usize,
+        children: Box<[ContainerID]>,
+    },
 }
 impl FutureInnerContent {
     fn estimate_storage_size(&self) -> usize {
@@ -92,6 +104,14 @@ impl FutureInnerContent {
             #[cfg(feature = "counter")]
             FutureInnerContent::Counter(_) => 4,
             FutureInnerContent::Unknown { .. } => 6,
+            FutureInnerContent::Placeholder { children, .. } => 2 + children.len() * 8,
+        }
+    }
+
+    fn atom_len(&self) -> usize {
+        match self {
+            FutureInnerContent::Placeholder { len, .. } => *len,
+            _ => 1,
         }
     }
 }
@@ -221,7 +241,7 @@ impl HasLength for InnerContent {
             InnerContent::List(list) => list.atom_len(),
             InnerContent::Map(_) => 1,
             InnerContent::Tree(_) => 1,
-            InnerContent::Future(_) => 1,
+            InnerContent::Future(f) => f.atom_len(),
         }
     }
 }
@@ -238,6 +258,18 @@ impl Sliceable for InnerContent {
                 a.clone()
             }
             InnerContent::List(x) => InnerContent::List(x.slice(from, to)),
+            InnerContent::Future(FutureInnerContent::Placeholder { len, children }) => {
+                assert!(from < to && to <= *len);
+                InnerContent::Future(FutureInnerContent::Placeholder {
+                    len: to - from,
+                    // The children are kept by the first slice, so they're registered only once
+                    children: if from == 0 {
+                        children.clone()
+                    } else {
+                        Default::default()
+                    },
+                })
+            }
             InnerContent::Future(f) => {
                 assert!(from == 0 && to == 1);
                 InnerContent::Future(f.clone())
@@ -253,6 +285,10 @@ impl Mergable for InnerContent {
     {
         match (self, other) {
             (InnerContent::List(x), InnerContent::List(y)) => x.is_mergable(y, &()),
+            (
+                InnerContent::Future(FutureInnerContent::Placeholder { .. }),
+                InnerContent::Future(FutureInnerContent::Placeholder { children, .. }),
+            ) => children.is_empty(),
             _ => false,
         }
     }
@@ -266,6 +302,12 @@ impl Mergable for InnerContent {
                 InnerContent::List(y) => x.merge(y, &()),
                 _ => unreachable!(),
             },
+            InnerContent::Future(FutureInnerContent::Placeholder { len, .. }) => match _other {
+                InnerContent::Future(FutureInnerContent::Placeholder {
+                    len: other_len, ..
+                }) => *len += other_len,
+                _ => unreachable!(),
+            },
             _ => unreachable!(),
         }
     }
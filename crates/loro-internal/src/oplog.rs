@@ -1,4 +1,6 @@
 mod change_store;
+#[cfg(feature = "signature")]
+pub(crate) use change_store::split_change;
 pub(crate) mod loro_dag;
 mod pending_changes;
 
@@ -54,6 +56,14 @@ pub struct OpLog {
     /// If so the Dag's frontiers won't be updated until the batch is finished.
     pub(crate) batch_importing: bool,
     pub(crate) configure: Configure,
+    /// The signer of the local changes and the verifier of the imported changes
+    #[cfg(feature = "signature")]
+    pub(crate) signature: crate::signature::ChangeSignatures,
+    /// The validator of the imported changes and the rejected changes
+    pub(crate) validation: crate::import_validator::ImportValidation,
+    /// The end counter of the placeholder ops of each peer, which must not be exported.
+    /// See [`crate::encoding::partition`].
+    pub(crate) placeholder_end: VersionVector,
 }
 
 impl std::fmt::Debug for OpLog {
@@ -79,6 +89,10 @@ impl OpLog {
             pending_changes: Default::default(),
             batch_importing: false,
             configure: cfg,
+            #[cfg(feature = "signature")]
+            signature: Default::default(),
+            validation: Default::default(),
+            placeholder_end: Default::default(),
         }
     }
 
@@ -137,6 +151,7 @@ impl OpLog {
             .unwrap()
             .insert_by_new_change(&change, true, true);
         self.register_container_and_parent_link(&change);
+        self.record_placeholders(&change);
         self.change_store.insert_change(change, true);
     }
 
@@ -332,7 +347,7 @@ impl OpLog {
     #[inline(always)]
     pub(crate) fn export_change_store_from(&self, vv: &VersionVector, f: &Frontiers) -> Bytes {
         self.change_store
-            .export_from(vv, f, self.vv(), self.frontiers())
+            .export_from(vv, f, self.vv(), self.frontiers(), &self.placeholder_end)
     }
 
     #[inline(always)]
@@ -343,7 +358,8 @@ impl OpLog {
         to_vv: &VersionVector,
         to_frontiers: &Frontiers,
     ) -> Bytes {
-        self.change_store.export_from(vv, f, to_vv, to_frontiers)
+        self.change_store
+            .export_from(vv, f, to_vv, to_frontiers, &self.placeholder_end)
     }
 
     #[inline(always)]
@@ -357,12 +373,43 @@ impl OpLog {
         self.change_store.export_blocks_in_range(spans, w)
     }
 
+    /// Pass the encoded bytes of each block of the changes since `vv` to `f`, one block
+    /// at a time.
+    #[inline(always)]
+    pub(crate) fn for_each_block_bytes_from<E>(
+        &self,
+        vv: &VersionVector,
+        f: impl FnMut(Bytes) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.change_store.for_each_mapped_block_bytes_from(
+            &self.arena,
+            vv,
+            self.shallow_since_vv(),
+            self.vv(),
+            std::convert::identity,
+            f,
+        )
+    }
+
+    /// Pass the encoded bytes of each block of the changes in `spans` to `f`, one block
+    /// at a time.
+    #[inline(always)]
+    pub(crate) fn for_each_block_bytes_in_range<E>(
+        &self,
+        spans: &[IdSpan],
+        f: impl FnMut(Bytes) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.change_store.for_each_block_bytes_in_range(spans, f)
+    }
+
     pub(crate) fn fork_changes_up_to(&self, frontiers: &Frontiers) -> Option<Bytes> {
         let vv = self.dag.frontiers_to_vv(frontiers)?;
-        Some(
-            self.change_store
-                .fork_changes_up_to(self.dag.shallow_since_vv(), frontiers, &vv),
-        )
+        Some(self.change_store.fork_changes_up_to(
+            self.dag.shallow_since_vv(),
+            frontiers,
+            &vv,
+            &self.placeholder_end,
+        ))
     }
 
     #[inline(always)]
@@ -574,7 +621,7 @@ impl OpLog {
 
     pub fn encode_change_store(&self) -> bytes::Bytes {
         self.change_store
-            .encode_all(self.dag.vv(), self.dag.frontiers())
+            .encode_all(self.dag.vv(), self.dag.frontiers(), &self.placeholder_end)
     }
 
     pub fn check_dag_correctness(&self) {
@@ -632,6 +679,7 @@ pub(crate) fn convert_change_to_remote(
         lamport: change.lamport,
         timestamp: change.timestamp,
         commit_msg: change.commit_msg.clone(),
+        signature: change.signature.clone(),
     }
 }
 
@@ -734,6 +782,12 @@ pub(crate) fn local_op_to_remote(
                     value: (**value).clone(),
                 })
             }
+            FutureInnerContent::Placeholder { .. } => {
+                contents.push(crate::op::RawOpContent::Unknown {
+                    prop: 0,
+                    value: crate::encoding::OwnedValue::Null,
+                })
+            }
         },
     };
 
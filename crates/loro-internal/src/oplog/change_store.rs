@@ -23,6 +23,7 @@ use rle::{HasLength, Mergable, RlePush, RleVec, Sliceable};
 use std::{
     cmp::Ordering,
     collections::{BTreeMap, VecDeque},
+    convert::Infallible,
     ops::{Bound, Deref},
     sync::{atomic::AtomicI64, Arc, Mutex},
 };
@@ -110,6 +111,10 @@ pub const START_VV_KEY: &[u8] = b"sv";
 pub const START_FRONTIERS_KEY: &[u8] = b"sf";
 pub const VV_KEY: &[u8] = b"vv";
 pub const FRONTIERS_KEY: &[u8] = b"fr";
+/// The end counters of the placeholder ops. See [`crate::encoding::partition`].
+///
+/// The blocks are the only 12-byte keys, so the versions that don't know this key ignore it.
+pub const PLACEHOLDER_END_KEY: &[u8] = b"ph";
 
 impl ChangeStore {
     pub fn new_mem(a: &SharedArena, merge_interval: Arc<AtomicI64>) -> Self {
@@ -132,7 +137,13 @@ impl ChangeStore {
         Self::new_mem(&SharedArena::new(), Arc::new(AtomicI64::new(0)))
     }
 
-    pub(super) fn encode_all(&self, vv: &VersionVector, frontiers: &Frontiers) -> Bytes {
+    pub(super) fn encode_all(
+        &self,
+        vv: &VersionVector,
+        frontiers: &Frontiers,
+        placeholder_end: &VersionVector,
+    ) -> Bytes {
+        self.set_placeholder_end(placeholder_end);
         self.flush_and_compact(vv, frontiers);
         let mut kv = self.external_kv.try_lock().unwrap();
         kv.export_all()
@@ -145,8 +156,10 @@ impl ChangeStore {
         start_frontiers: &Frontiers,
         latest_vv: &VersionVector,
         latest_frontiers: &Frontiers,
+        placeholder_end: &VersionVector,
     ) -> Bytes {
         let new_store = ChangeStore::new_mem(&self.arena, self.merge_interval.clone());
+        new_store.set_placeholder_end(placeholder_end);
         for span in latest_vv.sub_iter(start_vv) {
             // PERF: this can be optimized by reusing the current encoded blocks
             // In the current method, it needs to parse and re-encode the blocks
@@ -174,7 +187,51 @@ impl ChangeStore {
         new_store.encode_from(start_vv, start_frontiers, latest_vv, latest_frontiers)
     }
 
+    fn set_placeholder_end(&self, placeholder_end: &VersionVector) {
+        if placeholder_end.is_empty() {
+            return;
+        }
+
+        self.external_kv
+            .try_lock()
+            .unwrap()
+            .set(PLACEHOLDER_END_KEY, placeholder_end.encode().into());
+    }
+
     pub(super) fn export_blocks_in_range<W: std::io::Write>(&self, spans: &[IdSpan], w: &mut W) {
+        let new_store = self.collect_changes_in_range(spans);
+        encode_blocks_in_store(new_store, &self.arena, w);
+    }
+
+    /// Same as [`ChangeStore::export_blocks_in_range`], but passes the encoded bytes of each
+    /// block to `f` as soon as the block is full, so only one block is kept in memory.
+    pub(super) fn for_each_block_bytes_in_range<E>(
+        &self,
+        spans: &[IdSpan],
+        f: impl FnMut(Bytes) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut encoder = BlockEncoder::new(&self.arena, self.merge_interval.clone(), f);
+        for span in spans {
+            let mut span = *span;
+            span.normalize_();
+            for c in self.iter_changes(span) {
+                let start = ((span.counter.start - c.id.counter).max(0) as usize).min(c.atom_len());
+                let end = ((span.counter.end - c.id.counter).max(0) as usize).min(c.atom_len());
+                if start == end {
+                    continue;
+                }
+
+                encoder.push(c.slice(start, end))?;
+            }
+
+            // The spans of the same peer may not be continuous
+            encoder.flush(None)?;
+        }
+
+        Ok(())
+    }
+
+    fn collect_changes_in_range(&self, spans: &[IdSpan]) -> ChangeStore {
         let new_store = ChangeStore::new_mem(&self.arena, self.merge_interval.clone());
         for span in spans {
             let mut span = *span;
@@ -193,7 +250,7 @@ impl ChangeStore {
             }
         }
 
-        encode_blocks_in_store(new_store, &self.arena, w);
+        new_store
     }
 
     fn encode_from(
@@ -481,6 +538,43 @@ impl ChangeStore {
         w: &mut W,
     ) {
         let new_store = ChangeStore::new_mem(&self.arena, self.merge_interval.clone());
+        self.visit_changes_from(start_vv, shallow_since_vv, latest_vv, |ch| {
+            new_store.insert_change(ch, false);
+            Ok::<(), Infallible>(())
+        })
+        .unwrap();
+        encode_blocks_in_store(new_store, &self.arena, w);
+    }
+
+    /// Same as [`ChangeStore::export_blocks_from`], but each change is passed through
+    /// `map_change` before it's encoded, and the encoded bytes of each block are passed
+    /// to `f` as soon as the block is full. Only one block is kept in memory.
+    ///
+    /// The mapped changes are encoded with `arena`, which may be a fork of the arena
+    /// of the store with more containers registered.
+    pub(crate) fn for_each_mapped_block_bytes_from<E>(
+        &self,
+        arena: &SharedArena,
+        start_vv: &VersionVector,
+        shallow_since_vv: &ImVersionVector,
+        latest_vv: &VersionVector,
+        mut map_change: impl FnMut(Change) -> Change,
+        f: impl FnMut(Bytes) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut encoder = BlockEncoder::new(arena, self.merge_interval.clone(), f);
+        self.visit_changes_from(start_vv, shallow_since_vv, latest_vv, |ch| {
+            encoder.push(map_change(ch))
+        })?;
+        encoder.flush(None)
+    }
+
+    fn visit_changes_from<E>(
+        &self,
+        start_vv: &VersionVector,
+        shallow_since_vv: &ImVersionVector,
+        latest_vv: &VersionVector,
+        mut visit: impl FnMut(Change) -> Result<(), E>,
+    ) -> Result<(), E> {
         for mut span in latest_vv.sub_iter(start_vv) {
             let counter_lower_bound = shallow_since_vv.get(&span.peer).copied().unwrap_or(0);
             span.counter.start = span.counter.start.max(counter_lower_bound);
@@ -500,13 +594,11 @@ impl ChangeStore {
                     .min(c.atom_len());
 
                 assert_ne!(start, end);
-                let ch = c.slice(start, end);
-                new_store.insert_change(ch, false);
+                visit(c.slice(start, end))?;
             }
         }
 
-        let arena = &self.arena;
-        encode_blocks_in_store(new_store, arena, w);
+        Ok(())
     }
 
     pub(crate) fn fork_changes_up_to(
@@ -514,6 +606,7 @@ impl ChangeStore {
         start_vv: &ImVersionVector,
         frontiers: &Frontiers,
         vv: &VersionVector,
+        placeholder_end: &VersionVector,
     ) -> Bytes {
         let new_store = ChangeStore::new_mem(&self.arena, self.merge_interval.clone());
         for mut span in vv.sub_iter_im(start_vv) {
@@ -540,7 +633,7 @@ impl ChangeStore {
             }
         }
 
-        new_store.encode_all(vv, frontiers)
+        new_store.encode_all(vv, frontiers, placeholder_end)
     }
 }
 
@@ -557,6 +650,154 @@ fn encode_blocks_in_store<W: std::io::Write>(
     }
 }
 
+/// Encode the changes into blocks, and pass the bytes of each block to the sink once no
+/// more changes can be pushed into it.
+///
+/// Only the block that is being filled is kept in memory.
+struct BlockEncoder<'a, F> {
+    store: ChangeStore,
+    arena: &'a SharedArena,
+    sink: F,
+}
+
+impl<'a, E, F: FnMut(Bytes) -> Result<(), E>> BlockEncoder<'a, F> {
+    fn new(arena: &'a SharedArena, merge_interval: Arc<AtomicI64>, sink: F) -> Self {
+        Self {
+            store: ChangeStore::new_mem(arena, merge_interval),
+            arena,
+            sink,
+        }
+    }
+
+    fn push(&mut self, change: Change) -> Result<(), E> {
+        let id = change.id;
+        self.store.insert_change(change, false);
+        // Only the block that contains the new change can still grow
+        self.flush(Some(id))
+    }
+
+    /// Pass all the blocks to the sink, except the one that contains `keep`
+    fn flush(&mut self, keep: Option<ID>) -> Result<(), E> {
+        let mut inner = self.store.inner.try_lock().unwrap();
+        let keep = keep.and_then(|id| {
+            inner
+                .mem_parsed_kv
+                .range(..=id)
+                .next_back()
+                .map(|(k, _)| *k)
+        });
+        let full: Vec<ID> = inner
+            .mem_parsed_kv
+            .keys()
+            .filter(|k| Some(**k) != keep)
+            .copied()
+            .collect();
+        let blocks: Vec<Bytes> = full
+            .into_iter()
+            .map(|k| {
+                let mut block = inner.mem_parsed_kv.remove(&k).unwrap();
+                block.to_bytes(self.arena).bytes
+            })
+            .collect();
+        drop(inner);
+        for block in blocks {
+            (self.sink)(block)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Split the change into the changes that fit in a block, if it's larger than the block size.
+///
+/// The local changes are split before they are signed, so that every stored change keeps
+/// its signature.
+pub(crate) fn split_change(change: Change) -> Vec<Change> {
+    if change.estimate_storage_size() <= MAX_BLOCK_SIZE {
+        return vec![change];
+    }
+
+    let original_len = change.atom_len();
+    let mut ans = Vec::new();
+    let mut new_change = Change {
+        ops: RleVec::new(),
+        deps: change.deps,
+        id: change.id,
+        lamport: change.lamport,
+        timestamp: change.timestamp,
+        commit_msg: change.commit_msg.clone(),
+        // The parts of a split change cannot be verified
+        signature: None,
+    };
+
+    let mut estimated_size = new_change.estimate_storage_size();
+    'outer: for mut op in change.ops.into_iter() {
+        if op.estimate_storage_size() >= MAX_BLOCK_SIZE - estimated_size {
+            new_change = push_split_change(&mut ans, new_change, &mut estimated_size);
+        }
+
+        while let Some(end) =
+            op.check_whether_slice_content_to_fit_in_size(MAX_BLOCK_SIZE - estimated_size)
+        {
+            // The new op can take the rest of the room
+            let new = op.slice(0, end);
+            new_change.ops.push(new);
+            new_change = push_split_change(&mut ans, new_change, &mut estimated_size);
+
+            if end < op.atom_len() {
+                op = op.slice(end, op.atom_len());
+            } else {
+                continue 'outer;
+            }
+        }
+
+        estimated_size += op.estimate_storage_size();
+        if estimated_size > MAX_BLOCK_SIZE && !new_change.ops.is_empty() {
+            new_change = push_split_change(&mut ans, new_change, &mut estimated_size);
+            new_change.ops.push(op);
+        } else {
+            new_change.ops.push(op);
+        }
+    }
+
+    if !new_change.ops.is_empty() {
+        ans.push(new_change);
+    }
+
+    assert_eq!(
+        ans.iter().map(|c| c.atom_len()).sum::<usize>(),
+        original_len
+    );
+    ans
+}
+
+/// Push the split change into `ans` and return the next empty change after it
+fn push_split_change(
+    ans: &mut Vec<Change>,
+    new_change: Change,
+    estimated_size: &mut usize,
+) -> Change {
+    if new_change.atom_len() == 0 {
+        return new_change;
+    }
+
+    let ctr_end = new_change.id.counter + new_change.atom_len() as Counter;
+    let next_lamport = new_change.lamport + new_change.atom_len() as Lamport;
+    let next = Change {
+        ops: RleVec::new(),
+        deps: ID::new(new_change.id.peer, ctr_end - 1).into(),
+        id: ID::new(new_change.id.peer, ctr_end),
+        lamport: next_lamport,
+        timestamp: new_change.timestamp,
+        commit_msg: new_change.commit_msg.clone(),
+        signature: None,
+    };
+
+    ans.push(new_change);
+    *estimated_size = next.estimate_storage_size();
+    next
+}
+
 mod mut_external_kv {
     //! Only this module contains the code that mutate the external kv store
     //! All other modules should only read from the external kv store
@@ -602,6 +843,10 @@ mod mut_external_kv {
             } else {
                 Frontiers::decode(&start_frontiers).unwrap()
             };
+            let placeholder_end = match kv_store.get(PLACEHOLDER_END_KEY) {
+                Some(bytes) => VersionVector::decode(&bytes)?,
+                None => Default::default(),
+            };
 
             let mut max_lamport = None;
             let mut max_timestamp = 0;
@@ -627,6 +872,7 @@ mod mut_external_kv {
             Ok(BatchDecodeInfo {
                 vv,
                 frontiers,
+                placeholder_end,
                 start_version: if start_vv.is_empty() {
                     None
                 } else {
@@ -700,7 +946,9 @@ mod mut_inner_kv {
             let s = info_span!("change_store insert_change", id = ?change.id);
             let _e = s.enter();
             let estimated_size = change.estimate_storage_size();
-            if estimated_size > MAX_BLOCK_SIZE && split_when_exceeds {
+            // A signed change is verified as a whole. The large changes are split before
+            // they are signed, see [`split_change`]
+            if estimated_size > MAX_BLOCK_SIZE && split_when_exceeds && change.signature.is_none() {
                 self.split_change_then_insert(change);
                 return;
             }
@@ -900,92 +1148,9 @@ mod mut_inner_kv {
         }
 
         fn split_change_then_insert(&self, change: Change) {
-            let original_len = change.atom_len();
-            let mut new_change = Change {
-                ops: RleVec::new(),
-                deps: change.deps,
-                id: change.id,
-                lamport: change.lamport,
-                timestamp: change.timestamp,
-                commit_msg: change.commit_msg.clone(),
-            };
-
-            let mut total_len = 0;
-            let mut estimated_size = new_change.estimate_storage_size();
-            'outer: for mut op in change.ops.into_iter() {
-                if op.estimate_storage_size() >= MAX_BLOCK_SIZE - estimated_size {
-                    new_change = self._insert_splitted_change(
-                        new_change,
-                        &mut total_len,
-                        &mut estimated_size,
-                    );
-                }
-
-                while let Some(end) =
-                    op.check_whether_slice_content_to_fit_in_size(MAX_BLOCK_SIZE - estimated_size)
-                {
-                    // The new op can take the rest of the room
-                    let new = op.slice(0, end);
-                    new_change.ops.push(new);
-                    new_change = self._insert_splitted_change(
-                        new_change,
-                        &mut total_len,
-                        &mut estimated_size,
-                    );
-
-                    if end < op.atom_len() {
-                        op = op.slice(end, op.atom_len());
-                    } else {
-                        continue 'outer;
-                    }
-                }
-
-                estimated_size += op.estimate_storage_size();
-                if estimated_size > MAX_BLOCK_SIZE && !new_change.ops.is_empty() {
-                    new_change = self._insert_splitted_change(
-                        new_change,
-                        &mut total_len,
-                        &mut estimated_size,
-                    );
-                    new_change.ops.push(op);
-                } else {
-                    new_change.ops.push(op);
-                }
-            }
-
-            if !new_change.ops.is_empty() {
-                total_len += new_change.atom_len();
-                self.insert_change(new_change, false);
-            }
-
-            assert_eq!(total_len, original_len);
-        }
-
-        fn _insert_splitted_change(
-            &self,
-            new_change: Change,
-            total_len: &mut usize,
-            estimated_size: &mut usize,
-        ) -> Change {
-            if new_change.atom_len() == 0 {
-                return new_change;
+            for change in split_change(change) {
+                self.insert_change(change, false);
             }
-
-            let ctr_end = new_change.id.counter + new_change.atom_len() as Counter;
-            let next_lamport = new_change.lamport + new_change.atom_len() as Lamport;
-            *total_len += new_change.atom_len();
-            let ans = Change {
-                ops: RleVec::new(),
-                deps: ID::new(new_change.id.peer, ctr_end - 1).into(),
-                id: ID::new(new_change.id.peer, ctr_end),
-                lamport: next_lamport,
-                timestamp: new_change.timestamp,
-                commit_msg: new_change.commit_msg.clone(),
-            };
-
-            self.insert_change(new_change, false);
-            *estimated_size = ans.estimate_storage_size();
-            ans
         }
 
         fn get_parsed_block(&self, id: ID) -> Option<Arc<ChangesBlock>> {
@@ -1113,6 +1278,7 @@ pub(crate) struct BatchDecodeInfo {
     pub vv: VersionVector,
     pub frontiers: Frontiers,
     pub start_version: Option<(VersionVector, Frontiers)>,
+    pub placeholder_end: VersionVector,
 }
 
 #[derive(Clone, Debug)]
@@ -1555,9 +1721,10 @@ mod test {
     fn test_encode_decode(doc: LoroDoc) {
         doc.commit_then_renew();
         let oplog = doc.oplog().try_lock().unwrap();
-        let bytes = oplog
-            .change_store
-            .encode_all(oplog.vv(), oplog.dag.frontiers());
+        let bytes =
+            oplog
+                .change_store
+                .encode_all(oplog.vv(), oplog.dag.frontiers(), &Default::default());
         let store = ChangeStore::new_for_test();
         let _ = store.import_all(bytes.clone()).unwrap();
         assert_eq!(store.external_kv.try_lock().unwrap().export_all(), bytes);
@@ -29,6 +29,9 @@
 //! ┌────────────────────────────────┬─────────────────────────────┐
 //! │    N Rle Commit Msg Lengths    │       Commit Messages       │
 //! └────────────────────────────────┴─────────────────────────────┘
+//! ┌────────────────────────────────┬─────────────────────────────┐
+//! │ N Rle Signature Lengths (opt)  │      Signatures (opt)       │
+//! └────────────────────────────────┴─────────────────────────────┘
 //!
 //!  ─ ─ ─ ─ ─ ─ ─ ─ ─ ─ ─ Encoded Operations ─ ─ ─ ─ ─ ─ ─ ─ ─ ─ ─
 //!
@@ -85,6 +88,7 @@ use crate::arena::SharedArena;
 use crate::change::{Change, Timestamp};
 use crate::container::tree::tree_op;
 use crate::encoding::arena::{ContainerArena, PositionArena};
+use crate::encoding::partition::{decode_placeholder, is_hidden_container};
 use crate::encoding::value_register::ValueRegister;
 use crate::encoding::{
     self, decode_op, encode_op, get_op_prop, EncodedDeleteStartId, IterableEncodedDeleteStartId,
@@ -560,6 +564,17 @@ pub fn decode_block(
     let commit_msg_len_decoder = AnyRleDecoder::<u32>::new(bytes);
     let (commit_msg_lens, commit_msgs) = commit_msg_len_decoder.take_n_finalize(n_changes).unwrap();
     let mut commit_msg_index = 0;
+    let commit_msgs_len = commit_msg_lens.iter().sum::<u32>() as usize;
+    let (commit_msgs, signature_bytes) =
+        commit_msgs.split_at(commit_msgs_len.min(commit_msgs.len()));
+    let (signature_lens, signatures) = if signature_bytes.is_empty() {
+        (vec![0; n_changes], signature_bytes)
+    } else {
+        AnyRleDecoder::<u32>::new(signature_bytes)
+            .take_n_finalize(n_changes)
+            .map_err(|_| LoroError::DecodeDataCorruptionError)?
+    };
+    let mut signature_index = 0;
     let keys = header.keys.get_or_init(|| decode_keys(&keys));
     let decode_arena = ValueDecodeArena {
         peers: &header.peers,
@@ -608,6 +623,21 @@ pub fn decode_block(
                 }
             }
         };
+        let signature: Option<Arc<[u8]>> = {
+            let len = signature_lens[i] as usize;
+            if len == 0 {
+                None
+            } else {
+                let end = signature_index + len;
+                if end > signatures.len() {
+                    return LoroResult::Err(LoroError::DecodeDataCorruptionError);
+                }
+
+                let sig = Arc::from(&signatures[signature_index..end]);
+                signature_index = end;
+                Some(sig)
+            }
+        };
         changes.push(Change {
             ops: Default::default(),
             deps: header.deps_groups[i].clone(),
@@ -615,6 +645,7 @@ pub fn decode_block(
             lamport: header.lamports[i],
             timestamp: timestamps[i] as Timestamp,
             commit_msg,
+            signature,
         })
     }
 
@@ -636,16 +667,20 @@ pub fn decode_block(
         )?;
 
         let cid = &cids[container_index as usize];
-        let content = decode_op(
-            cid,
-            value,
-            &mut del_iter,
-            shared_arena,
-            &decode_arena,
-            &positions,
-            prop,
-            ID::new(peer, counter),
-        )?;
+        let content = if is_hidden_container(cid) {
+            decode_placeholder(value, len as usize)?
+        } else {
+            decode_op(
+                cid,
+                value,
+                &mut del_iter,
+                shared_arena,
+                &decode_arena,
+                &positions,
+                prop,
+                ID::new(peer, counter),
+            )?
+        };
 
         let c_idx = shared_arena.register_container(cid);
         let op = Op {
@@ -19,6 +19,8 @@ pub(crate) fn encode_changes(
     let mut lamport_encoder = DeltaOfDeltaEncoder::new();
     let mut commit_msg_len_encoder = AnyRleEncoder::<u32>::new();
     let mut commit_msgs = String::new();
+    let mut signature_len_encoder = AnyRleEncoder::<u32>::new();
+    let mut signatures = Vec::new();
     let mut dep_self_encoder = BoolRleEncoder::new();
     let mut dep_len_encoder = AnyRleEncoder::<usize>::new();
     let mut encoded_deps = EncodedDeps {
@@ -43,6 +45,12 @@ pub(crate) fn encode_changes(
         } else {
             commit_msg_len_encoder.append(0).unwrap();
         }
+        if let Some(sig) = c.signature.as_ref() {
+            signature_len_encoder.append(sig.len() as u32).unwrap();
+            signatures.extend_from_slice(sig);
+        } else {
+            signature_len_encoder.append(0).unwrap();
+        }
 
         let mut dep_on_self = false;
         for dep in c.deps().iter() {
@@ -83,6 +91,12 @@ pub(crate) fn encode_changes(
     meta.append(&mut t);
     meta.append(&mut cml);
     meta.append(&mut cms);
+    // The signatures are appended only when there are any, so the blocks without
+    // signatures stay the same. The older versions ignore the trailing bytes.
+    if block.iter().any(|c| c.signature.is_some()) {
+        meta.append(&mut signature_len_encoder.finish().unwrap());
+        meta.append(&mut signatures);
+    }
 
     (ans, meta)
 }
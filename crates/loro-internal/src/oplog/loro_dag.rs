@@ -655,6 +655,18 @@ impl AppDag {
         }
     }
 
+    /// Revert the version changes made by the ops of the pending local txn
+    pub(crate) fn abort_pending_txn(&mut self, start_id: ID, frontiers: Frontiers) {
+        self.pending_txn_node = None;
+        if start_id.counter == 0 {
+            // The peer has no op left, so it must not be kept with an empty span
+            self.vv.remove(&start_id.peer);
+        } else {
+            self.vv.set_end(start_id);
+        }
+        self.frontiers = frontiers;
+    }
+
     pub(crate) fn update_version_on_new_local_op(
         &mut self,
         deps: &Frontiers,
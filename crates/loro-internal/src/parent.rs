@@ -10,7 +10,7 @@ use crate::{
     arena::SharedArena,
     change::Change,
     container::{list::list_op::ListOp, map::MapSet},
-    op::{ListSlice, RawOp, RawOpContent},
+    op::{FutureInnerContent, InnerContent, ListSlice, RawOp, RawOpContent},
     DocState, OpLog,
 };
 
@@ -27,6 +27,12 @@ impl OpLog {
 
 pub(super) fn register_container_and_parent_link(arena: &SharedArena, change: &Change) {
     for op in change.ops.iter() {
+        if let InnerContent::Future(FutureInnerContent::Placeholder { .. }) = &op.content {
+            // The hidden container of a partial history is not created by any op
+            // (see [`crate::encoding::partition`])
+            arena.set_parent(op.container, None);
+        }
+
         op.content.visit_created_children(arena, &mut |c| {
             let idx = arena.register_container(c);
             arena.set_parent(idx, Some(op.container));
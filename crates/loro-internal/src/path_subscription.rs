@@ -0,0 +1,340 @@
+//! Subscribing to the changes at the paths matching a pattern.
+//!
+//! [`LoroDoc::subscribe`] listens to a single container. [`LoroDoc::subscribe_path`] listens to
+//! every location matching a [`PathPattern`], including the containers created after subscribing.
+//!
+//! A pattern is either a glob over the path segments, like `/projects/*/tasks/**/title`,
+//! or a JSONPath-like expression, like `$.projects[*].tasks..title`.
+use std::sync::Arc;
+
+use loro_common::TreeID;
+use thiserror::Error;
+
+use crate::{
+    event::{Diff, Index},
+    subscription::Subscriber,
+    ContainerDiff, DiffEvent, LoroDoc, Subscription,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PathPatternError {
+    #[error("Invalid path pattern {pattern:?}: {reason}")]
+    InvalidPattern { pattern: String, reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// A map key, a list index or a tree node id
+    Exact {
+        key: String,
+        index: Option<usize>,
+        node: Option<TreeID>,
+    },
+    /// Any single segment: `*`
+    Any,
+    /// Any number of segments, including none: `**` or `..`
+    AnyDepth,
+}
+
+/// A child that may be changed by a diff
+enum Child<'a> {
+    Key(&'a str),
+    /// The list items starting from the index
+    IndexFrom(usize),
+    Node(TreeID),
+}
+
+impl Segment {
+    fn exact(key: String) -> Self {
+        Segment::Exact {
+            index: key.parse().ok(),
+            node: TreeID::try_from(key.as_str()).ok(),
+            key,
+        }
+    }
+
+    fn matches_index(&self, index: &Index) -> bool {
+        match self {
+            Segment::Exact {
+                key,
+                index: i,
+                node,
+            } => match index {
+                Index::Key(k) => key == k.as_str(),
+                Index::Seq(s) => *i == Some(*s),
+                Index::Node(id) => *node == Some(*id),
+            },
+            Segment::Any | Segment::AnyDepth => true,
+        }
+    }
+
+    fn matches_child(&self, child: &Child) -> bool {
+        match self {
+            Segment::Exact { key, index, node } => match child {
+                Child::Key(k) => key.as_str() == *k,
+                Child::IndexFrom(from) => index.is_some_and(|i| i >= *from),
+                Child::Node(id) => *node == Some(*id),
+            },
+            Segment::Any | Segment::AnyDepth => true,
+        }
+    }
+}
+
+/// A pattern over the paths of the containers and their children.
+///
+/// - `*` or `[*]` matches any single map key, list index or tree node
+/// - `**` or `..` matches any number of segments
+/// - Other segments match a map key, a list index or a tree node id exactly.
+///   In globs, `~1` and `~0` escape `/` and `~` like JSON Pointer.
+///
+/// A diff matches if its container is at a matching path or inside one, or if it changes
+/// a child at a matching path. A list diff changes all the indexes after its first edit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPattern {
+    segments: Vec<Segment>,
+}
+
+impl PathPattern {
+    pub fn parse(pattern: &str) -> Result<Self, PathPatternError> {
+        let segments = if let Some(rest) = pattern.strip_prefix('$') {
+            parse_json_path(rest)
+        } else {
+            Ok(parse_glob(pattern))
+        }
+        .map_err(|reason| PathPatternError::InvalidPattern {
+            pattern: pattern.to_string(),
+            reason,
+        })?;
+        Ok(Self { segments })
+    }
+
+    pub fn is_match(&self, diff: &ContainerDiff) -> bool {
+        let mut states = self.start();
+        for (_, index) in diff.path.iter() {
+            states = self.step(&states, |seg| seg.matches_index(index));
+            if !states.contains(&true) {
+                return false;
+            }
+        }
+
+        if states[self.segments.len()] {
+            return true;
+        }
+
+        let matches_child =
+            |child: Child| self.step(&states, |seg| seg.matches_child(&child))[self.segments.len()];
+        match &diff.diff {
+            Diff::Map(map) => map
+                .updated
+                .keys()
+                .any(|k| matches_child(Child::Key(k.as_str()))),
+            Diff::List(list) => {
+                let mut index = 0;
+                for item in list.iter() {
+                    match item {
+                        loro_delta::DeltaItem::Retain { len, .. } => index += *len,
+                        loro_delta::DeltaItem::Replace { .. } => {
+                            return matches_child(Child::IndexFrom(index));
+                        }
+                    }
+                }
+                false
+            }
+            Diff::Tree(tree) => tree
+                .diff
+                .iter()
+                .any(|x| matches_child(Child::Node(x.target))),
+            _ => false,
+        }
+    }
+
+    /// The positions in the pattern reachable before consuming any segment
+    fn start(&self) -> Vec<bool> {
+        let mut states = vec![false; self.segments.len() + 1];
+        states[0] = true;
+        self.close(&mut states);
+        states
+    }
+
+    /// `**` can match zero segments
+    fn close(&self, states: &mut [bool]) {
+        for (i, seg) in self.segments.iter().enumerate() {
+            if states[i] && *seg == Segment::AnyDepth {
+                states[i + 1] = true;
+            }
+        }
+    }
+
+    fn step(&self, states: &[bool], matches: impl Fn(&Segment) -> bool) -> Vec<bool> {
+        let mut next = vec![false; self.segments.len() + 1];
+        for (i, seg) in self.segments.iter().enumerate() {
+            if !states[i] {
+                continue;
+            }
+
+            match seg {
+                Segment::AnyDepth => next[i] = true,
+                seg if matches(seg) => next[i + 1] = true,
+                _ => {}
+            }
+        }
+
+        self.close(&mut next);
+        next
+    }
+}
+
+impl std::str::FromStr for PathPattern {
+    type Err = PathPatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+fn parse_glob(pattern: &str) -> Vec<Segment> {
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    pattern
+        .split('/')
+        .map(|s| match s {
+            "*" => Segment::Any,
+            "**" => Segment::AnyDepth,
+            s => Segment::exact(s.replace("~1", "/").replace("~0", "~")),
+        })
+        .collect()
+}
+
+fn parse_json_path(mut rest: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix("..") {
+            segments.push(Segment::AnyDepth);
+            rest = r;
+            if rest.starts_with('[') || rest.is_empty() {
+                continue;
+            }
+        } else if let Some(r) = rest.strip_prefix('.') {
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = find_bracket_end(r).ok_or_else(|| "unclosed '['".to_string())?;
+            let inner = r[..end].trim();
+            segments.push(if inner == "*" {
+                Segment::Any
+            } else if let Some(key) = unquote(inner) {
+                Segment::exact(key.to_string())
+            } else if inner.parse::<usize>().is_ok() {
+                Segment::exact(inner.to_string())
+            } else {
+                return Err(format!("unsupported selector [{}]", inner));
+            });
+            rest = &r[end + 1..];
+            continue;
+        } else {
+            return Err(format!("unexpected {:?}", rest));
+        }
+
+        let end = rest.find(['.', '[']).unwrap_or(rest.len());
+        let name = &rest[..end];
+        if name.is_empty() {
+            return Err("empty name".to_string());
+        }
+
+        segments.push(if name == "*" {
+            Segment::Any
+        } else {
+            Segment::exact(name.to_string())
+        });
+        rest = &rest[end..];
+    }
+
+    Ok(segments)
+}
+
+/// Find the `]` closing the selector, skipping the quoted keys
+fn find_bracket_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, ']') => return Some(i),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn unquote(s: &str) -> Option<&str> {
+    s.strip_prefix('\'')
+        .and_then(|s| s.strip_suffix('\''))
+        .or_else(|| s.strip_prefix('"').and_then(|s| s.strip_suffix('"')))
+}
+
+impl LoroDoc {
+    /// Subscribe to the changes at the paths matching the pattern.
+    ///
+    /// The callback receives the diffs matching [`PathPattern`]. It's not called if
+    /// no diff in the event matches.
+    pub fn subscribe_path(
+        &self,
+        pattern: &str,
+        callback: Subscriber,
+    ) -> Result<Subscription, PathPatternError> {
+        let pattern = PathPattern::parse(pattern)?;
+        Ok(self.subscribe_root(Arc::new(move |e| {
+            let events: Vec<&ContainerDiff> = e
+                .events
+                .iter()
+                .copied()
+                .filter(|diff| pattern.is_match(diff))
+                .collect();
+            if events.is_empty() {
+                return;
+            }
+
+            callback(DiffEvent {
+                current_target: None,
+                events: &events,
+                event_meta: e.event_meta,
+            });
+        })))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn segments(pattern: &str) -> Vec<Segment> {
+        PathPattern::parse(pattern).unwrap().segments
+    }
+
+    #[test]
+    fn parse_patterns() {
+        let expected = vec![
+            Segment::exact("projects".into()),
+            Segment::Any,
+            Segment::exact("tasks".into()),
+            Segment::AnyDepth,
+            Segment::exact("title".into()),
+        ];
+        assert_eq!(segments("/projects/*/tasks/**/title"), expected);
+        assert_eq!(segments("projects/*/tasks/**/title"), expected);
+        assert_eq!(segments("$.projects[*].tasks..title"), expected);
+        assert_eq!(segments("$['projects'].*[\"tasks\"]..['title']"), expected);
+        assert_eq!(
+            segments("/a~1b/0"),
+            vec![Segment::exact("a/b".into()), Segment::exact("0".into())]
+        );
+        assert_eq!(segments("$.list[2]"), segments("/list/2"));
+        assert!(segments("$").is_empty());
+        assert!(PathPattern::parse("$.a[").is_err());
+        assert!(PathPattern::parse("$.a[1:2]").is_err());
+        assert!(PathPattern::parse("$a").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Inspecting and vetoing the local transactions before they are committed.
+//!
+//! [`LoroDoc::subscribe_local_update`] is called after a commit with the encoded updates.
+//! The hooks registered by [`LoroDoc::subscribe_pre_commit`] are called before the commit,
+//! with the pending ops resolved to [`ContainerID`](loro_common::ContainerID)s. A hook can
+//! abort the transaction, which rolls back the state as if the ops were never applied,
+//! or annotate the commit message and the timestamp.
+//!
+//! Aborting a transaction emits no event and no local update. The ids of the aborted ops
+//! are reused by the next transaction.
+use loro_common::ID;
+
+use crate::{
+    change::Timestamp,
+    json::{JsonChange, JsonOp},
+    utils::subscription::SubscriberSet,
+    LoroDoc, Subscription,
+};
+
+/// The callback of the pre-commit hook.
+///
+/// The doc is locked while the hook runs, so it must not access the doc.
+/// Return `false` to unsubscribe.
+pub type PreCommitCallback = Box<dyn Fn(&mut PreCommit) -> bool + Send + Sync + 'static>;
+
+pub(crate) type PreCommitHooks = SubscriberSet<(), PreCommitCallback>;
+
+/// A local transaction about to be committed
+#[derive(Debug)]
+pub struct PreCommit<'a> {
+    change: JsonChange,
+    origin: &'a str,
+    aborted: bool,
+}
+
+impl<'a> PreCommit<'a> {
+    pub(crate) fn new(change: JsonChange, origin: &'a str) -> Self {
+        Self {
+            change,
+            origin,
+            aborted: false,
+        }
+    }
+
+    /// The pending change, including the annotations made by the hooks so far.
+    ///
+    /// The ids in it are not compressed, they carry the original peers.
+    pub fn change(&self) -> &JsonChange {
+        &self.change
+    }
+
+    /// The pending ops
+    pub fn ops(&self) -> &[JsonOp] {
+        &self.change.ops
+    }
+
+    /// The id of the first pending op
+    pub fn id(&self) -> ID {
+        self.change.id
+    }
+
+    /// The origin of the transaction. It's propagated to the events.
+    pub fn origin(&self) -> &str {
+        self.origin
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.change.msg.as_deref()
+    }
+
+    /// Set the commit message. An empty message removes it.
+    pub fn set_message(&mut self, msg: &str) {
+        self.change.msg = if msg.is_empty() {
+            None
+        } else {
+            Some(msg.to_string())
+        };
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.change.timestamp
+    }
+
+    /// Set the timestamp of the change.
+    ///
+    /// Timestamps are forced to be in ascending order. If it's less than the timestamp of the
+    /// changes it depends on, the greatest of them is used instead.
+    pub fn set_timestamp(&mut self, timestamp: Timestamp) {
+        self.change.timestamp = timestamp;
+    }
+
+    /// Abort the transaction. Its ops are discarded and the state is rolled back.
+    ///
+    /// The hooks after this one are not called.
+    pub fn abort(&mut self) {
+        self.aborted = true;
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+
+    pub(crate) fn into_change(self) -> JsonChange {
+        self.change
+    }
+}
+
+/// Run the hooks in the order they were added, until one of them aborts.
+pub(crate) fn run_pre_commit_hooks(hooks: &PreCommitHooks, pre_commit: &mut PreCommit) {
+    // The doc is locked here, so the hooks can't commit recursively
+    let _ = hooks.retain(&(), &mut |callback| {
+        if pre_commit.aborted {
+            return true;
+        }
+
+        callback(&mut *pre_commit)
+    });
+}
+
+impl LoroDoc {
+    /// Subscribe to the local transactions before they are committed.
+    ///
+    /// The callback receives the pending ops, the origin and the commit message. It can abort
+    /// the transaction by [`PreCommit::abort`] or annotate the commit message and the timestamp.
+    ///
+    /// The pending auto-commit transaction is committed before subscribing, because the
+    /// transactions started earlier cannot be rolled back.
+    pub fn subscribe_pre_commit(&self, callback: PreCommitCallback) -> Subscription {
+        self.commit_then_stop();
+        let (sub, activate) = self.pre_commit_subs.insert((), callback);
+        activate();
+        self.renew_txn_if_auto_commit();
+        sub
+    }
+}
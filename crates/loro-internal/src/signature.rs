@@ -0,0 +1,344 @@
+//! Signing the local changes and verifying the signatures of the imported changes.
+//!
+//! A [`Change`] doesn't authenticate its author by itself, so anyone relaying the updates
+//! can forge changes under any [`PeerID`]. With a [`ChangeSigner`], every local change is
+//! signed by ed25519 on commit. The signature covers a canonical encoding of the change:
+//! its id, deps, lamport, timestamp, commit message and ops, with the absolute container
+//! ids and values. It doesn't depend on the arena or the block layout of any peer, so the
+//! change can be relayed and re-encoded by other peers. The signature is stored alongside
+//! the change and exported with it.
+//!
+//! With a [`ChangeVerifier`], every imported change must be signed by the key registered
+//! for its peer. Otherwise it's rejected or quarantined, depending on the [`SignaturePolicy`].
+//!
+//! Notes:
+//!
+//! - A signed change is never merged with other changes.
+//! - A slice of a signed change has no signature, because it cannot be verified. So a local
+//!   change larger than the block size is split before it's signed, and each part is signed
+//!   on its own. The signed changes are never split when stored.
+//! - The quarantined changes are never imported again, even if the key of their peer is
+//!   registered later. They are only kept to be inspected until they are cleared. To import
+//!   them, register the key and import the updates from their source again.
+//! - Snapshots are imported by replaying their history when a verifier is set, so that
+//!   every change is verified.
+//! - The JSON format doesn't carry the signatures. When a verifier is set, importing the
+//!   JSON updates that contain new changes fails with [`LoroError::SignatureRequired`].
+use std::sync::Arc;
+
+pub use ed25519_dalek as ed25519;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use fxhash::FxHashMap;
+use loro_common::{HasIdSpan, LoroError, LoroResult, LoroValue, PeerID, ID};
+
+use crate::{
+    arena::SharedArena,
+    change::Change,
+    encoding::json_schema::{change_to_json, json::JsonOpContent},
+    oplog::{split_change, OpLog},
+    ChangeMeta, LoroDoc,
+};
+
+/// The hook used to sign the local changes on commit.
+///
+/// It's implemented by [`SigningKey`]. Implement it to keep the key in a keystore or an HSM.
+pub trait ChangeSigner: Send + Sync {
+    fn sign(&self, payload: &[u8]) -> Signature;
+}
+
+impl ChangeSigner for SigningKey {
+    fn sign(&self, payload: &[u8]) -> Signature {
+        Signer::sign(self, payload)
+    }
+}
+
+/// What to do with the imported changes that are unsigned or have invalid signatures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SignaturePolicy {
+    /// Fail the whole import with [`LoroError::InvalidChangeSignature`]. Nothing is imported.
+    #[default]
+    Reject,
+    /// Import the valid changes and put the invalid ones aside.
+    ///
+    /// The changes depending on the quarantined ones become pending.
+    /// The quarantined changes can be inspected by [`LoroDoc::quarantined_changes`]. They are
+    /// never imported later, even if the key of their peer is registered afterwards.
+    Quarantine,
+}
+
+/// The public keys of the peers, used to verify the signatures of the imported changes.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeVerifier {
+    keys: FxHashMap<PeerID, VerifyingKey>,
+    policy: SignaturePolicy,
+}
+
+impl ChangeVerifier {
+    pub fn new(policy: SignaturePolicy) -> Self {
+        Self {
+            keys: Default::default(),
+            policy,
+        }
+    }
+
+    /// Register the public key of the peer.
+    ///
+    /// The changes from the peers without a registered key are regarded as unsigned.
+    pub fn peer_key(mut self, peer: PeerID, key: VerifyingKey) -> Self {
+        self.keys.insert(peer, key);
+        self
+    }
+
+    pub fn policy(&self) -> SignaturePolicy {
+        self.policy
+    }
+
+    /// Check whether the change is signed by the key of its peer
+    pub fn verify(&self, change: &Change, arena: &SharedArena) -> bool {
+        let Some(key) = self.keys.get(&change.peer()) else {
+            return false;
+        };
+        let Some(signature) = change
+            .signature
+            .as_deref()
+            .and_then(|s| Signature::from_slice(s).ok())
+        else {
+            return false;
+        };
+
+        let payload = encode_change_for_signing(change, arena);
+        key.verify(&payload, &signature).is_ok()
+    }
+}
+
+/// The signing states of an [`OpLog`]
+#[derive(Default)]
+pub(crate) struct ChangeSignatures {
+    signer: Option<Arc<dyn ChangeSigner>>,
+    verifier: Option<ChangeVerifier>,
+    quarantined: Vec<Change>,
+}
+
+impl std::fmt::Debug for ChangeSignatures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChangeSignatures")
+            .field("has_signer", &self.signer.is_some())
+            .field("verifier", &self.verifier)
+            .field("quarantined", &self.quarantined.len())
+            .finish()
+    }
+}
+
+/// The prefix of the signed payloads, so that the signatures can't be reused for other
+/// kinds of messages signed by the same key
+const SIGNING_DOMAIN: &[u8] = b"loro-change-signature:v1\n";
+
+/// Encode the change into the payload signed by its author.
+///
+/// The change is converted to JSON with the absolute peer ids, container ids and values.
+/// The deps and the map keys are sorted, and every value is tagged with its type, so the
+/// payload only depends on the content of the change.
+fn encode_change_for_signing(change: &Change, arena: &SharedArena) -> Vec<u8> {
+    let mut json = change_to_json(change, arena);
+    json.deps.sort_unstable();
+    for op in json.ops.iter_mut() {
+        for value in op_values(&mut op.content) {
+            *value = tag_value(value);
+        }
+    }
+
+    let mut ans = SIGNING_DOMAIN.to_vec();
+    write_canonical_json(&serde_json::to_value(&json).unwrap(), &mut ans);
+    ans
+}
+
+fn op_values(content: &mut JsonOpContent) -> Vec<&mut LoroValue> {
+    use crate::encoding::json_schema::json::{ListOp, MapOp, MovableListOp, TextOp};
+    match content {
+        JsonOpContent::List(ListOp::Insert { value, .. })
+        | JsonOpContent::MovableList(MovableListOp::Insert { value, .. }) => {
+            value.iter_mut().collect()
+        }
+        JsonOpContent::MovableList(MovableListOp::Set { value, .. })
+        | JsonOpContent::Map(MapOp::Insert { value, .. })
+        | JsonOpContent::Text(TextOp::Mark {
+            style_value: value, ..
+        }) => vec![value],
+        _ => vec![],
+    }
+}
+
+/// Tag the value with its type, so that the values that share the same JSON
+/// representation, such as a binary and a list of numbers, are encoded differently
+fn tag_value(value: &LoroValue) -> LoroValue {
+    let (tag, payload) = match value {
+        LoroValue::Null => (0, LoroValue::Null),
+        LoroValue::Bool(b) => (1, LoroValue::Bool(*b)),
+        // The JSON can't represent NaN and infinity
+        LoroValue::Double(d) => (2, LoroValue::I64(d.to_bits() as i64)),
+        LoroValue::I64(i) => (3, LoroValue::I64(*i)),
+        LoroValue::Binary(b) => (4, b.iter().map(|x| *x as i64).collect::<Vec<_>>().into()),
+        LoroValue::String(s) => (5, LoroValue::String(s.clone())),
+        LoroValue::List(l) => (6, l.iter().map(tag_value).collect::<Vec<_>>().into()),
+        LoroValue::Map(m) => (
+            7,
+            m.iter()
+                .map(|(k, v)| (k.clone(), tag_value(v)))
+                .collect::<FxHashMap<_, _>>()
+                .into(),
+        ),
+        LoroValue::Container(id) => (8, LoroValue::String(id.to_string().into())),
+    };
+
+    vec![LoroValue::I64(tag), payload].into()
+}
+
+/// Write the JSON with the object keys sorted
+fn write_canonical_json(value: &serde_json::Value, out: &mut Vec<u8>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+            out.push(b'{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+
+                serde_json::to_writer(&mut *out, key).unwrap();
+                out.push(b':');
+                write_canonical_json(value, out);
+            }
+            out.push(b'}');
+        }
+        serde_json::Value::Array(arr) => {
+            out.push(b'[');
+            for (i, value) in arr.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+
+                write_canonical_json(value, out);
+            }
+            out.push(b']');
+        }
+        value => serde_json::to_writer(&mut *out, value).unwrap(),
+    }
+}
+
+impl OpLog {
+    /// Sign the local change if there is a signer.
+    ///
+    /// A change larger than the block size is split first, as the change store would do, and
+    /// each part is signed. So every stored change keeps its signature.
+    pub(crate) fn sign_change(&self, change: Change) -> Vec<Change> {
+        let Some(signer) = self.signature.signer.as_ref() else {
+            return vec![change];
+        };
+
+        let mut changes = split_change(change);
+        for change in changes.iter_mut() {
+            let payload = encode_change_for_signing(change, &self.arena);
+            let signature = signer.sign(&payload);
+            change.signature = Some(Arc::from(signature.to_bytes().as_slice()));
+        }
+
+        changes
+    }
+
+    pub(crate) fn has_change_verifier(&self) -> bool {
+        self.signature.verifier.is_some()
+    }
+
+    /// Reject the changes imported from JSON when a verifier is set, because the JSON
+    /// format doesn't carry the signatures.
+    ///
+    /// The changes that are already included in the oplog are skipped by the import, so
+    /// they're allowed.
+    pub(crate) fn check_json_changes_can_be_verified(&self, changes: &[Change]) -> LoroResult<()> {
+        if !self.has_change_verifier() {
+            return Ok(());
+        }
+
+        match changes.iter().find(|c| !self.vv().includes_id(c.id_last())) {
+            Some(c) => Err(LoroError::SignatureRequired { id: c.id }),
+            None => Ok(()),
+        }
+    }
+
+    /// Verify the signatures of the imported changes.
+    ///
+    /// Return the changes that can be imported. The changes that are already included
+    /// in the oplog are not verified, they will be skipped by the import anyway.
+    pub(crate) fn verify_change_signatures(
+        &mut self,
+        changes: Vec<Change>,
+    ) -> LoroResult<Vec<Change>> {
+        let Some(verifier) = self.signature.verifier.as_ref() else {
+            return Ok(changes);
+        };
+
+        let mut valid = Vec::with_capacity(changes.len());
+        let mut invalid = Vec::new();
+        for change in changes {
+            if self.vv().includes_id(change.id_last()) || verifier.verify(&change, &self.arena) {
+                valid.push(change);
+            } else {
+                match verifier.policy() {
+                    SignaturePolicy::Reject => {
+                        return Err(LoroError::InvalidChangeSignature { id: change.id });
+                    }
+                    SignaturePolicy::Quarantine => invalid.push(change),
+                }
+            }
+        }
+
+        if !invalid.is_empty() {
+            tracing::warn!("Quarantined {} changes", invalid.len());
+            self.signature.quarantined.extend(invalid);
+        }
+
+        Ok(valid)
+    }
+}
+
+impl LoroDoc {
+    /// Set the signer of the local changes.
+    ///
+    /// Every change committed after this call is signed. Pass `None` to stop signing.
+    pub fn set_change_signer(&self, signer: Option<Arc<dyn ChangeSigner>>) {
+        self.oplog.try_lock().unwrap().signature.signer = signer;
+    }
+
+    /// Set the verifier of the imported changes.
+    ///
+    /// Pass `None` to import the changes without verifying their signatures.
+    pub fn set_change_verifier(&self, verifier: Option<ChangeVerifier>) {
+        self.oplog.try_lock().unwrap().signature.verifier = verifier;
+    }
+
+    /// Get the changes that were put aside by [`SignaturePolicy::Quarantine`]
+    pub fn quarantined_changes(&self) -> Vec<ChangeMeta> {
+        let oplog = self.oplog.try_lock().unwrap();
+        oplog
+            .signature
+            .quarantined
+            .iter()
+            .map(ChangeMeta::from_change)
+            .collect()
+    }
+
+    /// Drop the quarantined changes
+    pub fn clear_quarantined_changes(&self) {
+        self.oplog.try_lock().unwrap().signature.quarantined.clear();
+    }
+
+    /// Check whether the change at the given id is signed by the key of its peer.
+    ///
+    /// Return `None` if the change is not found.
+    pub fn verify_change_signature(&self, id: ID, verifier: &ChangeVerifier) -> Option<bool> {
+        let oplog = self.oplog.try_lock().unwrap();
+        let change = oplog.get_change_at(id)?;
+        Some(verifier.verify(&change, &oplog.arena))
+    }
+}
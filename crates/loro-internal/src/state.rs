@@ -76,6 +76,9 @@ pub struct DocState {
     // txn related stuff
     in_txn: bool,
     changed_idx_in_txn: FxHashSet<ContainerIdx>,
+    /// The states of the containers before they were changed in the txn.
+    /// It's only recorded when the txn can be rolled back.
+    txn_pre_images: Option<FxHashMap<ContainerIdx, State>>,
 
     // diff related stuff
     event_recorder: EventRecorder,
@@ -384,6 +387,7 @@ impl DocState {
                 global_txn,
                 in_txn: false,
                 changed_idx_in_txn: FxHashSet::default(),
+                txn_pre_images: None,
                 event_recorder: Default::default(),
                 dead_containers_cache: Default::default(),
             })
@@ -409,6 +413,7 @@ impl DocState {
                 global_txn,
                 in_txn: false,
                 changed_idx_in_txn: FxHashSet::default(),
+                txn_pre_images: None,
                 event_recorder: Default::default(),
                 dead_containers_cache: Default::default(),
             })
@@ -737,6 +742,11 @@ impl DocState {
         let state = self.store.get_or_create_mut(op.container);
         if self.in_txn {
             self.changed_idx_in_txn.insert(op.container);
+            if let Some(pre_images) = self.txn_pre_images.as_mut() {
+                pre_images
+                    .entry(op.container)
+                    .or_insert_with(|| state.fork(&self.config));
+            }
         }
         let ret = state.apply_local_op(raw_op, op)?;
         if !ret.deleted_containers.is_empty() {
@@ -749,10 +759,82 @@ impl DocState {
     pub(crate) fn start_txn(&mut self, origin: InternalString, trigger: EventTriggerKind) {
         self.pre_txn(origin, trigger);
         self.in_txn = true;
+        self.changed_idx_in_txn.clear();
     }
 
     pub(crate) fn abort_txn(&mut self) {
         self.in_txn = false;
+        self.txn_pre_images = None;
+    }
+
+    /// Record the states of the containers before they are changed in the current txn.
+    ///
+    /// Only the containers touched by the txn are recorded, on their first change.
+    pub(crate) fn record_txn_pre_images(&mut self) {
+        assert!(self.in_txn);
+        self.txn_pre_images.get_or_insert_with(Default::default);
+    }
+
+    /// Restore the containers changed in the current txn and end it
+    pub(crate) fn rollback_txn(&mut self, frontiers: Frontiers) {
+        let pre_images = self
+            .txn_pre_images
+            .take()
+            .expect("The pre-images of the txn are not recorded");
+        for (idx, state) in pre_images {
+            *self.store.get_or_create_mut(idx) = state;
+        }
+
+        self.changed_idx_in_txn.clear();
+        self.frontiers = frontiers;
+        self.dead_containers_cache.clear();
+        self.in_txn = false;
+    }
+
+    /// Compute the state of the container at the version, without changing the state of
+    /// the doc.
+    ///
+    /// Only the ops between the current version of the state and the given version are
+    /// applied. The version must be included by the oplog and not before the shallow root,
+    /// and the pending txn must have no op.
+    pub(crate) fn container_state_at(
+        &mut self,
+        oplog: &OpLog,
+        idx: ContainerIdx,
+        frontiers: &Frontiers,
+    ) -> State {
+        let mut state = self.store.get_or_create_imm(idx).fork(&self.config);
+        let before = oplog.dag.frontiers_to_vv(&self.frontiers).unwrap();
+        let after = oplog.dag.frontiers_to_vv(frontiers).unwrap();
+        let mut diff_calc = DiffCalculator::new(false);
+        let (diffs, _diff_mode) = diff_calc.calc_diff_internal(
+            oplog,
+            &before,
+            &self.frontiers,
+            &after,
+            frontiers,
+            Some(&|x| x == idx),
+        );
+        for diff in diffs {
+            if diff.idx != idx {
+                continue;
+            }
+
+            let Ok(internal_diff) = diff.diff.into_internal() else {
+                continue;
+            };
+            state.apply_diff(
+                internal_diff,
+                DiffApplyContext {
+                    mode: diff.diff_mode,
+                    arena: &self.arena,
+                    txn: &self.global_txn,
+                    state: &self.weak_state,
+                },
+            );
+        }
+
+        state
     }
 
     pub fn iter_and_decode_all(&mut self) -> impl Iterator<Item = &mut State> {
@@ -787,6 +869,7 @@ impl DocState {
 
     pub(crate) fn commit_txn(&mut self, new_frontiers: Frontiers, diff: Option<InternalDocDiff>) {
         self.in_txn = false;
+        self.txn_pre_images = None;
         self.frontiers = new_frontiers;
         if self.is_recording() {
             self.record_diff(diff.unwrap());
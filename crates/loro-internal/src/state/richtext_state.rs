@@ -14,11 +14,11 @@ use crate::{
         idx::ContainerIdx,
         list::list_op,
         richtext::{
-            config::StyleConfigMap,
+            config::{parse_annotation_key, StyleConfigMap},
             richtext_state::{
                 DrainInfo, EntityRangeInfo, IterRangeItem, PosType, RichtextStateChunk,
             },
-            AnchorType, RichtextState as InnerState, StyleOp, Styles,
+            AnchorType, RichtextSpan, RichtextState as InnerState, StyleOp, Styles,
         },
     },
     delta::{StyleMeta, StyleMetaItem},
@@ -259,11 +259,12 @@ impl RichtextState {
 
     pub(crate) fn get_delta(&mut self) -> Vec<TextDelta> {
         let mut delta = Vec::new();
+        let config = self.config.read().unwrap();
         // TODO: merge last
         for span in self.state.get_mut().iter() {
             delta.push(TextDelta::Insert {
                 insert: span.text.as_str().to_string(),
-                attributes: span.attributes.to_option_map(),
+                attributes: span.attributes.with_annotations(&config).to_option_map(),
             })
         }
         delta
@@ -308,6 +309,8 @@ impl ContainerState for RichtextState {
         };
 
         // tracing::info!("Self state = {:#?}", &self);
+        let config = self.config.clone();
+        let config = config.read().unwrap();
         // PERF: compose delta
         let mut ans: TextDiff = TextDiff::new();
         let mut style_delta: TextDiff = TextDiff::new();
@@ -385,6 +388,7 @@ impl ContainerState for RichtextState {
                                                 )
                                             }
                                         }
+                                        style_meta.resolve_annotations(&config);
                                         delta.push_retain(event_len, style_meta);
                                     }
                                     RichtextStateChunk::Style { .. } => {}
@@ -408,7 +412,8 @@ impl ContainerState for RichtextState {
                                         entity_index,
                                         RichtextStateChunk::Text(s.clone()),
                                     );
-                                let insert_styles = styles.clone().into();
+                                let insert_styles =
+                                    StyleMeta::from(styles).with_annotations(&config);
 
                                 if pos > event_index {
                                     ans.push_retain(pos - event_index, Default::default());
@@ -464,7 +469,7 @@ impl ContainerState for RichtextState {
                                                 style.clone(),
                                             );
                                         for (s, l) in event {
-                                            delta.push_retain(l, s);
+                                            delta.push_retain(l, s.with_annotations(&config));
                                         }
 
                                         delta.chop();
@@ -643,8 +648,9 @@ impl ContainerState for RichtextState {
         _state: &Weak<Mutex<DocState>>,
     ) -> Diff {
         let mut delta = TextDiff::new();
+        let config = self.config.read().unwrap();
         for span in self.state.get_mut().iter() {
-            delta.push_insert(span.text, span.attributes);
+            delta.push_insert(span.text, span.attributes.with_annotations(&config));
         }
 
         Diff::Text(delta)
@@ -825,6 +831,61 @@ impl RichtextState {
         self.state
             .get_mut()
             .get_styles_at_entity_index_for_insert(entity_index)
+            .with_annotations(&self.config.read().unwrap())
+    }
+
+    #[inline]
+    pub(crate) fn get_styles_at_event_index(
+        &mut self,
+        event_index: usize,
+    ) -> LoroResult<StyleMeta> {
+        Ok(self
+            .state
+            .get_mut()
+            .get_styles_at_event_index_for_insert(event_index)?
+            .with_annotations(&self.config.read().unwrap()))
+    }
+
+    #[inline]
+    pub(crate) fn get_styles_in_event_range(&mut self, range: Range<usize>) -> Vec<StyleMeta> {
+        let config = self.config.read().unwrap();
+        let mut styles = self.state.get_mut().get_styles_in_event_range(range);
+        for style in styles.iter_mut() {
+            style.resolve_annotations(&config);
+        }
+        styles
+    }
+
+    #[inline]
+    pub(crate) fn get_first_span_in_event_range(
+        &mut self,
+        range: Range<usize>,
+    ) -> Option<RichtextSpan> {
+        let config = self.config.read().unwrap();
+        let mut span = self.state.get_mut().get_first_span_in_event_range(range)?;
+        span.attributes.resolve_annotations(&config);
+        Some(span)
+    }
+
+    /// Get the event index ranges of the annotations of the key.
+    ///
+    /// It's empty if the key is not an annotation in the config.
+    pub(crate) fn get_annotations(&mut self, key: &str) -> Vec<(ID, LoroValue, Range<usize>)> {
+        if !self.config.read().unwrap().is_annotation(key) {
+            return Vec::new();
+        }
+
+        self.state
+            .get_mut()
+            .get_style_ranges(|style_key| {
+                parse_annotation_key(style_key).map_or(false, |(base, _)| base == key)
+            })
+            .into_iter()
+            .map(|(style_key, value, range)| {
+                let (_, id) = parse_annotation_key(&style_key).unwrap();
+                (id, value, range)
+            })
+            .collect()
     }
 
     #[inline]
@@ -840,7 +901,9 @@ impl RichtextState {
 
     #[inline]
     pub fn get_richtext_value(&mut self) -> LoroValue {
-        self.state.get_mut().get_richtext_value()
+        self.state
+            .get_mut()
+            .get_richtext_value_with_config(&self.config.read().unwrap())
     }
 
     #[inline]
@@ -869,6 +932,26 @@ impl RichtextState {
         self.state.get_mut().index_to_event_index(index, pos_type)
     }
 
+    pub(crate) fn convert_index(&mut self, index: usize, from: PosType, to: PosType) -> usize {
+        self.state.get_mut().convert_index(index, from, to)
+    }
+
+    pub(crate) fn convert_pos(&mut self, pos: usize, from: PosType, to: PosType) -> Option<usize> {
+        self.state.get_mut().convert_pos(pos, from, to)
+    }
+
+    pub(crate) fn len_line_breaks(&mut self) -> usize {
+        self.state.get_mut().len_line_breaks()
+    }
+
+    pub(crate) fn line_start_event_index(&mut self, line: usize) -> Option<usize> {
+        self.state.get_mut().line_start_event_index(line)
+    }
+
+    pub(crate) fn line_breaks_before_event_index(&mut self, index: usize) -> usize {
+        self.state.get_mut().line_breaks_before_event_index(index)
+    }
+
     pub(crate) fn event_index_to_unicode_index(&mut self, event_index: usize) -> usize {
         self.state
             .get_mut()
@@ -11,14 +11,18 @@ use loro_common::{ContainerID, ID};
 use smallvec::SmallVec;
 use std::{
     collections::VecDeque,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, Weak},
 };
+use web_time::{Duration, Instant};
 
 /// The callback of the local update.
 pub type LocalUpdateCallback = Box<dyn Fn(&Vec<u8>) -> bool + Send + Sync + 'static>;
 /// The callback of the peer id change. The second argument is the next counter for the peer.
 pub type PeerIdUpdateCallback = Box<dyn Fn(&ID) -> bool + Send + Sync + 'static>;
 pub type Subscriber = Arc<dyn (for<'a> Fn(DiffEvent<'a>)) + Send + Sync>;
+/// Runs the task after the given delay. It's used by the throttled subscriptions to deliver
+/// the pending events.
+pub type ThrottleScheduler = Arc<dyn Fn(Duration, Box<dyn FnOnce() + Send>) + Send + Sync>;
 
 impl LoroDoc {
     /// Subscribe to the changes of the peer id.
@@ -27,6 +31,177 @@ impl LoroDoc {
         enable();
         s
     }
+
+    /// Subscribe to all the events, delivering them at most once every `interval`.
+    ///
+    /// The first event is delivered immediately. The events arriving within `interval` after a
+    /// delivery are composed into one [`DocDiff`]. The composed event goes from the `from` of
+    /// the first event to the `to` of the last one.
+    ///
+    /// The doc has no timer of its own. When an event is kept pending, `scheduler` is called
+    /// with the time until it's due and a task that delivers it, and it should run the task
+    /// after that delay, e.g. with `setTimeout` or a runtime's sleep. At most one task is
+    /// scheduled at a time per subscription. The pending event can also be delivered earlier
+    /// by [`LoroDoc::poll_events`] or [`LoroDoc::flush_events`].
+    ///
+    /// The pending events are discarded when the subscription is dropped, and the scheduled
+    /// task then does nothing.
+    pub fn subscribe_root_throttled(
+        &self,
+        interval: Duration,
+        scheduler: ThrottleScheduler,
+        callback: Subscriber,
+    ) -> Subscription {
+        let throttled = Arc::new_cyclic(|this| Throttled {
+            interval,
+            scheduler,
+            callback,
+            this: this.clone(),
+            state: Default::default(),
+        });
+        let mut subs = self.throttled_subs.lock().unwrap();
+        subs.retain(|x| x.strong_count() > 0);
+        subs.push(Arc::downgrade(&throttled));
+        drop(subs);
+        self.subscribe_root(Arc::new(move |e| {
+            throttled.push(e);
+        }))
+    }
+
+    /// Deliver the pending events of the throttled subscribers now.
+    ///
+    /// The uncommitted changes are not included. Call [`LoroDoc::commit`] first to include them.
+    pub fn flush_events(&self) {
+        for sub in self.throttled_subscribers() {
+            sub.flush();
+        }
+    }
+
+    /// Deliver the pending events of the throttled subscribers whose interval has elapsed.
+    ///
+    /// It returns the time until the next pending event is due, or `None` if no event is
+    /// pending. The caller should poll again after that duration. The uncommitted changes are
+    /// not included.
+    pub fn poll_events(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.throttled_subscribers()
+            .into_iter()
+            .filter_map(|sub| sub.poll(now))
+            .min()
+    }
+
+    fn throttled_subscribers(&self) -> Vec<Arc<Throttled>> {
+        self.throttled_subs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|x| x.upgrade())
+            .collect()
+    }
+}
+
+/// The registry of the throttled subscribers of a doc
+pub(crate) type ThrottledSubscribers = Arc<Mutex<Vec<Weak<Throttled>>>>;
+
+pub(crate) struct Throttled {
+    interval: Duration,
+    scheduler: ThrottleScheduler,
+    callback: Subscriber,
+    this: Weak<Throttled>,
+    state: Mutex<ThrottledState>,
+}
+
+#[derive(Default)]
+struct ThrottledState {
+    pending: Option<DocDiff>,
+    last_emit: Option<Instant>,
+    /// Whether a task delivering the pending event is scheduled
+    scheduled: bool,
+}
+
+impl Throttled {
+    fn push(&self, event: DiffEvent) {
+        let diff = DocDiff {
+            from: event.event_meta.from.clone(),
+            to: event.event_meta.to.clone(),
+            origin: event.event_meta.origin.clone(),
+            by: event.event_meta.by,
+            diff: event.events.iter().map(|&x| x.clone()).collect(),
+        };
+        let mut state = self.state.lock().unwrap();
+        match &mut state.pending {
+            Some(pending) => pending.compose(diff),
+            None => state.pending = Some(diff),
+        }
+
+        drop(state);
+        if let Some(wait) = self.poll(Instant::now()) {
+            self.schedule(wait);
+        }
+    }
+
+    fn schedule(&self, wait: Duration) {
+        let mut state = self.state.lock().unwrap();
+        if state.scheduled {
+            return;
+        }
+
+        state.scheduled = true;
+        drop(state);
+        let this = self.this.clone();
+        (self.scheduler)(
+            wait,
+            Box::new(move || {
+                if let Some(this) = this.upgrade() {
+                    this.state.lock().unwrap().scheduled = false;
+                    if let Some(wait) = this.poll(Instant::now()) {
+                        this.schedule(wait);
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Deliver the pending event if the interval has elapsed since the last delivery.
+    ///
+    /// Returns the time until the pending event is due if it's kept pending.
+    fn poll(&self, now: Instant) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+        state.pending.as_ref()?;
+        if let Some(last) = state.last_emit {
+            let elapsed = now.saturating_duration_since(last);
+            if elapsed < self.interval {
+                return Some(self.interval - elapsed);
+            }
+        }
+
+        state.last_emit = Some(now);
+        let pending = state.pending.take().unwrap();
+        drop(state);
+        self.emit(&pending);
+        None
+    }
+
+    fn flush(&self) {
+        let mut state = self.state.lock().unwrap();
+        let Some(pending) = state.pending.take() else {
+            return;
+        };
+
+        state.last_emit = Some(Instant::now());
+        drop(state);
+        self.emit(&pending);
+    }
+
+    /// It must be called without holding the lock, because the callback may flush the events.
+    fn emit(&self, diff: &DocDiff) {
+        let events: Vec<_> = diff.diff.iter().collect();
+        (self.callback)(DiffEvent {
+            current_target: None,
+            events: &events,
+            event_meta: diff,
+        });
+    }
 }
 
 struct ObserverInner {
@@ -157,7 +332,7 @@ mod test {
     use tracing::trace;
 
     use super::*;
-    use crate::{handler::HandlerTrait, loro::LoroDoc};
+    use crate::{handler::HandlerTrait, loro::LoroDoc, version::Frontiers};
 
     #[test]
     fn test_recursive_events() {
@@ -220,4 +395,38 @@ mod test {
         }
         assert_eq!(count.load(Ordering::SeqCst), 2);
     }
+
+    #[test]
+    fn throttled_events_keep_the_frontiers() {
+        let loro = LoroDoc::new();
+        loro.set_peer_id(1).unwrap();
+        let versions = Arc::new(Mutex::new(Vec::new()));
+        let versions_cp = Arc::clone(&versions);
+        let _sub = loro.subscribe_root_throttled(
+            Duration::MAX,
+            Arc::new(|_, _| {}),
+            Arc::new(move |e| {
+                versions_cp
+                    .lock()
+                    .unwrap()
+                    .push((e.event_meta.from.clone(), e.event_meta.to.clone()));
+            }),
+        );
+
+        let text = loro.get_text("id");
+        for i in 0..3 {
+            let mut txn = loro.txn().unwrap();
+            text.insert_with_txn(&mut txn, i, "1").unwrap();
+            txn.commit().unwrap();
+        }
+        loro.flush_events();
+        let versions = versions.lock().unwrap();
+        assert_eq!(
+            *versions,
+            vec![
+                (Frontiers::default(), ID::new(1, 0).into()),
+                (ID::new(1, 0).into(), ID::new(1, 2).into()),
+            ]
+        );
+    }
 }
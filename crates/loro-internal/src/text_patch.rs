@@ -0,0 +1,553 @@
+//! Unified diffs of the texts.
+//!
+//! The diffs are computed by lines with the same Myers' diff used by
+//! [`TextHandler::update_by_line`]. A [`Hunk`] keeps the line breaks of its lines, so a text
+//! without the trailing line break is restored exactly, and it's written as
+//! `\ No newline at end of file` in the unified format.
+//!
+//! When a patch is applied, each hunk is located by its context. It may be found at another
+//! line than its header says, and if it can't be found, up to [`MAX_FUZZ`] lines of the
+//! context at both ends are ignored and the trailing whitespaces of the lines are ignored.
+use std::fmt::Display;
+
+use fxhash::FxHashMap;
+use loro_common::{ContainerID, ContainerType, LoroError, LoroResult};
+use thiserror::Error;
+
+use crate::{
+    dag::Dag,
+    diff::{diff, diff_impl::UpdateOptions, DiffHandler, OperateProxy},
+    handler::TextHandler,
+    state::State,
+    version::Frontiers,
+    LoroDoc,
+};
+
+/// The number of the unchanged lines around the changes in a hunk
+pub const DEFAULT_CONTEXT_LINES: usize = 3;
+
+/// The max number of the context lines that can be ignored at each end of a hunk when the
+/// hunk is applied
+pub const MAX_FUZZ: usize = 2;
+
+const NO_NEWLINE_MARKER: &str = "\\ No newline at end of file";
+
+/// A line in a [`Hunk`]. The content includes the trailing line break if the line has one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HunkLine {
+    /// A line that is not changed
+    Context(String),
+    /// A line that is deleted from the old text
+    Delete(String),
+    /// A line that is inserted into the new text
+    Insert(String),
+}
+
+impl HunkLine {
+    fn content(&self) -> &str {
+        match self {
+            HunkLine::Context(s) | HunkLine::Delete(s) | HunkLine::Insert(s) => s,
+        }
+    }
+
+    fn content_mut(&mut self) -> &mut String {
+        match self {
+            HunkLine::Context(s) | HunkLine::Delete(s) | HunkLine::Insert(s) => s,
+        }
+    }
+
+    fn is_context(&self) -> bool {
+        matches!(self, HunkLine::Context(_))
+    }
+}
+
+/// A hunk of a unified diff
+///
+/// The line numbers are 1-based like the header of the hunk. If the hunk doesn't contain any
+/// line of one side, the start of that side is the line before the hunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// The first line of the hunk in the old text
+    pub old_start: usize,
+    /// The number of the context and deleted lines
+    pub old_len: usize,
+    /// The first line of the hunk in the new text
+    pub new_start: usize,
+    /// The number of the context and inserted lines
+    pub new_len: usize,
+    /// The lines of the hunk
+    pub lines: Vec<HunkLine>,
+}
+
+impl Display for Hunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "@@ -{},{} +{},{} @@",
+            self.old_start, self.old_len, self.new_start, self.new_len
+        )?;
+        for line in self.lines.iter() {
+            let prefix = match line {
+                HunkLine::Context(_) => ' ',
+                HunkLine::Delete(_) => '-',
+                HunkLine::Insert(_) => '+',
+            };
+            let content = line.content();
+            write!(f, "{}{}", prefix, content)?;
+            if !content.ends_with('\n') {
+                writeln!(f)?;
+                writeln!(f, "{}", NO_NEWLINE_MARKER)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum TextPatchError {
+    #[error("Invalid unified diff at line {line}: {reason}")]
+    InvalidPatch { line: usize, reason: String },
+    #[error("Hunk #{index} cannot be applied to the text")]
+    HunkNotApplied { index: usize },
+    #[error(transparent)]
+    Loro(#[from] LoroError),
+}
+
+/// Write the hunks in the unified format with the file headers
+pub fn unified_diff(hunks: &[Hunk], old_name: &str, new_name: &str) -> String {
+    let mut ans = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for hunk in hunks {
+        ans.push_str(&hunk.to_string());
+    }
+    ans
+}
+
+/// Compute the hunks that turn `old` into `new`, with `context` unchanged lines around
+/// the changes.
+pub fn diff_lines(old: &str, new: &str, context: usize) -> Vec<Hunk> {
+    let mut ids: FxHashMap<&str, u32> = FxHashMap::default();
+    let mut intern = |line| {
+        let len = ids.len() as u32;
+        *ids.entry(line).or_insert(len)
+    };
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let old_ids: Vec<u32> = old_lines.iter().map(|l| intern(l)).collect();
+    let new_ids: Vec<u32> = new_lines.iter().map(|l| intern(l)).collect();
+
+    let mut proxy = OperateProxy::new(RegionCollector::default());
+    // It never times out without the timeout option
+    diff(&mut proxy, UpdateOptions::default(), &old_ids, &new_ids).unwrap();
+    let regions = proxy.unwrap().regions;
+
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < regions.len() {
+        // Group the regions whose contexts overlap
+        let mut j = i + 1;
+        while j < regions.len() && regions[j].old_start - regions[j - 1].old_end <= 2 * context {
+            j += 1;
+        }
+
+        let first = &regions[i];
+        let last = &regions[j - 1];
+        let old_begin = first.old_start.saturating_sub(context);
+        let old_end = (last.old_end + context).min(old_lines.len());
+        let new_begin = first.new_start - (first.old_start - old_begin);
+        let new_end = last.new_end + (old_end - last.old_end);
+
+        let mut lines = Vec::new();
+        let mut cursor = old_begin;
+        for region in &regions[i..j] {
+            lines.extend(
+                old_lines[cursor..region.old_start]
+                    .iter()
+                    .map(|l| HunkLine::Context(l.to_string())),
+            );
+            lines.extend(
+                old_lines[region.old_start..region.old_end]
+                    .iter()
+                    .map(|l| HunkLine::Delete(l.to_string())),
+            );
+            lines.extend(
+                new_lines[region.new_start..region.new_end]
+                    .iter()
+                    .map(|l| HunkLine::Insert(l.to_string())),
+            );
+            cursor = region.old_end;
+        }
+        lines.extend(
+            old_lines[cursor..old_end]
+                .iter()
+                .map(|l| HunkLine::Context(l.to_string())),
+        );
+
+        let start = |begin: usize, end: usize| if begin == end { begin } else { begin + 1 };
+        hunks.push(Hunk {
+            old_start: start(old_begin, old_end),
+            old_len: old_end - old_begin,
+            new_start: start(new_begin, new_end),
+            new_len: new_end - new_begin,
+            lines,
+        });
+        i = j;
+    }
+
+    hunks
+}
+
+/// A changed region. The old lines in it are replaced by the new lines.
+#[derive(Debug)]
+struct Region {
+    old_start: usize,
+    old_end: usize,
+    new_start: usize,
+    new_end: usize,
+}
+
+#[derive(Debug, Default)]
+struct RegionCollector {
+    regions: Vec<Region>,
+    old_index: usize,
+    new_index: usize,
+}
+
+impl RegionCollector {
+    fn push(&mut self, region: Region) {
+        self.old_index = region.old_end;
+        self.new_index = region.new_end;
+        if let Some(last) = self.regions.last_mut() {
+            if last.old_end == region.old_start && last.new_end == region.new_start {
+                last.old_end = region.old_end;
+                last.new_end = region.new_end;
+                return;
+            }
+        }
+
+        self.regions.push(region);
+    }
+}
+
+impl DiffHandler for RegionCollector {
+    fn insert(&mut self, old_index: usize, new_index: usize, new_len: usize) {
+        self.push(Region {
+            old_start: old_index,
+            old_end: old_index,
+            new_start: new_index,
+            new_end: new_index + new_len,
+        });
+    }
+
+    fn delete(&mut self, old_index: usize, old_len: usize) {
+        // The lines between the last region and this one are not changed
+        let new_index = self.new_index + (old_index - self.old_index);
+        self.push(Region {
+            old_start: old_index,
+            old_end: old_index + old_len,
+            new_start: new_index,
+            new_end: new_index,
+        });
+    }
+}
+
+/// Parse the hunks of a unified diff. The lines before the first hunk and between the
+/// hunks, like the file headers, are skipped.
+pub fn parse_unified_diff(patch: &str) -> Result<Vec<Hunk>, TextPatchError> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    // The numbers of the old and the new lines that the current hunk still expects
+    let mut remaining = (0, 0);
+    for (i, line) in patch.split_inclusive('\n').enumerate() {
+        let invalid = |reason: &str| TextPatchError::InvalidPatch {
+            line: i + 1,
+            reason: reason.to_string(),
+        };
+
+        if line.starts_with('\\') {
+            // The previous line doesn't end with a line break
+            let last = hunks
+                .last_mut()
+                .and_then(|h| h.lines.last_mut())
+                .ok_or_else(|| invalid("unexpected no newline marker"))?;
+            let content = last.content_mut();
+            if content.ends_with('\n') {
+                content.pop();
+                if content.ends_with('\r') {
+                    content.pop();
+                }
+            }
+            continue;
+        }
+
+        if remaining == (0, 0) {
+            if line.starts_with("@@") {
+                let hunk = parse_hunk_header(line).ok_or_else(|| invalid("invalid hunk header"))?;
+                remaining = (hunk.old_len, hunk.new_len);
+                hunks.push(hunk);
+            }
+            continue;
+        }
+
+        let (prefix, content) = match line.chars().next() {
+            Some(c @ (' ' | '-' | '+')) => (c, &line[1..]),
+            // Some tools strip the space of the empty context lines
+            Some('\n') | Some('\r') => (' ', line),
+            _ => return Err(invalid("unexpected line in the hunk")),
+        };
+        let (old_lines, new_lines) = match prefix {
+            ' ' => (1, 1),
+            '-' => (1, 0),
+            _ => (0, 1),
+        };
+        if remaining.0 < old_lines || remaining.1 < new_lines {
+            return Err(invalid("the hunk has more lines than its header says"));
+        }
+        remaining = (remaining.0 - old_lines, remaining.1 - new_lines);
+        let content = content.to_string();
+        let line = match prefix {
+            ' ' => HunkLine::Context(content),
+            '-' => HunkLine::Delete(content),
+            _ => HunkLine::Insert(content),
+        };
+        hunks.last_mut().unwrap().lines.push(line);
+    }
+
+    if remaining != (0, 0) {
+        return Err(TextPatchError::InvalidPatch {
+            line: patch.split_inclusive('\n').count(),
+            reason: "the hunk has fewer lines than its header says".to_string(),
+        });
+    }
+
+    Ok(hunks)
+}
+
+/// Parse `@@ -old_start[,old_len] +new_start[,new_len] @@`
+fn parse_hunk_header(line: &str) -> Option<Hunk> {
+    let mut parts = line.strip_prefix("@@ ")?.split_whitespace();
+    let parse_range = |s: &str| -> Option<(usize, usize)> {
+        match s.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((s.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_len) = parse_range(parts.next()?.strip_prefix('-')?)?;
+    let (new_start, new_len) = parse_range(parts.next()?.strip_prefix('+')?)?;
+    if parts.next()? != "@@" {
+        return None;
+    }
+
+    Some(Hunk {
+        old_start,
+        old_len,
+        new_start,
+        new_len,
+        lines: Vec::new(),
+    })
+}
+
+/// Where a hunk is applied
+#[derive(Debug)]
+struct Placement {
+    /// The line in the text that matches the first used line of the hunk
+    line: usize,
+    /// The used lines of the hunk
+    lines: std::ops::Range<usize>,
+}
+
+fn locate_hunks(text_lines: &[&str], hunks: &[Hunk]) -> Result<Vec<Placement>, TextPatchError> {
+    let mut ans = Vec::with_capacity(hunks.len());
+    // A hunk can't overlap the previous one
+    let mut min_line = 0;
+    // How far the hunks are moved from where their headers say
+    let mut shift: isize = 0;
+    for (index, hunk) in hunks.iter().enumerate() {
+        let leading = hunk.lines.iter().take_while(|l| l.is_context()).count();
+        let trailing = hunk
+            .lines
+            .iter()
+            .rev()
+            .take_while(|l| l.is_context())
+            .count();
+        let expected = if hunk.old_len == 0 {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+
+        let mut found = None;
+        'fuzz: for fuzz in 0..=MAX_FUZZ {
+            let skip_start = leading.min(fuzz);
+            let skip_end = trailing.min(fuzz).min(hunk.lines.len() - skip_start);
+            let used = skip_start..hunk.lines.len() - skip_end;
+            let pattern: Vec<&str> = hunk.lines[used.clone()]
+                .iter()
+                .filter(|l| !matches!(l, HunkLine::Insert(_)))
+                .map(|l| l.content())
+                .collect();
+            if pattern.len() + min_line > text_lines.len() {
+                continue;
+            }
+
+            let line_eq = |a: &str, b: &str| {
+                if fuzz == 0 {
+                    a == b
+                } else {
+                    a.trim_end() == b.trim_end()
+                }
+            };
+            let max_line = text_lines.len() - pattern.len();
+            let hint = (expected as isize + shift + skip_start as isize)
+                .clamp(min_line as isize, max_line as isize) as usize;
+            // Search from the hint outwards
+            for distance in 0..=(max_line - min_line) {
+                let before = hint.checked_sub(distance).filter(|_| distance > 0);
+                for line in [Some(hint + distance), before].into_iter().flatten() {
+                    if line < min_line || line > max_line {
+                        continue;
+                    }
+
+                    if pattern
+                        .iter()
+                        .zip(&text_lines[line..])
+                        .all(|(a, b)| line_eq(a, b))
+                    {
+                        found = Some((
+                            Placement {
+                                line,
+                                lines: used.clone(),
+                            },
+                            pattern.len(),
+                            line as isize - (expected + skip_start) as isize,
+                        ));
+                        break 'fuzz;
+                    }
+                }
+            }
+        }
+
+        let Some((placement, matched_len, new_shift)) = found else {
+            return Err(TextPatchError::HunkNotApplied { index });
+        };
+        min_line = placement.line + matched_len;
+        shift = new_shift;
+        ans.push(placement);
+    }
+
+    Ok(ans)
+}
+
+impl TextHandler {
+    /// Compute the unified diff hunks that turn the current text into `new`.
+    pub fn diff_with(&self, new: &str) -> Vec<Hunk> {
+        diff_lines(&self.to_string(), new, DEFAULT_CONTEXT_LINES)
+    }
+
+    /// Apply a unified diff to the text.
+    ///
+    /// Every hunk is located before the text is edited, so the text is not changed if any
+    /// of the hunks can't be applied.
+    pub fn apply_patch(&self, patch: &str) -> Result<(), TextPatchError> {
+        let hunks = parse_unified_diff(patch)?;
+        let text = self.to_string();
+        let text_lines: Vec<&str> = text.split_inclusive('\n').collect();
+        let placements = locate_hunks(&text_lines, &hunks)?;
+
+        let mut line_starts = Vec::with_capacity(text_lines.len() + 1);
+        let mut pos = 0;
+        line_starts.push(0);
+        for line in text_lines.iter() {
+            pos += line.chars().count();
+            line_starts.push(pos);
+        }
+
+        let delete = |pos: usize, len: &mut usize| -> LoroResult<()> {
+            if *len > 0 {
+                self.delete_unicode(pos, *len)?;
+                *len = 0;
+            }
+            Ok(())
+        };
+        // Apply from the end so that the positions of the previous hunks are not shifted
+        for (hunk, placement) in hunks.iter().zip(placements).rev() {
+            let mut line = placement.line;
+            let mut pos = line_starts[line];
+            // The deleted lines are merged into one deletion
+            let mut deleting = 0;
+            for hunk_line in &hunk.lines[placement.lines] {
+                match hunk_line {
+                    HunkLine::Delete(_) => {
+                        deleting += line_starts[line + 1] - line_starts[line];
+                        line += 1;
+                    }
+                    HunkLine::Context(_) => {
+                        delete(pos, &mut deleting)?;
+                        pos += line_starts[line + 1] - line_starts[line];
+                        line += 1;
+                    }
+                    HunkLine::Insert(s) => {
+                        delete(pos, &mut deleting)?;
+                        self.insert_unicode(pos, s)?;
+                        pos += s.chars().count();
+                    }
+                }
+            }
+            delete(pos, &mut deleting)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl LoroDoc {
+    /// Compute the unified diff hunks of the text container between two versions.
+    ///
+    /// The text is empty at the version where the container doesn't exist.
+    pub fn text_diff_between(
+        &self,
+        container: &ContainerID,
+        a: &Frontiers,
+        b: &Frontiers,
+    ) -> LoroResult<Vec<Hunk>> {
+        if container.container_type() != ContainerType::Text {
+            return Err(LoroError::ArgErr(
+                format!("{} is not a text container", container).into_boxed_str(),
+            ));
+        }
+
+        self.commit_then_renew();
+        let old = self.text_at(container, a)?;
+        let new = self.text_at(container, b)?;
+        Ok(diff_lines(&old, &new, DEFAULT_CONTEXT_LINES))
+    }
+
+    /// Compute the text at the version from the history, without changing the state of the doc
+    fn text_at(&self, container: &ContainerID, frontiers: &Frontiers) -> LoroResult<String> {
+        if frontiers.is_empty() {
+            return Ok(String::new());
+        }
+
+        let oplog = self.oplog.try_lock().unwrap();
+        for id in frontiers.iter() {
+            if !oplog.dag.contains(id) {
+                return Err(LoroError::FrontiersNotFound(id));
+            }
+        }
+
+        if oplog.dag.is_before_shallow_root(frontiers) {
+            return Err(LoroError::SwitchToVersionBeforeShallowRoot);
+        }
+
+        let Some(idx) = self.arena.id_to_idx(container) else {
+            // The container has no op in the history
+            return Ok(String::new());
+        };
+
+        let mut state = self.state.try_lock().unwrap();
+        let State::RichtextState(mut text) = state.container_state_at(&oplog, idx, frontiers)
+        else {
+            unreachable!()
+        };
+        Ok(text.to_string_mut())
+    }
+}
@@ -21,11 +21,12 @@ use crate::{
         IntoContainerId,
     },
     delta::{ResolvedMapDelta, ResolvedMapValue, StyleMeta, StyleMetaItem, TreeDiff, TreeDiffItem},
-    encoding::export_fast_updates_in_range,
+    encoding::{export_fast_updates_in_range, json_schema::change_to_json},
     event::{Diff, ListDeltaMeta, TextDiff},
     handler::{Handler, ValueOrHandler},
     id::{Counter, PeerID, ID},
     op::{Op, RawOp, RawOpContent},
+    pre_commit::{run_pre_commit_hooks, PreCommit, PreCommitHooks},
     span::HasIdSpan,
     version::Frontiers,
     InternalString, LoroError, LoroValue,
@@ -68,6 +69,10 @@ impl crate::LoroDoc {
             self.get_global_txn(),
         );
 
+        if !self.pre_commit_subs.is_empty() {
+            txn.set_pre_commit_hooks(self.pre_commit_subs.clone());
+        }
+
         let obs = self.observer.clone();
         let local_update_subs_weak = self.local_update_subs.downgrade();
         txn.set_on_commit(Box::new(move |state, oplog, id_span| {
@@ -105,6 +110,65 @@ impl crate::LoroDoc {
         Ok(v)
     }
 
+    /// Run `f` in a transaction that is rolled back if `f` returns an error.
+    ///
+    /// The edits made by `f` through the handlers of this doc are committed as one change if
+    /// `f` succeeds. If `f` fails, the state is restored to what it was before `f`, and no op
+    /// is recorded and no event is emitted. The pending auto-commit transaction is committed
+    /// before `f` is called.
+    ///
+    /// The transaction stays open until `f` returns. Inside `f`, `commit` does nothing, and
+    /// `import`, `checkout`, `set_peer_id` and a nested `transact` fail with
+    /// [`LoroError::TransactionInProgress`].
+    pub fn transact<R, E>(&self, f: impl FnOnce(&Self) -> Result<R, E>) -> Result<R, E>
+    where
+        E: From<LoroError>,
+    {
+        self.check_not_in_transact()?;
+        self.commit_then_stop();
+        let mut txn = self.txn()?;
+        txn.enable_rollback();
+        self.txn.try_lock().unwrap().replace(txn);
+        self.in_transact
+            .store(true, std::sync::atomic::Ordering::Release);
+        let ans = f(self);
+        self.in_transact
+            .store(false, std::sync::atomic::Ordering::Release);
+        let txn = self.txn.try_lock().unwrap().take();
+        let Some(mut txn) = txn else {
+            self.renew_txn_if_auto_commit();
+            return ans;
+        };
+
+        if ans.is_err() && txn.can_roll_back() {
+            txn.abort();
+            self.renew_txn_if_auto_commit();
+            return ans;
+        }
+
+        let on_commit = txn.take_on_commit();
+        let id_span = txn.commit_and_get_id_span()?;
+        self.renew_txn_if_auto_commit();
+        if let Some(on_commit) = on_commit {
+            on_commit(&self.state, &self.oplog, id_span);
+        }
+
+        ans
+    }
+
+    #[inline]
+    pub(crate) fn is_in_transact(&self) -> bool {
+        self.in_transact.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    pub(crate) fn check_not_in_transact(&self) -> LoroResult<()> {
+        if self.is_in_transact() {
+            return Err(LoroError::TransactionInProgress);
+        }
+
+        Ok(())
+    }
+
     pub fn start_auto_commit(&self) {
         self.auto_commit
             .store(true, std::sync::atomic::Ordering::Release);
@@ -153,6 +217,9 @@ pub struct Transaction {
     timestamp: Option<Timestamp>,
     msg: Option<Arc<str>>,
     latest_timestamp: Timestamp,
+    pre_commit: Option<PreCommitHooks>,
+    /// The oplog frontiers before the transaction. It's `Some` if the transaction can be rolled back.
+    rollback: Option<Frontiers>,
 }
 
 impl std::fmt::Debug for Transaction {
@@ -171,6 +238,7 @@ impl std::fmt::Debug for Transaction {
             .field("finished", &self.finished)
             .field("on_commit", &self.on_commit.is_some())
             .field("timestamp", &self.timestamp)
+            .field("rollback", &self.rollback)
             .finish()
     }
 }
@@ -188,6 +256,8 @@ pub(super) enum EventHint {
         start: u32,
         end: u32,
         style: Style,
+        /// The key of the annotation group of the style, if the key is configured as an annotation
+        annotation: Option<InternalString>,
     },
     InsertText {
         /// pos is a Unicode index. If wasm, it's a UTF-16 index.
@@ -371,6 +441,8 @@ impl Transaction {
             on_commit: None,
             msg: None,
             latest_timestamp,
+            pre_commit: None,
+            rollback: None,
         }
     }
 
@@ -394,10 +466,76 @@ impl Transaction {
         self.on_commit.take()
     }
 
+    /// Run the pre-commit hooks before committing. The transaction can be rolled back after this.
+    pub(crate) fn set_pre_commit_hooks(&mut self, hooks: PreCommitHooks) {
+        self.enable_rollback();
+        self.pre_commit = Some(hooks);
+    }
+
+    /// Record the states of the containers before they are changed,
+    /// so that the transaction can be rolled back.
+    ///
+    /// It must be called before any op is applied.
+    pub(crate) fn enable_rollback(&mut self) {
+        assert!(self.local_ops.is_empty());
+        if self.rollback.is_some() {
+            return;
+        }
+
+        self.state.try_lock().unwrap().record_txn_pre_images();
+        self.rollback = Some(self.oplog.try_lock().unwrap().frontiers().clone());
+    }
+
+    /// Discard the applied ops, restoring the state and the version before the transaction.
+    fn roll_back(&mut self, frontiers: Frontiers) {
+        let oplog_frontiers = self
+            .rollback
+            .take()
+            .expect("The transaction cannot be rolled back");
+        let mut state = self.state.try_lock().unwrap();
+        let mut oplog = self.oplog.try_lock().unwrap();
+        oplog
+            .dag
+            .abort_pending_txn(ID::new(self.peer, self.start_counter), oplog_frontiers);
+        state.rollback_txn(frontiers);
+        drop(state);
+        drop(oplog);
+        self.local_ops = RleVec::new();
+        self.event_hints.clear();
+        self.next_counter = self.start_counter;
+        self.next_lamport = self.start_lamport;
+    }
+
+    pub(crate) fn can_roll_back(&self) -> bool {
+        self.rollback.is_some()
+    }
+
+    /// Abort the transaction, rolling back the applied ops. No event is emitted.
+    ///
+    /// It must be able to be rolled back, see [`Transaction::enable_rollback`].
+    pub(crate) fn abort(mut self) {
+        self.finished = true;
+        if self.local_ops.is_empty() {
+            self.state.try_lock().unwrap().abort_txn();
+            return;
+        }
+
+        let frontiers = take(&mut self.frontiers);
+        self.roll_back(frontiers);
+    }
+
     pub fn commit(mut self) -> Result<(), LoroError> {
         self._commit()
     }
 
+    /// Commit the transaction and return the span of the committed ops.
+    ///
+    /// The span is empty if nothing is committed.
+    pub(crate) fn commit_and_get_id_span(mut self) -> Result<IdSpan, LoroError> {
+        self._commit()?;
+        Ok(self.id_span())
+    }
+
     #[tracing::instrument(level = "debug", skip(self))]
     fn _commit(&mut self) -> Result<(), LoroError> {
         if self.finished {
@@ -414,7 +552,7 @@ impl Transaction {
         let ops = std::mem::take(&mut self.local_ops);
         let mut oplog = self.oplog.try_lock().unwrap();
         let deps = take(&mut self.frontiers);
-        let change = Change {
+        let mut change = Change {
             lamport: self.start_lamport,
             ops,
             deps,
@@ -424,7 +562,24 @@ impl Transaction {
                     .unwrap_or_else(|| oplog.get_timestamp_for_next_txn()),
             ),
             commit_msg: take(&mut self.msg),
+            signature: None,
         };
+        if let Some(hooks) = self.pre_commit.as_ref() {
+            let mut pre_commit =
+                PreCommit::new(change_to_json(&change, &oplog.arena), &self.origin);
+            run_pre_commit_hooks(hooks, &mut pre_commit);
+            if pre_commit.is_aborted() {
+                let frontiers = change.deps.clone();
+                drop(state);
+                drop(oplog);
+                self.roll_back(frontiers);
+                return Ok(());
+            }
+
+            let json = pre_commit.into_change();
+            change.commit_msg = json.msg.map(|x| x.into());
+            change.timestamp = self.latest_timestamp.max(json.timestamp);
+        }
 
         let diff = if state.is_recording() {
             Some(change_to_diff(
@@ -439,11 +594,17 @@ impl Transaction {
         };
 
         let last_id = change.id_last();
-        if let Err(err) = oplog.import_local_change(change) {
-            state.abort_txn();
-            drop(state);
-            drop(oplog);
-            return Err(err);
+        #[cfg(feature = "signature")]
+        let changes = oplog.sign_change(change);
+        #[cfg(not(feature = "signature"))]
+        let changes = [change];
+        for change in changes {
+            if let Err(err) = oplog.import_local_change(change) {
+                state.abort_txn();
+                drop(state);
+                drop(oplog);
+                return Err(err);
+            }
         }
 
         state.commit_txn(
@@ -716,8 +877,16 @@ fn change_to_diff(
             }
         }
         match hint {
-            EventHint::Mark { start, end, style } => {
+            EventHint::Mark {
+                start,
+                end,
+                style,
+                annotation,
+            } => {
                 let mut meta = StyleMeta::default();
+                if let Some(annotation) = annotation {
+                    meta.add_annotation(annotation);
+                }
                 meta.insert(
                     style.key.clone(),
                     StyleMetaItem {
@@ -0,0 +1,161 @@
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Visit the grapheme boundaries of a text given chunk by chunk as
+/// `(grapheme index, utf8 index)`, until `f` returns `false`.
+///
+/// A grapheme may span several chunks, so only the current chunk and the last grapheme of
+/// the previous ones are segmented at a time. The walk stops at the first chunk that covers
+/// the target, so the whole text is never built nor segmented.
+pub(crate) fn for_each_boundary(
+    for_each_chunk: impl FnOnce(&mut dyn FnMut(&str) -> bool),
+    mut f: impl FnMut(usize, usize) -> bool,
+) {
+    let mut window = String::new();
+    let mut window_start = 0;
+    let mut index = 0;
+    let mut stopped = false;
+    for_each_chunk(&mut |chunk| {
+        window.push_str(chunk);
+        let mut starts = window.grapheme_indices(true).map(|(i, _)| i).peekable();
+        let mut last = 0;
+        while let Some(start) = starts.next() {
+            if starts.peek().is_none() {
+                // The last grapheme may continue in the next chunk
+                last = start;
+                break;
+            }
+
+            if !f(index, window_start + start) {
+                stopped = true;
+                return false;
+            }
+            index += 1;
+        }
+
+        window.drain(..last);
+        window_start += last;
+        true
+    });
+
+    if stopped {
+        return;
+    }
+
+    for (start, _) in window.grapheme_indices(true) {
+        if !f(index, window_start + start) {
+            return;
+        }
+        index += 1;
+    }
+
+    f(index, window_start + window.len());
+}
+
+/// Count the extended grapheme clusters in a text given chunk by chunk
+pub(crate) fn count_graphemes(for_each_chunk: impl FnOnce(&mut dyn FnMut(&str) -> bool)) -> usize {
+    let mut count = 0;
+    for_each_boundary(for_each_chunk, |index, _| {
+        count = index;
+        true
+    });
+    count
+}
+
+/// Convert a range of grapheme indexes into a range of utf8 indexes.
+///
+/// Returns `None` if the range is out of bound.
+pub(crate) fn grapheme_range_to_utf8(
+    for_each_chunk: impl FnOnce(&mut dyn FnMut(&str) -> bool),
+    range: Range<usize>,
+) -> Option<Range<usize>> {
+    let mut start = None;
+    let mut end = None;
+    for_each_boundary(for_each_chunk, |index, utf8| {
+        if index == range.start {
+            start = Some(utf8);
+        }
+        if index == range.end {
+            end = Some(utf8);
+        }
+        index < range.end
+    });
+    Some(start?..end?)
+}
+
+/// Convert a utf8 index into a grapheme index.
+///
+/// Returns `None` if the index is out of bound or not on a grapheme boundary.
+pub(crate) fn utf8_to_grapheme_index(
+    for_each_chunk: impl FnOnce(&mut dyn FnMut(&str) -> bool),
+    utf8_index: usize,
+) -> Option<usize> {
+    let mut ans = None;
+    for_each_boundary(for_each_chunk, |index, utf8| {
+        if utf8 == utf8_index {
+            ans = Some(index);
+        }
+        utf8 < utf8_index
+    });
+    ans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks<'a>(chunks: &'a [&'a str]) -> impl FnOnce(&mut dyn FnMut(&str) -> bool) + 'a {
+        move |f| {
+            for chunk in chunks {
+                if !f(chunk) {
+                    return;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_zwj_sequence() {
+        // 👨‍👩‍👧 is made of 5 code points
+        let s = "a👨‍👩‍👧b";
+        assert_eq!(count_graphemes(chunks(&[s])), 3);
+        assert_eq!(grapheme_range_to_utf8(chunks(&[s]), 0..0), Some(0..0));
+        assert_eq!(
+            grapheme_range_to_utf8(chunks(&[s]), 2..3),
+            Some(s.len() - 1..s.len())
+        );
+        assert_eq!(grapheme_range_to_utf8(chunks(&[s]), 3..4), None);
+        assert_eq!(utf8_to_grapheme_index(chunks(&[s]), s.len() - 1), Some(2));
+        assert_eq!(utf8_to_grapheme_index(chunks(&[s]), 5), None);
+        assert_eq!(utf8_to_grapheme_index(chunks(&[s]), s.len() + 1), None);
+    }
+
+    #[test]
+    fn test_grapheme_across_chunks() {
+        // The ZWJ sequence and the flags are split between the chunks
+        let parts = ["a👨\u{200d}", "👩\u{200d}👧", "🇩", "🇪🇫", "🇷b"];
+        let s = parts.concat();
+        assert_eq!(count_graphemes(chunks(&parts)), 5);
+        let boundaries: Vec<_> = s
+            .grapheme_indices(true)
+            .map(|(i, _)| i)
+            .chain(std::iter::once(s.len()))
+            .collect();
+        for (index, &utf8) in boundaries.iter().enumerate() {
+            assert_eq!(
+                grapheme_range_to_utf8(chunks(&parts), index..index),
+                Some(utf8..utf8)
+            );
+            assert_eq!(utf8_to_grapheme_index(chunks(&parts), utf8), Some(index));
+        }
+        assert_eq!(utf8_to_grapheme_index(chunks(&parts), 5), None);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(count_graphemes(chunks(&[])), 0);
+        assert_eq!(grapheme_range_to_utf8(chunks(&[""]), 0..0), Some(0..0));
+        assert_eq!(utf8_to_grapheme_index(chunks(&[]), 0), Some(0));
+    }
+}
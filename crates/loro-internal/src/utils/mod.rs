@@ -1,3 +1,4 @@
+pub(crate) mod grapheme;
 pub(crate) mod kv_wrapper;
 pub(crate) mod lazy;
 pub(crate) mod query_by_len;
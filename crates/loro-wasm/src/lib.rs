@@ -11,11 +11,12 @@ use loro_internal::{
     configure::{StyleConfig, StyleConfigMap},
     container::{richtext::ExpandType, ContainerID},
     cursor::{self, Side},
+    diff::diff_impl::UpdateTimeoutError,
     encoding::ImportBlobMetadata,
     event::Index,
     handler::{
-        Handler, ListHandler, MapHandler, TextDelta, TextHandler, TreeHandler, UpdateOptions,
-        ValueOrHandler,
+        Handler, ListHandler, MapHandler, TextDelta, TextHandler, TreeHandler, UpdateGranularity,
+        UpdateOptions, ValueOrHandler,
     },
     id::{Counter, PeerID, TreeID, ID},
     json::JsonSchema,
@@ -464,13 +465,25 @@ impl LoroDoc {
             // read expand value from value
             let expand = Reflect::get(&value, &"expand".into()).expect("`expand` not specified");
             let expand_str = expand.as_string().unwrap();
-            // read allowOverlap value from value
+            // read the optional annotation flag from value
+            let annotation = Reflect::get(&value, &"annotation".into())
+                .ok()
+                .and_then(|x| x.as_bool())
+                .unwrap_or(false);
+            // read the optional block flag from value
+            let block = Reflect::get(&value, &"block".into())
+                .ok()
+                .and_then(|x| x.as_bool())
+                .unwrap_or(false);
             style_config.insert(
                 key.into(),
-                StyleConfig {
-                    expand: ExpandType::try_from_str(&expand_str)
-                        .expect("`expand` must be one of `none`, `start`, `end`, `both`"),
-                },
+                StyleConfig::new()
+                    .expand(
+                        ExpandType::try_from_str(&expand_str)
+                            .expect("`expand` must be one of `none`, `start`, `end`, `both`"),
+                    )
+                    .annotation(annotation)
+                    .block(block),
             );
         }
 
@@ -1957,29 +1970,10 @@ impl LoroText {
     ///
     #[wasm_bindgen(skip_typescript)]
     pub fn update(&self, text: &str, options: JsValue) -> JsResult<()> {
-        let options = if options.is_null() || options.is_undefined() {
-            UpdateOptions {
-                timeout_ms: None,
-                use_refined_diff: true,
-            }
-        } else {
-            let opts = match js_sys::Object::try_from(&options) {
-                Some(o) => o,
-                None => return Err(JsError::new("Invalid options").into()),
-            };
-            UpdateOptions {
-                timeout_ms: js_sys::Reflect::get(opts, &"timeoutMs".into())
-                    .ok()
-                    .and_then(|v| v.as_f64()),
-                use_refined_diff: js_sys::Reflect::get(opts, &"useRefinedDiff".into())
-                    .ok()
-                    .and_then(|v| v.as_bool())
-                    .unwrap_or(true),
-            }
-        };
+        let options = js_to_update_options(options)?;
         self.handler
             .update(text, options)
-            .map_err(|_| JsError::new("Update timeout").into())
+            .map_err(update_error_to_js)
     }
 
     /// Update the current text to the target text, the difference is calculated line by line.
@@ -1987,29 +1981,10 @@ impl LoroText {
     /// It uses Myers' diff algorithm to compute the optimal difference.
     #[wasm_bindgen(js_name = "updateByLine", skip_typescript)]
     pub fn update_by_line(&self, text: &str, options: JsValue) -> JsResult<()> {
-        let options = if options.is_null() || options.is_undefined() {
-            UpdateOptions {
-                timeout_ms: None,
-                use_refined_diff: true,
-            }
-        } else {
-            let opts = match js_sys::Object::try_from(&options) {
-                Some(o) => o,
-                None => return Err(JsError::new("Invalid options").into()),
-            };
-            UpdateOptions {
-                timeout_ms: js_sys::Reflect::get(opts, &"timeoutMs".into())
-                    .ok()
-                    .and_then(|v| v.as_f64()),
-                use_refined_diff: js_sys::Reflect::get(opts, &"useRefinedDiff".into())
-                    .ok()
-                    .and_then(|v| v.as_bool())
-                    .unwrap_or(true),
-            }
-        };
+        let options = js_to_update_options(options)?;
         self.handler
             .update_by_line(text, options)
-            .map_err(|_| JsError::new("Update timeout").into())
+            .map_err(update_error_to_js)
     }
 
     /// Insert the string at the given index (utf-16 index).
@@ -4835,6 +4810,49 @@ fn js_to_export_mode(js_mode: JsExportMode) -> JsResult<ExportMode<'static>> {
     }
 }
 
+fn js_to_update_options(options: JsValue) -> JsResult<UpdateOptions> {
+    if options.is_null() || options.is_undefined() {
+        return Ok(UpdateOptions::default());
+    }
+
+    let opts = match js_sys::Object::try_from(&options) {
+        Some(o) => o,
+        None => return Err(JsError::new("Invalid options").into()),
+    };
+    let granularity = match js_sys::Reflect::get(opts, &"granularity".into())
+        .ok()
+        .and_then(|v| v.as_string())
+        .as_deref()
+    {
+        None | Some("char") => UpdateGranularity::Char,
+        Some("word") => UpdateGranularity::Word,
+        Some("line") => UpdateGranularity::Line,
+        Some("sentence") => UpdateGranularity::Sentence,
+        Some(_) => return Err(JsError::new("Invalid granularity").into()),
+    };
+    Ok(UpdateOptions {
+        timeout_ms: js_sys::Reflect::get(opts, &"timeoutMs".into())
+            .ok()
+            .and_then(|v| v.as_f64()),
+        use_refined_diff: js_sys::Reflect::get(opts, &"useRefinedDiff".into())
+            .ok()
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true),
+        granularity,
+        preserve_marks: js_sys::Reflect::get(opts, &"preserveMarks".into())
+            .ok()
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+    })
+}
+
+fn update_error_to_js(err: UpdateTimeoutError) -> JsValue {
+    match err {
+        UpdateTimeoutError::Timeout => JsError::new("Update timeout").into(),
+        UpdateTimeoutError::Apply(err) => err.into(),
+    }
+}
+
 fn subscription_to_js_function_callback(sub: Subscription) -> JsValue {
     struct JsSubscription {
         sub: Option<Subscription>,
@@ -5308,6 +5326,15 @@ export type JsonChange = {
 export interface TextUpdateOptions {
     timeoutMs?: number,
     useRefinedDiff?: boolean,
+    /**
+     * The unit that the texts are diffed by in `update`. Defaults to "char".
+     */
+    granularity?: "char" | "word" | "line" | "sentence",
+    /**
+     * Whether the inserted text should not inherit the styles of the text around it,
+     * so the existing marks stay on the unchanged text. Defaults to false.
+     */
+    preserveMarks?: boolean,
 }
 
 export type ExportMode = {
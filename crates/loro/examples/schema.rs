@@ -0,0 +1,30 @@
+//! Typed access to a map with `#[derive(LoroSchema)]`.
+//!
+//! Run it with `cargo run --example schema --features derive`.
+use loro::{schema::LoroSchema, LoroDoc, LoroList, LoroSchema, LoroText};
+
+// Only the handle of the schema is used
+#[allow(dead_code)]
+#[derive(LoroSchema)]
+struct Task {
+    title: LoroText,
+    done: bool,
+    tags: LoroList,
+}
+
+fn main() {
+    let doc = LoroDoc::new();
+    let task = Task::attach(doc.get_map("task")).unwrap();
+    // Nothing is written until the handle is edited
+    assert!(task.title().is_none());
+
+    task.get_or_create_title()
+        .unwrap()
+        .insert(0, "Write docs")
+        .unwrap();
+    task.get_or_create_tags().unwrap().push("docs").unwrap();
+    task.set_done(true).unwrap();
+    assert!(task.done().unwrap());
+    assert_eq!(task.title().unwrap().to_string(), "Write docs");
+    println!("{:?}", doc.get_deep_value());
+}
@@ -0,0 +1,382 @@
+//! Reading containers into Rust types and writing Rust types into containers with serde.
+//!
+//! The containers implement [`Deserializer`] over their deep values: a [`LoroMap`], a
+//! [`LoroList`] or a [`LoroMovableList`] is read with its child containers resolved, a
+//! [`LoroText`] is read as a string and a [`LoroTree`] as the nested nodes with their
+//! metadata and children.
+//!
+//! The deserializers read a materialized value, not the container itself: each call builds
+//! the whole deep value of the container, or the string of the text, and deserializes from
+//! it. So reading a large container costs as much as [`LoroMap::get_deep_value`], even if
+//! `T` only has a few fields.
+use fxhash::FxHashMap;
+use loro_internal::{to_loro_value, LoroResult, LoroSerdeError, LoroValue};
+use serde::{
+    de::{DeserializeOwned, Visitor},
+    Deserializer, Serialize,
+};
+
+use crate::{
+    json_value::{JsonArrayPolicy, JsonPolicy, JsonStringPolicy},
+    Container, ContainerTrait, LoroDoc, LoroList, LoroMap, LoroMovableList, LoroText, LoroTree,
+    ValueOrContainer,
+};
+
+macro_rules! forward_to_value {
+    ($value:expr; $($method:ident($($arg:ident: $arg_ty:ty),*))*) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($arg: $arg_ty,)*
+                visitor: V,
+            ) -> Result<V::Value, Self::Error> {
+                ($value)(self).$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+macro_rules! impl_deserializer {
+    ($($container:ty => $value:expr),* $(,)?) => {
+        $(
+            impl<'de> Deserializer<'de> for &$container {
+                type Error = LoroSerdeError;
+
+                forward_to_value! {
+                    $value;
+                    deserialize_any()
+                    deserialize_bool()
+                    deserialize_i8()
+                    deserialize_i16()
+                    deserialize_i32()
+                    deserialize_i64()
+                    deserialize_i128()
+                    deserialize_u8()
+                    deserialize_u16()
+                    deserialize_u32()
+                    deserialize_u64()
+                    deserialize_u128()
+                    deserialize_f32()
+                    deserialize_f64()
+                    deserialize_char()
+                    deserialize_str()
+                    deserialize_string()
+                    deserialize_bytes()
+                    deserialize_byte_buf()
+                    deserialize_option()
+                    deserialize_unit()
+                    deserialize_unit_struct(name: &'static str)
+                    deserialize_newtype_struct(name: &'static str)
+                    deserialize_seq()
+                    deserialize_tuple(len: usize)
+                    deserialize_tuple_struct(name: &'static str, len: usize)
+                    deserialize_map()
+                    deserialize_struct(name: &'static str, fields: &'static [&'static str])
+                    deserialize_enum(name: &'static str, variants: &'static [&'static str])
+                    deserialize_identifier()
+                    deserialize_ignored_any()
+                }
+            }
+        )*
+    };
+}
+
+impl_deserializer! {
+    LoroMap => |c: &LoroMap| c.get_deep_value(),
+    LoroList => |c: &LoroList| c.get_deep_value(),
+    LoroMovableList => |c: &LoroMovableList| c.get_deep_value(),
+    LoroText => |c: &LoroText| LoroValue::String(c.to_string().into()),
+    LoroTree => |c: &LoroTree| c.get_value_with_meta(),
+}
+
+impl LoroMap {
+    /// Deserialize the deep value of the map into `T`.
+    ///
+    /// The deep value of the whole map is built first, see the [module docs](self).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// # use serde::Deserialize;
+    /// #[derive(Deserialize)]
+    /// struct Settings {
+    ///     theme: String,
+    ///     font_size: u32,
+    /// }
+    ///
+    /// let doc = LoroDoc::new();
+    /// let map = doc.get_map("settings");
+    /// map.insert("theme", "dark").unwrap();
+    /// map.insert("font_size", 14).unwrap();
+    /// let settings: Settings = map.read_struct().unwrap();
+    /// assert_eq!(settings.theme, "dark");
+    /// assert_eq!(settings.font_size, 14);
+    /// ```
+    pub fn read_struct<T: DeserializeOwned>(&self) -> Result<T, LoroSerdeError> {
+        T::deserialize(self)
+    }
+}
+
+impl LoroDoc {
+    /// Write a struct into the map by diffing it against the current contents.
+    ///
+    /// Only the entries that differ produce ops, so writing the same value again is a no-op.
+    /// The writes are made in one [`LoroDoc::transact`], so they are all rolled back if one
+    /// of them fails.
+    ///
+    /// - The keys that are not in the serialized value are deleted.
+    /// - Nested maps and lists are written into the existing child containers recursively.
+    ///   New ones are created as child containers.
+    /// - Lists are diffed by trimming the common prefix and suffix.
+    /// - A string written into an existing [`LoroText`] updates it with a text diff.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// # use serde::Serialize;
+    /// #[derive(Serialize)]
+    /// struct Settings {
+    ///     theme: String,
+    ///     font_size: u32,
+    /// }
+    ///
+    /// let doc = LoroDoc::new();
+    /// let map = doc.get_map("settings");
+    /// let mut settings = Settings { theme: "dark".into(), font_size: 14 };
+    /// doc.write_struct(&map, &settings).unwrap();
+    /// let ops = doc.len_ops();
+    ///
+    /// settings.font_size = 16;
+    /// doc.write_struct(&map, &settings).unwrap();
+    /// assert_eq!(doc.len_ops(), ops + 1);
+    /// ```
+    pub fn write_struct<T: Serialize + ?Sized>(
+        &self,
+        map: &LoroMap,
+        value: &T,
+    ) -> Result<(), LoroSerdeError> {
+        let LoroValue::Map(value) = to_loro_value(value)? else {
+            return Err(LoroSerdeError::Message(
+                "Only a struct or a map can be written into a LoroMap".into(),
+            ));
+        };
+
+        self.transact(|_| write_map(map, value.unwrap(), JsonPolicy::default()))?;
+        Ok(())
+    }
+}
+
+/// The list containers that a [`LoroValue::List`] can be written into
+pub(crate) trait ListContainer {
+    fn get(&self, pos: usize) -> Option<ValueOrContainer>;
+    fn get_deep_value(&self) -> LoroValue;
+    fn insert(&self, pos: usize, value: LoroValue) -> LoroResult<()>;
+    fn insert_container<C: ContainerTrait>(&self, pos: usize, child: C) -> LoroResult<C>;
+    fn delete(&self, pos: usize, len: usize) -> LoroResult<()>;
+}
+
+macro_rules! impl_list_container {
+    ($($container:ty),*) => {
+        $(
+            impl ListContainer for $container {
+                fn get(&self, pos: usize) -> Option<ValueOrContainer> {
+                    <$container>::get(self, pos)
+                }
+
+                fn get_deep_value(&self) -> LoroValue {
+                    <$container>::get_deep_value(self)
+                }
+
+                fn insert(&self, pos: usize, value: LoroValue) -> LoroResult<()> {
+                    <$container>::insert(self, pos, value)
+                }
+
+                fn insert_container<C: ContainerTrait>(&self, pos: usize, child: C) -> LoroResult<C> {
+                    <$container>::insert_container(self, pos, child)
+                }
+
+                fn delete(&self, pos: usize, len: usize) -> LoroResult<()> {
+                    <$container>::delete(self, pos, len)
+                }
+            }
+        )*
+    };
+}
+
+impl_list_container!(LoroList, LoroMovableList);
+
+/// Write `value` into the container if they are of the same kind.
+///
+/// Return the value back if they are not.
+fn write_into(
+    container: &Container,
+    value: LoroValue,
+    policy: JsonPolicy,
+) -> LoroResult<Option<LoroValue>> {
+    match (container, value) {
+        (Container::Map(map), LoroValue::Map(value)) => write_map(map, value.unwrap(), policy)?,
+        (Container::List(list), LoroValue::List(value)) => {
+            write_list(list, value.unwrap(), policy)?
+        }
+        (Container::MovableList(list), LoroValue::List(value)) => {
+            write_list(list, value.unwrap(), policy)?
+        }
+        (Container::Text(text), LoroValue::String(value)) => {
+            if text.to_string() != *value {
+                text.update(&value, Default::default())?;
+            }
+        }
+        (_, value) => return Ok(Some(value)),
+    }
+
+    Ok(None)
+}
+
+/// Write the entries into the map, deleting the keys that are not in `value`.
+///
+/// The existing child containers are updated in place and the equal values are skipped, so
+/// only the differences produce ops. The new containers are created by the policy.
+pub(crate) fn write_map(
+    map: &LoroMap,
+    value: FxHashMap<String, LoroValue>,
+    policy: JsonPolicy,
+) -> LoroResult<()> {
+    let removed: Vec<_> = map
+        .keys()
+        .filter(|key| !value.contains_key(key.as_str()))
+        .collect();
+    for key in removed {
+        map.delete(&key)?;
+    }
+
+    for (key, value) in value {
+        let value = match map.get(&key) {
+            Some(ValueOrContainer::Container(c)) => match write_into(&c, value, policy)? {
+                Some(value) => value,
+                None => continue,
+            },
+            Some(ValueOrContainer::Value(old)) if old == value => continue,
+            _ => value,
+        };
+
+        insert_map_item(map, &key, value, policy)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn write_list<L: ListContainer>(
+    list: &L,
+    mut value: Vec<LoroValue>,
+    policy: JsonPolicy,
+) -> LoroResult<()> {
+    let old = match list.get_deep_value() {
+        LoroValue::List(old) => old,
+        _ => unreachable!(),
+    };
+    let prefix = old
+        .iter()
+        .zip(value.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(value[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_len = old.len() - prefix - suffix;
+    let new_end = value.len() - suffix;
+    let mut new = value.drain(prefix..new_end);
+    let mut pos = prefix;
+
+    // Write the changed items into the old ones at the same positions
+    for value in new.by_ref().take(old_len) {
+        let value = match list.get(pos) {
+            Some(ValueOrContainer::Container(c)) => write_into(&c, value, policy)?,
+            Some(ValueOrContainer::Value(old)) if old == value => None,
+            _ => Some(value),
+        };
+        if let Some(value) = value {
+            list.delete(pos, 1)?;
+            insert_list_item(list, pos, value, policy)?;
+        }
+
+        pos += 1;
+    }
+
+    let written = pos - prefix;
+    if written < old_len {
+        list.delete(pos, old_len - written)?;
+    }
+
+    for value in new {
+        insert_list_item(list, pos, value, policy)?;
+        pos += 1;
+    }
+
+    Ok(())
+}
+
+/// Insert the value into the map, creating the containers for the maps, the lists and, by the
+/// policy, the strings
+pub(crate) fn insert_map_item(
+    map: &LoroMap,
+    key: &str,
+    value: LoroValue,
+    policy: JsonPolicy,
+) -> LoroResult<()> {
+    match value {
+        LoroValue::Map(value) => {
+            let child = map.insert_container(key, LoroMap::new())?;
+            write_map(&child, value.unwrap(), policy)
+        }
+        LoroValue::List(value) => match policy.arrays {
+            JsonArrayPolicy::List => {
+                let child = map.insert_container(key, LoroList::new())?;
+                write_list(&child, value.unwrap(), policy)
+            }
+            JsonArrayPolicy::MovableList => {
+                let child = map.insert_container(key, LoroMovableList::new())?;
+                write_list(&child, value.unwrap(), policy)
+            }
+        },
+        LoroValue::String(value) if policy.strings == JsonStringPolicy::Text => {
+            let child = map.insert_container(key, LoroText::new())?;
+            child.insert(0, &value)
+        }
+        value => map.insert(key, value),
+    }
+}
+
+/// Insert the value into the list, creating the containers like [`insert_map_item`]
+fn insert_list_item<L: ListContainer>(
+    list: &L,
+    pos: usize,
+    value: LoroValue,
+    policy: JsonPolicy,
+) -> LoroResult<()> {
+    match value {
+        LoroValue::Map(value) => {
+            let child = list.insert_container(pos, LoroMap::new())?;
+            write_map(&child, value.unwrap(), policy)
+        }
+        LoroValue::List(value) => match policy.arrays {
+            JsonArrayPolicy::List => {
+                let child = list.insert_container(pos, LoroList::new())?;
+                write_list(&child, value.unwrap(), policy)
+            }
+            JsonArrayPolicy::MovableList => {
+                let child = list.insert_container(pos, LoroMovableList::new())?;
+                write_list(&child, value.unwrap(), policy)
+            }
+        },
+        LoroValue::String(value) if policy.strings == JsonStringPolicy::Text => {
+            let child = list.insert_container(pos, LoroText::new())?;
+            child.insert(0, &value)
+        }
+        value => list.insert(pos, value),
+    }
+}
@@ -186,3 +186,83 @@ impl From<ValueOrHandler> for ValueOrContainer {
         }
     }
 }
+
+#[cfg(feature = "event-stream")]
+pub use stream::{EventStream, OwnedDiffEvent};
+
+#[cfg(feature = "event-stream")]
+mod stream {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use futures_core::Stream;
+    use loro_internal::{
+        event_stream::{EventStream as InnerEventStream, OwnedDiffEvent as InnerOwnedDiffEvent},
+        version::Frontiers,
+    };
+
+    use super::DiffEvent;
+
+    /// An owned [DiffEvent] yielded by [EventStream].
+    ///
+    /// It can be sent to other threads and tasks. Use [OwnedDiffEvent::as_event] to read the diffs.
+    #[derive(Debug, Clone)]
+    pub struct OwnedDiffEvent(InnerOwnedDiffEvent);
+
+    impl OwnedDiffEvent {
+        /// Borrow the event as a [DiffEvent].
+        pub fn as_event(&self) -> DiffEvent<'_> {
+            DiffEvent {
+                triggered_by: self.0.event.by,
+                origin: &self.0.event.origin,
+                current_target: self.0.current_target.clone(),
+                events: self.0.event.diff.iter().map(|diff| diff.into()).collect(),
+            }
+        }
+
+        /// The version before the event.
+        pub fn from_frontiers(&self) -> &Frontiers {
+            &self.0.event.from
+        }
+
+        /// The version after the event.
+        pub fn to_frontiers(&self) -> &Frontiers {
+            &self.0.event.to
+        }
+
+        /// Compose a later event into this one.
+        ///
+        /// The diffs of the same container are composed. The trigger kind and the origin
+        /// of the later event are kept.
+        pub fn compose(&mut self, other: OwnedDiffEvent) {
+            self.0.compose(other.0);
+        }
+    }
+
+    /// A [Stream] of [OwnedDiffEvent]s.
+    ///
+    /// It's created by [crate::LoroDoc::subscribe_root_stream] and [crate::LoroDoc::subscribe_stream].
+    #[derive(Debug)]
+    pub struct EventStream(pub(crate) InnerEventStream);
+
+    impl EventStream {
+        /// The number of the events dropped because the buffer was full.
+        ///
+        /// It's always zero with [crate::BackpressurePolicy::Coalesce].
+        pub fn dropped_count(&self) -> usize {
+            self.0.dropped_count()
+        }
+    }
+
+    impl Stream for EventStream {
+        type Item = OwnedDiffEvent;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Pin::new(&mut self.0)
+                .poll_next(cx)
+                .map(|event| event.map(OwnedDiffEvent))
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Building containers from plain JSON values.
+//!
+//! The JSON objects become [`LoroMap`]s. The arrays and the strings become containers or values
+//! depending on the [`JsonPolicy`].
+use loro_internal::{LoroError, LoroResult, LoroValue};
+use serde_json::Value;
+
+use crate::{
+    container_serde::{insert_map_item, write_list, write_map},
+    Container, LoroDoc, LoroMap,
+};
+
+/// How the JSON strings are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonStringPolicy {
+    /// As string values, which are replaced as a whole
+    #[default]
+    Value,
+    /// As [`LoroText`](crate::LoroText) containers, which can be edited concurrently
+    Text,
+}
+
+/// How the JSON arrays are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonArrayPolicy {
+    /// As [`LoroList`](crate::LoroList) containers
+    #[default]
+    List,
+    /// As [`LoroMovableList`](crate::LoroMovableList) containers, whose elements can be moved
+    /// and set
+    MovableList,
+}
+
+/// How the JSON values are stored in containers.
+///
+/// The objects are always stored as [`LoroMap`]s.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonPolicy {
+    /// How the strings are stored
+    pub strings: JsonStringPolicy,
+    /// How the arrays are stored
+    pub arrays: JsonArrayPolicy,
+}
+
+impl LoroMap {
+    /// Set the key to the JSON value, creating the nested containers by the policy.
+    ///
+    /// The old entry under the key is replaced as a whole. Use [`LoroMap::update_from_json`] to
+    /// only apply the differences.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use loro::{JsonPolicy, JsonStringPolicy, LoroDoc, ToJson};
+    /// use serde_json::json;
+    ///
+    /// let doc = LoroDoc::new();
+    /// let map = doc.get_map("map");
+    /// let policy = JsonPolicy {
+    ///     strings: JsonStringPolicy::Text,
+    ///     ..Default::default()
+    /// };
+    /// map.set_from_json("note", json!({"title": "Hello", "tags": ["a"]}), policy)
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     map.get_deep_value().to_json_value(),
+    ///     json!({"note": {"title": "Hello", "tags": ["a"]}})
+    /// );
+    /// assert!(map.get("note").unwrap().into_container().is_ok());
+    /// ```
+    pub fn set_from_json(&self, key: &str, value: Value, policy: JsonPolicy) -> LoroResult<()> {
+        insert_map_item(self, key, LoroValue::from(value), policy)
+    }
+
+    /// Update the map to be equal to the JSON object, emitting the minimal ops.
+    ///
+    /// The keys that are not in the object are deleted. The existing child containers are
+    /// updated in place: the texts are diffed by [`LoroText::update`](crate::LoroText::update)
+    /// and the lists keep their common prefix and suffix. The equal values produce no op. The
+    /// new containers are created by the policy.
+    ///
+    /// Returns [`LoroError::ArgErr`] if the value is not an object.
+    pub fn update_from_json(&self, value: Value, policy: JsonPolicy) -> LoroResult<()> {
+        match LoroValue::from(value) {
+            LoroValue::Map(value) => write_map(self, value.unwrap(), policy),
+            _ => Err(LoroError::ArgErr(
+                "Only a JSON object can be written into a LoroMap".into(),
+            )),
+        }
+    }
+}
+
+impl LoroDoc {
+    /// Replace the contents of a root container with the JSON value.
+    ///
+    /// See [`LoroDoc::import_json_value_with`].
+    #[inline]
+    pub fn import_json_value(&self, root_name: &str, value: Value) -> LoroResult<Container> {
+        self.import_json_value_with(root_name, value, JsonPolicy::default())
+    }
+
+    /// Replace the contents of a root container with the JSON value, creating the nested
+    /// containers by the policy.
+    ///
+    /// An object is imported into the root map, an array into the root list (or movable list)
+    /// and a string into the root text. The other values cannot be stored in a root container
+    /// and return [`LoroError::ArgErr`].
+    ///
+    /// The edits are committed as one change. If an edit fails, they are all rolled back.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use loro::{LoroDoc, ToJson};
+    /// use serde_json::json;
+    ///
+    /// let doc = LoroDoc::new();
+    /// let value = json!({"name": "Loro", "tags": ["crdt", "rust"], "meta": {"stars": 1}});
+    /// let root = doc.import_json_value("project", value.clone()).unwrap();
+    /// assert!(root.is_map());
+    /// assert_eq!(doc.get_map("project").get_deep_value().to_json_value(), value);
+    /// ```
+    pub fn import_json_value_with(
+        &self,
+        root_name: &str,
+        value: Value,
+        policy: JsonPolicy,
+    ) -> LoroResult<Container> {
+        self.transact(|doc| match LoroValue::from(value) {
+            LoroValue::Map(value) => {
+                let map = doc.get_map(root_name);
+                map.clear()?;
+                write_map(&map, value.unwrap(), policy)?;
+                Ok(Container::Map(map))
+            }
+            LoroValue::List(value) => match policy.arrays {
+                JsonArrayPolicy::List => {
+                    let list = doc.get_list(root_name);
+                    list.clear()?;
+                    write_list(&list, value.unwrap(), policy)?;
+                    Ok(Container::List(list))
+                }
+                JsonArrayPolicy::MovableList => {
+                    let list = doc.get_movable_list(root_name);
+                    list.clear()?;
+                    write_list(&list, value.unwrap(), policy)?;
+                    Ok(Container::MovableList(list))
+                }
+            },
+            LoroValue::String(value) => {
+                let text = doc.get_text(root_name);
+                text.delete(0, text.len_unicode())?;
+                text.insert(0, &value)?;
+                Ok(Container::Text(text))
+            }
+            _ => Err(LoroError::ArgErr(
+                "Only a JSON object, array or string can be imported into a root container".into(),
+            )),
+        })
+    }
+}
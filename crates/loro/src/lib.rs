@@ -2,12 +2,14 @@
 #![warn(missing_docs)]
 #![warn(missing_debug_implementations)]
 use event::{DiffEvent, Subscriber};
-use fxhash::FxHashSet;
+use fxhash::{FxHashMap, FxHashSet};
 pub use loro_common::InternalString;
 pub use loro_internal::cursor::CannotFindRelativePosition;
 use loro_internal::cursor::Cursor;
 use loro_internal::cursor::PosQueryResult;
+use loro_internal::cursor::ResolvedTextRange;
 use loro_internal::cursor::Side;
+use loro_internal::cursor::TextRange;
 pub use loro_internal::encoding::ImportStatus;
 use loro_internal::handler::HandlerTrait;
 use loro_internal::handler::ValueOrHandler;
@@ -30,10 +32,11 @@ use std::ops::Range;
 use std::sync::Arc;
 use tracing::info;
 
-pub use loro_internal::diff::diff_impl::UpdateOptions;
 pub use loro_internal::diff::diff_impl::UpdateTimeoutError;
+pub use loro_internal::diff::diff_impl::{UpdateGranularity, UpdateOptions};
 pub use loro_internal::subscription::LocalUpdateCallback;
 pub use loro_internal::subscription::PeerIdUpdateCallback;
+pub use loro_internal::subscription::ThrottleScheduler;
 pub use loro_internal::ChangeMeta;
 pub mod event;
 pub use loro_internal::awareness;
@@ -47,29 +50,42 @@ pub use loro_internal::delta::{TreeDeltaItem, TreeDiff, TreeDiffItem, TreeExtern
 pub use loro_internal::encoding::ExportMode;
 pub use loro_internal::encoding::ImportBlobMetadata;
 pub use loro_internal::event::{EventTriggerKind, Index};
-pub use loro_internal::handler::TextDelta;
+/// The unit of a position in a [`LoroText`]. See [`LoroText::convert_pos`].
+pub use loro_internal::handler::TextPosType as PosType;
+pub use loro_internal::handler::{
+    HtmlMapping, LineChange, StyleRangeValue, TextDelta, TextMatch, TextSearchOptions, EMBED_CHAR,
+    EMBED_KEY,
+};
+pub use loro_internal::import_validator::{ImportValidator, RejectedChange};
 pub use loro_internal::json;
 pub use loro_internal::json::{
     FutureOp as JsonFutureOp, FutureOpWrapper as JsonFutureOpWrapper, JsonChange, JsonOp,
     JsonOpContent, JsonSchema, ListOp as JsonListOp, MapOp as JsonMapOp,
     MovableListOp as JsonMovableListOp, TextOp as JsonTextOp, TreeOp as JsonTreeOp,
 };
+pub use loro_internal::json_patch::{
+    JsonPatchCallback, JsonPatchError, JsonPatchOp, TextPatchStyle,
+};
 pub use loro_internal::kv_store::{KvStore, MemKvStore};
 pub use loro_internal::loro::CommitOptions;
 pub use loro_internal::loro::DocAnalysis;
 pub use loro_internal::oplog::FrontiersNotIncluded;
+pub use loro_internal::path_subscription::PathPatternError;
+pub use loro_internal::pre_commit::{PreCommit, PreCommitCallback};
+pub use loro_internal::text_patch::{unified_diff, Hunk, HunkLine, TextPatchError};
 pub use loro_internal::undo;
 pub use loro_internal::version::{Frontiers, VersionRange, VersionVector, VersionVectorDiff};
 pub use loro_internal::ApplyDiff;
 pub use loro_internal::Subscription;
 pub use loro_internal::UndoManager as InnerUndoManager;
+pub use loro_internal::{from_loro_value, to_loro_value};
 pub use loro_internal::{loro_value, to_value};
 pub use loro_internal::{
     Counter, CounterSpan, FractionalIndex, IdLp, IdSpan, Lamport, PeerID, TreeID, TreeParentId, ID,
 };
 pub use loro_internal::{
     LoroBinaryValue, LoroEncodeError, LoroError, LoroListValue, LoroMapValue, LoroResult,
-    LoroStringValue, LoroTreeError, LoroValue, ToJson,
+    LoroSchemaError, LoroSerdeError, LoroStringValue, LoroTreeError, LoroValue, ToJson,
 };
 pub use loro_kv_store as kv_store;
 
@@ -78,6 +94,25 @@ pub use loro_internal::jsonpath;
 #[cfg(feature = "jsonpath")]
 pub use loro_internal::jsonpath::JsonPathError;
 
+#[cfg(feature = "event-stream")]
+pub use event::{EventStream, OwnedDiffEvent};
+#[cfg(feature = "encryption")]
+pub use loro_internal::encoding::encryption::{
+    decrypt_blob, decrypt_blob_or_plaintext, encrypted_blob_key_id, EncryptionKey,
+};
+#[cfg(feature = "event-stream")]
+pub use loro_internal::event_stream::{BackpressurePolicy, LocalUpdateStream, StreamOptions};
+#[cfg(feature = "encryption")]
+pub use loro_internal::kv_store::EncryptedKvStore;
+#[cfg(feature = "signature")]
+pub use loro_internal::signature::{ed25519, ChangeSigner, ChangeVerifier, SignaturePolicy};
+
+mod container_serde;
+mod json_value;
+pub use json_value::{JsonArrayPolicy, JsonPolicy, JsonStringPolicy};
+pub mod schema;
+#[cfg(feature = "derive")]
+pub use loro_derive::LoroSchema;
 #[cfg(feature = "counter")]
 mod counter;
 #[cfg(feature = "counter")]
@@ -225,6 +260,129 @@ impl LoroDoc {
         self.doc.set_change_merge_interval(interval);
     }
 
+    /// Set the signer of the local changes.
+    ///
+    /// Every change committed after this call is signed by ed25519 over the encoded change,
+    /// including its deps. The signature is exported with the change. Signed changes are
+    /// never merged with other changes. Pass `None` to stop signing.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use loro::{ed25519::SigningKey, ChangeVerifier, LoroDoc, SignaturePolicy};
+    ///
+    /// let key = SigningKey::from_bytes(&[7; 32]);
+    /// let doc = LoroDoc::new();
+    /// doc.set_peer_id(1).unwrap();
+    /// doc.set_change_signer(Some(Arc::new(key.clone())));
+    /// doc.get_text("text").insert(0, "Hello").unwrap();
+    /// doc.commit();
+    ///
+    /// let receiver = LoroDoc::new();
+    /// receiver.set_change_verifier(Some(
+    ///     ChangeVerifier::new(SignaturePolicy::Reject).peer_key(1, key.verifying_key()),
+    /// ));
+    /// receiver.import(&doc.export(loro::ExportMode::all_updates()).unwrap()).unwrap();
+    /// assert_eq!(receiver.get_text("text").to_string(), "Hello");
+    /// ```
+    #[cfg(feature = "signature")]
+    #[inline]
+    pub fn set_change_signer(&self, signer: Option<Arc<dyn ChangeSigner>>) {
+        self.commit();
+        self.doc.set_change_signer(signer);
+    }
+
+    /// Set the verifier of the imported changes.
+    ///
+    /// Every imported change must be signed by the key registered for its peer in the verifier.
+    /// Otherwise, the import fails with [`LoroError::InvalidChangeSignature`] or the change
+    /// is quarantined, depending on the [`SignaturePolicy`].
+    ///
+    /// When a verifier is set, a snapshot imported into an empty doc is imported by replaying
+    /// its history, so that every change is verified. Pass `None` to stop verifying.
+    #[cfg(feature = "signature")]
+    #[inline]
+    pub fn set_change_verifier(&self, verifier: Option<ChangeVerifier>) {
+        self.doc.set_change_verifier(verifier);
+    }
+
+    /// Get the imported changes that were put aside by [`SignaturePolicy::Quarantine`].
+    ///
+    /// They are kept only to be inspected and are never retried. To import them, register
+    /// the key of their peer and import the updates from their source again.
+    #[cfg(feature = "signature")]
+    #[inline]
+    pub fn quarantined_changes(&self) -> Vec<ChangeMeta> {
+        self.doc.quarantined_changes()
+    }
+
+    /// Drop the quarantined changes.
+    #[cfg(feature = "signature")]
+    #[inline]
+    pub fn clear_quarantined_changes(&self) {
+        self.doc.clear_quarantined_changes()
+    }
+
+    /// Check whether the change at the given id is signed by the key of its peer in `verifier`.
+    ///
+    /// Return `None` if the change is not found.
+    #[cfg(feature = "signature")]
+    #[inline]
+    pub fn verify_change_signature(&self, id: ID, verifier: &ChangeVerifier) -> Option<bool> {
+        self.doc.verify_change_signature(id, verifier)
+    }
+
+    /// Set the validator of the imported changes.
+    ///
+    /// The validator is called with each imported change before it's applied. The ids in the
+    /// change carry the original peers, and the ops are resolved to [`ContainerID`]s.
+    /// A rejected change is dropped, and so is every change depending on it, including in the
+    /// later imports. The rejected changes can be inspected by [`LoroDoc::rejected_changes`].
+    ///
+    /// When a validator is set, a snapshot imported into an empty doc is imported by replaying
+    /// its history, so that every change is validated. Pass `None` to stop validating.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use loro::{json::JsonChange, ContainerID, ContainerType, LoroDoc};
+    ///
+    /// let config = ContainerID::new_root("config", ContainerType::Map);
+    /// let server = LoroDoc::new();
+    /// server.set_import_validator(Some(Arc::new(move |change: &JsonChange| {
+    ///     if change.ops.iter().any(|op| op.container == config) {
+    ///         return Err("config is read-only".to_string());
+    ///     }
+    ///     Ok(())
+    /// })));
+    ///
+    /// let client = LoroDoc::new();
+    /// client.get_map("config").insert("admin", true).unwrap();
+    /// server.import(&client.export(loro::ExportMode::all_updates()).unwrap()).unwrap();
+    /// assert!(server.get_map("config").is_empty());
+    /// assert_eq!(server.rejected_changes()[0].reason, "config is read-only");
+    /// ```
+    #[inline]
+    pub fn set_import_validator(&self, validator: Option<Arc<dyn ImportValidator>>) {
+        self.doc.set_import_validator(validator);
+    }
+
+    /// Get the changes rejected by the [`ImportValidator`] and their dependents.
+    #[inline]
+    pub fn rejected_changes(&self) -> Vec<RejectedChange> {
+        self.doc.rejected_changes()
+    }
+
+    /// Drop the records of the rejected changes.
+    ///
+    /// The changes depending on them are still rejected.
+    #[inline]
+    pub fn clear_rejected_changes(&self) {
+        self.doc.clear_rejected_changes()
+    }
+
     /// Set the rich text format configuration of the document.
     ///
     /// You need to config it if you use rich text `mark` method.
@@ -382,6 +540,8 @@ impl LoroDoc {
     /// - `doc.export(mode)` is called.
     /// - `doc.import(data)` is called.
     /// - `doc.checkout(version)` is called.
+    ///
+    /// It does nothing inside [`LoroDoc::transact`].
     #[inline]
     pub fn commit(&self) {
         self.doc.commit_then_renew()
@@ -397,6 +557,42 @@ impl LoroDoc {
         self.doc.commit_with(options)
     }
 
+    /// Run `f` in a transaction, rolling back its edits if it returns an error.
+    ///
+    /// The edits made in `f` are committed as one change if `f` succeeds. If `f` fails, the
+    /// doc is restored to the state before `f`: no op is recorded and no event is emitted.
+    /// The uncommitted changes before `f` are committed first.
+    ///
+    /// The transaction stays open until `f` returns. Inside `f`, [`LoroDoc::commit`] and
+    /// [`LoroDoc::export`] don't commit the edits, and [`LoroDoc::import`],
+    /// [`LoroDoc::checkout`], [`LoroDoc::set_peer_id`] and a nested `transact` fail with
+    /// [`LoroError::TransactionInProgress`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, LoroError};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// let ans: Result<(), LoroError> = doc.transact(|doc| {
+    ///     text.insert(0, "Hello")?;
+    ///     doc.get_map("map").insert("count", 1)?;
+    ///     Err(LoroError::ArgErr("invalid".into()))
+    /// });
+    /// assert!(ans.is_err());
+    /// assert_eq!(text.to_string(), "");
+    /// assert!(doc.oplog_vv().is_empty());
+    ///
+    /// doc.transact(|_| text.insert(0, "Hello")).unwrap();
+    /// assert_eq!(text.to_string(), "Hello");
+    /// ```
+    pub fn transact<R, E>(&self, f: impl FnOnce(&LoroDoc) -> Result<R, E>) -> Result<R, E>
+    where
+        E: From<LoroError>,
+    {
+        self.doc.transact(|_| f(self))
+    }
+
     /// Set commit message for the current uncommitted changes
     pub fn set_next_commit_message(&self, msg: &str) {
         self.doc.set_next_commit_message(msg)
@@ -424,6 +620,53 @@ impl LoroDoc {
         self.doc.import_with(bytes, origin.into())
     }
 
+    /// Import updates/snapshot from a reader, such as a file or a network stream.
+    ///
+    /// The data is the same as the one accepted by [`LoroDoc::import`], but it's never
+    /// loaded into a single buffer. The checksum is validated while reading, and nothing
+    /// is imported if it doesn't match.
+    ///
+    /// The updates written by [`LoroDoc::export_to_writer`] carry a checksum for each
+    /// block, so they're verified and decoded block by block. Only the bytes of one block
+    /// are kept in memory besides the decoded changes. The snapshots and the updates
+    /// returned by [`LoroDoc::export`] have a single checksum for the whole body, so their
+    /// bytes are buffered until it's verified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use loro::{ExportMode, LoroDoc};
+    ///
+    /// let doc = LoroDoc::new();
+    /// doc.get_text("text").insert(0, "Hello").unwrap();
+    /// let mut buf = Vec::new();
+    /// doc.export_to_writer(ExportMode::Snapshot, &mut buf).unwrap();
+    ///
+    /// let new_doc = LoroDoc::new();
+    /// new_doc.import_from_reader(buf.as_slice()).unwrap();
+    /// assert_eq!(new_doc.get_text("text").to_string(), "Hello");
+    /// ```
+    #[inline]
+    pub fn import_from_reader<R: std::io::Read>(
+        &self,
+        reader: R,
+    ) -> Result<ImportStatus, LoroError> {
+        self.doc.import_from_reader_with(reader, "".into())
+    }
+
+    /// Import updates/snapshot from a reader.
+    ///
+    /// It marks the import with a custom `origin` string. It can be used to track the import source
+    /// in the generated events.
+    #[inline]
+    pub fn import_from_reader_with<R: std::io::Read>(
+        &self,
+        reader: R,
+        origin: &str,
+    ) -> Result<ImportStatus, LoroError> {
+        self.doc.import_from_reader_with(reader, origin.into())
+    }
+
     /// Import the json schema updates.
     ///
     /// only supports backward compatibility but not forward compatibility.
@@ -667,11 +910,241 @@ impl LoroDoc {
         }))
     }
 
+    /// Subscribe all the events, delivering them at most once every `interval`.
+    ///
+    /// It's useful for UIs that should not re-render on every keystroke or imported update.
+    /// The first event is delivered immediately. The events arriving within `interval` after
+    /// a delivery are composed per container into one event. The composed event spans from the
+    /// version before the first event to the version after the last one.
+    ///
+    /// The doc has no timer of its own, so it asks `scheduler` to run a task after a delay
+    /// whenever an event is kept pending. The task delivers the trailing event of a burst.
+    /// Wire it to the timer of your runtime, e.g. a `std::thread::sleep` in a spawned thread
+    /// or `tokio::time::sleep` in a spawned task. [LoroDoc::poll_events] and
+    /// [LoroDoc::flush_events] deliver the pending events earlier.
+    ///
+    /// The pending events are discarded when the subscription is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::{sync::Arc, time::Duration};
+    /// use loro::LoroDoc;
+    ///
+    /// let doc = LoroDoc::new();
+    /// let _sub = doc.subscribe_root_throttled(
+    ///     Duration::from_millis(100),
+    ///     Arc::new(|delay, task| {
+    ///         std::thread::spawn(move || {
+    ///             std::thread::sleep(delay);
+    ///             task();
+    ///         });
+    ///     }),
+    ///     Arc::new(|event| println!("{:?}", event.triggered_by)),
+    /// );
+    /// ```
+    #[inline]
+    pub fn subscribe_root_throttled(
+        &self,
+        interval: std::time::Duration,
+        scheduler: ThrottleScheduler,
+        callback: Subscriber,
+    ) -> Subscription {
+        self.doc.subscribe_root_throttled(
+            interval,
+            scheduler,
+            Arc::new(move |e| {
+                callback(DiffEvent::from(e));
+            }),
+        )
+    }
+
+    /// Deliver the pending events of the throttled subscribers now.
+    ///
+    /// See [LoroDoc::subscribe_root_throttled]. The uncommitted changes are not included.
+    #[inline]
+    pub fn flush_events(&self) {
+        self.doc.flush_events()
+    }
+
+    /// Deliver the pending events of the throttled subscribers whose interval has elapsed.
+    ///
+    /// It returns the time until the next pending event is due, or `None` if no event is
+    /// pending. See [LoroDoc::subscribe_root_throttled].
+    #[inline]
+    pub fn poll_events(&self) -> Option<std::time::Duration> {
+        self.doc.poll_events()
+    }
+
+    /// Subscribe the changes at the paths matching the pattern.
+    ///
+    /// The pattern is a glob like `/projects/*/tasks/**/title`, or a JSONPath-like expression
+    /// like `$.projects[*].tasks..title`. `*` matches any map key, list index or tree node,
+    /// and `**` or `..` matches any number of them.
+    ///
+    /// A diff is delivered if its container is at a matching path or inside one, or if it changes
+    /// a map entry, a list item or a tree node at a matching path. The containers created after
+    /// subscribing are matched as well. The callback is not invoked if no diff matches.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, LoroMap};
+    /// # use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+    /// let doc = LoroDoc::new();
+    /// let count = Arc::new(AtomicUsize::new(0));
+    /// let count_clone = count.clone();
+    /// let _sub = doc
+    ///     .subscribe_path(
+    ///         "/projects/*/title",
+    ///         Arc::new(move |e| {
+    ///             count_clone.fetch_add(e.events.len(), Ordering::Relaxed);
+    ///         }),
+    ///     )
+    ///     .unwrap();
+    /// let project = doc.get_map("projects").insert_container("a", LoroMap::new()).unwrap();
+    /// project.insert("title", "A").unwrap();
+    /// doc.commit();
+    /// project.insert("owner", "B").unwrap();
+    /// doc.commit();
+    /// assert_eq!(count.load(Ordering::Relaxed), 1);
+    /// ```
+    pub fn subscribe_path(
+        &self,
+        pattern: &str,
+        callback: Subscriber,
+    ) -> Result<Subscription, PathPatternError> {
+        self.doc.subscribe_path(
+            pattern,
+            Arc::new(move |e| {
+                callback(DiffEvent::from(e));
+            }),
+        )
+    }
+
+    /// Subscribe the changes of the document as [JSON Patch](https://datatracker.ietf.org/doc/html/rfc6902).
+    ///
+    /// The patches apply to [`LoroDoc::get_deep_value`] at the time of subscribing.
+    /// The text edits are converted according to `style`. A new container is added as an
+    /// empty value, and its content follows in its own patches.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{JsonPatchOp, LoroDoc, TextPatchStyle};
+    /// # use std::sync::{Arc, Mutex};
+    /// let doc = LoroDoc::new();
+    /// let patches = Arc::new(Mutex::new(Vec::new()));
+    /// let patches_clone = patches.clone();
+    /// let _sub = doc.subscribe_json_patch(
+    ///     TextPatchStyle::Splice,
+    ///     Arc::new(move |patch| patches_clone.lock().unwrap().extend(patch)),
+    /// );
+    /// doc.get_text("text").insert(0, "Hello").unwrap();
+    /// doc.commit();
+    /// assert_eq!(
+    ///     *patches.lock().unwrap(),
+    ///     vec![
+    ///         JsonPatchOp::Add { path: "/text".into(), value: "".into() },
+    ///         JsonPatchOp::Splice { path: "/text".into(), pos: 0, delete: 0, insert: "Hello".into() },
+    ///     ]
+    /// );
+    /// ```
+    #[inline]
+    pub fn subscribe_json_patch(
+        &self,
+        style: TextPatchStyle,
+        callback: JsonPatchCallback,
+    ) -> Subscription {
+        self.doc.subscribe_json_patch(style, callback)
+    }
+
     /// Subscribe the local update of the document.
     pub fn subscribe_local_update(&self, callback: LocalUpdateCallback) -> Subscription {
         self.doc.subscribe_local_update(callback)
     }
 
+    /// Subscribe the local transactions before they are committed.
+    ///
+    /// The callback receives the pending ops with their container ids, the origin and the
+    /// commit message. It can abort the transaction, which rolls back the state and emits no
+    /// event or local update, or annotate the commit message and the timestamp.
+    ///
+    /// The doc is locked while the callback runs, so it must not access the doc.
+    /// Return `false` to unsubscribe.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{CommitOptions, LoroDoc, ID};
+    /// let doc = LoroDoc::new();
+    /// let _sub = doc.subscribe_pre_commit(Box::new(|pre_commit| {
+    ///     if pre_commit.origin() == "draft" {
+    ///         pre_commit.abort();
+    ///     } else {
+    ///         pre_commit.set_message("reviewed");
+    ///     }
+    ///     true
+    /// }));
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Hello").unwrap();
+    /// doc.commit();
+    /// text.insert(5, " world").unwrap();
+    /// doc.commit_with(CommitOptions::new().origin("draft"));
+    /// assert_eq!(text.to_string(), "Hello");
+    /// let change = doc.get_change(ID::new(doc.peer_id(), 0)).unwrap();
+    /// assert_eq!(change.message(), "reviewed");
+    /// ```
+    pub fn subscribe_pre_commit(&self, callback: PreCommitCallback) -> Subscription {
+        self.doc.subscribe_pre_commit(callback)
+    }
+
+    /// Subscribe all the events as an async [Stream](futures_core::Stream).
+    ///
+    /// The events are buffered, up to [StreamOptions::capacity] of them. When the buffer is full,
+    /// [StreamOptions::policy] decides whether the oldest event is dropped or the new event is
+    /// composed into the newest buffered one.
+    ///
+    /// The stream ends after the doc is dropped. Dropping the stream unsubscribes it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, StreamOptions};
+    /// # use futures::{executor::block_on, StreamExt};
+    /// let doc = LoroDoc::new();
+    /// let mut events = doc.subscribe_root_stream(StreamOptions::default());
+    /// doc.get_text("text").insert(0, "Hello").unwrap();
+    /// doc.commit();
+    /// let event = block_on(events.next()).unwrap();
+    /// assert_eq!(event.as_event().events.len(), 1);
+    /// ```
+    #[cfg(feature = "event-stream")]
+    pub fn subscribe_root_stream(&self, options: StreamOptions) -> EventStream {
+        EventStream(self.doc.subscribe_root_stream(options))
+    }
+
+    /// Subscribe the events of a container and its descendants as an async [Stream](futures_core::Stream).
+    ///
+    /// See [LoroDoc::subscribe_root_stream] for the buffering.
+    #[cfg(feature = "event-stream")]
+    pub fn subscribe_stream(
+        &self,
+        container_id: &ContainerID,
+        options: StreamOptions,
+    ) -> EventStream {
+        EventStream(self.doc.subscribe_stream(container_id, options))
+    }
+
+    /// Subscribe the local updates as an async [Stream](futures_core::Stream).
+    ///
+    /// Every item can be imported by the other peers. When the buffer is full and the policy is
+    /// [BackpressurePolicy::Coalesce], the new update is merged into the newest buffered one.
+    #[cfg(feature = "event-stream")]
+    pub fn subscribe_local_update_stream(&self, options: StreamOptions) -> LocalUpdateStream {
+        self.doc.subscribe_local_update_stream(options)
+    }
+
     /// Subscribe the peer id change of the document.
     pub fn subscribe_peer_id_change(&self, callback: PeerIdUpdateCallback) -> Subscription {
         self.doc.subscribe_peer_id_change(callback)
@@ -780,6 +1253,43 @@ impl LoroDoc {
         self.doc.query_pos(cursor)
     }
 
+    /// Get the current position of a [`TextRange`] created by [`LoroText::get_range`].
+    ///
+    /// It returns the range in Unicode, UTF-16 and UTF-8 offsets, and whether the range is
+    /// collapsed or its text is deleted. The range is resolved at the current version of the
+    /// doc, which follows [`LoroDoc::checkout`]. If the range is created after that version, it
+    /// returns [`CannotFindRelativePosition::IdNotFound`].
+    ///
+    /// When the text a range is anchored to is deleted, its position is found through the
+    /// history. If the deletion happened before the shallow root of the doc, it returns
+    /// [`CannotFindRelativePosition::HistoryCleared`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{ExpandType, LoroDoc};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Hello world").unwrap();
+    /// let range = text.get_range(6..11, ExpandType::None).unwrap();
+    /// text.insert(0, "😀 ").unwrap();
+    /// let resolved = doc.resolve_range(&range).unwrap();
+    /// assert_eq!(resolved.unicode, 8..13);
+    /// assert_eq!(resolved.utf16, 9..14);
+    /// assert_eq!(resolved.utf8, 11..16);
+    ///
+    /// text.delete(8, 5).unwrap();
+    /// let resolved = doc.resolve_range(&range).unwrap();
+    /// assert!(resolved.collapsed && resolved.deleted);
+    /// ```
+    #[inline]
+    pub fn resolve_range(
+        &self,
+        range: &TextRange,
+    ) -> Result<ResolvedTextRange, CannotFindRelativePosition> {
+        self.doc.resolve_range(range)
+    }
+
     /// Get the inner LoroDoc ref.
     #[inline]
     pub fn inner(&self) -> &InnerLoroDoc {
@@ -820,6 +1330,115 @@ impl LoroDoc {
         self.doc.export(mode)
     }
 
+    /// Export the document in the given mode, encrypted by the given key.
+    ///
+    /// The blob is wrapped in an XChaCha20-Poly1305 envelope. Only the id of the key is
+    /// stored in plaintext, so it can be relayed or stored by untrusted parties.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use loro::{EncryptionKey, ExportMode, LoroDoc};
+    ///
+    /// let key = EncryptionKey::generate("key-1");
+    /// let doc = LoroDoc::new();
+    /// doc.get_text("text").insert(0, "Hello").unwrap();
+    /// let blob = doc.export_encrypted(ExportMode::Snapshot, &key).unwrap();
+    ///
+    /// let new_doc = LoroDoc::new();
+    /// assert!(new_doc.import(&blob).is_err());
+    /// new_doc.import_encrypted(&blob, &[key]).unwrap();
+    /// assert_eq!(new_doc.get_text("text").to_string(), "Hello");
+    /// ```
+    #[cfg(feature = "encryption")]
+    #[inline]
+    pub fn export_encrypted(
+        &self,
+        mode: ExportMode,
+        key: &EncryptionKey,
+    ) -> Result<Vec<u8>, LoroEncodeError> {
+        self.doc.export_encrypted(mode, key)
+    }
+
+    /// Import a blob exported by [`LoroDoc::export_encrypted`].
+    ///
+    /// The key is looked up in `keys` by the key id stored in the blob. If it's not found,
+    /// [`LoroError::DecryptionKeyRequired`] is returned. Blobs that are not encrypted are
+    /// rejected with [`LoroError::NotEncrypted`]. To accept them, e.g. while migrating a
+    /// store to encryption, decrypt the blobs with [`decrypt_blob_or_plaintext`] and
+    /// import the result with [`LoroDoc::import`].
+    #[cfg(feature = "encryption")]
+    #[inline]
+    pub fn import_encrypted(
+        &self,
+        bytes: &[u8],
+        keys: &[EncryptionKey],
+    ) -> Result<ImportStatus, LoroError> {
+        self.doc.import_encrypted(bytes, keys)
+    }
+
+    /// Import a blob exported by [`LoroDoc::export_encrypted`].
+    ///
+    /// It marks the import with a custom `origin` string. It can be used to track the import source
+    /// in the generated events.
+    #[cfg(feature = "encryption")]
+    #[inline]
+    pub fn import_encrypted_with(
+        &self,
+        bytes: &[u8],
+        keys: &[EncryptionKey],
+        origin: &str,
+    ) -> Result<ImportStatus, LoroError> {
+        self.doc.import_encrypted_with(bytes, keys, origin.into())
+    }
+
+    /// Decodes the metadata of a blob exported by [`LoroDoc::export_encrypted`].
+    ///
+    /// [`LoroDoc::decode_import_blob_meta`] only reports the id of the needed key for
+    /// encrypted blobs.
+    #[cfg(feature = "encryption")]
+    #[inline]
+    pub fn decode_encrypted_blob_meta(
+        bytes: &[u8],
+        keys: &[EncryptionKey],
+        check_checksum: bool,
+    ) -> LoroResult<ImportBlobMetadata> {
+        InnerLoroDoc::decode_encrypted_blob_meta(bytes, keys, check_checksum)
+    }
+
+    /// Re-encrypt an encrypted snapshot or updates with a new key.
+    ///
+    /// It's the key rotation path for the stored blobs. The blob is decrypted with the
+    /// matching key in `keys` and encrypted again with `new_key`, without being imported.
+    #[cfg(feature = "encryption")]
+    #[inline]
+    pub fn reencrypt_blob(
+        bytes: &[u8],
+        keys: &[EncryptionKey],
+        new_key: &EncryptionKey,
+    ) -> LoroResult<Vec<u8>> {
+        loro_internal::encoding::encryption::reencrypt_blob(bytes, keys, new_key)
+    }
+
+    /// Export the document in the given mode into a writer, such as a file or a network stream.
+    ///
+    /// In the updates modes, the changes are written in a streamed format where each block
+    /// carries its own checksum. Each block is encoded and written before the next one is
+    /// encoded, so only one block is kept in memory. The output can be imported by both
+    /// [`LoroDoc::import`] and [`LoroDoc::import_from_reader`], but not by the versions
+    /// of Loro that predate this format.
+    ///
+    /// The other modes write the same bytes as [`LoroDoc::export`]. The sections of a
+    /// snapshot are written one by one, but each of them is still encoded as a whole, so
+    /// the peak memory is proportional to the size of the snapshot.
+    pub fn export_to_writer<W: std::io::Write>(
+        &self,
+        mode: ExportMode,
+        writer: W,
+    ) -> Result<(), LoroEncodeError> {
+        self.doc.export_to_writer(mode, writer)
+    }
+
     /// Analyze the container info of the doc
     ///
     /// This is used for development and debugging. It can be slow.
@@ -875,20 +1494,84 @@ impl LoroDoc {
         })
     }
 
-    /// Get the number of operations in the pending transaction.
+    /// Apply a [JSON Patch](https://datatracker.ietf.org/doc/html/rfc6902) to the document in one transaction.
     ///
-    /// The pending transaction is the one that is not committed yet. It will be committed
-    /// after calling `doc.commit()`, `doc.export(mode)` or `doc.checkout(version)`.
-    pub fn get_pending_txn_len(&self) -> usize {
-        self.doc.get_pending_txn_len()
+    /// The patch works on the shape of [`LoroDoc::get_deep_value`]. The existing containers
+    /// are edited in place, and the new objects and arrays become [`LoroMap`]s and [`LoroList`]s.
+    /// It also accepts the `splice` op produced by [`LoroDoc::subscribe_json_patch`].
+    ///
+    /// The patch is applied in one transaction with [`LoroDoc::transact`]. If an operation
+    /// fails, none of the operations is applied.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{JsonPatchOp, LoroDoc, ToJson};
+    /// let doc = LoroDoc::new();
+    /// doc.get_map("users").insert("alice", 30).unwrap();
+    /// let patch: Vec<JsonPatchOp> = serde_json::from_value(serde_json::json!([
+    ///     { "op": "replace", "path": "/users/alice", "value": 31 },
+    ///     { "op": "add", "path": "/users/bob", "value": { "age": 25 } },
+    /// ]))
+    /// .unwrap();
+    /// doc.apply_json_patch(&patch).unwrap();
+    /// assert_eq!(
+    ///     doc.get_deep_value().to_json_value(),
+    ///     serde_json::json!({ "users": { "alice": 31, "bob": { "age": 25 } } })
+    /// );
+    /// ```
+    #[inline]
+    pub fn apply_json_patch(&self, patch: &[JsonPatchOp]) -> Result<(), JsonPatchError> {
+        self.doc.apply_json_patch(patch)
     }
 
-    /// Traverses the ancestors of the Change containing the given ID, including itself.
+    /// Compute the unified diff hunks of a text container between two versions.
     ///
-    /// This method visits all ancestors in causal order, from the latest to the oldest,
-    /// based on their Lamport timestamps.
+    /// The text is empty at a version where the container doesn't exist. Use
+    /// [`unified_diff`] to write the hunks as a patch.
     ///
-    /// # Arguments
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{unified_diff, LoroDoc};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "a\nb\nc\n").unwrap();
+    /// doc.commit();
+    /// let v1 = doc.state_frontiers();
+    /// text.update_by_line("a\nB\nc\n", Default::default()).unwrap();
+    /// doc.commit();
+    /// let v2 = doc.state_frontiers();
+    /// let hunks = doc.text_diff_between(&text.id(), &v1, &v2).unwrap();
+    /// assert_eq!(
+    ///     unified_diff(&hunks, "a/text", "b/text"),
+    ///     "--- a/text\n+++ b/text\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+    /// );
+    /// ```
+    #[inline]
+    pub fn text_diff_between(
+        &self,
+        container: &ContainerID,
+        a: &Frontiers,
+        b: &Frontiers,
+    ) -> LoroResult<Vec<Hunk>> {
+        self.doc.text_diff_between(container, a, b)
+    }
+
+    /// Get the number of operations in the pending transaction.
+    ///
+    /// The pending transaction is the one that is not committed yet. It will be committed
+    /// after calling `doc.commit()`, `doc.export(mode)` or `doc.checkout(version)`.
+    pub fn get_pending_txn_len(&self) -> usize {
+        self.doc.get_pending_txn_len()
+    }
+
+    /// Traverses the ancestors of the Change containing the given ID, including itself.
+    ///
+    /// This method visits all ancestors in causal order, from the latest to the oldest,
+    /// based on their Lamport timestamps.
+    ///
+    /// # Arguments
     ///
     /// * `ids` - The IDs of the Change to start the traversal from.
     /// * `f` - A mutable function that is called for each ancestor. It can return `ControlFlow::Break(())` to stop the traversal.
@@ -1541,6 +2224,129 @@ impl LoroText {
         self.handler.len_utf16()
     }
 
+    /// Get the number of lines in the text.
+    ///
+    /// Lines are separated by `\n`, so an empty text has one line and a text ending with
+    /// `\n` has an empty last line.
+    pub fn line_count(&self) -> usize {
+        self.handler.line_count()
+    }
+
+    /// Get the unicode position of the start of the given line (0-based).
+    ///
+    /// Returns `None` if the line doesn't exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "fn main() {\n    println!();\n}").unwrap();
+    /// assert_eq!(text.line_count(), 3);
+    /// assert_eq!(text.line_to_offset(1), Some(12));
+    /// assert_eq!(text.offset_to_line_col(16), Some((1, 4)));
+    /// assert_eq!(text.get_line(1).unwrap(), "    println!();");
+    /// assert_eq!(text.line_to_offset(3), None);
+    /// ```
+    pub fn line_to_offset(&self, line: usize) -> Option<usize> {
+        self.handler.line_to_offset(line)
+    }
+
+    /// Get the line and the column (both 0-based) of the given unicode position.
+    ///
+    /// The column is counted in unicode characters. Returns `None` if the position is out
+    /// of bound.
+    pub fn offset_to_line_col(&self, pos: usize) -> Option<(usize, usize)> {
+        self.handler.offset_to_line_col(pos)
+    }
+
+    /// Get the content of the given line (0-based), without the trailing `\n`.
+    ///
+    /// Returns `None` if the line doesn't exist.
+    pub fn get_line(&self, line: usize) -> Option<String> {
+        self.handler.get_line(line)
+    }
+
+    /// Get the length of the text container in extended grapheme clusters.
+    pub fn len_grapheme(&self) -> usize {
+        self.handler.len_grapheme()
+    }
+
+    /// Convert a position of the `from` unit into the `to` unit.
+    ///
+    /// Returns `None` if the position is out of bound or it's inside a character of the
+    /// `from` unit, e.g. in the middle of a UTF-8 encoded code point or inside an emoji
+    /// ZWJ sequence when converting from [`PosType::Grapheme`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, PosType};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// // 👨‍👩‍👧 is a single grapheme made of 5 code points
+    /// text.insert(0, "a👨‍👩‍👧b").unwrap();
+    /// assert_eq!(text.convert_pos(2, PosType::Grapheme, PosType::Unicode), Some(6));
+    /// assert_eq!(text.convert_pos(6, PosType::Unicode, PosType::Utf16), Some(9));
+    /// assert_eq!(text.convert_pos(9, PosType::Utf16, PosType::Utf8), Some(19));
+    /// assert_eq!(text.convert_pos(19, PosType::Utf8, PosType::Grapheme), Some(2));
+    /// // Inside the ZWJ sequence
+    /// assert_eq!(text.convert_pos(3, PosType::Unicode, PosType::Grapheme), None);
+    /// // Inside a UTF-8 encoded code point
+    /// assert_eq!(text.convert_pos(2, PosType::Utf8, PosType::Unicode), None);
+    /// ```
+    pub fn convert_pos(&self, pos: usize, from: PosType, to: PosType) -> Option<usize> {
+        self.handler.convert_pos(pos, from, to)
+    }
+
+    /// Get the length of the text container in the given unit.
+    pub fn len_with_pos_type(&self, pos_type: PosType) -> usize {
+        self.handler.len_with_pos_type(pos_type)
+    }
+
+    /// Insert a string at the given position of the `pos_type` unit.
+    ///
+    /// With [`PosType::Grapheme`] the position is counted in user-perceived characters, so
+    /// the text is never inserted inside an emoji ZWJ sequence or a character with combining
+    /// marks. Returns an error if the position is out of bound or inside a character of the
+    /// unit.
+    pub fn insert_with_pos_type(&self, pos: usize, s: &str, pos_type: PosType) -> LoroResult<()> {
+        self.handler.insert_with_pos_type(pos, s, pos_type)
+    }
+
+    /// Delete `len` characters of the `pos_type` unit starting from `pos`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, PosType};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "a👨‍👩‍👧b").unwrap();
+    /// text.delete_with_pos_type(1, 1, PosType::Grapheme).unwrap();
+    /// assert_eq!(text.to_string(), "ab");
+    /// ```
+    pub fn delete_with_pos_type(
+        &self,
+        pos: usize,
+        len: usize,
+        pos_type: PosType,
+    ) -> LoroResult<()> {
+        self.handler.delete_with_pos_type(pos, len, pos_type)
+    }
+
+    /// Get a string slice between the given positions of the `pos_type` unit.
+    pub fn slice_with_pos_type(
+        &self,
+        start_index: usize,
+        end_index: usize,
+        pos_type: PosType,
+    ) -> LoroResult<String> {
+        self.handler
+            .slice_with_pos_type(start_index, end_index, pos_type)
+    }
+
     /// Update the current text based on the provided text.
     ///
     /// It will calculate the minimal difference and apply it to the current text.
@@ -1560,6 +2366,32 @@ impl LoroText {
     /// assert_eq!(text.to_string(), "Hello World");
     /// ```
     ///
+    /// For prose, diff by words or sentences with [`UpdateOptions::granularity()`], so an
+    /// edited word is replaced as a whole instead of being merged char by char with the
+    /// concurrent edits. With [`UpdateOptions::preserve_marks()`], the inserted text doesn't
+    /// inherit the styles around it, so the marks stay on the unchanged words.
+    ///
+    /// ```rust
+    /// use loro::{LoroDoc, ToJson, UpdateGranularity, UpdateOptions};
+    /// use serde_json::json;
+    ///
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "The quick fox").unwrap();
+    /// text.mark(4..9, "bold", true).unwrap();
+    /// let options = UpdateOptions::default()
+    ///     .granularity(UpdateGranularity::Word)
+    ///     .preserve_marks(true);
+    /// text.update("The quick, brown fox", options).unwrap();
+    /// assert_eq!(
+    ///     text.to_delta().to_json_value(),
+    ///     json!([
+    ///         { "insert": "The " },
+    ///         { "insert": "quick", "attributes": { "bold": true } },
+    ///         { "insert": ", brown fox" },
+    ///     ])
+    /// );
+    /// ```
     pub fn update(&self, text: &str, options: UpdateOptions) -> Result<(), UpdateTimeoutError> {
         self.handler.update(text, options)
     }
@@ -1623,6 +2455,197 @@ impl LoroText {
         self.handler.unmark(range.start, range.end, key)
     }
 
+    /// Add an annotation of the key to a range of text and return its ID.
+    ///
+    /// The key must be configured as an annotation by [`StyleConfig::annotation`]. Unlike the
+    /// marks of the other keys, the annotations of the same key don't override each other, so
+    /// the comments of different users can overlap. Each one keeps its ID and value, and can be
+    /// removed by [`LoroText::remove_annotation`].
+    ///
+    /// In [`LoroText::to_delta`] and the text events, the annotations are reported under the
+    /// key as a map from their IDs to their values. [`LoroText::mark`] also adds an annotation
+    /// when the key is configured as one, and [`LoroText::unmark`] removes all the annotations
+    /// of the key from the range.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, StyleConfig, StyleConfigMap, ToJson};
+    /// # use serde_json::json;
+    /// let doc = LoroDoc::new();
+    /// let mut styles = StyleConfigMap::default_rich_text_config();
+    /// styles.insert("comment".into(), StyleConfig::new().annotation(true));
+    /// doc.config_text_style(styles);
+    ///
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Hello world").unwrap();
+    /// let a = text.annotate(0..5, "comment", "alice").unwrap();
+    /// let b = text.annotate(3..8, "comment", "bob").unwrap();
+    /// assert_eq!(
+    ///     text.to_delta().to_json_value(),
+    ///     json!([
+    ///         {"insert": "Hel", "attributes": {"comment": {a.to_string(): "alice"}}},
+    ///         {"insert": "lo", "attributes": {"comment": {a.to_string(): "alice", b.to_string(): "bob"}}},
+    ///         {"insert": " wo", "attributes": {"comment": {b.to_string(): "bob"}}},
+    ///         {"insert": "rld"},
+    ///     ])
+    /// );
+    ///
+    /// text.remove_annotation("comment", a).unwrap();
+    /// assert_eq!(text.get_annotations("comment").len(), 1);
+    /// ```
+    pub fn annotate(
+        &self,
+        range: Range<usize>,
+        key: &str,
+        value: impl Into<LoroValue>,
+    ) -> LoroResult<ID> {
+        self.handler
+            .annotate(range.start, range.end, key, value.into())
+    }
+
+    /// Remove the annotation of the key with the ID.
+    ///
+    /// It's a no-op if the annotation doesn't exist or has been removed.
+    pub fn remove_annotation(&self, key: &str, id: ID) -> LoroResult<()> {
+        self.handler.remove_annotation(key, id)
+    }
+
+    /// Get the annotations of the key with their values and the ranges they cover.
+    ///
+    /// An annotation is reported once for each range it covers, which happens when a part of
+    /// it is removed by [`LoroText::unmark`].
+    pub fn get_annotations(&self, key: &str) -> Vec<(ID, LoroValue, Range<usize>)> {
+        self.handler.get_annotations(key)
+    }
+
+    /// Insert an embed, like an image or a mention, at the given position.
+    ///
+    /// The embed occupies one position, and it's reported by [`LoroText::to_delta`] as an
+    /// insert of its value, like `{ "insert": { "image": "a.png" } }`. In the text and in the
+    /// events it's an [`EMBED_CHAR`] with the value as the [`EMBED_KEY`] style, which
+    /// [`LoroText::apply_delta`] accepts too.
+    ///
+    /// The value can't be null or a container, because the containers can't be created
+    /// inside a text.
+    ///
+    /// The embeds and the block attributes are not new kinds of elements of the text: they
+    /// are stored as marks, so the encoding is unchanged, and [`LoroText::to_string`] and
+    /// the positions count an embed as one [`EMBED_CHAR`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{loro_value, LoroDoc, ToJson};
+    /// # use serde_json::json;
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Title\nSee ").unwrap();
+    /// text.insert_embed(10, loro_value!({ "image": "a.png" })).unwrap();
+    /// text.set_block_attribute(0, "header", 1).unwrap();
+    /// text.set_block_attribute(1, "list", "bullet").unwrap();
+    /// assert_eq!(
+    ///     text.to_delta().to_json_value(),
+    ///     json!([
+    ///         { "insert": "Title" },
+    ///         { "insert": "\n", "attributes": { "header": 1 } },
+    ///         { "insert": "See " },
+    ///         { "insert": { "image": "a.png" } },
+    ///         { "insert": "\n", "attributes": { "list": "bullet" } },
+    ///     ])
+    /// );
+    /// ```
+    pub fn insert_embed(&self, pos: usize, value: impl Into<LoroValue>) -> LoroResult<()> {
+        self.handler.insert_embed(pos, value.into())
+    }
+
+    /// Set the block attribute of the line (0-based), like a heading or a list item.
+    ///
+    /// The key must be configured as a block attribute by [`StyleConfig::block`]. The
+    /// default config has `header`, `list` and `blockquote`. As in the Quill deltas, the
+    /// attribute is set on the line break that ends the line, so a line break is appended
+    /// if it's the last line without one.
+    pub fn set_block_attribute(
+        &self,
+        line: usize,
+        key: &str,
+        value: impl Into<LoroValue>,
+    ) -> LoroResult<()> {
+        self.handler.set_block_attribute(line, key, value.into())
+    }
+
+    /// Remove the block attribute of the line (0-based).
+    pub fn remove_block_attribute(&self, line: usize, key: &str) -> LoroResult<()> {
+        self.handler.remove_block_attribute(line, key)
+    }
+
+    /// Get the block attributes of the line (0-based).
+    ///
+    /// Returns `None` if the line doesn't exist.
+    pub fn get_block_attributes(&self, line: usize) -> Option<FxHashMap<String, LoroValue>> {
+        self.handler.get_block_attributes(line)
+    }
+
+    /// Get the styles at the cursor position `pos`, i.e. the styles that the text inserted
+    /// there would have.
+    ///
+    /// It respects the expand behavior of the styles, so a bold range configured with
+    /// `ExpandType::After` reports `bold` right after its end.
+    ///
+    /// # Example
+    /// ```
+    /// # use loro::{LoroDoc, LoroValue};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Hello world").unwrap();
+    /// text.mark(0..5, "bold", true).unwrap();
+    /// assert_eq!(text.get_styles_at(5).unwrap().get("bold"), Some(&LoroValue::from(true)));
+    /// assert!(text.get_styles_at(6).unwrap().is_empty());
+    /// ```
+    pub fn get_styles_at(&self, pos: usize) -> LoroResult<FxHashMap<String, LoroValue>> {
+        self.handler.get_styles_at(pos)
+    }
+
+    /// Get the value of each style key across the selection `range`.
+    ///
+    /// A key is [`StyleRangeValue::Uniform`] if all the text in the range has it with the same
+    /// value, and [`StyleRangeValue::Mixed`] if only a part of the text has it or the values
+    /// differ. The keys that no text in the range has are absent. A collapsed range returns the
+    /// styles of [`LoroText::get_styles_at`].
+    ///
+    /// # Example
+    /// ```
+    /// # use loro::{LoroDoc, LoroValue, StyleRangeValue};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Hello world").unwrap();
+    /// text.mark(0..5, "bold", true).unwrap();
+    /// text.mark(0..8, "link", "a").unwrap();
+    /// let styles = text.get_styles_in(0..8).unwrap();
+    /// assert_eq!(styles.get("bold"), Some(&StyleRangeValue::Mixed));
+    /// assert_eq!(
+    ///     styles.get("link"),
+    ///     Some(&StyleRangeValue::Uniform(LoroValue::from("a")))
+    /// );
+    /// assert_eq!(styles.get("italic"), None);
+    /// ```
+    pub fn get_styles_in(
+        &self,
+        range: Range<usize>,
+    ) -> LoroResult<FxHashMap<String, StyleRangeValue>> {
+        self.handler.get_styles_in(range.start, range.end)
+    }
+
+    /// Iterate the text spans in `range` with their styles.
+    ///
+    /// The spans have the same form as the items of [`LoroText::to_delta`]. They are read from
+    /// the text one by one as the iterator advances, so only the text of the consumed spans is
+    /// visited. Each step reads the current text, so an edit made while iterating shifts the
+    /// text that the remaining steps read.
+    pub fn iter_spans(&self, range: Range<usize>) -> LoroResult<impl Iterator<Item = TextDelta>> {
+        self.handler.iter_spans(range.start, range.end)
+    }
+
     /// Get the text in [Delta](https://quilljs.com/docs/delta/) format.
     ///
     /// # Example
@@ -1694,6 +2717,232 @@ impl LoroText {
         self.handler.get_cursor(pos, side)
     }
 
+    /// Get a [`TextRange`] that keeps covering the text in `range` when the text is edited
+    /// concurrently. Use [`LoroDoc::resolve_range`] to get its current position.
+    ///
+    /// Like the expand type of [`LoroText::mark`], `expand` decides whether the text inserted
+    /// at the boundaries of the range is included:
+    ///
+    /// - `None`: the range is anchored to its first and last chars, so the text inserted at
+    ///   either boundary is outside of it
+    /// - `After`: the text inserted at the end is included
+    /// - `Before`: the text inserted at the start is included
+    /// - `Both`: the range is anchored to the chars around it, so the text inserted at either
+    ///   boundary is included
+    ///
+    /// Returns `None` if the range is out of bound or the text is detached.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{ExpandType, LoroDoc};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Hello world").unwrap();
+    /// let range = text.get_range(0..5, ExpandType::After).unwrap();
+    /// text.insert(5, "!").unwrap();
+    /// text.insert(0, ">").unwrap();
+    /// assert_eq!(doc.resolve_range(&range).unwrap().unicode, 1..7);
+    /// ```
+    pub fn get_range(&self, range: Range<usize>, expand: ExpandType) -> Option<TextRange> {
+        self.handler.get_range(range.start, range.end, expand)
+    }
+
+    /// Find the non-overlapping occurrences of `pattern` in the text.
+    ///
+    /// The text is scanned chunk by chunk, so it's not copied. The ranges of the matches are
+    /// in the unit of [`TextSearchOptions::pos_type`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, PosType, TextSearchOptions};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "😀 Cat, cat, concat").unwrap();
+    /// let ranges = |options| {
+    ///     text.find("cat", &options)
+    ///         .into_iter()
+    ///         .map(|m| m.range)
+    ///         .collect::<Vec<_>>()
+    /// };
+    /// assert_eq!(ranges(TextSearchOptions::default()), vec![7..10, 15..18]);
+    /// let options = TextSearchOptions {
+    ///     case_sensitive: false,
+    ///     whole_word: true,
+    ///     pos_type: PosType::Utf16,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(ranges(options), vec![3..6, 8..11]);
+    /// ```
+    pub fn find(&self, pattern: &str, options: &TextSearchOptions) -> Vec<TextMatch> {
+        self.handler.find(pattern, options)
+    }
+
+    /// Replace all the non-overlapping occurrences of `pattern` with `replacement`.
+    ///
+    /// Only the matched text is edited, so the styles and the cursors of the rest of the
+    /// text are kept. All the edits are in the same transaction.
+    ///
+    /// Returns the number of the replaced matches.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, TextSearchOptions};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "colour, Colour").unwrap();
+    /// let options = TextSearchOptions {
+    ///     case_sensitive: false,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(text.replace_all("colour", "color", &options).unwrap(), 2);
+    /// assert_eq!(text.to_string(), "color, color");
+    /// ```
+    pub fn replace_all(
+        &self,
+        pattern: &str,
+        replacement: &str,
+        options: &TextSearchOptions,
+    ) -> LoroResult<usize> {
+        self.handler.replace_all(pattern, replacement, options)
+    }
+
+    /// Find the non-overlapping matches of the regular expression in the text.
+    ///
+    /// [`TextSearchOptions::case_sensitive`] and [`TextSearchOptions::whole_word`] are
+    /// applied to the regex. Returns [`LoroError::ArgErr`] if the regex is invalid.
+    #[cfg(feature = "text-regex")]
+    pub fn find_regex(
+        &self,
+        pattern: &str,
+        options: &TextSearchOptions,
+    ) -> LoroResult<Vec<TextMatch>> {
+        self.handler.find_regex(pattern, options)
+    }
+
+    /// Replace all the non-overlapping matches of the regular expression with `replacement`.
+    ///
+    /// The replacement can refer to the capture groups like `$1` or `${name}`. Only the
+    /// matched text is edited, and all the edits are in the same transaction.
+    ///
+    /// Returns the number of the replaced matches.
+    #[cfg(feature = "text-regex")]
+    pub fn replace_all_regex(
+        &self,
+        pattern: &str,
+        replacement: &str,
+        options: &TextSearchOptions,
+    ) -> LoroResult<usize> {
+        self.handler
+            .replace_all_regex(pattern, replacement, options)
+    }
+
+    /// Compute the unified diff hunks that turn the current text into `new`.
+    ///
+    /// The text is compared by lines, and each hunk has up to 3 unchanged lines around
+    /// the changes. Use [`unified_diff`] to write the hunks as a patch.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{unified_diff, LoroDoc};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "a\nb\nc").unwrap();
+    /// let hunks = text.diff_with("a\nb\nd");
+    /// assert_eq!(
+    ///     unified_diff(&hunks, "old", "new"),
+    ///     "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n b\n-c\n\\ No newline at end of file\n+d\n\\ No newline at end of file\n"
+    /// );
+    /// ```
+    pub fn diff_with(&self, new: &str) -> Vec<Hunk> {
+        self.handler.diff_with(new)
+    }
+
+    /// Apply a patch in the unified diff format to the text.
+    ///
+    /// Each hunk is located by its context lines, so it still applies when the text has
+    /// been edited around it. If the context doesn't match exactly, up to 2 context lines
+    /// at each end of the hunk and the trailing whitespaces are ignored.
+    ///
+    /// All the hunks are located before the text is edited. If any of them can't be
+    /// applied, [`TextPatchError::HunkNotApplied`] is returned and the text is unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "new line\na\nb\nc\n").unwrap();
+    /// let patch = "--- a\n+++ b\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n";
+    /// text.apply_patch(patch).unwrap();
+    /// assert_eq!(text.to_string(), "new line\na\nB\nc\n");
+    /// ```
+    pub fn apply_patch(&self, patch: &str) -> Result<(), TextPatchError> {
+        self.handler.apply_patch(patch)
+    }
+
+    /// Write the rich text as HTML, with the tags of the styles given by the mapping.
+    ///
+    /// The styles without a rule in the mapping are not written. Each [`EMBED_CHAR`] with
+    /// the style of an embed key is written as the tag of the embedded object. The line
+    /// breaks are written as `<br>`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{HtmlMapping, LoroDoc};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Hello world!\nBye").unwrap();
+    /// text.mark(0..5, "bold", true).unwrap();
+    /// text.mark(6..11, "link", "https://loro.dev").unwrap();
+    /// assert_eq!(
+    ///     text.to_html(&HtmlMapping::default_rich_text()),
+    ///     "<strong>Hello</strong> <a href=\"https://loro.dev\">world</a>!<br>Bye"
+    /// );
+    /// ```
+    pub fn to_html(&self, mapping: &HtmlMapping) -> String {
+        self.handler.to_html(mapping)
+    }
+
+    /// Insert the HTML at the given unicode position, with the styles given by the mapping.
+    ///
+    /// The inserted text has exactly the styles of its tags, so it doesn't inherit the
+    /// styles around the position. The unknown tags and attributes are dropped while their
+    /// text is kept, and the content of the tags like `<script>` and `<style>` is dropped.
+    /// The attribute values with the `javascript:` scheme are dropped too.
+    ///
+    /// The rules whose style keys are not configured by [`LoroDoc::config_text_style`] are
+    /// ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{HtmlMapping, LoroDoc, ToJson};
+    /// # use serde_json::json;
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "[]").unwrap();
+    /// let html = "<p>Hi <b>there</b><script>alert(1)</script></p><p>Bye</p>";
+    /// text.insert_html(1, html, &HtmlMapping::default_rich_text())
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     text.to_delta().to_json_value(),
+    ///     json!([
+    ///         { "insert": "[Hi " },
+    ///         { "insert": "there", "attributes": { "bold": true } },
+    ///         { "insert": "\nBye]" },
+    ///     ])
+    /// );
+    /// ```
+    pub fn insert_html(&self, pos: usize, html: &str, mapping: &HtmlMapping) -> LoroResult<()> {
+        self.handler.insert_html(pos, html, mapping)
+    }
+
     /// Whether the text container is deleted.
     pub fn is_deleted(&self) -> bool {
         self.handler.is_deleted()
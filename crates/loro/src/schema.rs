@@ -0,0 +1,223 @@
+//! Typed access to the maps in a document.
+//!
+//! A [`LoroSchema`] type describes the entries of a [`LoroMap`] and wraps it in a typed handle.
+//! It's usually derived with `#[derive(LoroSchema)]`, which requires the `derive` feature.
+//!
+//! Remote peers may write anything under a key, so the handle is only created after the map is
+//! validated against the schema. The entries that are missing are allowed. Attaching doesn't
+//! write anything: the missing child containers and nested maps are created by the first write
+//! into them, and the getters of the missing values return [`LoroSchemaError::MissingField`]
+//! unless the value is optional.
+//!
+//! A [`DocSchema`] describes the root containers of a whole document. It can check a document
+//! or the changes in a [`DiffEvent`](crate::event::DiffEvent), e.g. the remote changes after an
+//! import. The shape of a document can be upgraded with the versioned migrations of a
+//! [`Migrator`], which run once per document.
+use loro_internal::{from_loro_value, to_loro_value, LoroSchemaError, LoroSerdeError, LoroValue};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{Container, ContainerTrait, LoroMap, ValueOrContainer};
+
+mod migration;
+mod validation;
+pub use migration::{Migrator, MIGRATIONS_ROOT};
+pub use validation::{DocSchema, MapSchema, Schema};
+
+/// A schema of the entries in a [`LoroMap`]
+pub trait LoroSchema {
+    /// The typed handle over the map
+    type Handle: Clone + std::fmt::Debug;
+
+    /// Check that the entries in the map have the types of the schema.
+    ///
+    /// The entries that are missing are allowed.
+    fn validate(map: &LoroMap) -> Result<(), LoroSchemaError>;
+
+    /// Validate the map and wrap it in the typed handle.
+    ///
+    /// No op is created. The missing child containers are created when they are written.
+    fn attach(map: LoroMap) -> Result<Self::Handle, LoroSchemaError> {
+        Self::validate(&map)?;
+        Ok(Self::handle(SchemaMap::new(map)))
+    }
+
+    /// Wrap the map, which may not exist yet, in the typed handle without validating it
+    #[doc(hidden)]
+    fn handle(map: SchemaMap) -> Self::Handle;
+}
+
+/// A map of a typed handle, which may not be created yet.
+///
+/// The nested maps of a schema are looked up from their parents on each read, and are only
+/// created on the first write into them.
+#[derive(Debug, Clone)]
+pub struct SchemaMap(SchemaMapInner);
+
+#[derive(Debug, Clone)]
+enum SchemaMapInner {
+    Map(LoroMap),
+    Child {
+        parent: Box<SchemaMap>,
+        key: Box<str>,
+    },
+}
+
+impl SchemaMap {
+    /// Wrap an existing map
+    pub fn new(map: LoroMap) -> Self {
+        Self(SchemaMapInner::Map(map))
+    }
+
+    /// The map under `key` of this map, which may not exist yet
+    pub fn child(&self, key: &str) -> Self {
+        Self(SchemaMapInner::Child {
+            parent: Box::new(self.clone()),
+            key: key.into(),
+        })
+    }
+
+    /// Get the map if it exists, without creating it
+    pub fn get(&self) -> Option<LoroMap> {
+        match &self.0 {
+            SchemaMapInner::Map(map) => Some(map.clone()),
+            SchemaMapInner::Child { parent, key } => match parent.get()?.get(key)? {
+                ValueOrContainer::Container(Container::Map(map)) => Some(map),
+                _ => None,
+            },
+        }
+    }
+
+    /// Get the map, creating it and its missing parents
+    pub fn get_or_create(&self) -> Result<LoroMap, LoroSchemaError> {
+        match &self.0 {
+            SchemaMapInner::Map(map) => Ok(map.clone()),
+            SchemaMapInner::Child { parent, key } => Ok(parent
+                .get_or_create()?
+                .get_or_create_container(key, LoroMap::new())?),
+        }
+    }
+}
+
+fn describe(value: &ValueOrContainer) -> String {
+    match value {
+        ValueOrContainer::Value(v) => match v {
+            LoroValue::Null => "null",
+            LoroValue::Bool(_) => "a bool",
+            LoroValue::Double(_) | LoroValue::I64(_) => "a number",
+            LoroValue::Binary(_) => "a binary",
+            LoroValue::String(_) => "a string",
+            LoroValue::List(_) => "a list",
+            LoroValue::Map(_) => "a map",
+            LoroValue::Container(_) => "a container id",
+        }
+        .to_string(),
+        ValueOrContainer::Container(c) => format!("a {} container", c.get_type()),
+    }
+}
+
+fn wrong_type(path: &str, reason: impl ToString) -> LoroSchemaError {
+    LoroSchemaError::WrongType {
+        path: path.into(),
+        reason: reason.to_string().into(),
+    }
+}
+
+/// Prefix the path in the error with the key of the parent map
+fn in_parent(key: &str, err: LoroSchemaError) -> LoroSchemaError {
+    match err {
+        LoroSchemaError::WrongType { path, reason } => LoroSchemaError::WrongType {
+            path: format!("{}.{}", key, path).into(),
+            reason,
+        },
+        LoroSchemaError::MissingField { path } => LoroSchemaError::MissingField {
+            path: format!("{}.{}", key, path).into(),
+        },
+        err => err,
+    }
+}
+
+#[doc(hidden)]
+pub fn validate_container<C: ContainerTrait + Default>(
+    map: &LoroMap,
+    key: &str,
+) -> Result<(), LoroSchemaError> {
+    match map.get(key) {
+        None => Ok(()),
+        Some(ValueOrContainer::Container(c)) if C::try_from_container(c.clone()).is_some() => {
+            Ok(())
+        }
+        Some(found) => Err(wrong_type(
+            key,
+            format_args!(
+                "expected a {} container, found {}",
+                C::default().to_container().get_type(),
+                describe(&found)
+            ),
+        )),
+    }
+}
+
+#[doc(hidden)]
+pub fn validate_nested<S: LoroSchema>(map: &LoroMap, key: &str) -> Result<(), LoroSchemaError> {
+    match map.get(key) {
+        None => Ok(()),
+        Some(ValueOrContainer::Container(Container::Map(child))) => {
+            S::validate(&child).map_err(|e| in_parent(key, e))
+        }
+        Some(found) => Err(wrong_type(
+            key,
+            format_args!("expected a Map container, found {}", describe(&found)),
+        )),
+    }
+}
+
+#[doc(hidden)]
+pub fn validate_value<T: DeserializeOwned>(
+    map: &LoroMap,
+    key: &str,
+) -> Result<(), LoroSchemaError> {
+    match map.get(key) {
+        None => Ok(()),
+        Some(found) => from_loro_value::<T>(found.get_deep_value())
+            .map(|_| ())
+            .map_err(|e| wrong_type(key, e)),
+    }
+}
+
+#[doc(hidden)]
+pub fn get_container<C: ContainerTrait>(map: &SchemaMap, key: &str) -> Option<C> {
+    match map.get()?.get(key)? {
+        ValueOrContainer::Container(c) => C::try_from_container(c),
+        ValueOrContainer::Value(_) => None,
+    }
+}
+
+#[doc(hidden)]
+pub fn get_or_create_container<C: ContainerTrait + Default>(
+    map: &SchemaMap,
+    key: &str,
+) -> Result<C, LoroSchemaError> {
+    Ok(map
+        .get_or_create()?
+        .get_or_create_container(key, C::default())?)
+}
+
+#[doc(hidden)]
+pub fn get_value<T: DeserializeOwned>(map: &SchemaMap, key: &str) -> Result<T, LoroSchemaError> {
+    match map.get().and_then(|map| map.get(key)) {
+        // Optional values are read as `None`
+        None => from_loro_value(LoroValue::Null)
+            .map_err(|_: LoroSerdeError| LoroSchemaError::MissingField { path: key.into() }),
+        Some(found) => from_loro_value(found.get_deep_value()).map_err(|e| wrong_type(key, e)),
+    }
+}
+
+#[doc(hidden)]
+pub fn set_value<T: Serialize + ?Sized>(
+    map: &SchemaMap,
+    key: &str,
+    value: &T,
+) -> Result<(), LoroSchemaError> {
+    map.get_or_create()?.insert(key, to_loro_value(value)?)?;
+    Ok(())
+}
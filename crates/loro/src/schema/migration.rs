@@ -0,0 +1,217 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use loro_internal::{json::JsonChange, Lamport, LoroError, LoroResult, LoroValue, PeerID, ID};
+
+use crate::LoroDoc;
+
+/// The root map recording the migrations applied to a document.
+///
+/// The keys are the versions and the values are the names of the migrations.
+pub const MIGRATIONS_ROOT: &str = "__loro_migrations";
+
+type MigrationFn = Box<dyn Fn(&LoroDoc) -> LoroResult<()> + Send + Sync>;
+
+struct Migration {
+    name: String,
+    run: MigrationFn,
+}
+
+/// Versioned migrations of a document.
+///
+/// Each migration runs once per document: the applied versions are recorded in the
+/// [`MIGRATIONS_ROOT`] map in the same change as the edits of the migration, so they are synced
+/// with the document.
+///
+/// Several peers may migrate their replicas at the same time. A migration is committed with a
+/// peer id derived from its version and its name, and without a timestamp. The peers running it
+/// on the same version of the document create the same change, which is only imported once.
+/// So the migrations must be deterministic: they should only depend on the state of the
+/// document. [`Migrator::migrate`] runs each migration twice and fails if the changes differ.
+///
+/// The replicas should run the migrations on the same version, e.g. right after loading the
+/// synced document and before editing it. The changes made on different versions share the
+/// same ids, and only one of them is kept by each replica.
+///
+/// # Example
+///
+/// ```
+/// use loro::{schema::Migrator, LoroDoc};
+///
+/// let migrator = Migrator::new().add(1, "init", |doc| {
+///     doc.get_map("settings").insert("theme", "light")?;
+///     Ok(())
+/// });
+/// let doc = LoroDoc::new();
+/// assert_eq!(migrator.migrate(&doc).unwrap(), vec![1]);
+/// assert!(migrator.migrate(&doc).unwrap().is_empty());
+/// assert_eq!(Migrator::applied_versions(&doc), vec![1]);
+/// ```
+#[derive(Default)]
+pub struct Migrator {
+    migrations: BTreeMap<u32, Migration>,
+}
+
+impl Debug for Migrator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.migrations.iter().map(|(v, m)| (v, &m.name)))
+            .finish()
+    }
+}
+
+impl Migrator {
+    /// Create a migrator without migrations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the migration to the version.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the version already has a migration.
+    pub fn add(
+        mut self,
+        version: u32,
+        name: &str,
+        run: impl Fn(&LoroDoc) -> LoroResult<()> + Send + Sync + 'static,
+    ) -> Self {
+        let old = self.migrations.insert(
+            version,
+            Migration {
+                name: name.to_string(),
+                run: Box::new(run),
+            },
+        );
+        assert!(old.is_none(), "Migration {} is added twice", version);
+        self
+    }
+
+    /// The versions of the migrations applied to the document, in ascending order
+    pub fn applied_versions(doc: &LoroDoc) -> Vec<u32> {
+        let mut ans: Vec<u32> = doc
+            .get_map(MIGRATIONS_ROOT)
+            .keys()
+            .filter_map(|k| k.parse().ok())
+            .collect();
+        ans.sort_unstable();
+        ans
+    }
+
+    /// Run the pending migrations in the order of their versions.
+    ///
+    /// Returns the versions that are applied by this call. If a migration fails, its edits are
+    /// rolled back and the error is returned; the migrations before it stay applied.
+    ///
+    /// Each migration is run on a fork of the document first. If it makes a different change
+    /// when it's run again on the document, it's rolled back and an error is returned.
+    pub fn migrate(&self, doc: &LoroDoc) -> LoroResult<Vec<u32>> {
+        let record = doc.get_map(MIGRATIONS_ROOT);
+        let mut ans = Vec::new();
+        for (&version, migration) in self.migrations.iter() {
+            let key = version.to_string();
+            if record.get(&key).is_some() {
+                continue;
+            }
+
+            doc.commit();
+            let peer = migration_peer(version, &migration.name);
+            let fork = doc.fork();
+            let expected = Arc::new(Mutex::new(None));
+            let expected_clone = expected.clone();
+            let _record = fork.subscribe_pre_commit(Box::new(move |pre_commit| {
+                *expected_clone.lock().unwrap() = Some(ChangeDigest::new(pre_commit.change()));
+                true
+            }));
+            migration.run_on(&fork, version, peer)?;
+            let expected = expected.lock().unwrap().take();
+
+            let differs = Arc::new(AtomicBool::new(false));
+            let differs_clone = differs.clone();
+            let check = doc.subscribe_pre_commit(Box::new(move |pre_commit| {
+                if expected.as_ref() != Some(&ChangeDigest::new(pre_commit.change())) {
+                    differs_clone.store(true, Ordering::Relaxed);
+                    pre_commit.abort();
+                }
+                true
+            }));
+            let result = migration.run_on(doc, version, peer);
+            drop(check);
+            result?;
+            if differs.load(Ordering::Relaxed) {
+                return Err(LoroError::ArgErr(
+                    format!(
+                        "Migration {} ({}) is not deterministic: it made different changes on the same version",
+                        version, migration.name
+                    )
+                    .into_boxed_str(),
+                ));
+            }
+
+            ans.push(version);
+        }
+
+        Ok(ans)
+    }
+}
+
+impl Migration {
+    /// Run the migration in one transaction committed by `peer` without a timestamp
+    fn run_on(&self, doc: &LoroDoc, version: u32, peer: PeerID) -> LoroResult<()> {
+        let old_peer = doc.peer_id();
+        let record_timestamp = doc.config().record_timestamp();
+        doc.set_peer_id(peer)?;
+        doc.set_record_timestamp(false);
+        let result = doc.transact(|doc| {
+            (self.run)(doc)?;
+            doc.get_map(MIGRATIONS_ROOT)
+                .insert(&version.to_string(), LoroValue::from(self.name.as_str()))?;
+            doc.set_next_commit_message(&format!("Migrate to version {}", version));
+            Ok(())
+        });
+        doc.set_record_timestamp(record_timestamp);
+        doc.set_peer_id(old_peer)?;
+        result
+    }
+}
+
+/// The parts of a change that must be the same wherever a migration runs.
+///
+/// The commit message and the timestamp may be annotated by the pre-commit hooks.
+#[derive(PartialEq)]
+struct ChangeDigest {
+    id: ID,
+    deps: Vec<ID>,
+    lamport: Lamport,
+    ops: serde_json::Value,
+}
+
+impl ChangeDigest {
+    fn new(change: &JsonChange) -> Self {
+        Self {
+            id: change.id,
+            deps: change.deps.clone(),
+            lamport: change.lamport,
+            ops: serde_json::to_value(&change.ops).unwrap(),
+        }
+    }
+}
+
+/// A peer id that only depends on the version and the name of the migration (FNV-1a)
+fn migration_peer(version: u32, name: &str) -> PeerID {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in version.to_le_bytes().iter().chain(name.as_bytes()) {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    // `PeerID::MAX` is reserved
+    hash.min(PeerID::MAX - 1)
+}
@@ -0,0 +1,441 @@
+use std::collections::BTreeMap;
+
+use loro_internal::{event::Index, LoroError, LoroResult, LoroSchemaError, LoroValue};
+
+use super::{describe, wrong_type};
+use crate::{
+    event::{Diff, DiffEvent, ListDiffItem},
+    Container, LoroDoc, LoroMap, LoroTree, ValueOrContainer,
+};
+
+/// The expected shape of a value or a container
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    /// Anything
+    Any,
+    /// The null value
+    Null,
+    /// A bool value
+    Bool,
+    /// A number value, either an integer or a double
+    Number,
+    /// A string value
+    String,
+    /// A binary value
+    Binary,
+    /// A [`LoroText`](crate::LoroText)
+    Text,
+    /// A counter container
+    Counter,
+    /// A [`LoroMap`] with the given entries
+    Map(MapSchema),
+    /// A [`LoroList`](crate::LoroList) whose elements match the schema
+    List(Box<Schema>),
+    /// A [`LoroMovableList`](crate::LoroMovableList) whose elements match the schema
+    MovableList(Box<Schema>),
+    /// A [`LoroTree`] whose node metadata match the schema
+    Tree(Box<Schema>),
+    /// Any of the schemas
+    OneOf(Vec<Schema>),
+}
+
+impl Schema {
+    /// A [`LoroList`](crate::LoroList) whose elements match the schema
+    pub fn list(element: Schema) -> Self {
+        Schema::List(Box::new(element))
+    }
+
+    /// A [`LoroMovableList`](crate::LoroMovableList) whose elements match the schema
+    pub fn movable_list(element: Schema) -> Self {
+        Schema::MovableList(Box::new(element))
+    }
+
+    /// A [`LoroTree`] whose node metadata match the schema
+    pub fn tree(meta: MapSchema) -> Self {
+        Schema::Tree(Box::new(Schema::Map(meta)))
+    }
+
+    fn expected(&self) -> String {
+        match self {
+            Schema::Any => "anything".to_string(),
+            Schema::Null => "null".to_string(),
+            Schema::Bool => "a bool".to_string(),
+            Schema::Number => "a number".to_string(),
+            Schema::String => "a string".to_string(),
+            Schema::Binary => "a binary".to_string(),
+            Schema::Text => "a Text container".to_string(),
+            Schema::Counter => "a Counter container".to_string(),
+            Schema::Map(_) => "a Map container".to_string(),
+            Schema::List(_) => "a List container".to_string(),
+            Schema::MovableList(_) => "a MovableList container".to_string(),
+            Schema::Tree(_) => "a Tree container".to_string(),
+            Schema::OneOf(schemas) => format!(
+                "one of ({})",
+                schemas
+                    .iter()
+                    .map(|s| s.expected())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    /// Check the value or the container deeply
+    fn check(&self, value: &ValueOrContainer, path: &str) -> Result<(), LoroSchemaError> {
+        let mismatch = || {
+            wrong_type(
+                path,
+                format!("expected {}, found {}", self.expected(), describe(value)),
+            )
+        };
+        match (self, value) {
+            (Schema::Any, _) => Ok(()),
+            (Schema::OneOf(schemas), value) => {
+                if schemas.iter().any(|s| s.check(value, path).is_ok()) {
+                    Ok(())
+                } else {
+                    Err(mismatch())
+                }
+            }
+            (Schema::Null, ValueOrContainer::Value(LoroValue::Null))
+            | (Schema::Bool, ValueOrContainer::Value(LoroValue::Bool(_)))
+            | (Schema::Number, ValueOrContainer::Value(LoroValue::Double(_)))
+            | (Schema::Number, ValueOrContainer::Value(LoroValue::I64(_)))
+            | (Schema::String, ValueOrContainer::Value(LoroValue::String(_)))
+            | (Schema::Binary, ValueOrContainer::Value(LoroValue::Binary(_)))
+            | (Schema::Text, ValueOrContainer::Container(Container::Text(_))) => Ok(()),
+            #[cfg(feature = "counter")]
+            (Schema::Counter, ValueOrContainer::Container(Container::Counter(_))) => Ok(()),
+            (Schema::Map(schema), ValueOrContainer::Container(Container::Map(map))) => {
+                schema.check_map(map, path)
+            }
+            (Schema::List(element), ValueOrContainer::Container(Container::List(list))) => {
+                for i in 0..list.len() {
+                    element.check(&list.get(i).unwrap(), &child_path(path, i))?;
+                }
+                Ok(())
+            }
+            (
+                Schema::MovableList(element),
+                ValueOrContainer::Container(Container::MovableList(list)),
+            ) => {
+                for i in 0..list.len() {
+                    element.check(&list.get(i).unwrap(), &child_path(path, i))?;
+                }
+                Ok(())
+            }
+            (Schema::Tree(meta), ValueOrContainer::Container(Container::Tree(tree))) => {
+                check_tree(meta, tree, path)
+            }
+            _ => Err(mismatch()),
+        }
+    }
+
+    /// Check a diff of the container at the path.
+    ///
+    /// The new values and containers are checked deeply, the removed keys are checked against
+    /// the required keys.
+    fn check_diff(&self, diff: &Diff, path: &str) -> Result<(), LoroSchemaError> {
+        match (self, diff) {
+            (Schema::Any | Schema::OneOf(_), _) => Ok(()),
+            (Schema::Map(schema), Diff::Map(delta)) => {
+                for (key, value) in delta.updated.iter() {
+                    match value {
+                        Some(value) => schema.check_entry(key, value, path)?,
+                        None => {
+                            if schema.is_required(key) {
+                                return Err(LoroSchemaError::MissingField {
+                                    path: child_path(path, key).into(),
+                                });
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+            (Schema::List(element) | Schema::MovableList(element), Diff::List(items)) => {
+                let mut index = 0;
+                for item in items {
+                    match item {
+                        ListDiffItem::Retain { retain } => index += retain,
+                        ListDiffItem::Delete { .. } => {}
+                        ListDiffItem::Insert { insert, .. } => {
+                            for value in insert {
+                                element.check(value, &child_path(path, index))?;
+                                index += 1;
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+            (Schema::Text, Diff::Text(_)) | (Schema::Tree(_), Diff::Tree(_)) => Ok(()),
+            #[cfg(feature = "counter")]
+            (Schema::Counter, Diff::Counter(_)) => Ok(()),
+            (_, diff) => Err(wrong_type(
+                path,
+                format!(
+                    "expected {}, found a {} container",
+                    self.expected(),
+                    diff_kind(diff)
+                ),
+            )),
+        }
+    }
+
+    /// The schema of the child at the index
+    fn child(&self, index: &Index) -> Option<&Schema> {
+        match (self, index) {
+            (Schema::Map(schema), Index::Key(key)) => schema.entry(key),
+            (Schema::List(element) | Schema::MovableList(element), Index::Seq(_)) => Some(element),
+            (Schema::Tree(meta), Index::Node(_)) => Some(meta),
+            // There is no constraint, or the mismatch is reported by the diff of the parent
+            _ => None,
+        }
+    }
+}
+
+fn diff_kind(diff: &Diff) -> &'static str {
+    match diff {
+        Diff::List(_) => "List",
+        Diff::Text(_) => "Text",
+        Diff::Map(_) => "Map",
+        Diff::Tree(_) => "Tree",
+        #[cfg(feature = "counter")]
+        Diff::Counter(_) => "Counter",
+        Diff::Unknown => "Unknown",
+    }
+}
+
+fn child_path(path: &str, child: impl std::fmt::Display) -> String {
+    format!("{}.{}", path, child)
+}
+
+fn check_tree(meta: &Schema, tree: &LoroTree, path: &str) -> Result<(), LoroSchemaError> {
+    for node in tree.nodes() {
+        if tree.is_node_deleted(&node).unwrap_or(true) {
+            continue;
+        }
+
+        let map = tree.get_meta(node)?;
+        meta.check(
+            &ValueOrContainer::Container(Container::Map(map)),
+            &child_path(path, node),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The expected entries of a [`LoroMap`].
+///
+/// The keys that are not listed are allowed and can be anything by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapSchema {
+    entries: BTreeMap<String, (Schema, bool)>,
+    others: Option<Box<Schema>>,
+}
+
+impl Default for MapSchema {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            others: Some(Box::new(Schema::Any)),
+        }
+    }
+}
+
+impl MapSchema {
+    /// A map schema without entries, which allows any key
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The key must exist and match the schema
+    pub fn required(mut self, key: &str, schema: Schema) -> Self {
+        self.entries.insert(key.to_string(), (schema, true));
+        self
+    }
+
+    /// The key may be missing. If it exists, it must match the schema.
+    pub fn optional(mut self, key: &str, schema: Schema) -> Self {
+        self.entries.insert(key.to_string(), (schema, false));
+        self
+    }
+
+    /// The schema of the keys that are not listed
+    pub fn others(mut self, schema: Schema) -> Self {
+        self.others = Some(Box::new(schema));
+        self
+    }
+
+    /// Reject the keys that are not listed
+    pub fn deny_others(mut self) -> Self {
+        self.others = None;
+        self
+    }
+
+    fn entry(&self, key: &str) -> Option<&Schema> {
+        match self.entries.get(key) {
+            Some((schema, _)) => Some(schema),
+            None => self.others.as_deref(),
+        }
+    }
+
+    fn is_required(&self, key: &str) -> bool {
+        self.entries
+            .get(key)
+            .map_or(false, |(_, required)| *required)
+    }
+
+    fn check_entry(
+        &self,
+        key: &str,
+        value: &ValueOrContainer,
+        path: &str,
+    ) -> Result<(), LoroSchemaError> {
+        match self.entry(key) {
+            Some(schema) => schema.check(value, &child_path(path, key)),
+            None => Err(wrong_type(
+                &child_path(path, key),
+                "the key is not allowed by the schema",
+            )),
+        }
+    }
+
+    fn check_map(&self, map: &LoroMap, path: &str) -> Result<(), LoroSchemaError> {
+        for (key, (_, required)) in self.entries.iter() {
+            if *required && map.get(key).is_none() {
+                return Err(LoroSchemaError::MissingField {
+                    path: child_path(path, key).into(),
+                });
+            }
+        }
+
+        let mut ans = Ok(());
+        map.for_each(|key, value| {
+            if ans.is_ok() {
+                ans = self.check_entry(key, &value.into(), path);
+            }
+        });
+        ans
+    }
+}
+
+/// The expected root containers of a document.
+///
+/// The root containers that are not listed are not checked.
+///
+/// # Example
+///
+/// ```
+/// # use loro::{LoroDoc, LoroSchemaError, schema::{DocSchema, MapSchema, Schema}};
+/// let schema = DocSchema::new().root(
+///     "settings",
+///     Schema::Map(
+///         MapSchema::new()
+///             .required("theme", Schema::String)
+///             .optional("font_size", Schema::Number),
+///     ),
+/// );
+///
+/// let doc = LoroDoc::new();
+/// let settings = doc.get_map("settings");
+/// assert!(matches!(
+///     schema.validate_doc(&doc),
+///     Err(LoroSchemaError::MissingField { .. })
+/// ));
+/// settings.insert("theme", "dark").unwrap();
+/// settings.insert("font_size", "large").unwrap();
+/// assert!(matches!(
+///     schema.validate_doc(&doc),
+///     Err(LoroSchemaError::WrongType { .. })
+/// ));
+/// settings.insert("font_size", 14).unwrap();
+/// assert!(schema.validate_doc(&doc).is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DocSchema {
+    roots: BTreeMap<String, Schema>,
+}
+
+impl DocSchema {
+    /// A document schema without root containers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The root container with the name must match the schema.
+    ///
+    /// The schema must be a container schema.
+    pub fn root(mut self, name: &str, schema: Schema) -> Self {
+        self.roots.insert(name.to_string(), schema);
+        self
+    }
+
+    /// Check the whole document against the schema
+    pub fn validate_doc(&self, doc: &LoroDoc) -> Result<(), LoroSchemaError> {
+        for (name, schema) in self.roots.iter() {
+            let root = get_root(doc, name, schema)?;
+            schema.check(&ValueOrContainer::Container(root), name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check the changes in the event against the schema.
+    ///
+    /// The inserted values and containers are checked deeply, so it can be used to check the
+    /// remote changes after importing them. The diffs of the containers that are not covered by
+    /// the schema are skipped.
+    pub fn validate_event(&self, event: &DiffEvent) -> Result<(), LoroSchemaError> {
+        for container_diff in event.events.iter() {
+            let Some(((_, Index::Key(name)), rest)) = container_diff.path.split_first() else {
+                continue;
+            };
+            let Some(mut schema) = self.roots.get(name.as_str()) else {
+                continue;
+            };
+
+            let mut path = name.to_string();
+            let mut covered = true;
+            for (_, index) in rest {
+                match schema.child(index) {
+                    Some(child) => schema = child,
+                    None => {
+                        covered = false;
+                        break;
+                    }
+                }
+                path = match index {
+                    Index::Key(key) => child_path(&path, key),
+                    Index::Seq(i) => child_path(&path, i),
+                    Index::Node(node) => child_path(&path, node),
+                };
+            }
+
+            if covered {
+                schema.check_diff(&container_diff.diff, &path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn get_root(doc: &LoroDoc, name: &str, schema: &Schema) -> LoroResult<Container> {
+    Ok(match schema {
+        Schema::Map(_) => Container::Map(doc.get_map(name)),
+        Schema::List(_) => Container::List(doc.get_list(name)),
+        Schema::MovableList(_) => Container::MovableList(doc.get_movable_list(name)),
+        Schema::Text => Container::Text(doc.get_text(name)),
+        Schema::Tree(_) => Container::Tree(doc.get_tree(name)),
+        #[cfg(feature = "counter")]
+        Schema::Counter => Container::Counter(doc.get_counter(name)),
+        _ => {
+            return Err(LoroError::ArgErr(
+                format!("The schema of the root \"{}\" is not a container", name).into(),
+            ))
+        }
+    })
+}
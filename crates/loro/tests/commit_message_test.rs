@@ -1,4 +1,14 @@
-use loro::{CommitOptions, LoroDoc, VersionVector, ID};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use loro::{
+    json::{JsonOpContent, MapOp},
+    CommitOptions, ContainerID, ContainerType, ExportMode, LoroDoc, LoroError, LoroList, LoroMap,
+    LoroText, ToJson, TreeParentId, VersionVector, ID,
+};
+use serde_json::json;
 
 #[test]
 fn test_commit_message() {
@@ -156,3 +166,394 @@ fn test_commit_message_json_updates() {
     let text2 = doc2.get_text("text");
     assert_eq!(text2.to_string(), "hello world");
 }
+
+fn count_events(doc: &LoroDoc) -> (Arc<AtomicUsize>, loro::Subscription) {
+    let events = Arc::new(AtomicUsize::new(0));
+    let events_clone = events.clone();
+    let sub = doc.subscribe_root(Arc::new(move |_| {
+        events_clone.fetch_add(1, Ordering::SeqCst);
+    }));
+    (events, sub)
+}
+
+#[test]
+fn error_rolls_back_the_edits() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.get_text("text").insert(0, "Hello")?;
+    doc.get_map("map").insert("a", 1)?;
+    doc.commit();
+    let before = doc.get_deep_value().to_json_value();
+    let vv = doc.oplog_vv();
+    let (events, _sub) = count_events(&doc);
+
+    let ans: anyhow::Result<()> = doc.transact(|doc| {
+        let text = doc.get_text("text");
+        text.insert(5, " world")?;
+        text.mark(0..5, "bold", true)?;
+        let map = doc.get_map("map");
+        map.delete("a")?;
+        let list = map.insert_container("list", LoroList::new())?;
+        list.push_container(LoroText::new())?.insert(0, "nested")?;
+        doc.get_movable_list("movable").push(1)?;
+        let tree = doc.get_tree("tree");
+        let node = tree.create(TreeParentId::Root)?;
+        tree.get_meta(node)?.insert("title", "node")?;
+        assert_eq!(text.to_string(), "Hello world");
+        anyhow::bail!("validation failed")
+    });
+
+    assert_eq!(ans.unwrap_err().to_string(), "validation failed");
+    let mut expected = before;
+    expected["movable"] = json!([]);
+    expected["tree"] = json!([]);
+    assert_eq!(doc.get_deep_value().to_json_value(), expected);
+    assert_eq!(doc.oplog_vv(), vv);
+    assert_eq!(doc.oplog_frontiers(), doc.state_frontiers());
+    assert_eq!(events.load(Ordering::SeqCst), 0);
+
+    // The doc is still editable and the richtext state is intact
+    let text = doc.get_text("text");
+    text.insert(5, "!")?;
+    doc.commit();
+    assert_eq!(
+        text.to_delta().to_json_value(),
+        json!([{"insert": "Hello!"}])
+    );
+    assert_eq!(events.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[test]
+fn success_commits_one_change() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let (events, _sub) = count_events(&doc);
+    let updates = Arc::new(AtomicUsize::new(0));
+    let updates_clone = updates.clone();
+    let _update_sub = doc.subscribe_local_update(Box::new(move |_| {
+        updates_clone.fetch_add(1, Ordering::SeqCst);
+        true
+    }));
+
+    let len = doc.transact(|doc| -> Result<usize, LoroError> {
+        let map = doc.get_map("map");
+        map.insert("a", 1)?;
+        let child = map.insert_container("child", LoroMap::new())?;
+        child.insert("b", 2)?;
+        Ok(map.len())
+    })?;
+
+    assert_eq!(len, 2);
+    assert_eq!(events.load(Ordering::SeqCst), 1);
+    assert_eq!(updates.load(Ordering::SeqCst), 1);
+    assert_eq!(doc.len_changes(), 1);
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"map": {"a": 1, "child": {"b": 2}}})
+    );
+    Ok(())
+}
+
+#[test]
+fn pending_changes_are_committed_first() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "kept")?;
+    let ans: Result<(), LoroError> = doc.transact(|_| {
+        text.insert(0, "dropped ")?;
+        Err(LoroError::ArgErr("invalid".into()))
+    });
+    assert!(ans.is_err());
+    assert_eq!(text.to_string(), "kept");
+
+    let replica = LoroDoc::new();
+    replica.import(&doc.export(ExportMode::all_updates())?)?;
+    assert_eq!(replica.get_text("text").to_string(), "kept");
+    Ok(())
+}
+
+#[test]
+fn rolled_back_ids_are_reused() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let replica = LoroDoc::new();
+    for i in 0..4 {
+        let ans: Result<(), LoroError> = doc.transact(|doc| {
+            let list = doc.get_list("list");
+            list.push(i)?;
+            list.push_container(LoroMap::new())?.insert("i", i)?;
+            if i % 2 == 1 {
+                return Err(LoroError::ArgErr("odd".into()));
+            }
+            Ok(())
+        });
+        assert_eq!(ans.is_ok(), i % 2 == 0);
+        let vv = replica.oplog_vv();
+        replica.import(&doc.export(ExportMode::updates(&vv))?)?;
+    }
+
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"list": [0, {"i": 0}, 2, {"i": 2}]})
+    );
+    assert_eq!(
+        replica.get_deep_value().to_json_value(),
+        doc.get_deep_value().to_json_value()
+    );
+    Ok(())
+}
+
+#[test]
+fn committing_inside_the_closure_keeps_it_atomic() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let other = LoroDoc::new();
+    other.get_text("text").insert(0, "other")?;
+    let updates = other.export(ExportMode::all_updates())?;
+    let (events, _sub) = count_events(&doc);
+    let ans: Result<(), LoroError> = doc.transact(|doc| {
+        let text = doc.get_text("text");
+        text.insert(0, "Hello")?;
+        doc.commit();
+        assert_eq!(doc.import(&updates), Err(LoroError::TransactionInProgress));
+        assert_eq!(
+            doc.checkout(&Default::default()),
+            Err(LoroError::TransactionInProgress)
+        );
+        assert_eq!(doc.set_peer_id(1), Err(LoroError::TransactionInProgress));
+        assert_eq!(
+            doc.transact(|_| Ok::<_, LoroError>(())),
+            Err(LoroError::TransactionInProgress)
+        );
+        text.insert(5, " world")?;
+        Err(LoroError::ArgErr("invalid".into()))
+    });
+    assert!(ans.is_err());
+    assert_eq!(doc.get_text("text").to_string(), "");
+    assert!(doc.oplog_vv().is_empty());
+    assert_eq!(events.load(Ordering::SeqCst), 0);
+
+    // The doc works as usual after the transaction
+    doc.import(&updates)?;
+    assert_eq!(doc.get_text("text").to_string(), "other");
+    Ok(())
+}
+
+fn count_events_and_updates(
+    doc: &LoroDoc,
+) -> (Arc<AtomicUsize>, Arc<AtomicUsize>, Vec<loro::Subscription>) {
+    let events = Arc::new(AtomicUsize::new(0));
+    let updates = Arc::new(AtomicUsize::new(0));
+    let events_clone = events.clone();
+    let updates_clone = updates.clone();
+    let subs = vec![
+        doc.subscribe_root(Arc::new(move |_| {
+            events_clone.fetch_add(1, Ordering::SeqCst);
+        })),
+        doc.subscribe_local_update(Box::new(move |_| {
+            updates_clone.fetch_add(1, Ordering::SeqCst);
+            true
+        })),
+    ];
+    (events, updates, subs)
+}
+
+#[test]
+fn inspect_and_annotate_the_commit() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let seen_clone = seen.clone();
+    let _sub = doc.subscribe_pre_commit(Box::new(move |pre_commit| {
+        let keys: Vec<String> = pre_commit
+            .ops()
+            .iter()
+            .filter_map(|op| match &op.content {
+                JsonOpContent::Map(MapOp::Insert { key, .. }) => Some(key.clone()),
+                _ => None,
+            })
+            .collect();
+        seen_clone.lock().unwrap().push((
+            pre_commit.id(),
+            pre_commit.origin().to_string(),
+            pre_commit.message().map(|x| x.to_string()),
+            keys,
+        ));
+        let msg = format!("{} ops", pre_commit.ops().len());
+        pre_commit.set_message(&msg);
+        pre_commit.set_timestamp(1000);
+        true
+    }));
+
+    let map = doc.get_map("map");
+    map.insert("a", 1)?;
+    map.insert("b", 2)?;
+    doc.commit_with(CommitOptions::new().origin("app").commit_msg("edit"));
+    assert_eq!(
+        seen.lock().unwrap().as_slice(),
+        &[(
+            ID::new(1, 0),
+            "app".to_string(),
+            Some("edit".to_string()),
+            vec!["a".to_string(), "b".to_string()]
+        )]
+    );
+
+    let change = doc.get_change(ID::new(1, 0)).unwrap();
+    assert_eq!(change.message(), "2 ops");
+    assert_eq!(change.timestamp, 1000);
+
+    // Empty transactions are not inspected
+    doc.commit();
+    assert_eq!(seen.lock().unwrap().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn abort_rolls_back_the_transaction() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let locked = ContainerID::new_root("locked", ContainerType::Map);
+    let _sub = doc.subscribe_pre_commit(Box::new(move |pre_commit| {
+        if pre_commit.ops().iter().any(|op| op.container == locked) {
+            pre_commit.abort();
+        }
+        true
+    }));
+
+    let text = doc.get_text("text");
+    text.insert(0, "Hello")?;
+    doc.commit();
+    let (events, updates, _subs) = count_events_and_updates(&doc);
+    let vv = doc.oplog_vv();
+    let frontiers = doc.state_frontiers();
+
+    text.insert(5, " world")?;
+    text.delete(0, 1)?;
+    let child = doc
+        .get_map("map")
+        .insert_container("child", LoroMap::new())?;
+    child.insert("x", 1)?;
+    doc.get_map("locked").insert("y", 2)?;
+    assert_eq!(text.to_string(), "ello world");
+    doc.commit();
+
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"text": "Hello", "map": {}, "locked": {}})
+    );
+    assert_eq!(doc.oplog_vv(), vv);
+    assert_eq!(doc.state_frontiers(), frontiers);
+    assert_eq!(doc.oplog_frontiers(), frontiers);
+    assert_eq!(events.load(Ordering::SeqCst), 0);
+    assert_eq!(updates.load(Ordering::SeqCst), 0);
+
+    // The ids of the aborted ops are reused
+    let list = doc
+        .get_map("map")
+        .insert_container("child", loro::LoroList::new())?;
+    list.push("z")?;
+    text.insert(5, "!")?;
+    doc.commit();
+    assert_eq!(events.load(Ordering::SeqCst), 1);
+    assert_eq!(updates.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"text": "Hello!", "map": {"child": ["z"]}, "locked": {}})
+    );
+
+    let replica = LoroDoc::new();
+    replica.import(&doc.export(ExportMode::all_updates())?)?;
+    assert_eq!(
+        replica.get_deep_value().to_json_value(),
+        doc.get_deep_value().to_json_value()
+    );
+    assert_eq!(replica.oplog_vv(), doc.oplog_vv());
+    Ok(())
+}
+
+#[test]
+fn stop_calling_the_hooks_after_abort() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls_clone = calls.clone();
+    let _first = doc.subscribe_pre_commit(Box::new(|pre_commit| {
+        pre_commit.abort();
+        true
+    }));
+    let _second = doc.subscribe_pre_commit(Box::new(move |_| {
+        calls_clone.fetch_add(1, Ordering::SeqCst);
+        true
+    }));
+    doc.get_text("text").insert(0, "a")?;
+    doc.commit();
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    assert!(doc.oplog_vv().is_empty());
+    Ok(())
+}
+
+#[test]
+fn unsubscribe_pre_commit_hooks() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls_clone = calls.clone();
+    let _once = doc.subscribe_pre_commit(Box::new(move |_| {
+        calls_clone.fetch_add(1, Ordering::SeqCst);
+        false
+    }));
+    let sub = doc.subscribe_pre_commit(Box::new(|pre_commit| {
+        pre_commit.abort();
+        true
+    }));
+    let text = doc.get_text("text");
+    text.insert(0, "a")?;
+    doc.commit();
+    assert_eq!(text.to_string(), "");
+
+    sub.unsubscribe();
+    text.insert(0, "b")?;
+    doc.commit();
+    assert_eq!(text.to_string(), "b");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[test]
+fn abort_restores_the_containers_of_a_shallow_doc() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    text.mark(0..5, "bold", true)?;
+    doc.get_movable_list("list").push(1)?;
+    doc.get_tree("tree").create(None)?;
+    doc.commit();
+    text.insert(11, "!")?;
+    doc.get_movable_list("list").push(2)?;
+    doc.commit();
+
+    let shallow = LoroDoc::new();
+    shallow.import(&doc.export(ExportMode::shallow_snapshot(&doc.oplog_frontiers()))?)?;
+    shallow.get_map("map").insert("x", 1)?;
+    shallow.commit();
+    let value = shallow.get_deep_value();
+    let delta = shallow.get_text("text").to_delta().to_json_value();
+    let _sub = shallow.subscribe_pre_commit(Box::new(|pre_commit| {
+        pre_commit.abort();
+        true
+    }));
+
+    let text = shallow.get_text("text");
+    text.delete(0, 6)?;
+    text.mark(0..3, "italic", true)?;
+    let list = shallow.get_movable_list("list");
+    list.mov(0, 1)?;
+    list.set(0, 3)?;
+    let tree = shallow.get_tree("tree");
+    tree.delete(tree.roots()[0])?;
+    shallow.get_map("map").insert("x", 2)?;
+    shallow.commit();
+
+    assert_eq!(shallow.get_deep_value(), value);
+    assert_eq!(shallow.get_text("text").to_delta().to_json_value(), delta);
+    assert_eq!(shallow.oplog_frontiers(), shallow.state_frontiers());
+    Ok(())
+}
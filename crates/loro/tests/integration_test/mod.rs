@@ -4,8 +4,10 @@ mod detached_editing_test;
 #[cfg(feature = "jsonpath")]
 mod jsonpath_test;
 mod redact_test;
+mod serde_test;
 mod shallow_snapshot_test;
 mod snapshot_at_test;
+mod subscription_test;
 mod text_update_test;
 mod undo_test;
 
@@ -0,0 +1,1033 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use super::gen_action;
+#[cfg(feature = "derive")]
+use loro::{schema::LoroSchema, LoroSchema};
+use loro::{
+    schema::{DocSchema, MapSchema, Migrator, Schema, MIGRATIONS_ROOT},
+    Container, ExportMode, JsonArrayPolicy, JsonPatchError, JsonPatchOp, JsonPolicy,
+    JsonStringPolicy, LoroDoc, LoroError, LoroList, LoroMap, LoroSchemaError, LoroSerdeError,
+    LoroText, TextPatchStyle, ToJson, TreeParentId, ValueOrContainer,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Item {
+    name: String,
+    done: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Theme {
+    Light,
+    Dark,
+    Custom { accent: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Settings {
+    theme: Theme,
+    font_size: u32,
+    ratio: f64,
+    items: Vec<Item>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+}
+
+fn settings() -> Settings {
+    Settings {
+        theme: Theme::Dark,
+        font_size: 14,
+        ratio: 1.5,
+        items: vec![
+            Item {
+                name: "a".into(),
+                done: false,
+            },
+            Item {
+                name: "b".into(),
+                done: true,
+            },
+        ],
+        note: Some("hello".into()),
+    }
+}
+
+#[test]
+fn read_containers() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let map = doc.get_map("settings");
+    map.insert("theme", "Light")?;
+    map.insert("font_size", 12)?;
+    // Integral doubles can be read as integers and the other way around
+    map.insert("ratio", 2)?;
+    let items = map.insert_container("items", LoroList::new())?;
+    let item = items.push_container(LoroMap::new())?;
+    item.insert("name", "x")?;
+    item.insert("done", true)?;
+    map.insert_container("note", LoroText::new())?
+        .insert(0, "note")?;
+
+    let settings: Settings = map.read_struct()?;
+    assert_eq!(
+        settings,
+        Settings {
+            theme: Theme::Light,
+            font_size: 12,
+            ratio: 2.0,
+            items: vec![Item {
+                name: "x".into(),
+                done: true
+            }],
+            note: Some("note".into()),
+        }
+    );
+    assert_eq!(Vec::<Item>::deserialize(&items)?, settings.items);
+    assert_eq!(String::deserialize(&doc.get_text("note"))?, "");
+
+    let tree = doc.get_tree("tree");
+    let root = tree.create(TreeParentId::Root)?;
+    tree.get_meta(root)?.insert("title", "root")?;
+    let child = tree.create(root)?;
+    tree.get_meta(child)?.insert("title", "child")?;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Meta {
+        title: String,
+    }
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Node {
+        meta: Meta,
+        children: Vec<Node>,
+    }
+    let nodes = Vec::<Node>::deserialize(&tree)?;
+    assert_eq!(
+        nodes,
+        vec![Node {
+            meta: Meta {
+                title: "root".into()
+            },
+            children: vec![Node {
+                meta: Meta {
+                    title: "child".into()
+                },
+                children: vec![]
+            }]
+        }]
+    );
+
+    let err = map.read_struct::<Item>().unwrap_err();
+    assert!(matches!(err, LoroSerdeError::Message(_)));
+    Ok(())
+}
+
+#[test]
+fn write_struct_round_trip() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let map = doc.get_map("settings");
+    let mut settings = settings();
+    settings.theme = Theme::Custom {
+        accent: "red".into(),
+    };
+    doc.write_struct(&map, &settings)?;
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"settings": {
+            "theme": {"Custom": {"accent": "red"}},
+            "font_size": 14,
+            "ratio": 1.5,
+            "items": [{"name": "a", "done": false}, {"name": "b", "done": true}],
+            "note": "hello",
+        }})
+    );
+    // Nested structs and lists become child containers
+    assert!(map.get("items").unwrap().into_container().is_ok());
+    assert_eq!(map.read_struct::<Settings>()?, settings);
+
+    assert!(matches!(
+        doc.write_struct(&map, &vec![1, 2]),
+        Err(LoroSerdeError::Message(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn write_struct_only_changes_the_diff() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let map = doc.get_map("settings");
+    let mut settings = settings();
+    doc.write_struct(&map, &settings)?;
+    doc.commit();
+    let ops = doc.len_ops();
+
+    // Writing the same value again is a no-op
+    doc.write_struct(&map, &settings)?;
+    doc.commit();
+    assert_eq!(doc.len_ops(), ops);
+
+    // A field of an item in the list
+    settings.items[1].done = false;
+    doc.write_struct(&map, &settings)?;
+    doc.commit();
+    assert_eq!(doc.len_ops(), ops + 1);
+
+    // A skipped field is deleted
+    settings.note = None;
+    doc.write_struct(&map, &settings)?;
+    doc.commit();
+    assert_eq!(doc.len_ops(), ops + 2);
+
+    // Appending to the list inserts the new item only
+    settings.items.push(Item {
+        name: "c".into(),
+        done: false,
+    });
+    doc.write_struct(&map, &settings)?;
+    doc.commit();
+    assert_eq!(doc.len_ops(), ops + 2 + 3);
+    assert_eq!(map.read_struct::<Settings>()?, settings);
+
+    // Removing an item deletes it
+    settings.items.remove(0);
+    doc.write_struct(&map, &settings)?;
+    doc.commit();
+    assert_eq!(doc.len_ops(), ops + 2 + 3 + 1);
+    assert_eq!(map.read_struct::<Settings>()?, settings);
+    Ok(())
+}
+
+#[test]
+fn write_struct_updates_text_containers() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let map = doc.get_map("settings");
+    let mut settings = settings();
+    doc.write_struct(&map, &settings)?;
+    let note = map.insert_container("note", LoroText::new())?;
+    note.insert(0, "hello")?;
+    doc.commit();
+    let ops = doc.len_ops();
+
+    settings.note = Some("hello world".into());
+    doc.write_struct(&map, &settings)?;
+    doc.commit();
+    // The text is kept and only the inserted text produces ops
+    assert_eq!(note.to_string(), "hello world");
+    assert!(map.get("note").unwrap().into_container().is_ok());
+    assert_eq!(doc.len_ops(), ops + " world".len());
+    Ok(())
+}
+
+#[test]
+fn write_struct_commits_one_change() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let map = doc.get_map("settings");
+    let events = Arc::new(AtomicUsize::new(0));
+    let events_clone = events.clone();
+    let _sub = doc.subscribe_root(Arc::new(move |_| {
+        events_clone.fetch_add(1, Ordering::SeqCst);
+    }));
+    doc.write_struct(&map, &settings())?;
+    assert_eq!(doc.len_changes(), 1);
+    assert_eq!(events.load(Ordering::SeqCst), 1);
+
+    // A value that is not a map writes nothing
+    assert!(doc.write_struct(&map, &1).is_err());
+    assert_eq!(doc.len_changes(), 1);
+    Ok(())
+}
+
+fn project() -> serde_json::Value {
+    json!({
+        "name": "Loro",
+        "stars": 1,
+        "tags": ["crdt", "rust"],
+        "owner": {"name": "Alice", "active": true, "score": 1.5},
+        "notes": [{"title": "a"}, {"title": "b"}],
+    })
+}
+
+#[test]
+fn import_json_value_into_roots() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.get_map("project").insert("old", 1)?;
+
+    let root = doc.import_json_value("project", project())?;
+    assert!(root.is_map());
+    // The old contents are replaced
+    assert_eq!(
+        doc.get_map("project").get_deep_value().to_json_value(),
+        project()
+    );
+
+    doc.import_json_value("list", json!([1, [2], {"a": 3}]))?;
+    assert_eq!(
+        doc.get_list("list").get_deep_value().to_json_value(),
+        json!([1, [2], {"a": 3}])
+    );
+    doc.import_json_value("text", json!("hello"))?;
+    assert_eq!(doc.get_text("text").to_string(), "hello");
+
+    let vv = doc.oplog_vv();
+    assert!(matches!(
+        doc.import_json_value("number", json!(1)),
+        Err(LoroError::ArgErr(_))
+    ));
+    assert_eq!(doc.oplog_vv(), vv);
+    Ok(())
+}
+
+#[test]
+fn json_policies() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let policy = JsonPolicy {
+        strings: JsonStringPolicy::Text,
+        arrays: JsonArrayPolicy::MovableList,
+    };
+    doc.import_json_value_with("project", project(), policy)?;
+    let map = doc.get_map("project");
+    assert_eq!(map.get_deep_value().to_json_value(), project());
+    assert!(matches!(
+        map.get("name"),
+        Some(ValueOrContainer::Container(Container::Text(_)))
+    ));
+    let tags = match map.get("tags") {
+        Some(ValueOrContainer::Container(Container::MovableList(tags))) => tags,
+        other => panic!("expected a movable list, found {:?}", other),
+    };
+    assert!(matches!(
+        tags.get(0),
+        Some(ValueOrContainer::Container(Container::Text(_)))
+    ));
+    assert!(matches!(map.get("stars"), Some(ValueOrContainer::Value(_))));
+
+    let root = doc.import_json_value_with("list", json!(["a"]), policy)?;
+    assert!(root.is_movable_list());
+
+    // The default policy stores the strings as values
+    map.set_from_json("name", json!("Loro"), JsonPolicy::default())?;
+    assert!(matches!(map.get("name"), Some(ValueOrContainer::Value(_))));
+    Ok(())
+}
+
+#[test]
+fn set_from_json_replaces_the_entry() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let map = doc.get_map("map");
+    map.set_from_json("project", project(), JsonPolicy::default())?;
+    let old = map.get("project").unwrap().into_container().unwrap();
+    map.set_from_json("project", project(), JsonPolicy::default())?;
+    let new = map.get("project").unwrap().into_container().unwrap();
+    assert_ne!(old.id(), new.id());
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"map": {"project": project()}})
+    );
+    Ok(())
+}
+
+#[test]
+fn update_from_json_emits_minimal_ops() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let policy = JsonPolicy {
+        strings: JsonStringPolicy::Text,
+        ..Default::default()
+    };
+    doc.import_json_value_with("project", project(), policy)?;
+    let map = doc.get_map("project");
+    let ops = doc.len_ops();
+
+    // The same value is a no-op
+    map.update_from_json(project(), policy)?;
+    doc.commit();
+    assert_eq!(doc.len_ops(), ops);
+
+    let mut value = project();
+    value["stars"] = json!(2);
+    value["name"] = json!("Loro!");
+    value["tags"] = json!(["crdt", "rust", "local-first"]);
+    value["owner"].as_object_mut().unwrap().remove("score");
+    value["notes"][1]["title"] = json!("bc");
+    map.update_from_json(value.clone(), policy)?;
+    doc.commit();
+    // stars, "!", the new tag and its text, the deleted score and "c"
+    assert_eq!(doc.len_ops(), ops + 1 + 1 + 1 + "local-first".len() + 1 + 1);
+    assert_eq!(map.get_deep_value().to_json_value(), value);
+
+    assert!(matches!(
+        map.update_from_json(json!([1]), policy),
+        Err(LoroError::ArgErr(_))
+    ));
+    Ok(())
+}
+
+fn record_patches(
+    doc: &LoroDoc,
+    style: TextPatchStyle,
+) -> (Arc<Mutex<Vec<JsonPatchOp>>>, loro::Subscription) {
+    let patches = Arc::new(Mutex::new(Vec::new()));
+    let patches_clone = patches.clone();
+    let sub = doc.subscribe_json_patch(
+        style,
+        Arc::new(move |patch| patches_clone.lock().unwrap().extend(patch)),
+    );
+    (patches, sub)
+}
+
+/// Remove the fields of the tree nodes that differ between the replicas
+fn without_node_ids(value: Value) -> Value {
+    match value {
+        Value::Object(obj) => obj
+            .into_iter()
+            .filter(|(k, _)| !matches!(k.as_str(), "id" | "parent" | "fractional_index"))
+            .map(|(k, v)| (k, without_node_ids(v)))
+            .collect(),
+        Value::Array(arr) => arr.into_iter().map(without_node_ids).collect(),
+        v => v,
+    }
+}
+
+fn sync_by_patches(style: TextPatchStyle) -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let replica = LoroDoc::new();
+    let (patches, _sub) = record_patches(&doc, style);
+    for seed in 0..10 {
+        gen_action(&doc, seed, 20);
+        let map = doc.get_map("root");
+        let list = map.insert_container("nested", LoroList::new())?;
+        list.push("a")?;
+        let text = list.insert_container(0, LoroText::new())?;
+        text.insert(0, "abc")?;
+        doc.commit();
+        text.insert(1, "🦜")?;
+        text.delete(3, 1)?;
+        list.delete(1, 1)?;
+        doc.commit();
+
+        let patch = std::mem::take(&mut *patches.lock().unwrap());
+        replica.apply_json_patch(&patch)?;
+        assert_eq!(
+            replica.get_deep_value().to_json_value(),
+            doc.get_deep_value().to_json_value()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn sync_by_replace_patches() -> anyhow::Result<()> {
+    sync_by_patches(TextPatchStyle::Replace)
+}
+
+#[test]
+fn sync_by_splice_patches() -> anyhow::Result<()> {
+    sync_by_patches(TextPatchStyle::Splice)
+}
+
+#[test]
+fn encode_map_list_and_text_diffs() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let map = doc.get_map("map");
+    let text = doc.get_text("text");
+    doc.commit();
+    let (patches, _sub) = record_patches(&doc, TextPatchStyle::Splice);
+
+    map.insert("a/b", 1)?;
+    let list = map.insert_container("list", LoroList::new())?;
+    list.push(1)?;
+    doc.commit();
+    text.insert(0, "Hello")?;
+    doc.commit();
+    list.insert(0, 0)?;
+    list.delete(1, 1)?;
+    map.delete("a/b")?;
+    doc.commit();
+    text.delete(0, 1)?;
+    text.insert(0, "J")?;
+    doc.commit();
+
+    let patches = serde_json::to_value(&*patches.lock().unwrap())?;
+    assert_eq!(
+        patches,
+        json!([
+            { "op": "add", "path": "/map/a~1b", "value": 1 },
+            { "op": "add", "path": "/map/list", "value": [] },
+            { "op": "add", "path": "/map/list/0", "value": 1 },
+            { "op": "splice", "path": "/text", "pos": 0, "delete": 0, "insert": "Hello" },
+            { "op": "remove", "path": "/map/a~1b" },
+            { "op": "add", "path": "/map/list/0", "value": 0 },
+            { "op": "remove", "path": "/map/list/1" },
+            { "op": "splice", "path": "/text", "pos": 0, "delete": 1, "insert": "J" },
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn encode_and_apply_tree_diffs() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let tree = doc.get_tree("tree");
+    tree.enable_fractional_index(0);
+    let root = tree.create(TreeParentId::Root)?;
+    let child = tree.create(root)?;
+    doc.commit();
+
+    let replica = LoroDoc::new();
+    replica.import(&doc.export(loro::ExportMode::all_updates())?)?;
+    replica.get_tree("tree").enable_fractional_index(0);
+    let (patches, _sub) = record_patches(&doc, TextPatchStyle::Replace);
+
+    tree.get_meta(child)?.insert("title", "child")?;
+    doc.commit();
+    let other = tree.create(TreeParentId::Root)?;
+    tree.get_meta(other)?.insert("title", "other")?;
+    tree.mov(child, other)?;
+    doc.commit();
+    tree.delete(root)?;
+    doc.commit();
+
+    let patch = std::mem::take(&mut *patches.lock().unwrap());
+    assert!(
+        matches!(&patch[0], JsonPatchOp::Add { path, .. } if path == "/tree/0/children/0/meta/title")
+    );
+    replica.apply_json_patch(&patch)?;
+    assert_eq!(
+        without_node_ids(replica.get_deep_value().to_json_value()),
+        without_node_ids(doc.get_deep_value().to_json_value())
+    );
+    // The existing nodes are moved, not recreated
+    let replica_tree = replica.get_tree("tree");
+    assert!(!replica_tree.is_node_deleted(&child)?);
+    assert!(replica_tree.is_node_deleted(&root)?);
+    Ok(())
+}
+
+#[test]
+fn apply_json_patch() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc
+        .get_map("doc")
+        .insert_container("text", LoroText::new())?;
+    text.insert(0, "Hello")?;
+    let tree = doc.get_tree("tree");
+    tree.enable_fractional_index(0);
+    let a = tree.create(TreeParentId::Root)?;
+    let b = tree.create(TreeParentId::Root)?;
+    doc.commit();
+
+    let patch: Vec<JsonPatchOp> = serde_json::from_value(json!([
+        { "op": "splice", "path": "/doc/text", "pos": 5, "delete": 0, "insert": " world" },
+        { "op": "add", "path": "/doc/items", "value": [{ "name": "x" }] },
+        { "op": "add", "path": "/doc/items/-", "value": { "name": "y" } },
+        { "op": "replace", "path": "/doc/items/0/name", "value": "z" },
+        { "op": "copy", "from": "/doc/items/1", "path": "/doc/copied" },
+        { "op": "move", "from": "/tree/1", "path": "/tree/0/children/0" },
+        { "op": "add", "path": "/tree/0/children/0/meta/title", "value": "moved" },
+        { "op": "add", "path": "/list", "value": [1, 2] },
+        { "op": "test", "path": "/doc/copied/name", "value": "y" },
+    ]))?;
+    doc.apply_json_patch(&patch)?;
+
+    // The existing containers are edited in place
+    assert_eq!(text.to_string(), "Hello world");
+    assert_eq!(tree.parent(b), Some(a.into()));
+    assert_eq!(
+        tree.get_meta(b)?.get_deep_value().to_json_value(),
+        json!({ "title": "moved" })
+    );
+    assert_eq!(
+        doc.get_map("doc").get_deep_value().to_json_value(),
+        json!({
+            "text": "Hello world",
+            "items": [{ "name": "z" }, { "name": "y" }],
+            "copied": { "name": "y" },
+        })
+    );
+    assert_eq!(doc.get_list("list").len(), 2);
+    // The new objects and arrays become containers
+    let items = doc.get_map("doc").get("items");
+    let Some(ValueOrContainer::Container(Container::List(items))) = items else {
+        panic!("expected a list container, got {:?}", items);
+    };
+    assert!(matches!(
+        items.get(0),
+        Some(ValueOrContainer::Container(Container::Map(_)))
+    ));
+
+    let err = doc
+        .apply_json_patch(&[JsonPatchOp::Test {
+            path: "/doc/copied/name".into(),
+            value: "x".into(),
+        }])
+        .unwrap_err();
+    assert!(matches!(err, JsonPatchError::TestFailed(_)));
+    assert!(matches!(
+        doc.apply_json_patch(&[JsonPatchOp::Remove {
+            path: "/doc/missing".into()
+        }]),
+        Err(JsonPatchError::PathNotFound(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn failed_json_patch_is_rolled_back() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.get_map("doc").insert("name", "x")?;
+    doc.commit();
+    let vv = doc.oplog_vv();
+    let (patches, _sub) = record_patches(&doc, TextPatchStyle::Replace);
+
+    let err = doc
+        .apply_json_patch(&[
+            JsonPatchOp::Add {
+                path: "/doc/age".into(),
+                value: 1.into(),
+            },
+            JsonPatchOp::Test {
+                path: "/doc/name".into(),
+                value: "y".into(),
+            },
+        ])
+        .unwrap_err();
+    assert!(matches!(err, JsonPatchError::TestFailed(_)));
+    assert_eq!(doc.oplog_vv(), vv);
+    assert_eq!(
+        doc.get_map("doc").get_deep_value().to_json_value(),
+        json!({ "name": "x" })
+    );
+    assert!(patches.lock().unwrap().is_empty());
+
+    // The doc can still be edited after the rollback
+    doc.apply_json_patch(&[JsonPatchOp::Add {
+        path: "/doc/age".into(),
+        value: 2.into(),
+    }])?;
+    assert_eq!(
+        doc.get_map("doc").get_deep_value().to_json_value(),
+        json!({ "name": "x", "age": 2 })
+    );
+    Ok(())
+}
+
+#[test]
+fn new_containers_are_added_empty() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let (patches, _sub) = record_patches(&doc, TextPatchStyle::Replace);
+    let map = doc.get_map("map");
+    let child = map.insert_container("child", LoroMap::new())?;
+    child.insert("text", "plain")?;
+    let text = child.insert_container("rich", LoroText::new())?;
+    text.insert(0, "rich")?;
+    doc.commit();
+
+    // The content of the new containers follows in their own patches
+    let patches = serde_json::to_value(&*patches.lock().unwrap())?;
+    assert_eq!(
+        patches,
+        json!([
+            { "op": "add", "path": "/map", "value": {} },
+            { "op": "add", "path": "/map/child", "value": {} },
+            { "op": "add", "path": "/map/child/rich", "value": "" },
+            { "op": "add", "path": "/map/child/text", "value": "plain" },
+            { "op": "replace", "path": "/map/child/rich", "value": "rich" },
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn moved_containers_keep_their_values() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let list = doc.get_movable_list("list");
+    let first = list.insert_container(0, LoroMap::new())?;
+    first.insert("name", "first")?;
+    list.insert(1, "second")?;
+    doc.commit();
+
+    let replica = LoroDoc::new();
+    replica.import(&doc.export(loro::ExportMode::all_updates())?)?;
+    let (patches, _sub) = record_patches(&doc, TextPatchStyle::Replace);
+    list.mov(0, 1)?;
+    first.insert("age", 1)?;
+    doc.commit();
+
+    // The value of the moved container isn't in the diff, so it's read from the copy
+    let patch = std::mem::take(&mut *patches.lock().unwrap());
+    replica.apply_json_patch(&patch)?;
+    assert_eq!(
+        replica.get_deep_value().to_json_value(),
+        json!({ "list": ["second", { "name": "first", "age": 1 }] })
+    );
+    Ok(())
+}
+
+// The schema types are never constructed, only their handles are
+#[cfg(feature = "derive")]
+#[allow(dead_code)]
+#[derive(LoroSchema)]
+struct Person {
+    name: String,
+    avatar: LoroMap,
+}
+
+#[cfg(feature = "derive")]
+#[allow(dead_code)]
+#[derive(LoroSchema)]
+struct Task {
+    title: LoroText,
+    done: bool,
+    tags: LoroList,
+    #[loro(rename = "due_at")]
+    due: Option<i64>,
+    #[loro(nested)]
+    owner: Person,
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn attach_creates_nothing_until_written() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let task = Task::attach(doc.get_map("task"))?;
+    doc.commit();
+    assert!(doc.oplog_vv().is_empty());
+    assert!(task.title().is_none());
+    assert!(task.owner().avatar().is_none());
+    assert!(task.owner().map().is_none());
+    assert_eq!(
+        task.owner().name(),
+        Err(LoroSchemaError::MissingField {
+            path: "name".into()
+        })
+    );
+
+    task.get_or_create_title()?.insert(0, "Write docs")?;
+    task.get_or_create_tags()?.push("docs")?;
+    task.owner()
+        .get_or_create_avatar()?
+        .insert("url", "a.png")?;
+    assert_eq!(
+        task.done(),
+        Err(LoroSchemaError::MissingField {
+            path: "done".into()
+        })
+    );
+    assert_eq!(task.due()?, None);
+    task.set_done(true)?;
+    task.set_due(Some(100))?;
+    task.owner().set_name("Alice".to_string())?;
+    assert!(task.done()?);
+    assert_eq!(task.due()?, Some(100));
+    assert_eq!(task.owner().name()?, "Alice");
+    assert_eq!(task.title().unwrap().to_string(), "Write docs");
+    assert_eq!(task.map().unwrap().id(), doc.get_map("task").id());
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"task": {
+            "title": "Write docs",
+            "done": true,
+            "tags": ["docs"],
+            "due_at": 100,
+            "owner": {"name": "Alice", "avatar": {"url": "a.png"}},
+        }})
+    );
+    Ok(())
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn attach_reuses_the_remote_containers() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let task = Task::attach(doc.get_map("task"))?;
+    task.get_or_create_title()?.insert(0, "remote")?;
+    task.set_done(false)?;
+    doc.commit();
+
+    let replica = LoroDoc::new();
+    replica.import(&doc.export(ExportMode::all_updates())?)?;
+    let vv = replica.oplog_vv();
+    let task = Task::attach(replica.get_map("task"))?;
+    replica.commit();
+    assert_eq!(replica.oplog_vv(), vv);
+    assert_eq!(task.get_or_create_title()?.to_string(), "remote");
+    replica.commit();
+    assert_eq!(replica.oplog_vv(), vv);
+    assert!(!task.done()?);
+    Ok(())
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn wrong_types_are_rejected() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let map = doc.get_map("task");
+    map.insert("title", "not a text")?;
+    let err = Task::attach(map.clone()).unwrap_err();
+    assert_eq!(
+        err,
+        LoroSchemaError::WrongType {
+            path: "title".into(),
+            reason: "expected a Text container, found a string".into(),
+        }
+    );
+    // Nothing is created when the validation fails
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"task": {"title": "not a text"}})
+    );
+
+    map.insert_container("title", LoroText::new())?;
+    map.insert("done", "yes")?;
+    let err = Task::attach(map.clone()).unwrap_err();
+    assert!(matches!(err, LoroSchemaError::WrongType { ref path, .. } if &**path == "done"));
+
+    map.insert("done", true)?;
+    let owner = map.insert_container("owner", LoroMap::new())?;
+    owner.insert_container("avatar", LoroText::new())?;
+    let err = Task::validate(&map).unwrap_err();
+    assert_eq!(
+        err,
+        LoroSchemaError::WrongType {
+            path: "owner.avatar".into(),
+            reason: "expected a Map container, found a Text container".into(),
+        }
+    );
+
+    owner.insert_container("avatar", LoroMap::new())?;
+    Task::validate(&map)?;
+    Ok(())
+}
+
+fn todo_schema() -> DocSchema {
+    DocSchema::new().root(
+        "todos",
+        Schema::list(Schema::Map(
+            MapSchema::new()
+                .required("title", Schema::Text)
+                .optional("done", Schema::Bool)
+                .deny_others(),
+        )),
+    )
+}
+
+#[test]
+fn validate_doc() -> anyhow::Result<()> {
+    let schema = todo_schema();
+    let doc = LoroDoc::new();
+    schema.validate_doc(&doc)?;
+
+    let todos = doc.get_list("todos");
+    let todo = todos.push_container(LoroMap::new())?;
+    assert_eq!(
+        schema.validate_doc(&doc),
+        Err(LoroSchemaError::MissingField {
+            path: "todos.0.title".into()
+        })
+    );
+
+    todo.insert_container("title", LoroText::new())?;
+    todo.insert("done", "no")?;
+    assert_eq!(
+        schema.validate_doc(&doc),
+        Err(LoroSchemaError::WrongType {
+            path: "todos.0.done".into(),
+            reason: "expected a bool, found a string".into(),
+        })
+    );
+
+    todo.insert("done", false)?;
+    schema.validate_doc(&doc)?;
+    todo.insert("priority", 1)?;
+    assert_eq!(
+        schema.validate_doc(&doc),
+        Err(LoroSchemaError::WrongType {
+            path: "todos.0.priority".into(),
+            reason: "the key is not allowed by the schema".into(),
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn validate_remote_changes() -> anyhow::Result<()> {
+    let schema = Arc::new(todo_schema());
+    let doc = LoroDoc::new();
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let _sub = doc.subscribe_root({
+        let schema = schema.clone();
+        let results = results.clone();
+        Arc::new(move |event| {
+            results.lock().unwrap().push(schema.validate_event(&event));
+        })
+    });
+
+    let remote = LoroDoc::new();
+    let todo = remote.get_list("todos").push_container(LoroMap::new())?;
+    todo.insert_container("title", LoroText::new())?
+        .insert(0, "write tests")?;
+    // Not covered by the schema
+    remote.get_map("other").insert("anything", 1)?;
+    remote.commit();
+    doc.import(&remote.export(ExportMode::all_updates())?)?;
+    assert_eq!(results.lock().unwrap().pop(), Some(Ok(())));
+
+    todo.insert("done", 1)?;
+    remote.commit();
+    doc.import(&remote.export(ExportMode::all_updates())?)?;
+    assert_eq!(
+        results.lock().unwrap().pop(),
+        Some(Err(LoroSchemaError::WrongType {
+            path: "todos.0.done".into(),
+            reason: "expected a bool, found a number".into(),
+        }))
+    );
+
+    todo.delete("title")?;
+    remote.commit();
+    doc.import(&remote.export(ExportMode::all_updates())?)?;
+    assert_eq!(
+        results.lock().unwrap().pop(),
+        Some(Err(LoroSchemaError::MissingField {
+            path: "todos.0.title".into()
+        }))
+    );
+
+    remote.get_list("todos").push("not a map")?;
+    remote.commit();
+    doc.import(&remote.export(ExportMode::all_updates())?)?;
+    assert_eq!(
+        results.lock().unwrap().pop(),
+        Some(Err(LoroSchemaError::WrongType {
+            path: "todos.1".into(),
+            reason: "expected a Map container, found a string".into(),
+        }))
+    );
+    Ok(())
+}
+
+fn migrator() -> Migrator {
+    Migrator::new()
+        .add(2, "add tags", |doc| {
+            doc.get_map("settings")
+                .get_or_create_container("tags", LoroList::new())?
+                .push("default")?;
+            Ok(())
+        })
+        .add(1, "init", |doc| {
+            doc.get_map("settings").insert("theme", "light")?;
+            Ok(())
+        })
+}
+
+#[test]
+fn migrations_run_once() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    assert_eq!(migrator().migrate(&doc)?, vec![1, 2]);
+    assert_eq!(migrator().migrate(&doc)?, Vec::<u32>::new());
+    assert_eq!(Migrator::applied_versions(&doc), vec![1, 2]);
+    assert_eq!(doc.peer_id(), 1);
+    assert_eq!(doc.get_map(MIGRATIONS_ROOT).len(), 2);
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({
+            "settings": {"theme": "light", "tags": ["default"]},
+            "__loro_migrations": {"1": "init", "2": "add tags"},
+        })
+    );
+
+    // The migrations are synced with the document
+    let replica = LoroDoc::new();
+    replica.import(&doc.export(ExportMode::all_updates())?)?;
+    let vv = replica.oplog_vv();
+    assert_eq!(migrator().migrate(&replica)?, Vec::<u32>::new());
+    assert_eq!(replica.oplog_vv(), vv);
+    Ok(())
+}
+
+#[test]
+fn concurrent_migrations_converge() -> anyhow::Result<()> {
+    let base = LoroDoc::new();
+    base.get_map("settings").insert("theme", "dark")?;
+    base.commit();
+
+    let a = LoroDoc::new();
+    a.set_peer_id(1)?;
+    a.import(&base.export(ExportMode::all_updates())?)?;
+    let b = LoroDoc::new();
+    b.set_peer_id(2)?;
+    b.import(&base.export(ExportMode::all_updates())?)?;
+
+    assert_eq!(migrator().migrate(&a)?, vec![1, 2]);
+    assert_eq!(migrator().migrate(&b)?, vec![1, 2]);
+    assert_eq!(a.oplog_vv(), b.oplog_vv());
+
+    a.import(&b.export(ExportMode::all_updates())?)?;
+    b.import(&a.export(ExportMode::all_updates())?)?;
+    assert_eq!(a.get_deep_value(), b.get_deep_value());
+    // The list is only created and filled once
+    assert_eq!(
+        a.get_deep_value().to_json_value()["settings"],
+        json!({"theme": "light", "tags": ["default"]})
+    );
+    Ok(())
+}
+
+#[test]
+fn failed_migration_is_rolled_back() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let migrator = migrator().add(3, "broken", |doc| {
+        doc.get_map("settings").insert("theme", "broken")?;
+        Err(LoroError::ArgErr("broken".into()))
+    });
+    assert!(migrator.migrate(&doc).is_err());
+    assert_eq!(Migrator::applied_versions(&doc), vec![1, 2]);
+    assert_eq!(doc.peer_id(), 1);
+    assert_eq!(
+        doc.get_map("settings").get_deep_value().to_json_value(),
+        json!({"theme": "light", "tags": ["default"]})
+    );
+    Ok(())
+}
+
+#[test]
+fn migration_peer_only_depends_on_the_migration() -> anyhow::Result<()> {
+    let a = LoroDoc::new();
+    a.get_text("text").insert(0, "a")?;
+    a.commit();
+    let b = LoroDoc::new();
+    migrator().migrate(&a)?;
+    migrator().migrate(&b)?;
+    let record = |doc: &LoroDoc| {
+        let map = doc.get_map(MIGRATIONS_ROOT);
+        (map.get_last_editor("1"), map.get_last_editor("2"))
+    };
+    assert_eq!(record(&a), record(&b));
+    assert_ne!(record(&a).0, record(&a).1);
+    Ok(())
+}
+
+#[test]
+fn non_deterministic_migration_fails() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let runs = Arc::new(Mutex::new(0));
+    let runs_clone = runs.clone();
+    let migrator = Migrator::new().add(1, "random", move |doc| {
+        let mut runs = runs_clone.lock().unwrap();
+        *runs += 1;
+        doc.get_map("settings").insert("seed", *runs)?;
+        Ok(())
+    });
+    assert!(migrator.migrate(&doc).is_err());
+    assert_eq!(*runs.lock().unwrap(), 2);
+    assert!(Migrator::applied_versions(&doc).is_empty());
+    assert!(doc.oplog_vv().is_empty());
+    assert_eq!(doc.peer_id(), 1);
+    assert!(doc.get_map("settings").is_empty());
+    Ok(())
+}
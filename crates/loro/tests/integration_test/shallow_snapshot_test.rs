@@ -4,7 +4,16 @@ use std::{
 };
 
 use super::gen_action;
-use loro::{cursor::CannotFindRelativePosition, ExportMode, Frontiers, LoroDoc, ID};
+#[cfg(feature = "encryption")]
+use loro::EncryptionKey;
+use loro::{
+    cursor::CannotFindRelativePosition,
+    json::{JsonChange, JsonOpContent},
+    ContainerID, ContainerType, ExportMode, Frontiers, LoroDoc, LoroEncodeError, LoroError,
+    LoroMap, LoroText, ID,
+};
+#[cfg(feature = "signature")]
+use loro::{ed25519::SigningKey, ChangeVerifier, SignaturePolicy};
 
 #[test]
 fn test_gc() -> anyhow::Result<()> {
@@ -381,3 +390,784 @@ fn test_export_snapshot_from_shallow_doc() {
     // using frontiers before its shallow version
     shallow_doc.export_snapshot();
 }
+
+#[test]
+fn snapshot_export_to_writer_is_same_as_export() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    gen_action(&doc, 1, 100);
+    doc.commit();
+    let frontiers = doc.oplog_frontiers();
+    gen_action(&doc, 2, 100);
+    doc.commit();
+
+    for mode in [
+        ExportMode::Snapshot,
+        ExportMode::shallow_snapshot(&frontiers),
+        ExportMode::state_only(None),
+        ExportMode::snapshot_at(&frontiers),
+    ] {
+        let expected = doc.export(mode.clone())?;
+        let mut actual = Vec::new();
+        doc.export_to_writer(mode, &mut actual)?;
+        assert_eq!(actual, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn streamed_updates() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    gen_action(&doc, 1, 100);
+    doc.commit();
+    let vv = doc.oplog_vv();
+    let other = LoroDoc::new();
+    other.set_peer_id(2)?;
+    gen_action(&other, 2, 100);
+    other.commit();
+    doc.import(&other.export(ExportMode::all_updates())?)?;
+
+    for mode in [
+        ExportMode::all_updates(),
+        ExportMode::updates(&vv),
+        ExportMode::updates_till(&doc.oplog_vv()),
+    ] {
+        let expected = LoroDoc::new();
+        expected.import(&doc.export(mode.clone())?)?;
+        let mut bytes = Vec::new();
+        doc.export_to_writer(mode, &mut bytes)?;
+
+        let meta = LoroDoc::decode_import_blob_meta(&bytes, true)?;
+        assert!(!meta.mode.is_snapshot());
+        assert!(meta.change_num > 0);
+        let from_reader = LoroDoc::new();
+        from_reader.import_from_reader(bytes.as_slice())?;
+        let from_bytes = LoroDoc::new();
+        from_bytes.import(&bytes)?;
+        assert_eq!(from_reader.oplog_vv(), expected.oplog_vv());
+        assert_eq!(from_bytes.oplog_vv(), expected.oplog_vv());
+        assert_eq!(from_reader.get_deep_value(), expected.get_deep_value());
+        assert_eq!(from_bytes.get_deep_value(), expected.get_deep_value());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn import_from_reader() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    gen_action(&doc, 1, 100);
+    doc.commit();
+    let vv = doc.oplog_vv();
+    let snapshot = doc.export(ExportMode::Snapshot)?;
+    gen_action(&doc, 2, 100);
+    doc.commit();
+    let updates = doc.export(ExportMode::updates(&vv))?;
+
+    let new_doc = LoroDoc::new();
+    new_doc.import_from_reader(snapshot.as_slice())?;
+    let status = new_doc.import_from_reader(updates.as_slice())?;
+    assert!(status.pending.is_none());
+    assert_eq!(new_doc.get_deep_value(), doc.get_deep_value());
+
+    // Import a snapshot into a non-empty doc
+    let other = LoroDoc::new();
+    other.set_peer_id(2)?;
+    gen_action(&other, 3, 10);
+    other.commit();
+    other.import_from_reader(doc.export(ExportMode::Snapshot)?.as_slice())?;
+    doc.import(&other.export(ExportMode::all_updates())?)?;
+    assert_eq!(other.get_deep_value(), doc.get_deep_value());
+    Ok(())
+}
+
+#[test]
+fn import_from_reader_checks_checksum() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    gen_action(&doc, 1, 100);
+    doc.commit();
+    for mode in [ExportMode::Snapshot, ExportMode::all_updates()] {
+        let mut bytes = doc.export(mode)?;
+        // Corrupt a byte in the body, right after the header
+        bytes[32] = bytes[32].wrapping_add(1);
+        let new_doc = LoroDoc::new();
+        assert_eq!(
+            new_doc.import_from_reader(bytes.as_slice()),
+            Err(LoroError::DecodeChecksumMismatchError)
+        );
+        assert!(new_doc.get_deep_value().as_map().unwrap().is_empty());
+
+        let truncated = &bytes[..bytes.len() / 2];
+        assert!(new_doc.import_from_reader(truncated).is_err());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn streamed_updates_are_verified_block_by_block() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    gen_action(&doc, 1, 100);
+    doc.commit();
+    let mut bytes = Vec::new();
+    doc.export_to_writer(ExportMode::all_updates(), &mut bytes)?;
+
+    let mut corrupted = bytes.clone();
+    corrupted[32] = corrupted[32].wrapping_add(1);
+    let new_doc = LoroDoc::new();
+    assert_eq!(
+        new_doc.import_from_reader(corrupted.as_slice()),
+        Err(LoroError::DecodeChecksumMismatchError)
+    );
+    assert_eq!(
+        new_doc.import(&corrupted),
+        Err(LoroError::DecodeChecksumMismatchError)
+    );
+    assert!(new_doc.get_deep_value().as_map().unwrap().is_empty());
+
+    // The end mark is missing
+    let truncated = &bytes[..bytes.len() - 5];
+    assert!(new_doc.import_from_reader(truncated).is_err());
+    assert!(new_doc.import(truncated).is_err());
+    assert!(new_doc.oplog_vv().is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn export_and_import_encrypted() -> anyhow::Result<()> {
+    let key = EncryptionKey::generate("key-1");
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    gen_action(&doc, 1, 100);
+    doc.commit();
+    let vv = doc.oplog_vv();
+    let snapshot = doc.export_encrypted(ExportMode::Snapshot, &key)?;
+    gen_action(&doc, 2, 100);
+    doc.commit();
+    let updates = doc.export_encrypted(ExportMode::updates(&vv), &key)?;
+    assert_eq!(loro::encrypted_blob_key_id(&snapshot), Some("key-1"));
+
+    let new_doc = LoroDoc::new();
+    assert_eq!(
+        new_doc.import(&snapshot),
+        Err(LoroError::DecryptionKeyRequired {
+            key_id: "key-1".into()
+        })
+    );
+    assert_eq!(
+        new_doc.import_encrypted(&snapshot, &[EncryptionKey::generate("key-2")]),
+        Err(LoroError::DecryptionKeyRequired {
+            key_id: "key-1".into()
+        })
+    );
+    new_doc.import_encrypted(&snapshot, &[key.clone()])?;
+    new_doc.import_encrypted(&updates, &[key])?;
+    assert_eq!(new_doc.get_deep_value(), doc.get_deep_value());
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encrypted_blob_is_authenticated() -> anyhow::Result<()> {
+    let key = EncryptionKey::generate("key-1");
+    let doc = LoroDoc::new();
+    gen_action(&doc, 1, 100);
+    doc.commit();
+    let blob = doc.export_encrypted(ExportMode::all_updates(), &key)?;
+
+    let wrong_key = EncryptionKey::generate("key-1");
+    let new_doc = LoroDoc::new();
+    assert_eq!(
+        new_doc.import_encrypted(&blob, &[wrong_key]),
+        Err(LoroError::DecryptionError)
+    );
+
+    // Tamper the ciphertext and fix the checksum, the tag should still catch it
+    let mut tampered = blob.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    let checksum = xxhash_checksum(&tampered[20..]);
+    tampered[16..20].copy_from_slice(&checksum.to_le_bytes());
+    assert_eq!(
+        new_doc.import_encrypted(&tampered, &[key]),
+        Err(LoroError::DecryptionError)
+    );
+    assert!(new_doc.get_deep_value().as_map().unwrap().is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn decode_meta_of_encrypted_blob() -> anyhow::Result<()> {
+    let key = EncryptionKey::generate("key-1");
+    let doc = LoroDoc::new();
+    gen_action(&doc, 1, 10);
+    doc.commit();
+    let blob = doc.export_encrypted(ExportMode::Snapshot, &key)?;
+    assert_eq!(
+        LoroDoc::decode_import_blob_meta(&blob, true).unwrap_err(),
+        LoroError::DecryptionKeyRequired {
+            key_id: "key-1".into()
+        }
+    );
+
+    let meta = LoroDoc::decode_encrypted_blob_meta(&blob, &[key], true)?;
+    assert!(meta.mode.is_snapshot());
+    assert_eq!(meta.partial_end_vv, doc.oplog_vv());
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn rotate_key_of_snapshot() -> anyhow::Result<()> {
+    let old_key = EncryptionKey::generate("2024-01");
+    let new_key = EncryptionKey::generate("2024-02");
+    let doc = LoroDoc::new();
+    gen_action(&doc, 1, 100);
+    doc.commit();
+    let blob = doc.export_encrypted(ExportMode::Snapshot, &old_key)?;
+
+    let rotated = LoroDoc::reencrypt_blob(&blob, &[old_key.clone()], &new_key)?;
+    assert_eq!(loro::encrypted_blob_key_id(&rotated), Some("2024-02"));
+    let new_doc = LoroDoc::new();
+    assert!(new_doc.import_encrypted(&rotated, &[old_key]).is_err());
+    new_doc.import_encrypted(&rotated, &[new_key])?;
+    assert_eq!(new_doc.get_deep_value(), doc.get_deep_value());
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn plaintext_blob_is_rejected() -> anyhow::Result<()> {
+    let key = EncryptionKey::generate("key-1");
+    let doc = LoroDoc::new();
+    gen_action(&doc, 1, 100);
+    doc.commit();
+    let plaintext = doc.export(ExportMode::Snapshot)?;
+
+    let new_doc = LoroDoc::new();
+    assert_eq!(
+        new_doc.import_encrypted(&plaintext, &[key.clone()]),
+        Err(LoroError::NotEncrypted)
+    );
+    assert!(new_doc.get_deep_value().as_map().unwrap().is_empty());
+    assert_eq!(
+        loro::decrypt_blob(&plaintext, &[key.clone()]),
+        Err(LoroError::NotEncrypted)
+    );
+    assert_eq!(
+        LoroDoc::reencrypt_blob(&plaintext, &[key.clone()], &key).unwrap_err(),
+        LoroError::NotEncrypted
+    );
+
+    // Accepting the plaintext is an explicit opt-in
+    new_doc.import(&loro::decrypt_blob_or_plaintext(&plaintext, &[key])?)?;
+    assert_eq!(new_doc.get_deep_value(), doc.get_deep_value());
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+fn xxhash_checksum(bytes: &[u8]) -> u32 {
+    xxhash_rust::xxh32::xxh32(bytes, u32::from_le_bytes(*b"LORO"))
+}
+
+#[cfg(feature = "signature")]
+fn signed_doc(peer: u64, key: &SigningKey) -> LoroDoc {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(peer).unwrap();
+    doc.set_change_signer(Some(Arc::new(key.clone())));
+    doc
+}
+
+#[cfg(feature = "signature")]
+#[test]
+fn signed_changes_pass_verification() -> anyhow::Result<()> {
+    let key_a = SigningKey::from_bytes(&[1; 32]);
+    let key_b = SigningKey::from_bytes(&[2; 32]);
+    let a = signed_doc(1, &key_a);
+    let b = signed_doc(2, &key_b);
+    gen_action(&a, 1, 50);
+    a.commit();
+    gen_action(&a, 2, 50);
+    a.commit();
+    b.import(&a.export(ExportMode::all_updates())?)?;
+    gen_action(&b, 3, 50);
+    b.commit();
+
+    let verifier = ChangeVerifier::new(SignaturePolicy::Reject)
+        .peer_key(1, key_a.verifying_key())
+        .peer_key(2, key_b.verifying_key());
+    let receiver = LoroDoc::new();
+    receiver.set_change_verifier(Some(verifier.clone()));
+    receiver.import(&b.export(ExportMode::all_updates())?)?;
+    assert_eq!(receiver.get_deep_value(), b.get_deep_value());
+    assert_eq!(
+        receiver.verify_change_signature(ID::new(2, 0), &verifier),
+        Some(true)
+    );
+
+    // Snapshots are verified by replaying the history
+    let receiver = LoroDoc::new();
+    receiver.set_change_verifier(Some(verifier));
+    receiver.import(&b.export(ExportMode::Snapshot)?)?;
+    assert_eq!(receiver.get_deep_value(), b.get_deep_value());
+    Ok(())
+}
+
+#[cfg(feature = "signature")]
+#[test]
+fn reject_unsigned_and_forged_changes() -> anyhow::Result<()> {
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let verifier = ChangeVerifier::new(SignaturePolicy::Reject).peer_key(1, key.verifying_key());
+
+    // Unsigned
+    let unsigned = LoroDoc::new();
+    unsigned.set_peer_id(1)?;
+    unsigned.get_text("text").insert(0, "forged")?;
+    unsigned.commit();
+    let receiver = LoroDoc::new();
+    receiver.set_change_verifier(Some(verifier.clone()));
+    assert_eq!(
+        receiver.import(&unsigned.export(ExportMode::all_updates())?),
+        Err(LoroError::InvalidChangeSignature { id: ID::new(1, 0) })
+    );
+
+    // Signed by another key under the same peer id
+    let forged = signed_doc(1, &SigningKey::from_bytes(&[9; 32]));
+    forged.get_text("text").insert(0, "forged")?;
+    forged.commit();
+    assert_eq!(
+        receiver.import(&forged.export(ExportMode::all_updates())?),
+        Err(LoroError::InvalidChangeSignature { id: ID::new(1, 0) })
+    );
+    assert!(receiver.get_deep_value().as_map().unwrap().is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "signature")]
+#[test]
+fn quarantine_invalid_changes() -> anyhow::Result<()> {
+    let key_a = SigningKey::from_bytes(&[1; 32]);
+    let a = signed_doc(1, &key_a);
+    a.get_text("text").insert(0, "a")?;
+    a.commit();
+
+    let b = LoroDoc::new();
+    b.set_peer_id(2)?;
+    b.get_map("map").insert("b", 1)?;
+    b.commit();
+    a.import(&b.export(ExportMode::all_updates())?)?;
+    a.get_text("text").insert(1, "b")?;
+    a.commit();
+
+    let receiver = LoroDoc::new();
+    receiver.set_change_verifier(Some(
+        ChangeVerifier::new(SignaturePolicy::Quarantine).peer_key(1, key_a.verifying_key()),
+    ));
+    let status = receiver.import(&a.export(ExportMode::all_updates())?)?;
+    // The change of peer 2 is quarantined, the second change of peer 1 depends on it
+    assert!(status.pending.is_some());
+    assert_eq!(receiver.get_text("text").to_string(), "a");
+    let quarantined = receiver.quarantined_changes();
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].id, ID::new(2, 0));
+    receiver.clear_quarantined_changes();
+    assert!(receiver.quarantined_changes().is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "signature")]
+#[test]
+fn large_signed_changes_keep_their_signatures() -> anyhow::Result<()> {
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let doc = signed_doc(1, &key);
+    // Larger than the block size of the change store
+    doc.get_text("text")
+        .insert(0, &"Hello world. ".repeat(1000))?;
+    doc.get_list("list").push("a")?;
+    doc.commit();
+
+    let verifier = ChangeVerifier::new(SignaturePolicy::Reject).peer_key(1, key.verifying_key());
+    for mode in [ExportMode::all_updates(), ExportMode::Snapshot] {
+        let receiver = LoroDoc::new();
+        receiver.set_change_verifier(Some(verifier.clone()));
+        receiver.import(&doc.export(mode)?)?;
+        assert_eq!(receiver.get_deep_value(), doc.get_deep_value());
+        assert!(receiver.quarantined_changes().is_empty());
+    }
+
+    for counter in [0, 6000, 13000] {
+        assert_eq!(
+            doc.verify_change_signature(ID::new(1, counter), &verifier),
+            Some(true)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "signature")]
+#[test]
+fn signature_survives_reencoding_by_other_peers() -> anyhow::Result<()> {
+    let key_a = SigningKey::from_bytes(&[1; 32]);
+    let a = signed_doc(1, &key_a);
+    a.get_map("map")
+        .insert("key", loro::loro_value!({"a": 1, "b": [1.5, "x"]}))?;
+    a.get_text("text").insert(0, "Hello")?;
+    a.get_text("text").mark(0..2, "bold", true)?;
+    a.get_list("list")
+        .insert_container(0, loro::LoroText::new())?;
+    a.commit();
+
+    // B registers other containers first, so it encodes the changes of A with another
+    // container table and block layout
+    let b = LoroDoc::new();
+    b.set_peer_id(2)?;
+    b.get_list("other").push(1)?;
+    b.get_text("other text").insert(0, "b")?;
+    b.commit();
+    b.import(&a.export(ExportMode::all_updates())?)?;
+
+    let verifier =
+        ChangeVerifier::new(SignaturePolicy::Quarantine).peer_key(1, key_a.verifying_key());
+    let c = LoroDoc::new();
+    c.set_change_verifier(Some(verifier.clone()));
+    c.import(&b.export(ExportMode::all_updates())?)?;
+    assert_eq!(
+        c.get_map("map").get_deep_value(),
+        a.get_map("map").get_deep_value()
+    );
+    assert_eq!(c.get_text("text").to_delta(), a.get_text("text").to_delta());
+    // Only the unsigned change of B is quarantined
+    let quarantined = c.quarantined_changes();
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].id, ID::new(2, 0));
+    assert_eq!(
+        c.verify_change_signature(ID::new(1, 0), &verifier),
+        Some(true)
+    );
+    Ok(())
+}
+
+#[cfg(feature = "signature")]
+#[test]
+fn json_updates_require_signatures() -> anyhow::Result<()> {
+    let key = SigningKey::from_bytes(&[1; 32]);
+    let doc = signed_doc(1, &key);
+    doc.get_text("text").insert(0, "hello")?;
+    doc.commit();
+    let json = doc.export_json_updates(&Default::default(), &doc.oplog_vv());
+
+    let receiver = LoroDoc::new();
+    receiver.set_change_verifier(Some(
+        ChangeVerifier::new(SignaturePolicy::Reject).peer_key(1, key.verifying_key()),
+    ));
+    assert_eq!(
+        receiver.import_json_updates(json.clone()),
+        Err(LoroError::SignatureRequired { id: ID::new(1, 0) })
+    );
+    assert!(receiver.oplog_vv().is_empty());
+
+    // The changes that are already imported are skipped
+    receiver.import(&doc.export(ExportMode::all_updates())?)?;
+    receiver.import_json_updates(json)?;
+    Ok(())
+}
+
+fn read_only(name: &str) -> impl Fn(&JsonChange) -> Result<(), String> {
+    let container = ContainerID::new_root(name, ContainerType::Map);
+    move |change: &JsonChange| {
+        if change.ops.iter().any(|op| op.container == container) {
+            return Err(format!("{} is read-only", container));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn valid_changes_are_imported() -> anyhow::Result<()> {
+    let client = LoroDoc::new();
+    gen_action(&client, 1, 100);
+    client.commit();
+
+    let server = LoroDoc::new();
+    server.set_import_validator(Some(Arc::new(read_only("config"))));
+    let status = server.import(&client.export(ExportMode::all_updates())?)?;
+    assert!(status.pending.is_none());
+    assert_eq!(server.get_deep_value(), client.get_deep_value());
+    assert!(server.rejected_changes().is_empty());
+    Ok(())
+}
+
+#[test]
+fn reject_change_and_its_dependents() -> anyhow::Result<()> {
+    let a = LoroDoc::new();
+    a.set_peer_id(1)?;
+    a.get_text("text").insert(0, "hello")?;
+    a.set_next_commit_message("text");
+    a.commit();
+    a.get_map("config").insert("admin", true)?;
+    a.set_next_commit_message("config");
+    a.commit();
+    a.get_text("text").insert(5, " world")?;
+    a.set_next_commit_message("more text");
+    a.commit();
+
+    let b = LoroDoc::new();
+    b.set_peer_id(2)?;
+    b.import(&a.export(ExportMode::all_updates())?)?;
+    b.get_text("text").insert(0, ">")?;
+    b.commit();
+
+    let c = LoroDoc::new();
+    c.set_peer_id(3)?;
+    c.get_text("other").insert(0, "unrelated")?;
+    c.commit();
+
+    let server = LoroDoc::new();
+    server.set_import_validator(Some(Arc::new(read_only("config"))));
+    let status = server.import(&b.export(ExportMode::all_updates())?)?;
+    assert!(status.pending.is_none());
+    assert_eq!(status.success.get(&1), Some(&(0, 5)));
+    assert_eq!(status.success.get(&2), None);
+    assert_eq!(server.get_text("text").to_string(), "hello");
+    assert!(server.get_map("config").is_empty());
+
+    let rejected = server.rejected_changes();
+    assert_eq!(rejected.len(), 3);
+    assert!(rejected
+        .iter()
+        .any(|r| r.change.id == ID::new(1, 5) && r.reason == "cid:root-config:Map is read-only"));
+
+    // Later dependents are rejected too, while the independent changes are imported
+    server.clear_rejected_changes();
+    b.get_text("text").insert(0, ">")?;
+    b.commit();
+    server.set_import_validator(None);
+    server.import(&b.export(ExportMode::updates(&server.oplog_vv()))?)?;
+    server.import(&c.export(ExportMode::all_updates())?)?;
+    assert_eq!(server.get_text("text").to_string(), "hello");
+    assert_eq!(server.get_text("other").to_string(), "unrelated");
+    let rejected = server.rejected_changes();
+    assert!(rejected.iter().any(|r| r.change.id.peer == 2));
+    assert!(rejected.iter().all(|r| r.change.id.peer != 3));
+    Ok(())
+}
+
+#[test]
+fn validate_snapshot_and_json_updates() -> anyhow::Result<()> {
+    let max_insert_len = |change: &JsonChange| {
+        for op in change.ops.iter() {
+            if let JsonOpContent::Text(loro::JsonTextOp::Insert { text, .. }) = &op.content {
+                if text.chars().count() > 10 {
+                    return Err("The inserted text is too long".to_string());
+                }
+            }
+        }
+        Ok(())
+    };
+
+    let client = LoroDoc::new();
+    client.get_text("text").insert(0, "a very long text")?;
+    client.commit();
+
+    let server = LoroDoc::new();
+    server.set_import_validator(Some(Arc::new(max_insert_len)));
+    server.import(&client.export(ExportMode::Snapshot)?)?;
+    assert_eq!(server.get_text("text").to_string(), "");
+
+    let server = LoroDoc::new();
+    server.set_import_validator(Some(Arc::new(max_insert_len)));
+    server
+        .import_json_updates(client.export_json_updates(&Default::default(), &client.oplog_vv()))?;
+    assert_eq!(server.get_text("text").to_string(), "");
+    assert_eq!(server.rejected_changes().len(), 1);
+    Ok(())
+}
+
+fn multi_tenant_doc() -> anyhow::Result<LoroDoc> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let public = doc.get_map("public");
+    public.insert("title", "Hello")?;
+    let body = public.insert_container("body", LoroText::new())?;
+    body.insert(0, "public body")?;
+    doc.get_text("private").insert(0, "secret")?;
+    let notes = doc.get_map("notes");
+    notes.insert("owner", "alice")?;
+    doc.commit();
+    Ok(doc)
+}
+
+#[test]
+fn export_updates_for_containers() -> anyhow::Result<()> {
+    let doc = multi_tenant_doc()?;
+    let public = ContainerID::new_root("public", ContainerType::Map);
+    let bytes = doc.export(ExportMode::updates_for_containers(
+        &Default::default(),
+        vec![public.clone()],
+    ))?;
+    assert!(!bytes.windows(6).any(|w| w == b"secret"));
+    assert!(!bytes.windows(5).any(|w| w == b"alice"));
+
+    let receiver = LoroDoc::new();
+    let status = receiver.import(&bytes)?;
+    assert!(status.pending.is_none());
+    assert_eq!(receiver.oplog_vv(), doc.oplog_vv());
+    assert_eq!(
+        receiver.get_map("public").get_deep_value(),
+        doc.get_map("public").get_deep_value()
+    );
+
+    // The later updates can be exported incrementally
+    doc.get_map("public").insert("version", 2)?;
+    doc.get_text("private").insert(0, "more ")?;
+    doc.commit();
+    let mut streamed = Vec::new();
+    doc.export_to_writer(
+        ExportMode::updates_for_containers(&receiver.oplog_vv(), vec![public]),
+        &mut streamed,
+    )?;
+    let status = receiver.import(&streamed)?;
+    assert!(status.pending.is_none());
+    assert_eq!(
+        receiver.get_map("public").get_deep_value(),
+        doc.get_map("public").get_deep_value()
+    );
+
+    // The edits of the receiver can be merged back
+    receiver.set_peer_id(2)?;
+    receiver.get_map("public").insert("reviewed", true)?;
+    receiver.commit();
+    doc.import(&receiver.export(ExportMode::updates(&doc.oplog_vv()))?)?;
+    assert!(doc.get_map("public").get("reviewed").is_some());
+    assert_eq!(doc.get_text("private").to_string(), "more secret");
+    Ok(())
+}
+
+#[test]
+fn descendants_of_whitelisted_containers_are_exported() -> anyhow::Result<()> {
+    let doc = multi_tenant_doc()?;
+    let notes = doc.get_map("notes");
+    let child = notes.insert_container("child", LoroMap::new())?;
+    child.insert("a", 1)?;
+    doc.commit();
+
+    let receiver = LoroDoc::new();
+    receiver.import(&doc.export(ExportMode::updates_for_containers(
+        &Default::default(),
+        vec![ContainerID::new_root("notes", ContainerType::Map)],
+    ))?)?;
+    assert_eq!(
+        receiver.get_map("notes").get_deep_value(),
+        notes.get_deep_value()
+    );
+    assert!(receiver.get_text("private").to_string().is_empty());
+    Ok(())
+}
+
+#[test]
+fn placeholders_are_not_exported_again() -> anyhow::Result<()> {
+    let doc = multi_tenant_doc()?;
+    let receiver = LoroDoc::new();
+    receiver.import(&doc.export(ExportMode::updates_for_containers(
+        &Default::default(),
+        vec![ContainerID::new_root("public", ContainerType::Map)],
+    ))?)?;
+
+    assert_eq!(
+        receiver.export(ExportMode::all_updates()),
+        Err(LoroEncodeError::PartialHistory)
+    );
+    assert_eq!(
+        receiver.fork().export(ExportMode::all_updates()),
+        Err(LoroEncodeError::PartialHistory)
+    );
+
+    // The snapshots keep the placeholders, so they're still not exported as updates
+    let restored = LoroDoc::new();
+    restored.import(&receiver.export(ExportMode::Snapshot)?)?;
+    assert_eq!(restored.oplog_vv(), doc.oplog_vv());
+    assert_eq!(
+        restored.get_map("public").get_deep_value(),
+        doc.get_map("public").get_deep_value()
+    );
+    assert_eq!(
+        restored.export(ExportMode::all_updates()),
+        Err(LoroEncodeError::PartialHistory)
+    );
+
+    // The changes of the receiver itself can still be exported
+    receiver.set_peer_id(2)?;
+    receiver.get_map("public").insert("reviewed", true)?;
+    receiver.commit();
+    assert!(receiver
+        .export(ExportMode::updates(&doc.oplog_vv()))
+        .is_ok());
+    Ok(())
+}
+
+#[test]
+fn whitelisted_container_created_in_hidden_container() -> anyhow::Result<()> {
+    let doc = multi_tenant_doc()?;
+    let shared = doc
+        .get_map("notes")
+        .insert_container("shared", LoroText::new())?;
+    shared.insert(0, "hi")?;
+    doc.commit();
+
+    let receiver = LoroDoc::new();
+    receiver.import(&doc.export(ExportMode::updates_for_containers(
+        &Default::default(),
+        vec![shared.id()],
+    ))?)?;
+    assert_eq!(receiver.get_text(shared.id()).to_string(), "hi");
+    assert!(receiver.get_map("notes").is_empty());
+
+    shared.insert(2, "!")?;
+    doc.commit();
+    receiver.import(&doc.export(ExportMode::updates_for_containers(
+        &receiver.oplog_vv(),
+        vec![shared.id()],
+    ))?)?;
+    assert_eq!(receiver.get_text(shared.id()).to_string(), "hi!");
+    assert_eq!(receiver.fork().get_text(shared.id()).to_string(), "hi!");
+    Ok(())
+}
+
+#[test]
+fn granted_access_later() -> anyhow::Result<()> {
+    let doc = multi_tenant_doc()?;
+    let receiver = LoroDoc::new();
+    receiver.import(&doc.export(ExportMode::updates_for_containers(
+        &Default::default(),
+        vec![ContainerID::new_root("public", ContainerType::Map)],
+    ))?)?;
+    receiver.set_peer_id(2)?;
+    receiver.get_map("public").insert("reviewed", true)?;
+    receiver.commit();
+
+    // The placeholders cannot be replaced by the real ops
+    let full = doc.export(ExportMode::all_updates())?;
+    assert_eq!(
+        receiver.import(&full),
+        Err(LoroError::PlaceholdersNotReplaceable { id: ID::new(1, 13) })
+    );
+    assert!(receiver.get_text("private").to_string().is_empty());
+
+    // Import the full history into a new doc, then move the local changes over
+    let granted = LoroDoc::new();
+    granted.import(&full)?;
+    granted.import(&receiver.export(ExportMode::updates(&doc.oplog_vv()))?)?;
+    assert_eq!(granted.oplog_vv(), receiver.oplog_vv());
+    assert_eq!(granted.get_text("private").to_string(), "secret");
+    assert_eq!(
+        granted.get_map("public").get_deep_value(),
+        receiver.get_map("public").get_deep_value()
+    );
+    assert!(granted.export(ExportMode::all_updates()).is_ok());
+    Ok(())
+}
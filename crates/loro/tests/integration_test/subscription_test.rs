@@ -0,0 +1,524 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[cfg(feature = "event-stream")]
+use futures::{executor::block_on, FutureExt, StreamExt};
+use loro::{
+    event::Diff, ContainerID, EventTriggerKind, LoroDoc, LoroList, LoroMap, LoroText, LoroValue,
+    PathPatternError, Subscription, TextDelta, ThrottleScheduler, TreeParentId, ValueOrContainer,
+};
+#[cfg(feature = "event-stream")]
+use loro::{BackpressurePolicy, OwnedDiffEvent, StreamOptions, ToJson};
+#[cfg(feature = "event-stream")]
+use serde_json::json;
+
+type Tasks = Arc<Mutex<Vec<(Duration, Box<dyn FnOnce() + Send>)>>>;
+
+/// A scheduler that keeps the tasks so the test can run them
+fn manual_scheduler() -> (Tasks, ThrottleScheduler) {
+    let tasks: Tasks = Default::default();
+    let tasks_clone = tasks.clone();
+    (
+        tasks,
+        Arc::new(move |delay, task| tasks_clone.lock().unwrap().push((delay, task))),
+    )
+}
+
+#[derive(Debug, Default)]
+struct Received {
+    kinds: Vec<EventTriggerKind>,
+    text: Vec<Vec<TextDelta>>,
+    map: Vec<Vec<(String, Option<LoroValue>)>>,
+}
+
+fn record(
+    doc: &LoroDoc,
+    interval: Duration,
+    scheduler: ThrottleScheduler,
+) -> (Arc<Mutex<Received>>, Subscription) {
+    let received = Arc::new(Mutex::new(Received::default()));
+    let received_clone = received.clone();
+    let sub = doc.subscribe_root_throttled(
+        interval,
+        scheduler,
+        Arc::new(move |e| {
+            let mut received = received_clone.lock().unwrap();
+            received.kinds.push(e.triggered_by);
+            for diff in e.events {
+                match diff.diff {
+                    Diff::Text(delta) => received.text.push(delta),
+                    Diff::Map(map) => {
+                        let mut updated: Vec<_> = map
+                            .updated
+                            .into_iter()
+                            .map(|(k, v)| {
+                                let v = v.map(|v| match v {
+                                    ValueOrContainer::Value(v) => v,
+                                    ValueOrContainer::Container(_) => unreachable!(),
+                                });
+                                (k.to_string(), v)
+                            })
+                            .collect();
+                        updated.sort_by(|a, b| a.0.cmp(&b.0));
+                        received.map.push(updated);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }),
+    );
+    (received, sub)
+}
+
+#[test]
+fn compose_the_events_within_the_interval() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let (received, _sub) = record(&doc, Duration::from_secs(3600), manual_scheduler().1);
+    let text = doc.get_text("text");
+    let map = doc.get_map("map");
+    text.insert(0, "a")?;
+    doc.commit();
+    assert_eq!(received.lock().unwrap().kinds.len(), 1);
+
+    text.insert(1, "b")?;
+    map.insert("x", 1)?;
+    doc.commit();
+    text.insert(2, "c")?;
+    map.insert("y", 2)?;
+    doc.commit();
+    map.delete("x")?;
+    text.delete(0, 1)?;
+    doc.commit();
+    assert_eq!(received.lock().unwrap().kinds.len(), 1);
+
+    doc.flush_events();
+    let received = std::mem::take(&mut *received.lock().unwrap());
+    assert_eq!(received.kinds.len(), 2);
+    assert_eq!(
+        received.map,
+        vec![vec![
+            ("x".to_string(), None),
+            ("y".to_string(), Some(2.into()))
+        ]]
+    );
+
+    // Replaying the composed text diffs gives the same text
+    let mirror = LoroDoc::new();
+    let mirror_text = mirror.get_text("text");
+    assert_eq!(received.text.len(), 2);
+    for delta in received.text.iter() {
+        mirror_text.apply_delta(delta)?;
+    }
+    assert_eq!(mirror_text.to_string(), "bc");
+
+    // Nothing is pending
+    doc.flush_events();
+    Ok(())
+}
+
+#[test]
+fn compose_imported_bursts() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let (received, _sub) = record(&doc, Duration::from_secs(3600), manual_scheduler().1);
+    doc.get_text("text").insert(0, "local ")?;
+    doc.commit();
+
+    let other = LoroDoc::new();
+    other.import(&doc.export(loro::ExportMode::all_updates())?)?;
+    let other_text = other.get_text("text");
+    for i in 0..5 {
+        let vv = doc.oplog_vv();
+        other_text.insert(other_text.len_unicode(), &i.to_string())?;
+        other.commit();
+        doc.import(&other.export(loro::ExportMode::updates(&vv))?)?;
+    }
+    assert_eq!(received.lock().unwrap().kinds.len(), 1);
+
+    doc.flush_events();
+    let received = received.lock().unwrap();
+    assert_eq!(
+        received.kinds,
+        vec![EventTriggerKind::Local, EventTriggerKind::Import]
+    );
+    let mirror = LoroDoc::new();
+    for delta in received.text.iter() {
+        mirror.get_text("text").apply_delta(delta)?;
+    }
+    assert_eq!(mirror.get_text("text").to_string(), "local 01234");
+    Ok(())
+}
+
+#[test]
+fn deliver_every_event_with_zero_interval() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let (received, sub) = record(&doc, Duration::ZERO, manual_scheduler().1);
+    let text = doc.get_text("text");
+    for i in 0..3 {
+        text.insert(i, "a")?;
+        doc.commit();
+    }
+    assert_eq!(received.lock().unwrap().kinds.len(), 3);
+
+    drop(sub);
+    text.insert(0, "a")?;
+    doc.commit();
+    doc.flush_events();
+    assert_eq!(received.lock().unwrap().kinds.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn poll_delivers_the_trailing_event() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let (received, _sub) = record(&doc, Duration::from_millis(50), manual_scheduler().1);
+    let text = doc.get_text("text");
+    assert_eq!(doc.poll_events(), None);
+    text.insert(0, "a")?;
+    doc.commit();
+    text.insert(1, "b")?;
+    doc.commit();
+    assert_eq!(received.lock().unwrap().kinds.len(), 1);
+
+    let wait = doc.poll_events().unwrap();
+    assert!(wait <= Duration::from_millis(50));
+    assert_eq!(received.lock().unwrap().kinds.len(), 1);
+
+    std::thread::sleep(wait);
+    assert_eq!(doc.poll_events(), None);
+    assert_eq!(received.lock().unwrap().kinds.len(), 2);
+    assert_eq!(doc.poll_events(), None);
+    Ok(())
+}
+
+#[test]
+fn scheduler_delivers_the_trailing_event() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let (tasks, scheduler) = manual_scheduler();
+    let (received, _sub) = record(&doc, Duration::from_millis(50), scheduler);
+    let text = doc.get_text("text");
+    text.insert(0, "a")?;
+    doc.commit();
+    assert!(tasks.lock().unwrap().is_empty());
+    for i in 1..4 {
+        text.insert(i, "b")?;
+        doc.commit();
+    }
+    assert_eq!(received.lock().unwrap().kinds.len(), 1);
+
+    // Only one task is scheduled for the burst
+    let mut scheduled = std::mem::take(&mut *tasks.lock().unwrap());
+    assert_eq!(scheduled.len(), 1);
+    let (delay, task) = scheduled.pop().unwrap();
+    assert!(delay <= Duration::from_millis(50));
+    std::thread::sleep(delay);
+    task();
+    assert_eq!(received.lock().unwrap().kinds.len(), 2);
+    assert!(tasks.lock().unwrap().is_empty());
+    assert_eq!(doc.poll_events(), None);
+    Ok(())
+}
+
+#[test]
+fn scheduled_task_after_unsubscribe_does_nothing() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let (tasks, scheduler) = manual_scheduler();
+    let (received, sub) = record(&doc, Duration::from_secs(3600), scheduler);
+    let text = doc.get_text("text");
+    text.insert(0, "a")?;
+    doc.commit();
+    text.insert(1, "b")?;
+    doc.commit();
+    drop(sub);
+    let (_, task) = tasks.lock().unwrap().pop().unwrap();
+    task();
+    assert_eq!(received.lock().unwrap().kinds.len(), 1);
+    Ok(())
+}
+
+fn record_paths(doc: &LoroDoc, pattern: &str) -> (Arc<Mutex<Vec<Vec<ContainerID>>>>, Subscription) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let events_clone = events.clone();
+    let sub = doc
+        .subscribe_path(
+            pattern,
+            Arc::new(move |e| {
+                assert!(!e.events.is_empty());
+                events_clone
+                    .lock()
+                    .unwrap()
+                    .push(e.events.iter().map(|x| x.target.clone()).collect());
+            }),
+        )
+        .unwrap();
+    (events, sub)
+}
+
+fn take(events: &Mutex<Vec<Vec<ContainerID>>>) -> Vec<Vec<ContainerID>> {
+    std::mem::take(&mut *events.lock().unwrap())
+}
+
+#[test]
+fn match_the_containers_created_later() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let (glob_events, _sub) = record_paths(&doc, "/projects/*/tasks/*/title");
+    let (json_path_events, _sub) = record_paths(&doc, "$.projects[*].tasks[*].title");
+    let projects = doc.get_list("projects");
+    let project = projects.push_container(LoroMap::new())?;
+    project.insert("name", "loro")?;
+    let tasks = project.insert_container("tasks", LoroList::new())?;
+    let task = tasks.push_container(LoroMap::new())?;
+    task.insert("title", "first")?;
+    task.insert("done", false)?;
+    doc.commit();
+    let expected = vec![vec![task.id()]];
+    assert_eq!(take(&glob_events), expected);
+    assert_eq!(take(&json_path_events), expected);
+
+    // Not under a matching path
+    task.insert("done", true)?;
+    project.insert("name", "crdt")?;
+    doc.commit();
+    assert!(take(&glob_events).is_empty());
+
+    // A title container and an edit inside it
+    let task = tasks.insert_container(0, LoroMap::new())?;
+    let title = task.insert_container("title", LoroText::new())?;
+    title.insert(0, "second")?;
+    doc.commit();
+    assert_eq!(take(&glob_events), vec![vec![task.id(), title.id()]]);
+    title.insert(0, "the ")?;
+    doc.commit();
+    assert_eq!(take(&glob_events), vec![vec![title.id()]]);
+    assert_eq!(take(&json_path_events).len(), 2);
+    Ok(())
+}
+
+#[test]
+fn match_any_depth() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let (glob_events, _sub) = record_paths(&doc, "/root/**/title");
+    let (json_path_events, _sub) = record_paths(&doc, "$.root..title");
+    let root = doc.get_map("root");
+    root.insert("title", "root")?;
+    doc.commit();
+    let child = root.insert_container("child", LoroMap::new())?;
+    let grandchild = child.insert_container("grandchild", LoroMap::new())?;
+    grandchild.insert("title", "grandchild")?;
+    doc.commit();
+    doc.get_map("other").insert("title", "other")?;
+    doc.commit();
+
+    let expected = vec![vec![root.id()], vec![grandchild.id()]];
+    assert_eq!(take(&glob_events), expected);
+    assert_eq!(take(&json_path_events), expected);
+    Ok(())
+}
+
+#[test]
+fn match_descendants_list_indexes_and_tree_nodes() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let (descendant_events, _sub) = record_paths(&doc, "/map/child");
+    let (index_events, _sub) = record_paths(&doc, "/list/1");
+    let (tree_events, _sub) = record_paths(&doc, "/tree/*/title");
+
+    let map = doc.get_map("map");
+    let child = map.insert_container("child", LoroMap::new())?;
+    let text = child.insert_container("text", LoroText::new())?;
+    text.insert(0, "abc")?;
+    doc.commit();
+    // The parent map is included because it changes the matching entry
+    assert_eq!(
+        take(&descendant_events),
+        vec![vec![map.id(), child.id(), text.id()]]
+    );
+    map.insert("other", 1)?;
+    doc.commit();
+    assert!(take(&descendant_events).is_empty());
+
+    let list = doc.get_list("list");
+    list.push(0)?;
+    list.push(1)?;
+    doc.commit();
+    list.push(2)?;
+    doc.commit();
+    list.delete(0, 1)?;
+    doc.commit();
+    assert_eq!(take(&index_events), vec![vec![list.id()], vec![list.id()]]);
+
+    let tree = doc.get_tree("tree");
+    let node = tree.create(TreeParentId::Root)?;
+    doc.commit();
+    tree.get_meta(node)?.insert("title", "node")?;
+    doc.commit();
+    tree.get_meta(node)?.insert("other", "node")?;
+    doc.commit();
+    assert_eq!(take(&tree_events), vec![vec![tree.get_meta(node)?.id()]]);
+    Ok(())
+}
+
+#[test]
+fn invalid_path_pattern() {
+    let doc = LoroDoc::new();
+    for pattern in ["$.a[", "$.a[1:2]", "$a", "$.a..[?(@.b)]"] {
+        assert!(matches!(
+            doc.subscribe_path(pattern, Arc::new(|_| {})),
+            Err(PathPatternError::InvalidPattern { .. })
+        ));
+    }
+}
+
+#[cfg(feature = "event-stream")]
+fn inserted_text(event: &OwnedDiffEvent) -> String {
+    let event = event.as_event();
+    let Diff::Text(delta) = &event.events[0].diff else {
+        unreachable!()
+    };
+    delta
+        .iter()
+        .filter_map(|d| match d {
+            TextDelta::Insert { insert, .. } => Some(insert.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(feature = "event-stream")]
+#[test]
+fn stream_events() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let mut events = doc.subscribe_root_stream(StreamOptions::default());
+    let map = doc.get_map("map");
+    map.insert("a", 1)?;
+    doc.commit();
+    let child = map.insert_container("child", LoroMap::new())?;
+    child.insert("b", 2)?;
+    doc.commit();
+
+    let event = block_on(events.next()).unwrap();
+    assert_eq!(event.as_event().events.len(), 1);
+    let event = std::thread::spawn(move || block_on(events.next()))
+        .join()
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.as_event().events.len(), 2);
+    assert_eq!(event.to_frontiers(), &doc.state_frontiers());
+    Ok(())
+}
+
+#[cfg(feature = "event-stream")]
+#[test]
+fn stream_ends_after_the_doc_is_dropped() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let mut events = doc.subscribe_root_stream(StreamOptions::default());
+    let mut updates = doc.subscribe_local_update_stream(StreamOptions::default());
+    doc.get_text("text").insert(0, "a")?;
+    doc.commit();
+    assert!(events.next().now_or_never().is_some());
+    assert!(events.next().now_or_never().is_none());
+    assert!(updates.next().now_or_never().is_some());
+
+    drop(doc);
+    assert!(matches!(events.next().now_or_never(), Some(None)));
+    assert!(matches!(updates.next().now_or_never(), Some(None)));
+    Ok(())
+}
+
+#[cfg(feature = "event-stream")]
+#[test]
+fn container_stream() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    let mut events = doc.subscribe_stream(&text.id(), StreamOptions::default());
+    doc.get_map("map").insert("a", 1)?;
+    doc.commit();
+    text.insert(0, "Hello")?;
+    doc.commit();
+
+    let event = block_on(events.next()).unwrap();
+    assert_eq!(event.as_event().current_target, Some(text.id()));
+    assert_eq!(inserted_text(&event), "Hello");
+    assert!(events.next().now_or_never().is_none());
+    Ok(())
+}
+
+#[cfg(feature = "event-stream")]
+#[test]
+fn coalesce_events_when_the_buffer_is_full() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let mut events = doc.subscribe_root_stream(StreamOptions {
+        capacity: 1,
+        policy: BackpressurePolicy::Coalesce,
+    });
+    let text = doc.get_text("text");
+    for (i, s) in ["a", "b", "c"].into_iter().enumerate() {
+        text.insert(i, s)?;
+        doc.commit();
+    }
+
+    let event = block_on(events.next()).unwrap();
+    assert_eq!(inserted_text(&event), "abc");
+    assert!(event.from_frontiers().is_empty());
+    assert_eq!(event.to_frontiers(), &doc.state_frontiers());
+    assert!(events.next().now_or_never().is_none());
+    assert_eq!(events.dropped_count(), 0);
+    Ok(())
+}
+
+#[cfg(feature = "event-stream")]
+#[test]
+fn drop_oldest_events_when_the_buffer_is_full() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let mut events = doc.subscribe_root_stream(StreamOptions {
+        capacity: 2,
+        policy: BackpressurePolicy::DropOldest,
+    });
+    let text = doc.get_text("text");
+    for (i, s) in ["a", "b", "c"].into_iter().enumerate() {
+        text.insert(i, s)?;
+        doc.commit();
+    }
+
+    assert_eq!(events.dropped_count(), 1);
+    assert_eq!(inserted_text(&block_on(events.next()).unwrap()), "b");
+    assert_eq!(inserted_text(&block_on(events.next()).unwrap()), "c");
+    assert!(events.next().now_or_never().is_none());
+    Ok(())
+}
+
+#[cfg(feature = "event-stream")]
+#[test]
+fn coalesce_local_updates_when_the_buffer_is_full() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let mut updates = doc.subscribe_local_update_stream(StreamOptions {
+        capacity: 2,
+        policy: BackpressurePolicy::Coalesce,
+    });
+    let map = doc.get_map("map");
+    for i in 0..10 {
+        map.insert(&i.to_string(), i)?;
+        doc.commit();
+        if i == 4 {
+            doc.set_peer_id(2)?;
+        }
+    }
+
+    let replica = LoroDoc::new();
+    let mut count = 0;
+    while let Some(update) = updates.next().now_or_never().flatten() {
+        replica.import(&update)?;
+        count += 1;
+    }
+    assert_eq!(count, 2);
+    assert_eq!(updates.dropped_count(), 0);
+    assert_eq!(
+        replica.get_deep_value().to_json_value(),
+        doc.get_deep_value().to_json_value()
+    );
+    assert_eq!(
+        replica.get_map("map").get_deep_value().to_json_value()["9"],
+        json!(9)
+    );
+    Ok(())
+}
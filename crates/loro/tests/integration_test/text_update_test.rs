@@ -1,4 +1,19 @@
-use loro::LoroDoc;
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use fxhash::FxHashMap;
+use loro::{
+    cursor::{CannotFindRelativePosition, TextRange},
+    event::Diff,
+    loro_value, unified_diff, ExpandType, ExportMode, Frontiers, HtmlMapping, HunkLine, LineChange,
+    LoroDoc, LoroError, LoroText, LoroValue, PosType, StyleConfig, StyleConfigMap, StyleRangeValue,
+    TextDelta, TextPatchError, TextSearchOptions, ToJson, UpdateGranularity, UpdateOptions,
+    UpdateTimeoutError, EMBED_CHAR, EMBED_KEY, ID,
+};
+use rand::prelude::*;
+use serde_json::json;
 
 #[test]
 fn test_text_update() -> anyhow::Result<()> {
@@ -157,3 +172,1869 @@ fn test_failed_case_2() -> anyhow::Result<()> {
     assert_eq!(&text.to_string(), new1);
     Ok(())
 }
+
+fn by(granularity: UpdateGranularity) -> UpdateOptions {
+    UpdateOptions::default().granularity(granularity)
+}
+
+#[test]
+fn test_text_update_by_word_keeps_words_intact() -> anyhow::Result<()> {
+    let doc_a = LoroDoc::new();
+    doc_a.get_text("text").insert(0, "I like red apples.")?;
+    doc_a.commit();
+    let doc_b = LoroDoc::new();
+    doc_b.import(&doc_a.export(ExportMode::Snapshot)?)?;
+
+    doc_a
+        .get_text("text")
+        .update("I like green apples.", by(UpdateGranularity::Word))
+        .unwrap();
+    doc_b
+        .get_text("text")
+        .update("I like blue apples.", by(UpdateGranularity::Word))
+        .unwrap();
+    doc_a.import(&doc_b.export(ExportMode::all_updates())?)?;
+    doc_b.import(&doc_a.export(ExportMode::all_updates())?)?;
+
+    // Both rewrites replace the whole word, so the words are not mixed char by char
+    let s = doc_a.get_text("text").to_string();
+    assert_eq!(s, doc_b.get_text("text").to_string());
+    assert!(
+        s == "I like greenblue apples." || s == "I like bluegreen apples.",
+        "{}",
+        s
+    );
+    Ok(())
+}
+
+#[test]
+fn test_text_update_by_sentence() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world. How are you? Fine.")?;
+    doc.commit();
+    let doc_b = LoroDoc::new();
+    doc_b.import(&doc.export(ExportMode::Snapshot)?)?;
+
+    text.update(
+        "Hello world. How is it going? Fine.",
+        by(UpdateGranularity::Sentence),
+    )
+    .unwrap();
+    // The rewritten sentence is replaced as a whole, and the concurrent edit in the
+    // unchanged sentence stays in place
+    let text_b = doc_b.get_text("text");
+    text_b.insert(16, " old")?;
+    text_b.insert(5, ",")?;
+    doc.import(&doc_b.export(ExportMode::all_updates())?)?;
+    let s = text.to_string();
+    assert!(s.starts_with("Hello, world. "), "{}", s);
+    assert!(s.contains("How is it going? "), "{}", s);
+    assert!(s.contains(" old"), "{}", s);
+    assert!(s.ends_with("Fine."), "{}", s);
+    assert!(!s.contains("are you"), "{}", s);
+    Ok(())
+}
+
+#[test]
+fn test_text_update_preserve_marks() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "The quick fox")?;
+    text.mark(4..9, "bold", true)?;
+
+    let other = doc.get_text("other");
+    other.insert(0, "The quick fox")?;
+    other.mark(4..9, "bold", true)?;
+
+    // Without preserving the marks, the text inserted after the bold word is bold too
+    text.update("The quick, brown fox", by(UpdateGranularity::Word))
+        .unwrap();
+    assert_eq!(
+        text.to_delta().to_json_value(),
+        json!([
+            { "insert": "The " },
+            { "insert": "quick, brown", "attributes": { "bold": true } },
+            { "insert": " fox" },
+        ])
+    );
+
+    other
+        .update(
+            "The quick, brown fox",
+            UpdateOptions::default()
+                .granularity(UpdateGranularity::Word)
+                .preserve_marks(true),
+        )
+        .unwrap();
+    assert_eq!(
+        other.to_delta().to_json_value(),
+        json!([
+            { "insert": "The " },
+            { "insert": "quick", "attributes": { "bold": true } },
+            { "insert": ", brown fox" },
+        ])
+    );
+
+    // The line inserted after a bold line break isn't bold
+    let lines = doc.get_text("lines");
+    lines.insert(0, "a\n")?;
+    lines.mark(0..2, "bold", true)?;
+    lines
+        .update_by_line("a\nb\n", UpdateOptions::default().preserve_marks(true))
+        .unwrap();
+    assert_eq!(
+        lines.to_delta().to_json_value(),
+        json!([
+            { "insert": "a\n", "attributes": { "bold": true } },
+            { "insert": "b\n" },
+        ])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_text_update_by_tokens_random() -> anyhow::Result<()> {
+    let mut rng = StdRng::seed_from_u64(50);
+    let words = [
+        "a", "bc", " ", "  ", ".", "? ", "\n", "好的", "👋", "don't", "1.5", "\r\n",
+    ];
+    let random_text = |rng: &mut StdRng| -> String {
+        let len = rng.gen_range(0..16);
+        (0..len).map(|_| *words.choose(rng).unwrap()).collect()
+    };
+    let granularities = [
+        UpdateGranularity::Char,
+        UpdateGranularity::Word,
+        UpdateGranularity::Line,
+        UpdateGranularity::Sentence,
+    ];
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    let detached = LoroText::new();
+    for i in 0..400 {
+        let new = random_text(&mut rng);
+        let options = UpdateOptions::default()
+            .granularity(granularities[i % granularities.len()])
+            .preserve_marks(i % 8 >= 4);
+        text.update(&new, options.clone()).unwrap();
+        assert_eq!(text.to_string(), new, "{:?}", options);
+        detached.update(&new, options.clone()).unwrap();
+        assert_eq!(detached.to_string(), new, "{:?}", options);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_text_update_by_tokens_returns_the_edit_error() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    doc.commit();
+    let frontiers = doc.oplog_frontiers();
+    text.insert(11, "!")?;
+    doc.commit();
+    doc.checkout(&frontiers)?;
+    for preserve_marks in [false, true] {
+        let options = UpdateOptions::default()
+            .granularity(UpdateGranularity::Word)
+            .preserve_marks(preserve_marks);
+        assert!(matches!(
+            text.update("Hello Loro", options),
+            Err(UpdateTimeoutError::Apply(_))
+        ));
+    }
+    assert_eq!(text.to_string(), "Hello world");
+    Ok(())
+}
+
+const UNITS: [PosType; 4] = [
+    PosType::Unicode,
+    PosType::Utf16,
+    PosType::Utf8,
+    PosType::Grapheme,
+];
+
+#[test]
+fn convert_pos_between_units() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    // "é" is written as "e" with a combining acute accent
+    text.insert(0, "Hi 👋🏽 e\u{301}!")?;
+    // The boundaries of every grapheme in all the units
+    let boundaries: [[usize; 4]; 8] = [
+        [0, 0, 0, 0],
+        [1, 1, 1, 1],
+        [2, 2, 2, 2],
+        [3, 3, 3, 3],
+        [5, 7, 11, 4],
+        [6, 8, 12, 5],
+        [8, 10, 15, 6],
+        [9, 11, 16, 7],
+    ];
+    assert_eq!(text.len_grapheme(), 7);
+    for pos in boundaries {
+        for (i, from) in UNITS.iter().enumerate() {
+            for (j, to) in UNITS.iter().enumerate() {
+                assert_eq!(text.convert_pos(pos[i], *from, *to), Some(pos[j]));
+            }
+        }
+    }
+
+    // Inside the emoji with the skin tone modifier
+    assert_eq!(
+        text.convert_pos(4, PosType::Unicode, PosType::Utf16),
+        Some(5)
+    );
+    assert_eq!(
+        text.convert_pos(4, PosType::Unicode, PosType::Grapheme),
+        None
+    );
+    // Inside a surrogate pair
+    assert_eq!(text.convert_pos(4, PosType::Utf16, PosType::Unicode), None);
+    // Inside a UTF-8 encoded code point
+    assert_eq!(text.convert_pos(9, PosType::Utf8, PosType::Unicode), None);
+    // Out of bound
+    for unit in UNITS {
+        assert_eq!(text.convert_pos(20, unit, PosType::Unicode), None);
+        assert_eq!(text.convert_pos(20, PosType::Unicode, unit), None);
+    }
+    Ok(())
+}
+
+#[test]
+fn edit_by_grapheme() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "👨‍👩‍👧👩🏻‍💻")?;
+    assert_eq!(text.len_grapheme(), 2);
+    assert_eq!(text.len_unicode(), 9);
+
+    text.insert_with_pos_type(1, " and ", PosType::Grapheme)?;
+    assert_eq!(text.to_string(), "👨‍👩‍👧 and 👩🏻‍💻");
+    assert_eq!(text.slice_with_pos_type(0, 1, PosType::Grapheme)?, "👨‍👩‍👧");
+    assert_eq!(text.slice_with_pos_type(6, 7, PosType::Grapheme)?, "👩🏻‍💻");
+    assert_eq!(text.slice_with_pos_type(1, 6, PosType::Grapheme)?, " and ");
+
+    text.delete_with_pos_type(0, 2, PosType::Grapheme)?;
+    assert_eq!(text.to_string(), "and 👩🏻‍💻");
+    text.delete_with_pos_type(4, 1, PosType::Grapheme)?;
+    assert_eq!(text.to_string(), "and ");
+
+    assert!(matches!(
+        text.insert_with_pos_type(5, "!", PosType::Grapheme),
+        Err(LoroError::OutOfBound { .. })
+    ));
+    assert!(matches!(
+        text.delete_with_pos_type(2, 3, PosType::Grapheme),
+        Err(LoroError::OutOfBound { .. })
+    ));
+    assert!(matches!(
+        text.slice_with_pos_type(2, 1, PosType::Grapheme),
+        Err(LoroError::EndIndexLessThanStartIndex { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn grapheme_edits_sync_and_work_when_detached() -> anyhow::Result<()> {
+    let text = LoroText::new();
+    text.insert(0, "🇯🇵🇫🇷")?;
+    assert_eq!(text.len_grapheme(), 2);
+    text.insert_with_pos_type(1, "🇩🇪", PosType::Grapheme)?;
+    text.delete_with_pos_type(0, 1, PosType::Grapheme)?;
+    assert_eq!(text.to_string(), "🇩🇪🇫🇷");
+    assert_eq!(
+        text.convert_pos(1, PosType::Grapheme, PosType::Utf16),
+        Some(4)
+    );
+
+    let doc = LoroDoc::new();
+    let text = doc.get_map("map").insert_container("text", text)?;
+    text.insert_with_pos_type(2, "🇮🇹", PosType::Grapheme)?;
+    let other = LoroDoc::new();
+    other.import(&doc.export(ExportMode::all_updates())?)?;
+    let other_text = other.get_text(text.id());
+    assert_eq!(
+        other_text.slice_with_pos_type(1, 3, PosType::Grapheme)?,
+        "🇫🇷🇮🇹"
+    );
+    Ok(())
+}
+
+#[test]
+fn edit_with_other_pos_types() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "😀a")?;
+    text.insert_with_pos_type(2, "b", PosType::Utf16)?;
+    assert_eq!(text.to_string(), "😀ba");
+    assert_eq!(text.slice_with_pos_type(4, 6, PosType::Utf8)?, "ba");
+    text.delete_with_pos_type(0, 1, PosType::Unicode)?;
+    assert_eq!(text.to_string(), "ba");
+    assert_eq!(text.len_with_pos_type(PosType::Utf8), 2);
+
+    // Inside the surrogate pair of the emoji
+    text.insert(0, "😀")?;
+    assert!(matches!(
+        text.insert_with_pos_type(1, "c", PosType::Utf16),
+        Err(LoroError::OutOfBound { .. })
+    ));
+    Ok(())
+}
+
+fn attrs(items: &[(&str, LoroValue)]) -> Option<FxHashMap<String, LoroValue>> {
+    Some(
+        items
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect(),
+    )
+}
+
+fn insert(text: &str, attributes: Option<FxHashMap<String, LoroValue>>) -> TextDelta {
+    TextDelta::Insert {
+        insert: text.to_string(),
+        attributes,
+    }
+}
+
+#[test]
+fn styles_at_cursor() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    // bold expands after the range, link doesn't expand
+    text.mark(0..5, "bold", true)?;
+    text.mark(6..11, "link", "https://loro.dev")?;
+
+    assert!(text.get_styles_at(0)?.is_empty());
+    assert_eq!(
+        text.get_styles_at(3)?,
+        attrs(&[("bold", true.into())]).unwrap()
+    );
+    assert_eq!(
+        text.get_styles_at(5)?,
+        attrs(&[("bold", true.into())]).unwrap()
+    );
+    assert!(text.get_styles_at(6)?.is_empty());
+    assert_eq!(
+        text.get_styles_at(8)?,
+        attrs(&[("link", "https://loro.dev".into())]).unwrap()
+    );
+    assert!(text.get_styles_at(11)?.is_empty());
+    assert!(matches!(
+        text.get_styles_at(12),
+        Err(LoroError::OutOfBound { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn styles_in_selection() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    text.mark(0..5, "bold", true)?;
+    text.mark(0..3, "highlight", "red")?;
+    text.mark(3..11, "highlight", "blue")?;
+
+    let styles = text.get_styles_in(1..3)?;
+    assert_eq!(styles.len(), 2);
+    assert_eq!(
+        styles["bold"],
+        StyleRangeValue::Uniform(LoroValue::from(true))
+    );
+    assert_eq!(
+        styles["highlight"],
+        StyleRangeValue::Uniform(LoroValue::from("red"))
+    );
+
+    // The values differ
+    let styles = text.get_styles_in(0..5)?;
+    assert_eq!(
+        styles["bold"],
+        StyleRangeValue::Uniform(LoroValue::from(true))
+    );
+    assert_eq!(styles["highlight"], StyleRangeValue::Mixed);
+
+    // Only a part of the range is bold
+    let styles = text.get_styles_in(4..8)?;
+    assert_eq!(styles["bold"], StyleRangeValue::Mixed);
+    assert_eq!(
+        styles["highlight"],
+        StyleRangeValue::Uniform(LoroValue::from("blue"))
+    );
+    assert!(!text.get_styles_in(6..8)?.contains_key("bold"));
+
+    // The unmarked text doesn't have the style
+    text.unmark(0..2, "bold")?;
+    assert!(!text.get_styles_in(0..2)?.contains_key("bold"));
+    assert_eq!(text.get_styles_in(0..5)?["bold"], StyleRangeValue::Mixed);
+
+    // A collapsed range reports the styles at the cursor
+    let styles = text.get_styles_in(5..5)?;
+    assert_eq!(
+        styles["bold"],
+        StyleRangeValue::Uniform(LoroValue::from(true))
+    );
+
+    assert!(matches!(
+        text.get_styles_in(3..20),
+        Err(LoroError::OutOfBound { .. })
+    ));
+    #[allow(clippy::reversed_empty_ranges)]
+    let reversed = text.get_styles_in(3..1);
+    assert!(matches!(
+        reversed,
+        Err(LoroError::EndIndexLessThanStartIndex { .. })
+    ));
+
+    let empty = LoroDoc::new().get_text("text");
+    assert!(empty.get_styles_in(0..0)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn iter_spans_in_range() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    text.mark(0..5, "bold", true)?;
+    text.mark(3..8, "italic", true)?;
+
+    assert_eq!(
+        text.iter_spans(1..10)?.collect::<Vec<_>>(),
+        vec![
+            insert("el", attrs(&[("bold", true.into())])),
+            insert(
+                "lo",
+                attrs(&[("bold", true.into()), ("italic", true.into())])
+            ),
+            insert(" wo", attrs(&[("italic", true.into())])),
+            insert("rl", None),
+        ]
+    );
+    // The adjacent spans with the same styles are merged
+    text.insert(9, "-")?;
+    assert_eq!(
+        text.iter_spans(8..12)?.collect::<Vec<_>>(),
+        vec![insert("r-ld", None)]
+    );
+    assert_eq!(text.iter_spans(4..4)?.count(), 0);
+    // The spans are read as the iterator advances
+    let mut spans = text.iter_spans(0..12)?;
+    assert_eq!(
+        spans.next(),
+        Some(insert("Hel", attrs(&[("bold", true.into())])))
+    );
+    assert_eq!(spans.count(), 3);
+    assert!(text.iter_spans(0..13).is_err());
+
+    // Works on the detached text
+    let detached = LoroText::new();
+    detached.insert(0, "Hello")?;
+    detached.mark(1..3, "bold", true)?;
+    assert_eq!(
+        detached.iter_spans(0..4)?.collect::<Vec<_>>(),
+        vec![
+            insert("H", None),
+            insert("el", attrs(&[("bold", true.into())])),
+            insert("l", None),
+        ]
+    );
+    assert_eq!(
+        detached.get_styles_in(0..3)?["bold"],
+        StyleRangeValue::Mixed
+    );
+    assert_eq!(
+        detached.get_styles_at(2)?,
+        attrs(&[("bold", true.into())]).unwrap()
+    );
+    Ok(())
+}
+
+fn unicode(doc: &LoroDoc, range: &TextRange) -> Range<usize> {
+    doc.resolve_range(range).unwrap().unicode
+}
+
+#[test]
+fn ranges_follow_the_edits() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    let none = text.get_range(0..5, ExpandType::None).unwrap();
+    let after = text.get_range(0..5, ExpandType::After).unwrap();
+    let before = text.get_range(6..11, ExpandType::Before).unwrap();
+    let both = text.get_range(6..11, ExpandType::Both).unwrap();
+
+    // Hello! world
+    text.insert(5, "!")?;
+    assert_eq!(unicode(&doc, &none), 0..5);
+    assert_eq!(unicode(&doc, &after), 0..6);
+    // Hello! big world
+    text.insert(7, "big ")?;
+    assert_eq!(unicode(&doc, &before), 7..16);
+    assert_eq!(unicode(&doc, &both), 7..16);
+    // Hello! big world!
+    text.insert(16, "!")?;
+    assert_eq!(unicode(&doc, &before), 7..16);
+    assert_eq!(unicode(&doc, &both), 7..17);
+    // > Hello! big world!
+    text.insert(0, "> ")?;
+    assert_eq!(unicode(&doc, &none), 2..7);
+    assert_eq!(unicode(&doc, &after), 2..8);
+    assert_eq!(unicode(&doc, &before), 9..18);
+    assert_eq!(unicode(&doc, &both), 9..19);
+
+    let resolved = doc.resolve_range(&none)?;
+    assert!(!resolved.collapsed && !resolved.deleted);
+
+    assert!(text.get_range(3..12, ExpandType::None).is_some());
+    assert!(text.get_range(3..20, ExpandType::None).is_none());
+    Ok(())
+}
+
+#[test]
+fn ranges_converge_after_concurrent_edits() -> anyhow::Result<()> {
+    let a = LoroDoc::new();
+    a.set_peer_id(1)?;
+    a.get_text("text").insert(0, "Hello world")?;
+    a.commit();
+    let range = a
+        .get_text("text")
+        .get_range(6..11, ExpandType::None)
+        .unwrap();
+    // The range can be sent to the other peers
+    let range = TextRange::decode(&range.encode())?;
+
+    let b = LoroDoc::new();
+    b.set_peer_id(2)?;
+    b.import(&a.export(ExportMode::all_updates())?)?;
+    a.get_text("text").insert(0, "A: ")?;
+    b.get_text("text").insert(8, "👋")?;
+    b.get_text("text").insert(12, "!")?;
+    a.commit();
+    b.commit();
+    a.import(&b.export(ExportMode::all_updates())?)?;
+    b.import(&a.export(ExportMode::all_updates())?)?;
+
+    // A: Hello wo👋rld!
+    let resolved = a.resolve_range(&range)?;
+    assert_eq!(resolved, b.resolve_range(&range)?);
+    assert_eq!(resolved.unicode, 9..15);
+    assert_eq!(resolved.utf16, 9..16);
+    assert_eq!(resolved.utf8, 9..18);
+    Ok(())
+}
+
+#[test]
+fn collapsed_and_deleted_ranges() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    let caret = text.get_range(5..5, ExpandType::None).unwrap();
+    let hello = text.get_range(0..5, ExpandType::None).unwrap();
+    let world = text.get_range(6..11, ExpandType::Both).unwrap();
+
+    let resolved = doc.resolve_range(&caret)?;
+    assert_eq!(resolved.unicode, 5..5);
+    assert!(resolved.collapsed && !resolved.deleted);
+    text.insert(5, ",")?;
+    let resolved = doc.resolve_range(&caret)?;
+    assert!(resolved.collapsed && !resolved.deleted);
+
+    // Hlo, world
+    text.delete(1, 2)?;
+    assert_eq!(unicode(&doc, &hello), 0..3);
+    // , world
+    text.delete(0, 3)?;
+    let resolved = doc.resolve_range(&hello)?;
+    assert_eq!(resolved.unicode, 0..0);
+    assert!(resolved.collapsed && resolved.deleted);
+
+    // The range expanding on both sides is anchored to the chars around it, which are kept
+    text.delete(2, 5)?;
+    let resolved = doc.resolve_range(&world)?;
+    assert_eq!(resolved.unicode, 2..2);
+    assert!(resolved.collapsed && !resolved.deleted);
+    text.insert(2, "there")?;
+    assert_eq!(unicode(&doc, &world), 2..7);
+    Ok(())
+}
+
+#[test]
+fn ranges_follow_checkout() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    doc.commit();
+    let v1 = doc.oplog_frontiers();
+    let world = text.get_range(6..11, ExpandType::None).unwrap();
+    text.insert(0, "Say: ")?;
+    doc.commit();
+    let v2 = doc.oplog_frontiers();
+    let say = text.get_range(0..3, ExpandType::None).unwrap();
+    // Say: Hello
+    text.delete(10, 6)?;
+    doc.commit();
+    let v3 = doc.oplog_frontiers();
+    text.insert(0, "> ")?;
+    doc.commit();
+
+    let resolved = doc.resolve_range(&world)?;
+    assert_eq!(resolved.unicode, 12..12);
+    assert!(resolved.deleted);
+
+    doc.checkout(&v1)?;
+    let resolved = doc.resolve_range(&world)?;
+    assert_eq!(resolved.unicode, 6..11);
+    assert!(!resolved.deleted);
+    // The range doesn't exist at this version
+    assert!(matches!(
+        doc.resolve_range(&say),
+        Err(CannotFindRelativePosition::IdNotFound)
+    ));
+
+    doc.checkout(&v2)?;
+    assert_eq!(unicode(&doc, &world), 11..16);
+    assert_eq!(unicode(&doc, &say), 0..3);
+
+    // The deleted range is located at the checked out version
+    doc.checkout(&v3)?;
+    let resolved = doc.resolve_range(&world)?;
+    assert_eq!(resolved.unicode, 10..10);
+    assert!(resolved.collapsed && resolved.deleted);
+
+    doc.checkout_to_latest();
+    assert_eq!(unicode(&doc, &world), 12..12);
+    assert_eq!(unicode(&doc, &say), 2..5);
+    Ok(())
+}
+
+#[test]
+fn ranges_in_shallow_snapshot() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    doc.commit();
+    let hello = text.get_range(0..5, ExpandType::None).unwrap();
+    let world = text.get_range(6..11, ExpandType::None).unwrap();
+    text.delete(5, 6)?;
+    text.insert(0, "Hi ")?;
+    doc.commit();
+    let hi = text.get_range(0..2, ExpandType::None).unwrap();
+
+    let new_doc = LoroDoc::new();
+    new_doc.import(&doc.export(ExportMode::shallow_snapshot(&doc.oplog_frontiers()))?)?;
+    assert_eq!(unicode(&new_doc, &hi), 0..2);
+    assert_eq!(unicode(&new_doc, &hello), 3..8);
+    // The deletion is before the shallow root
+    assert!(matches!(
+        new_doc.resolve_range(&world),
+        Err(CannotFindRelativePosition::HistoryCleared)
+    ));
+
+    // The deletion after the shallow root can be traced
+    new_doc.get_text("text").delete(3, 5)?;
+    new_doc.commit();
+    let resolved = new_doc.resolve_range(&hello)?;
+    assert_eq!(resolved.unicode, 3..3);
+    assert!(resolved.collapsed && resolved.deleted);
+    Ok(())
+}
+
+fn line_starts(s: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    for (i, c) in s.chars().enumerate() {
+        if c == '\n' {
+            starts.push(i + 1);
+        }
+    }
+    starts
+}
+
+fn assert_lines_match(text: &LoroText) {
+    let s = text.to_string();
+    let starts = line_starts(&s);
+    assert_eq!(text.line_count(), starts.len());
+    for (line, start) in starts.iter().enumerate() {
+        assert_eq!(text.line_to_offset(line), Some(*start));
+    }
+    assert_eq!(text.line_to_offset(starts.len()), None);
+    for (line, content) in s.split('\n').enumerate() {
+        assert_eq!(text.get_line(line).as_deref(), Some(content));
+    }
+
+    let len = text.len_unicode();
+    for pos in 0..=len {
+        let line = starts.iter().rposition(|start| *start <= pos).unwrap();
+        assert_eq!(
+            text.offset_to_line_col(pos),
+            Some((line, pos - starts[line]))
+        );
+    }
+    assert_eq!(text.offset_to_line_col(len + 1), None);
+}
+
+#[test]
+fn line_and_column_addressing() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    assert_eq!(text.line_count(), 1);
+    assert_eq!(text.get_line(0).unwrap(), "");
+    assert_eq!(text.offset_to_line_col(0), Some((0, 0)));
+
+    text.insert(0, "first\nsecond 👋\n\nlast\n")?;
+    assert_eq!(text.line_count(), 5);
+    assert_eq!(text.line_to_offset(2), Some(15));
+    assert_eq!(text.get_line(1).unwrap(), "second 👋");
+    assert_eq!(text.get_line(2).unwrap(), "");
+    assert_eq!(text.get_line(4).unwrap(), "");
+    assert_eq!(text.get_line(5), None);
+    // The line break belongs to the line it ends
+    assert_eq!(text.offset_to_line_col(5), Some((0, 5)));
+    assert_eq!(text.offset_to_line_col(6), Some((1, 0)));
+    assert_eq!(text.offset_to_line_col(14), Some((1, 8)));
+    assert_lines_match(&text);
+
+    // Styles don't affect the lines
+    text.mark(3..10, "bold", true)?;
+    assert_lines_match(&text);
+
+    text.delete(5, 1)?;
+    assert_eq!(text.get_line(0).unwrap(), "firstsecond 👋");
+    assert_lines_match(&text);
+    Ok(())
+}
+
+#[test]
+fn line_index_follows_random_edits() -> anyhow::Result<()> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let a = LoroDoc::new();
+    a.set_peer_id(1)?;
+    let b = LoroDoc::new();
+    b.set_peer_id(2)?;
+    let pieces = ["\n", "ab", "\n\n", "x\ny", "😀", "line\n", "z"];
+    for _ in 0..100 {
+        for doc in [&a, &b] {
+            let text = doc.get_text("text");
+            let len = text.len_unicode();
+            if len > 0 && rng.gen_bool(0.3) {
+                let pos = rng.gen_range(0..len);
+                let del = rng.gen_range(1..=(len - pos).min(5));
+                text.delete(pos, del)?;
+            } else {
+                let pos = rng.gen_range(0..=len);
+                text.insert(pos, pieces[rng.gen_range(0..pieces.len())])?;
+            }
+        }
+        if rng.gen_bool(0.2) {
+            a.import(&b.export(loro::ExportMode::all_updates())?)?;
+            b.import(&a.export(loro::ExportMode::all_updates())?)?;
+        }
+    }
+
+    assert_lines_match(&a.get_text("text"));
+    assert_lines_match(&b.get_text("text"));
+    Ok(())
+}
+
+#[test]
+fn lines_of_detached_text() -> anyhow::Result<()> {
+    let text = LoroText::new();
+    text.insert(0, "a\nb\nc")?;
+    assert_eq!(text.line_count(), 3);
+    assert_eq!(text.offset_to_line_col(4), Some((2, 0)));
+    assert_eq!(text.get_line(1).unwrap(), "b");
+    assert_lines_match(&text);
+    Ok(())
+}
+
+#[test]
+fn describe_changes_as_lines() -> anyhow::Result<()> {
+    let delta = [
+        TextDelta::Retain {
+            retain: 2,
+            attributes: None,
+        },
+        TextDelta::Insert {
+            insert: "x\ny".into(),
+            attributes: None,
+        },
+        TextDelta::Retain {
+            retain: 4,
+            attributes: None,
+        },
+        TextDelta::Delete { delete: 1 },
+    ];
+    // "a\nb\nc\nd" -> "a\nx\nyb\nc\n"
+    assert_eq!(
+        TextDelta::line_changes(&delta, "a\nb\nc\nd"),
+        vec![
+            LineChange {
+                old: 1..2,
+                new: 1..3
+            },
+            LineChange {
+                old: 3..4,
+                new: 4..5
+            },
+        ]
+    );
+
+    // The format changes are included
+    let delta = [
+        TextDelta::Retain {
+            retain: 3,
+            attributes: None,
+        },
+        TextDelta::Retain {
+            retain: 3,
+            attributes: Some([("bold".to_string(), true.into())].into_iter().collect()),
+        },
+    ];
+    assert_eq!(
+        TextDelta::line_changes(
+            &delta, "ab
+cd
+ef"
+        ),
+        vec![LineChange {
+            old: 1..3,
+            new: 1..3
+        }]
+    );
+
+    // The changes on the same line are merged
+    let delta = [
+        TextDelta::Insert {
+            insert: "x".into(),
+            attributes: None,
+        },
+        TextDelta::Retain {
+            retain: 1,
+            attributes: None,
+        },
+        TextDelta::Delete { delete: 1 },
+    ];
+    assert_eq!(
+        TextDelta::line_changes(&delta, "abc\nd"),
+        vec![LineChange {
+            old: 0..1,
+            new: 0..1
+        }]
+    );
+
+    // Describe the changes in the events
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "one\ntwo\nthree")?;
+    doc.commit();
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let changes_clone = changes.clone();
+    let old_text = text.to_string();
+    let _sub = doc.subscribe_root(Arc::new(move |batch| {
+        for e in batch.events {
+            if let Diff::Text(delta) = e.diff {
+                changes_clone
+                    .lock()
+                    .unwrap()
+                    .extend(TextDelta::line_changes(&delta, &old_text));
+            }
+        }
+    }));
+    text.insert(6, "!\n")?;
+    text.delete(10, 2)?;
+    doc.commit();
+    // "one\ntw!\no\nree"
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![
+            LineChange {
+                old: 1..2,
+                new: 1..3
+            },
+            LineChange {
+                old: 2..3,
+                new: 3..4
+            },
+        ]
+    );
+    Ok(())
+}
+
+fn ranges(text: &LoroText, pattern: &str, options: &TextSearchOptions) -> Vec<Range<usize>> {
+    text.find(pattern, options)
+        .into_iter()
+        .map(|m| m.range)
+        .collect()
+}
+
+#[test]
+fn find_across_chunks() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    // The text is split into many chunks by the edits and the styles
+    for (i, c) in "the cat, the Cat and the 👋 catalog".chars().enumerate() {
+        text.insert(i, &c.to_string())?;
+        doc.commit();
+    }
+    text.mark(5..15, "bold", true)?;
+    let default = TextSearchOptions::default();
+
+    assert_eq!(ranges(&text, "cat", &default), vec![4..7, 27..30]);
+    assert_eq!(ranges(&text, "he ", &default), vec![1..4, 10..13, 22..25]);
+    assert!(ranges(&text, "dog", &default).is_empty());
+    assert!(ranges(&text, "", &default).is_empty());
+
+    let insensitive = TextSearchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        ranges(&text, "CAT", &insensitive),
+        vec![4..7, 13..16, 27..30]
+    );
+    let whole_word = TextSearchOptions {
+        case_sensitive: false,
+        whole_word: true,
+        ..Default::default()
+    };
+    assert_eq!(ranges(&text, "cat", &whole_word), vec![4..7, 13..16]);
+    assert_eq!(ranges(&text, "the", &whole_word), vec![0..3, 9..12, 21..24]);
+
+    let utf16 = TextSearchOptions {
+        pos_type: PosType::Utf16,
+        ..Default::default()
+    };
+    assert_eq!(ranges(&text, "catalog", &utf16), vec![28..35]);
+    let utf8 = TextSearchOptions {
+        pos_type: PosType::Utf8,
+        ..Default::default()
+    };
+    assert_eq!(ranges(&text, "👋 c", &utf8), vec![25..31]);
+    Ok(())
+}
+
+#[test]
+fn find_by_grapheme() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    // The second "e" has a combining acute accent
+    text.insert(0, "👨‍👩‍👧 e e\u{301} e")?;
+    let options = TextSearchOptions {
+        pos_type: PosType::Grapheme,
+        ..Default::default()
+    };
+    // The match that splits a grapheme is skipped
+    assert_eq!(ranges(&text, "e", &options), vec![2..3, 6..7]);
+    assert_eq!(ranges(&text, "e\u{301}", &options), vec![4..5]);
+    Ok(())
+}
+
+#[test]
+fn matches_with_text_ranges() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "TODO: a, TODO: b")?;
+    let options = TextSearchOptions {
+        with_text_ranges: true,
+        ..Default::default()
+    };
+    let matches = text.find("TODO", &options);
+    assert_eq!(matches.len(), 2);
+
+    text.insert(0, "> ")?;
+    text.delete(9, 1)?;
+    // > TODO: a TODO: b
+    let resolved = matches
+        .iter()
+        .map(|m| {
+            doc.resolve_range(m.text_range.as_ref().unwrap())
+                .unwrap()
+                .unicode
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(resolved, vec![2..6, 10..14]);
+
+    assert!(text
+        .find("TODO", &TextSearchOptions::default())
+        .iter()
+        .all(|m| m.text_range.is_none()));
+    Ok(())
+}
+
+#[test]
+fn replace_all_keeps_the_rest_of_the_text() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "colour of the colours")?;
+    text.mark(0..6, "bold", true)?;
+    let cursor = text.get_cursor(16, Default::default()).unwrap();
+
+    assert_eq!(
+        text.replace_all("colour", "color", &TextSearchOptions::default())?,
+        2
+    );
+    assert_eq!(text.to_string(), "color of the colors");
+    // Only the "u"s are deleted, so the style and the cursor are kept
+    assert_eq!(
+        text.get_styles_in(0..5)?["bold"],
+        StyleRangeValue::Uniform(LoroValue::from(true))
+    );
+    assert!(!text.get_styles_in(5..6)?.contains_key("bold"));
+    assert_eq!(doc.get_cursor_pos(&cursor)?.current.pos, 15);
+
+    assert_eq!(
+        text.replace_all("missing", "x", &TextSearchOptions::default())?,
+        0
+    );
+    // The same text is counted but not edited
+    doc.commit();
+    let vv = doc.oplog_vv();
+    assert_eq!(
+        text.replace_all("of", "of", &TextSearchOptions::default())?,
+        1
+    );
+    doc.commit();
+    assert_eq!(doc.oplog_vv(), vv);
+
+    let whole_word = TextSearchOptions {
+        whole_word: true,
+        ..Default::default()
+    };
+    text.replace_all("color", "shade", &whole_word)?;
+    assert_eq!(text.to_string(), "shade of the colors");
+    Ok(())
+}
+
+#[test]
+fn replace_all_in_detached_text() -> anyhow::Result<()> {
+    let text = LoroText::new();
+    text.insert(0, "a-b-c")?;
+    assert_eq!(
+        text.replace_all("-", " + ", &TextSearchOptions::default())?,
+        2
+    );
+    assert_eq!(text.to_string(), "a + b + c");
+    Ok(())
+}
+
+#[cfg(feature = "text-regex")]
+#[test]
+fn find_and_replace_by_regex() -> anyhow::Result<()> {
+    use loro::LoroError;
+
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "👋 2024-01-02, 2025-12-31, 12345-1-1")?;
+    let matches = text.find_regex(r"\d{4}-\d{2}-\d{2}", &TextSearchOptions::default())?;
+    assert_eq!(
+        matches.into_iter().map(|m| m.range).collect::<Vec<_>>(),
+        vec![2..12, 14..24]
+    );
+
+    let options = TextSearchOptions {
+        whole_word: true,
+        pos_type: PosType::Utf16,
+        ..Default::default()
+    };
+    let matches = text.find_regex(r"\d+", &options)?;
+    assert_eq!(matches.len(), 9);
+    assert_eq!(matches[0].range, 3..7);
+
+    let replaced = text.replace_all_regex(
+        r"(?P<y>\d{4})-(?P<m>\d{2})-(?P<d>\d{2})",
+        "$d/$m/$y",
+        &TextSearchOptions::default(),
+    )?;
+    assert_eq!(replaced, 2);
+    assert_eq!(text.to_string(), "👋 02/01/2024, 31/12/2025, 12345-1-1");
+
+    let options = TextSearchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+    text.insert(0, "Hello ")?;
+    assert_eq!(text.find_regex("HELLO", &options)?.len(), 1);
+    assert!(matches!(
+        text.find_regex("(", &options),
+        Err(LoroError::ArgErr(_))
+    ));
+    Ok(())
+}
+
+fn text_with(s: &str) -> (LoroDoc, LoroText) {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, s).unwrap();
+    (doc, text)
+}
+
+#[test]
+fn diff_with_writes_unified_format() {
+    let (_doc, text) = text_with("1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n");
+    let hunks = text.diff_with("1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n");
+    assert_eq!(hunks.len(), 2);
+    assert_eq!(
+        unified_diff(&hunks, "a/text", "b/text"),
+        "--- a/text\n+++ b/text\n\
+         @@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n\
+         @@ -9,4 +9,3 @@\n 9\n 10\n 11\n-12\n"
+    );
+
+    // Close changes are in the same hunk
+    let hunks = text.diff_with("1\n2\nthree\n4\n5\n6\n7\n8\nnine\n10\n11\n12\n");
+    assert_eq!(hunks.len(), 1);
+    assert_eq!((hunks[0].old_start, hunks[0].old_len), (1, 12));
+
+    assert!(text.diff_with(&text.to_string()).is_empty());
+}
+
+#[test]
+fn diff_of_empty_text() {
+    let (_doc, text) = text_with("");
+    let hunks = text.diff_with("a\nb\n");
+    assert_eq!(
+        unified_diff(&hunks, "a", "b"),
+        "--- a\n+++ b\n@@ -0,0 +1,2 @@\n+a\n+b\n"
+    );
+
+    let (_doc, empty) = text_with("");
+    empty.apply_patch(&unified_diff(&hunks, "a", "b")).unwrap();
+    assert_eq!(empty.to_string(), "a\nb\n");
+}
+
+#[test]
+fn no_newline_at_end_of_file() {
+    let (_doc, text) = text_with("a\nb");
+    let hunks = text.diff_with("a\nb\n");
+    assert_eq!(
+        hunks[0].lines,
+        vec![
+            HunkLine::Context("a\n".into()),
+            HunkLine::Delete("b".into()),
+            HunkLine::Insert("b\n".into()),
+        ]
+    );
+    let patch = unified_diff(&hunks, "a", "b");
+    assert_eq!(
+        patch,
+        "--- a\n+++ b\n@@ -1,2 +1,2 @@\n a\n-b\n\\ No newline at end of file\n+b\n"
+    );
+
+    text.apply_patch(&patch).unwrap();
+    assert_eq!(text.to_string(), "a\nb\n");
+}
+
+#[test]
+fn apply_patch_to_shifted_text() {
+    let old = "fn main() {\n    let a = 1;\n    let b = 2;\n    println!(\"{}\", a + b);\n}\n";
+    let new = "fn main() {\n    let a = 1;\n    let b = 3;\n    println!(\"{}\", a + b);\n}\n";
+    let (_doc, text) = text_with(old);
+    let patch = unified_diff(&text.diff_with(new), "a", "b");
+
+    let (_doc, other) = text_with(&format!("use std::fmt;\n\n{}\nfn other() {{}}\n", old));
+    other.apply_patch(&patch).unwrap();
+    assert_eq!(
+        other.to_string(),
+        format!("use std::fmt;\n\n{}\nfn other() {{}}\n", new)
+    );
+}
+
+#[test]
+fn apply_patch_with_fuzz() {
+    let patch = "--- a\n+++ b\n@@ -1,5 +1,5 @@\n a\n b\n-c\n+C\n d\n e\n";
+
+    // The first and the last context lines are changed
+    let (_doc, text) = text_with("x\nb\nc\nd\ny\n");
+    text.apply_patch(patch).unwrap();
+    assert_eq!(text.to_string(), "x\nb\nC\nd\ny\n");
+
+    // The trailing whitespaces are ignored with fuzz, and the text keeps its context lines
+    let (_doc, text) = text_with("a\nb  \nc\nd\t\ne\n");
+    text.apply_patch(patch).unwrap();
+    assert_eq!(text.to_string(), "a\nb  \nC\nd\t\ne\n");
+}
+
+#[test]
+fn failed_hunk_leaves_text_unchanged() {
+    let patch =
+        "--- a\n+++ b\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -10,3 +10,3 @@\n j\n-k\n+K\n l\n";
+    let (doc, text) = text_with("a\nb\nc\nd\n");
+    doc.commit();
+    let vv = doc.oplog_vv();
+    assert_eq!(
+        text.apply_patch(patch),
+        Err(TextPatchError::HunkNotApplied { index: 1 })
+    );
+    doc.commit();
+    assert_eq!(text.to_string(), "a\nb\nc\nd\n");
+    assert_eq!(doc.oplog_vv(), vv);
+}
+
+#[test]
+fn invalid_patch() {
+    let (_doc, text) = text_with("a\nb\n");
+    assert!(matches!(
+        text.apply_patch("@@ -1,2 +1,2 @@\n a\n-b\n"),
+        Err(TextPatchError::InvalidPatch { .. })
+    ));
+    assert!(matches!(
+        text.apply_patch("@@ -1,2 +1,1 @@\n a\n b\n"),
+        Err(TextPatchError::InvalidPatch { line: 3, .. })
+    ));
+    assert!(matches!(
+        text.apply_patch("@@ -1,2 +1,2 @@\n a\n?b\n"),
+        Err(TextPatchError::InvalidPatch { .. })
+    ));
+    assert!(matches!(
+        text.apply_patch("@@ x @@\n"),
+        Err(TextPatchError::InvalidPatch { line: 1, .. })
+    ));
+    assert_eq!(text.to_string(), "a\nb\n");
+}
+
+#[test]
+fn text_diff_between_versions() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    let v0 = doc.state_frontiers();
+    text.insert(0, "a\nb\nc\n").unwrap();
+    doc.commit();
+    let v1 = doc.state_frontiers();
+    text.insert(2, "inserted\n").unwrap();
+    text.delete(text.len_unicode() - 2, 2).unwrap();
+    doc.commit();
+    let v2 = doc.state_frontiers();
+
+    let hunks = doc.text_diff_between(&text.id(), &v1, &v2).unwrap();
+    assert_eq!(
+        unified_diff(&hunks, "v1", "v2"),
+        "--- v1\n+++ v2\n@@ -1,3 +1,3 @@\n a\n+inserted\n b\n-c\n"
+    );
+    let hunks = doc.text_diff_between(&text.id(), &v0, &v1).unwrap();
+    assert_eq!(
+        unified_diff(&hunks, "v0", "v1"),
+        "--- v0\n+++ v1\n@@ -0,0 +1,3 @@\n+a\n+b\n+c\n"
+    );
+
+    // The patch between the versions turns the old text into the new one
+    let (_other_doc, other) = text_with("a\nb\nc\n");
+    other
+        .apply_patch(&unified_diff(
+            &doc.text_diff_between(&text.id(), &v1, &v2).unwrap(),
+            "v1",
+            "v2",
+        ))
+        .unwrap();
+    assert_eq!(other.to_string(), text.to_string());
+
+    // Checking the diff doesn't change the doc
+    assert_eq!(doc.state_frontiers(), v2);
+    assert!(!doc.is_detached());
+    assert!(doc
+        .text_diff_between(&doc.get_map("map").id(), &v1, &v2)
+        .is_err());
+}
+
+#[test]
+fn text_diff_between_versions_of_a_shallow_doc() {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1).unwrap();
+    let text = doc.get_text("text");
+    text.insert(0, "a\nb\n").unwrap();
+    doc.commit();
+    let v1 = doc.state_frontiers();
+    text.insert(0, "x\n").unwrap();
+    doc.commit();
+    let v2 = doc.state_frontiers();
+    text.delete(2, 2).unwrap();
+    text.mark(0..1, "bold", true).unwrap();
+    doc.commit();
+    let v3 = doc.state_frontiers();
+
+    let shallow = LoroDoc::new();
+    shallow
+        .import(&doc.export(ExportMode::shallow_snapshot(&v2)).unwrap())
+        .unwrap();
+    assert_eq!(
+        unified_diff(
+            &shallow.text_diff_between(&text.id(), &v2, &v3).unwrap(),
+            "v2",
+            "v3"
+        ),
+        unified_diff(
+            &doc.text_diff_between(&text.id(), &v2, &v3).unwrap(),
+            "v2",
+            "v3"
+        )
+    );
+    assert!(matches!(
+        shallow.text_diff_between(&text.id(), &v1, &v3),
+        Err(LoroError::SwitchToVersionBeforeShallowRoot)
+    ));
+    assert!(matches!(
+        shallow.text_diff_between(&text.id(), &Frontiers::from_id(ID::new(2, 0)), &v3),
+        Err(LoroError::FrontiersNotFound(_))
+    ));
+    assert_eq!(shallow.state_frontiers(), v3);
+}
+
+#[test]
+fn random_diff_and_apply_patch() {
+    let mut rng = StdRng::seed_from_u64(47);
+    let words = ["a\n", "b\n", "c\n", "\n", "d", "e\n"];
+    let random_text = |rng: &mut StdRng| -> String {
+        let len = rng.gen_range(0..20);
+        (0..len).map(|_| *words.choose(rng).unwrap()).collect()
+    };
+    for _ in 0..200 {
+        let old = random_text(&mut rng);
+        let new = random_text(&mut rng);
+        let (_doc, text) = text_with(&old);
+        let hunks = text.diff_with(&new);
+        for hunk in hunks.iter() {
+            let count = |f: fn(&HunkLine) -> bool| hunk.lines.iter().filter(|l| f(l)).count();
+            assert_eq!(hunk.old_len, count(|l| !matches!(l, HunkLine::Insert(_))));
+            assert_eq!(hunk.new_len, count(|l| !matches!(l, HunkLine::Delete(_))));
+        }
+
+        text.apply_patch(&unified_diff(&hunks, "old", "new"))
+            .unwrap();
+        assert_eq!(text.to_string(), new, "old: {:?}", old);
+    }
+}
+
+#[test]
+fn to_html_nests_and_escapes() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "one two three <&>").unwrap();
+    text.mark(0..7, "bold", true).unwrap();
+    text.mark(4..13, "italic", true).unwrap();
+    text.mark(14..17, "comment", "not mapped").unwrap();
+    assert_eq!(
+        text.to_html(&HtmlMapping::default_rich_text()),
+        "<strong>one <em>two</em></strong><em> three</em> &lt;&amp;&gt;"
+    );
+    assert_eq!(
+        text.to_html(&HtmlMapping::new()),
+        "one two three &lt;&amp;&gt;"
+    );
+
+    let mapping = HtmlMapping::new()
+        .style("italic", "i")
+        .style_with_attribute("comment", "span", "data-comment");
+    assert_eq!(
+        text.to_html(&mapping),
+        "one <i>two three</i> <span data-comment=\"not mapped\">&lt;&amp;&gt;</span>"
+    );
+}
+
+#[test]
+fn html_round_trip() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "  Hi <there> & co\n\nline  two \nimage: ")
+        .unwrap();
+    text.insert_embed(
+        text.len_unicode(),
+        loro_value!({ "image": { "src": "a.png", "alt": "An \"image\"" } }),
+    )
+    .unwrap();
+    text.mark(2..4, "bold", true).unwrap();
+    text.mark(3..12, "link", "https://a.com/?x=1&y=\"2\"")
+        .unwrap();
+    text.mark(19..23, "italic", true).unwrap();
+
+    let mapping = HtmlMapping::default_rich_text();
+    let html = text.to_html(&mapping);
+    assert_eq!(
+        html,
+        "&nbsp;&nbsp;<strong>H</strong>\
+         <a href=\"https://a.com/?x=1&amp;y=&quot;2&quot;\"><strong>i</strong> &lt;there&gt;</a> \
+         &amp; co<br><br><em>line</em> &nbsp;two&nbsp;<br>\
+         image: <img src=\"a.png\" alt=\"An &quot;image&quot;\">"
+    );
+
+    let other_doc = LoroDoc::new();
+    let other = other_doc.get_text("text");
+    other.insert_html(0, &html, &mapping).unwrap();
+    assert_eq!(other.to_string(), text.to_string());
+    assert_eq!(
+        other.to_delta().to_json_value(),
+        text.to_delta().to_json_value()
+    );
+}
+
+#[test]
+fn inserted_html_does_not_inherit_styles() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "ab").unwrap();
+    text.mark(0..2, "bold", true).unwrap();
+    let mapping = HtmlMapping::default_rich_text();
+    text.insert_html(2, "c<em>d</em>", &mapping).unwrap();
+    text.insert_html(1, "<b>x</b>", &mapping).unwrap();
+    assert_eq!(
+        text.to_delta().to_json_value(),
+        json!([
+            { "insert": "axb", "attributes": { "bold": true } },
+            { "insert": "c" },
+            { "insert": "d", "attributes": { "italic": true } },
+        ])
+    );
+
+    assert!(text.insert_html(100, "<b>x</b>", &mapping).is_err());
+    assert_eq!(text.to_string(), "axbcd");
+}
+
+#[test]
+fn insert_html_sanitizes() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    let html = r#"<!DOCTYPE html><html><head><title>Title</title><style>p { color: red }</style></head>
+        <body><div onclick="steal()"><a href="javascript:alert(1)">click</a>
+        <a href=" JaVa&#10;script:alert(1)">here</a>
+        <unknown class="x">kept</unknown><script>alert("<b>1</b>")</script>
+        <img src="javascript:alert(1)" alt="pic" onerror="steal()">
+        <iframe src="https://evil.com">frame</iframe><!-- <b>comment</b> --></div></body></html>"#;
+    text.insert_html(0, html, &HtmlMapping::default_rich_text())
+        .unwrap();
+    assert_eq!(
+        text.to_delta().to_json_value(),
+        json!([
+            { "insert": "click here kept " },
+            { "insert": { "image": { "alt": "pic" } } },
+        ])
+    );
+}
+
+#[test]
+fn unconfigured_keys_are_ignored() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    let mapping = HtmlMapping::default_rich_text().style("strike", "s");
+    text.insert_html(0, "<s>a</s><img src=\"a.png\"><strong>b</strong>", &mapping)
+        .unwrap();
+    assert_eq!(
+        text.to_delta().to_json_value(),
+        json!([
+            { "insert": "a" },
+            { "insert": { "image": { "src": "a.png" } } },
+            { "insert": "b", "attributes": { "bold": true } },
+        ])
+    );
+}
+
+#[test]
+fn embeds_to_html() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "ab").unwrap();
+    text.insert_embed(1, loro_value!({ "image": "a.png" }))
+        .unwrap();
+    text.insert_embed(2, loro_value!({ "video": "a.mp4" }))
+        .unwrap();
+    text.insert_embed(3, "@alice").unwrap();
+    text.insert_embed(
+        4,
+        loro_value!({ "image": { "src": "javascript:alert(1)", "alt": "pic" } }),
+    )
+    .unwrap();
+    // A plain `EMBED_CHAR` isn't an embed
+    text.insert(5, &EMBED_CHAR.to_string()).unwrap();
+    text.mark(0..7, "bold", true).unwrap();
+    // The embeds without a rule are not written, and the string embeds are not written
+    // as text
+    assert_eq!(
+        text.to_html(&HtmlMapping::default_rich_text()),
+        format!(
+            "<strong>a<img src=\"a.png\"><img alt=\"pic\">{}b</strong>",
+            EMBED_CHAR
+        )
+    );
+}
+
+#[test]
+fn insert_html_whitespaces_and_blocks() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    let mapping = HtmlMapping::new();
+    text.insert_html(
+        0,
+        "<p>  a \n b  </p><pre>x  y\r\n z</pre><ul><li>one</li> <li>two<br></li></ul>end",
+        &mapping,
+    )
+    .unwrap();
+    assert_eq!(text.to_string(), "a b\nx  y\n z\none\ntwo\nend");
+
+    let text = doc.get_text("text2");
+    text.insert_html(
+        0,
+        "&lt;&#65;&#x42;&amp;&unknown; a&b&nbsp;&nbsp;c<br>  <br>d < e",
+        &mapping,
+    )
+    .unwrap();
+    assert_eq!(text.to_string(), "<AB&&unknown; a&b  c\n\nd < e");
+}
+
+#[test]
+fn embeds_in_delta() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "ab").unwrap();
+    text.mark(0..2, "bold", true).unwrap();
+    text.insert_embed(1, loro_value!({ "image": "a.png" }))
+        .unwrap();
+    text.insert_embed(3, "@alice").unwrap();
+    assert_eq!(text.to_string(), format!("a{}b{}", EMBED_CHAR, EMBED_CHAR));
+    assert_eq!(text.len_unicode(), 4);
+    assert_eq!(
+        text.to_delta().to_json_value(),
+        json!([
+            { "insert": "a", "attributes": { "bold": true } },
+            { "insert": { "image": "a.png" }, "attributes": { "bold": true } },
+            { "insert": "b", "attributes": { "bold": true } },
+            { "insert": "@alice", "attributes": { "bold": true } },
+        ])
+    );
+
+    assert!(text.insert_embed(0, LoroValue::Null).is_err());
+    assert!(text
+        .insert_embed(0, LoroValue::Container(doc.get_map("map").id()))
+        .is_err());
+    assert!(text.mark(0..1, EMBED_KEY, true).is_err());
+    assert!(text.unmark(0..1, EMBED_KEY).is_err());
+    assert_eq!(text.len_unicode(), 4);
+}
+
+#[test]
+fn block_attributes_of_lines() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "a\nb").unwrap();
+    assert!(text.get_block_attributes(1).unwrap().is_empty());
+    assert!(text.get_block_attributes(2).is_none());
+
+    text.set_block_attribute(0, "header", 1).unwrap();
+    // The line break is appended to the last line
+    text.set_block_attribute(1, "list", "ordered").unwrap();
+    assert_eq!(text.to_string(), "a\nb\n");
+    assert_eq!(
+        text.to_delta().to_json_value(),
+        json!([
+            { "insert": "a" },
+            { "insert": "\n", "attributes": { "header": 1 } },
+            { "insert": "b" },
+            { "insert": "\n", "attributes": { "list": "ordered" } },
+        ])
+    );
+    let attrs = text.get_block_attributes(1).unwrap();
+    assert_eq!(attrs.len(), 1);
+    assert_eq!(attrs.get("list"), Some(&"ordered".into()));
+    assert!(text.get_block_attributes(2).unwrap().is_empty());
+
+    // The line break typed before the marked one doesn't inherit the attribute
+    text.insert(1, "x\ny").unwrap();
+    assert!(text.get_block_attributes(0).unwrap().is_empty());
+    assert_eq!(
+        text.get_block_attributes(1).unwrap().get("header"),
+        Some(&1.into())
+    );
+
+    text.remove_block_attribute(1, "header").unwrap();
+    assert!(text.get_block_attributes(1).unwrap().is_empty());
+    assert!(text.set_block_attribute(0, "bold", true).is_err());
+    assert!(text.set_block_attribute(10, "header", 1).is_err());
+}
+
+#[test]
+fn mark_with_block_key_marks_line_breaks() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "a\nb\nc").unwrap();
+    text.mark(0..5, "blockquote", true).unwrap();
+    assert_eq!(
+        text.to_delta().to_json_value(),
+        json!([
+            { "insert": "a" },
+            { "insert": "\n", "attributes": { "blockquote": true } },
+            { "insert": "b" },
+            { "insert": "\n", "attributes": { "blockquote": true } },
+            { "insert": "c" },
+        ])
+    );
+
+    text.unmark(2..4, "blockquote").unwrap();
+    assert_eq!(
+        text.get_block_attributes(0).unwrap().get("blockquote"),
+        Some(&true.into())
+    );
+    assert!(text.get_block_attributes(1).unwrap().is_empty());
+}
+
+#[test]
+fn custom_block_key() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "a\n").unwrap();
+    assert!(text.set_block_attribute(0, "align", "center").is_err());
+
+    let mut styles = StyleConfigMap::default_rich_text_config();
+    styles.insert("align".into(), StyleConfig::new().block(true));
+    doc.config_text_style(styles);
+    text.set_block_attribute(0, "align", "center").unwrap();
+    assert_eq!(
+        text.get_block_attributes(0).unwrap().get("align"),
+        Some(&"center".into())
+    );
+}
+
+#[test]
+fn apply_delta_with_blocks_and_embeds() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.apply_delta(&[
+        TextDelta::Insert {
+            insert: "Title\n".into(),
+            attributes: Some([("header".to_string(), 2.into())].into_iter().collect()),
+        },
+        TextDelta::Insert {
+            insert: EMBED_CHAR.to_string(),
+            attributes: Some(
+                [(EMBED_KEY.to_string(), loro_value!({ "video": "a.mp4" }))]
+                    .into_iter()
+                    .collect(),
+            ),
+        },
+    ])
+    .unwrap();
+    assert_eq!(
+        text.to_delta().to_json_value(),
+        json!([
+            { "insert": "Title" },
+            { "insert": "\n", "attributes": { "header": 2 } },
+            { "insert": { "video": "a.mp4" } },
+        ])
+    );
+}
+
+#[test]
+fn blocks_and_embeds_sync() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "Title\nSee ").unwrap();
+    text.insert_embed(10, loro_value!({ "image": "a.png" }))
+        .unwrap();
+    text.set_block_attribute(0, "header", 1).unwrap();
+    text.set_block_attribute(1, "list", "bullet").unwrap();
+    doc.commit();
+
+    let snapshot_doc = LoroDoc::new();
+    snapshot_doc
+        .import(&doc.export(ExportMode::Snapshot).unwrap())
+        .unwrap();
+    let updates_doc = LoroDoc::new();
+    updates_doc
+        .import(&doc.export(ExportMode::all_updates()).unwrap())
+        .unwrap();
+    for other in [snapshot_doc, updates_doc] {
+        let other_text = other.get_text("text");
+        assert_eq!(
+            other_text.to_delta().to_json_value(),
+            text.to_delta().to_json_value()
+        );
+        assert_eq!(
+            other_text.get_block_attributes(0).unwrap().get("header"),
+            Some(&1.into())
+        );
+    }
+}
+
+fn new_doc(peer: u64) -> LoroDoc {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(peer).unwrap();
+    let mut styles = StyleConfigMap::default_rich_text_config();
+    styles.insert("comment".into(), StyleConfig::new().annotation(true));
+    doc.config_text_style(styles);
+    doc
+}
+
+#[test]
+fn concurrent_annotations_overlap() -> anyhow::Result<()> {
+    let a = new_doc(1);
+    let text = a.get_text("text");
+    text.insert(0, "Hello world")?;
+    a.commit();
+    let b = new_doc(2);
+    b.import(&a.export(ExportMode::all_updates())?)?;
+
+    let alice = text.annotate(0..5, "comment", "alice")?;
+    let bob = b.get_text("text").annotate(3..8, "comment", "bob")?;
+    a.import(&b.export(ExportMode::all_updates())?)?;
+    b.import(&a.export(ExportMode::all_updates())?)?;
+
+    let expected = json!([
+        {"insert": "Hel", "attributes": {"comment": {alice.to_string(): "alice"}}},
+        {"insert": "lo", "attributes": {"comment": {alice.to_string(): "alice", bob.to_string(): "bob"}}},
+        {"insert": " wo", "attributes": {"comment": {bob.to_string(): "bob"}}},
+        {"insert": "rld"},
+    ]);
+    assert_eq!(text.to_delta().to_json_value(), expected);
+    assert_eq!(b.get_text("text").to_delta().to_json_value(), expected);
+
+    let mut annotations = text.get_annotations("comment");
+    annotations.sort_by_key(|(id, _, _)| id.peer);
+    assert_eq!(
+        annotations,
+        vec![
+            (alice, LoroValue::from("alice"), 0..5),
+            (bob, LoroValue::from("bob"), 3..8)
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn remove_annotations() -> anyhow::Result<()> {
+    let doc = new_doc(1);
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    let a = text.annotate(0..5, "comment", "a")?;
+    let b = text.annotate(3..8, "comment", "b")?;
+    // `mark` adds an annotation for the annotation keys
+    text.mark(0..11, "comment", "c")?;
+    assert_eq!(text.get_annotations("comment").len(), 3);
+
+    text.remove_annotation("comment", a)?;
+    let ids: Vec<_> = text
+        .get_annotations("comment")
+        .into_iter()
+        .map(|(id, _, _)| id)
+        .collect();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&b) && !ids.contains(&a));
+    // Removing it again is a no-op
+    text.remove_annotation("comment", a)?;
+
+    // `unmark` removes all the annotations in the range
+    text.unmark(0..4, "comment")?;
+    let mut ranges: Vec<_> = text
+        .get_annotations("comment")
+        .into_iter()
+        .map(|(_, value, range)| (value, range))
+        .collect();
+    ranges.sort_by_key(|(_, range)| range.start);
+    assert_eq!(
+        ranges,
+        vec![(LoroValue::from("b"), 4..8), (LoroValue::from("c"), 4..11)]
+    );
+
+    assert!(matches!(
+        text.annotate(0..1, "bold", true),
+        Err(LoroError::ArgErr(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn annotation_events_report_sets() -> anyhow::Result<()> {
+    let doc = new_doc(1);
+    let text = doc.get_text("text");
+    text.insert(0, "Hello")?;
+    doc.commit();
+
+    let deltas = Arc::new(Mutex::new(Vec::new()));
+    let _sub = doc.subscribe_root({
+        let deltas = deltas.clone();
+        Arc::new(move |event| {
+            for e in event.events {
+                if let Diff::Text(delta) = e.diff {
+                    deltas.lock().unwrap().push(delta);
+                }
+            }
+        })
+    });
+
+    let id = text.annotate(1..3, "comment", "note")?;
+    doc.commit();
+    let mut attributes = fxhash::FxHashMap::default();
+    attributes.insert(
+        "comment".to_string(),
+        LoroValue::Map(
+            vec![(id.to_string(), LoroValue::from("note"))]
+                .into_iter()
+                .collect::<fxhash::FxHashMap<_, _>>()
+                .into(),
+        ),
+    );
+    assert_eq!(
+        deltas.lock().unwrap().pop(),
+        Some(vec![
+            TextDelta::Retain {
+                retain: 1,
+                attributes: None
+            },
+            TextDelta::Retain {
+                retain: 2,
+                attributes: Some(attributes.clone())
+            },
+        ])
+    );
+
+    // The grouped attributes can be applied to another text
+    let other = new_doc(2);
+    let other_text = other.get_text("text");
+    other_text.apply_delta(&[TextDelta::Insert {
+        insert: "Hi".into(),
+        attributes: Some(attributes),
+    }])?;
+    assert_eq!(
+        other_text.to_delta().to_json_value(),
+        json!([{"insert": "Hi", "attributes": {"comment": {id.to_string(): "note"}}}])
+    );
+    let annotations = other_text.get_annotations("comment");
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations[0].0, id);
+    Ok(())
+}
+
+#[test]
+fn only_configured_keys_are_grouped() -> anyhow::Result<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let mut styles = StyleConfigMap::default_rich_text_config();
+    styles.insert("comment".into(), StyleConfig::new());
+    doc.config_text_style(styles);
+    let text = doc.get_text("text");
+    text.insert(0, "Hello")?;
+    // Shaped like an annotation key, but `comment` is a normal style here
+    text.mark(0..2, "comment:0@2", "a")?;
+    let expected = json!([
+        {"insert": "He", "attributes": {"comment:0@2": "a"}},
+        {"insert": "llo"},
+    ]);
+    assert_eq!(text.to_delta().to_json_value(), expected);
+    assert_eq!(text.get_richtext_value().to_json_value(), expected);
+    assert_eq!(
+        text.get_styles_at(1)?.get("comment:0@2"),
+        Some(&LoroValue::from("a"))
+    );
+
+    let other = new_doc(2);
+    other.import(&doc.export(ExportMode::all_updates())?)?;
+    assert_eq!(
+        other.get_text("text").to_delta().to_json_value(),
+        json!([
+            {"insert": "He", "attributes": {"comment": {"0@2": "a"}}},
+            {"insert": "llo"},
+        ])
+    );
+    Ok(())
+}
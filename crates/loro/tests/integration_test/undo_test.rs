@@ -625,9 +625,7 @@ fn undo_richtext_conflict_set_style() -> LoroResult<()> {
     let mut config = StyleConfigMap::new();
     config.insert(
         "color".into(),
-        StyleConfig {
-            expand: loro::ExpandType::After,
-        },
+        StyleConfig::new().expand(loro::ExpandType::After),
     );
     doc_a.config_text_style(config.clone());
     let mut undo = UndoManager::new(&doc_a);
@@ -2130,6 +2130,22 @@ fn test_decode_import_blob_meta_updates_range() {
     assert_eq!(meta.partial_end_vv, vv!(0 => 5, 1 => 5));
 }
 
+#[test]
+fn test_decode_import_blob_meta_updates_with_several_changes() {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(0).unwrap();
+    doc.get_text("t").insert(0, "12").unwrap();
+    doc.commit_with(CommitOptions::default().commit_msg("a"));
+    doc.get_text("t").insert(0, "345").unwrap();
+    doc.commit_with(CommitOptions::default().commit_msg("b"));
+    let bytes = doc.export(ExportMode::all_updates()).unwrap();
+    let meta = LoroDoc::decode_import_blob_meta(&bytes, false).unwrap();
+    assert_eq!(meta.change_num, 2);
+    // The range covers all the changes of the peer, not only the last one
+    assert_eq!(meta.partial_start_vv, vv!(0 => 0));
+    assert_eq!(meta.partial_end_vv, vv!(0 => 5));
+}
+
 #[test]
 fn should_import_snapshot_before_shallow_snapshot() {
     let doc = LoroDoc::new();